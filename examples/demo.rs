@@ -51,7 +51,7 @@ impl Block {
 
     fn mine_block(&mut self, difficulty: usize) {
         let target = "0".repeat(difficulty);
-        while &self.hash[..difficulty] != target {
+        while self.hash[..difficulty] != target {
             self.nonce += 1;
             self.hash = self.calculate_hash();
         }
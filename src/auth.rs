@@ -0,0 +1,347 @@
+use crate::config::RpcConfig;
+use crate::hash_function::sha256_hash;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use rand::Rng;
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use warp::http::header::{RETRY_AFTER, WWW_AUTHENTICATE};
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+// cookie 文件中的用户名
+pub const COOKIE_USER: &str = "__cookie__";
+// 速率限制表最多记录的客户端数，超出时清理空闲的客户端，仍然超出时丢弃最久未请求的客户端
+pub const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// 权限组，高一级的权限包含低一级的全部权限
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Group {
+    Read,   // 查询链、交易池和网络状态
+    Wallet, // 另外可以提交交易
+    Admin,  // 另外可以挖矿、导入区块和管理节点连接
+}
+
+impl fmt::Display for Group {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Group::Read => "read",
+            Group::Wallet => "wallet",
+            Group::Admin => "admin",
+        })
+    }
+}
+
+impl FromStr for Group {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(Group::Read),
+            "wallet" => Ok(Group::Wallet),
+            "admin" => Ok(Group::Admin),
+            _ => Err(format!("unknown permission group: {}", s)),
+        }
+    }
+}
+
+/// 认证或限流失败，由 `recover` 转换为 HTTP 响应
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthError {
+    Unauthorized,     // 缺少凭据或凭据错误
+    Forbidden(Group), // 权限不足，附带所需的权限组
+    RateLimited,      // 超过每秒请求数上限
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AuthError::Unauthorized => f.write_str("authentication required"),
+            AuthError::Forbidden(group) => write!(f, "requires {} permission", group),
+            AuthError::RateLimited => f.write_str("rate limit exceeded"),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+impl warp::reject::Reject for AuthError {}
+
+/// 由盐和密码生成配置中保存的凭据：`盐$HMAC-SHA256(盐, 密码)` 的十六进制编码
+pub fn hash_password(salt: &str, password: &str) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, salt.as_bytes());
+    let tag = hmac::sign(&key, password.as_bytes());
+    format!("{}${}", salt, hex::encode(tag.as_ref()))
+}
+
+// 校验 `盐$摘要` 格式的凭据，返回盐和摘要
+pub fn parse_password_hash(credential: &str) -> Option<(&str, Vec<u8>)> {
+    let (salt, digest) = credential.split_once('$')?;
+    let digest = hex::decode(digest).ok()?;
+    (!salt.is_empty() && digest.len() == 32).then_some((salt, digest))
+}
+
+// 32 字节随机数的十六进制编码，用作 cookie 密码和盐
+pub fn random_secret() -> String {
+    hex::encode(rand::rng().random::<[u8; 32]>())
+}
+
+/// 生成新的 cookie 凭据并写入文件，返回密码
+///
+/// 先删除上次遗留的文件再新建，创建时即只有所有者可以读写，不会跟随符号链接。
+pub fn write_cookie(path: &Path) -> io::Result<String> {
+    let password = random_secret();
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    file.write_all(format!("{}:{}", COOKIE_USER, password).as_bytes())?;
+    Ok(password)
+}
+
+// 比较两者的哈希而不是原文，比较时间不会泄露令牌的前缀
+fn same_secret(a: &[u8], b: &[u8]) -> bool {
+    sha256_hash(a).as_ref() == sha256_hash(b).as_ref()
+}
+
+// 令牌桶：容量和每秒补充量都等于速率上限
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+struct User {
+    salt: String,
+    digest: Vec<u8>,
+    group: Group,
+}
+
+/// HTTP 服务的访问控制：识别请求的凭据，决定其权限组，并按客户端限流
+///
+/// 没有配置任何凭据时不做认证，所有请求都有管理员权限。
+pub struct Access {
+    tokens: Vec<(String, String, Group)>, // (名称, 令牌, 权限组)
+    users: HashMap<String, User>,
+    anonymous: Option<Group>,
+    rate_limit: Option<u32>,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl Access {
+    /// 由配置构造，`cookie` 为本次启动写入 cookie 文件的密码
+    pub fn new(config: &RpcConfig, cookie: Option<String>) -> Self {
+        let tokens = config
+            .tokens
+            .iter()
+            .map(|token| (token.name.clone(), token.token.clone(), token.group))
+            .collect();
+        let mut users = HashMap::new();
+        for user in &config.users {
+            // 配置校验时已检查格式
+            if let Some((salt, digest)) = parse_password_hash(&user.hmac) {
+                users.insert(
+                    user.name.clone(),
+                    User {
+                        salt: salt.to_string(),
+                        digest,
+                        group: user.group,
+                    },
+                );
+            }
+        }
+        if let Some(password) = cookie {
+            let salt = random_secret();
+            let (_, digest) = parse_password_hash(&hash_password(&salt, &password)).unwrap();
+            users.insert(
+                COOKIE_USER.to_string(),
+                User {
+                    salt,
+                    digest,
+                    group: Group::Admin,
+                },
+            );
+        }
+        Access {
+            tokens,
+            users,
+            anonymous: config.anonymous,
+            rate_limit: config.rate_limit,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.tokens.is_empty() || !self.users.is_empty()
+    }
+
+    /// 按 Authorization 请求头识别客户端，返回凭据名称和权限组；匿名请求的名称为 None
+    pub fn authenticate(
+        &self,
+        authorization: Option<&str>,
+    ) -> Result<(Option<String>, Group), AuthError> {
+        if !self.enabled() {
+            return Ok((None, Group::Admin));
+        }
+        let Some(authorization) = authorization else {
+            return self
+                .anonymous
+                .map(|group| (None, group))
+                .ok_or(AuthError::Unauthorized);
+        };
+        let (scheme, credentials) = authorization
+            .split_once(' ')
+            .ok_or(AuthError::Unauthorized)?;
+        if scheme.eq_ignore_ascii_case("bearer") {
+            let token = credentials.trim();
+            return self
+                .tokens
+                .iter()
+                .find(|(_, expected, _)| same_secret(expected.as_bytes(), token.as_bytes()))
+                .map(|(name, _, group)| (Some(name.clone()), *group))
+                .ok_or(AuthError::Unauthorized);
+        }
+        if scheme.eq_ignore_ascii_case("basic") {
+            let decoded = BASE64
+                .decode(credentials.trim())
+                .map_err(|_| AuthError::Unauthorized)?;
+            let decoded = String::from_utf8(decoded).map_err(|_| AuthError::Unauthorized)?;
+            let (name, password) = decoded.split_once(':').ok_or(AuthError::Unauthorized)?;
+            let user = self.users.get(name).ok_or(AuthError::Unauthorized)?;
+            let key = hmac::Key::new(hmac::HMAC_SHA256, user.salt.as_bytes());
+            return hmac::verify(&key, password.as_bytes(), &user.digest)
+                .map(|_| (Some(name.to_string()), user.group))
+                .map_err(|_| AuthError::Unauthorized);
+        }
+        Err(AuthError::Unauthorized)
+    }
+
+    // 速率限制表中记录的客户端数
+    pub fn tracked_clients(&self) -> usize {
+        self.buckets.lock().unwrap().len()
+    }
+
+    /// 消耗客户端的一次请求额度，超过上限时返回 false
+    pub fn allow(&self, client: &str, now: Instant) -> bool {
+        let Some(rate) = self.rate_limit else {
+            return true;
+        };
+        let rate = rate as f64;
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_TRACKED_CLIENTS && !buckets.contains_key(client) {
+            // 空闲超过一秒的客户端额度已经补满，可以丢弃
+            buckets.retain(|_, bucket| now.duration_since(bucket.updated).as_secs_f64() < 1.0);
+            if buckets.len() >= MAX_TRACKED_CLIENTS {
+                let oldest = buckets
+                    .iter()
+                    .min_by_key(|(_, bucket)| bucket.updated)
+                    .map(|(name, _)| name.clone());
+                if let Some(oldest) = oldest {
+                    buckets.remove(&oldest);
+                }
+            }
+        }
+        let bucket = buckets.entry(client.to_string()).or_insert(Bucket {
+            tokens: rate,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(rate);
+        bucket.updated = now;
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+
+    // 认证并限流，已认证的客户端按凭据名称计数，匿名客户端按 IP 计数
+    fn admit(
+        &self,
+        authorization: Option<&str>,
+        remote: Option<SocketAddr>,
+    ) -> Result<Group, AuthError> {
+        let (name, group) = self.authenticate(authorization)?;
+        let client = match name {
+            Some(name) => name,
+            None => remote.map_or_else(String::new, |addr| addr.ip().to_string()),
+        };
+        if !self.allow(&client, Instant::now()) {
+            return Err(AuthError::RateLimited);
+        }
+        Ok(group)
+    }
+}
+
+/// 识别请求的客户端，提取其权限组
+pub fn identify(access: Arc<Access>) -> impl Filter<Extract = (Group,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and(warp::addr::remote())
+        .and_then(
+            move |authorization: Option<String>, remote: Option<SocketAddr>| {
+                let result = access
+                    .admit(authorization.as_deref(), remote)
+                    .map_err(warp::reject::custom);
+                async move { result }
+            },
+        )
+}
+
+/// 要求请求至少具有指定的权限组
+pub fn require(
+    access: Arc<Access>,
+    required: Group,
+) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    identify(access)
+        .and_then(move |group: Group| async move {
+            if group >= required {
+                Ok(())
+            } else {
+                Err(warp::reject::custom(AuthError::Forbidden(required)))
+            }
+        })
+        .untuple_one()
+}
+
+/// 把认证失败转换为 401、403 或 429 响应，其他拒绝原因保持不变
+pub async fn recover(rejection: Rejection) -> Result<warp::reply::Response, Rejection> {
+    let Some(error) = rejection.find::<AuthError>() else {
+        return Err(rejection);
+    };
+    let message = warp::reply::json(&error.to_string());
+    let response = match error {
+        AuthError::Unauthorized => warp::reply::with_header(
+            warp::reply::with_status(message, StatusCode::UNAUTHORIZED),
+            WWW_AUTHENTICATE,
+            "Bearer, Basic realm=\"node\"",
+        )
+        .into_response(),
+        AuthError::Forbidden(_) => {
+            warp::reply::with_status(message, StatusCode::FORBIDDEN).into_response()
+        }
+        AuthError::RateLimited => warp::reply::with_header(
+            warp::reply::with_status(message, StatusCode::TOO_MANY_REQUESTS),
+            RETRY_AFTER,
+            "1",
+        )
+        .into_response(),
+    };
+    Ok(response)
+}
@@ -0,0 +1,90 @@
+use ::block_chain::auth;
+use ::block_chain::config::{Command, NodeConfig, USAGE};
+use ::block_chain::logging;
+use ::block_chain::service::Node;
+use ::block_chain::wallet::mnemonic::Mnemonic;
+use ::block_chain::wallet::{self, Wallet, WalletError, DEFAULT_WORD_COUNT};
+use std::process;
+
+// 用配置的密码创建新钱包，返回助记词
+fn create_wallet(config: &NodeConfig) -> Result<String, WalletError> {
+    let password = match &config.wallet.password_file {
+        Some(path) => wallet::read_password_file(path)?,
+        None => String::new(),
+    };
+    let path = config.wallet_path();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| WalletError::Io(dir.to_path_buf(), e))?;
+    }
+    let mnemonic = Mnemonic::generate(DEFAULT_WORD_COUNT)?;
+    Wallet::create(&path, &password, &mnemonic, "")?;
+    Ok(mnemonic.phrase())
+}
+
+#[tokio::main]
+async fn main() {
+    let config = match NodeConfig::from_args(std::env::args().skip(1)) {
+        Ok(Command::Run(config)) => config,
+        Ok(Command::PrintConfig(config)) => {
+            print!("{}", config.to_toml());
+            return;
+        }
+        Ok(Command::GenerateAuth(name)) => {
+            let password = auth::random_secret();
+            let hmac = auth::hash_password(&auth::random_secret()[..32], &password);
+            println!("Password for {}: {}\n", name, password);
+            println!(
+                "[[rpc.users]]\nname = {:?}\nhmac = {:?}\ngroup = \"read\"",
+                name, hmac
+            );
+            return;
+        }
+        Ok(Command::CreateWallet(config)) => match create_wallet(&config) {
+            Ok(phrase) => {
+                println!("Created wallet {}", config.wallet_path().display());
+                println!(
+                    "Write down this mnemonic to restore the wallet:\n\n{}",
+                    phrase
+                );
+                return;
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        },
+        Ok(Command::Help) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+    logging::set_level(config.log_level);
+
+    // 启动节点
+    let handle = match Node::start(config).await {
+        Ok(handle) => handle,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    if let Some(identity) = handle.node().network.identity() {
+        println!("Node identity: {}", hex::encode(identity));
+    }
+    let scheme = match handle.node().config.rpc.tls {
+        Some(_) => "https",
+        None => "http",
+    };
+    for addr in handle.rpc_addrs() {
+        println!("HTTP server listening on {}://{}", scheme, addr);
+    }
+
+    // 收到 Ctrl-C 后关闭节点
+    let _ = tokio::signal::ctrl_c().await;
+    println!("Shutting down");
+    handle.shutdown().await;
+}
@@ -1,20 +1,408 @@
+use crate::events::{ChainEvent, EventBus, RemovalReason};
 use crate::hash_function::{calculate_merkle_root, hash_block_header};
 
+use crate::miner::{meets_difficulty, Miner};
+use crate::serialization::serialize_bc;
 use crate::transaction::Transaction;
 use chrono::Utc;
 use rand::Rng;
 
+use schemars::JsonSchema;
 use serde::de::{self, Visitor};
 use serde::{ser::SerializeStruct, Deserialize, Deserializer, Serialize, Serializer};
-use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt;
+use std::sync::atomic::AtomicBool;
 use std::sync::{Arc, Mutex};
 
+// 每个区块 coinbase 交易的奖励
+pub const BLOCK_REWARD: u64 = 50;
+// 创世区块的时间戳（2025-03-06 00:00:00 UTC）
+pub const GENESIS_TIMESTAMP: u32 = 1_741_219_200;
+// 区块时间戳允许超前当前时间的最大秒数
+pub const MAX_FUTURE_BLOCK_TIME: u32 = 2 * 60 * 60;
+// 交易池接受的单笔交易序列化后的最大长度
+pub const MAX_TRANSACTION_SIZE: usize = 100_000;
+// 分叉链最多可以回退的主链区块数，更早分叉的区块不再保存
+pub const MAX_REORG_DEPTH: u32 = 100;
+// 最多保存的分叉区块数，超出时丢弃高度最低的区块
+pub const MAX_SIDE_BLOCKS: usize = 1000;
+
+/// 区块校验失败的原因
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockError {
+    PrevHashMismatch,                     // 前一个区块哈希不是当前链顶
+    MerkleRootMismatch,                   // Merkle Root 与交易列表不符
+    BadBits,                              // 难度字段与链的难度不一致
+    InsufficientWork,                     // 哈希不满足难度
+    TimeTooNew,                           // 时间戳超前太多
+    BadCoinbase,                          // coinbase 位置、数量、高度或金额错误
+    DuplicateTransaction,                 // 区块内有重复交易
+    NonFinalTransaction,                  // 交易的 lock_time 未到
+    InvalidSignature,                     // 交易签名校验失败
+    UnknownParent,                        // 前一个区块未知（孤块）
+    InvalidTransaction(TransactionError), // 交易的输入不满足交易池规则
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            BlockError::PrevHashMismatch => "previous block hash does not match chain tip",
+            BlockError::MerkleRootMismatch => "merkle root does not match transactions",
+            BlockError::BadBits => "bits do not match chain difficulty",
+            BlockError::InsufficientWork => "block hash does not meet difficulty",
+            BlockError::TimeTooNew => "block timestamp too far in the future",
+            BlockError::BadCoinbase => "invalid coinbase transaction",
+            BlockError::DuplicateTransaction => "duplicate transaction in block",
+            BlockError::NonFinalTransaction => "block contains non-final transaction",
+            BlockError::InvalidSignature => "transaction signature verification failed",
+            BlockError::UnknownParent => "previous block is unknown",
+            BlockError::InvalidTransaction(e) => {
+                return write!(f, "invalid transaction in block: {}", e)
+            }
+        };
+        f.write_str(message)
+    }
+}
+
+impl std::error::Error for BlockError {}
+
+impl From<TransactionError> for BlockError {
+    fn from(error: TransactionError) -> Self {
+        match error {
+            TransactionError::MissingSignature | TransactionError::InvalidSignature => {
+                BlockError::InvalidSignature
+            }
+            error => BlockError::InvalidTransaction(error),
+        }
+    }
+}
+
+/// 交易未被交易池接受的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionError {
+    Coinbase,          // coinbase 交易只能由区块产生
+    Empty,             // 没有输入或输出
+    TooLarge,          // 序列化后超过长度上限
+    AlreadyKnown,      // 已在交易池或主链上
+    NonFinal,          // lock_time 未到
+    MissingSignature,  // 有未签名的输入
+    InvalidSignature,  // 交易签名校验失败
+    MissingParent,     // 引用的父交易既不在主链也不在交易池中
+    NoSpendableOutput, // 父交易中没有锁定给签名公钥的输出
+    DoubleSpend,       // 输出已被主链或交易池中的交易花费
+    InsufficientFunds, // 输出金额超过输入金额
+}
+
+impl fmt::Display for TransactionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            TransactionError::Coinbase => "coinbase transaction is only valid in a block",
+            TransactionError::Empty => "transaction has no inputs or outputs",
+            TransactionError::TooLarge => "transaction is too large",
+            TransactionError::AlreadyKnown => "transaction already known",
+            TransactionError::NonFinal => "transaction is not final",
+            TransactionError::MissingSignature => "transaction has an unsigned input",
+            TransactionError::InvalidSignature => "transaction signature verification failed",
+            TransactionError::MissingParent => "referenced transaction is unknown",
+            TransactionError::NoSpendableOutput => "input key does not own any parent output",
+            TransactionError::DoubleSpend => "output already spent",
+            TransactionError::InsufficientFunds => "outputs exceed inputs",
+        };
+        f.write_str(message)
+    }
+}
+
+impl std::error::Error for TransactionError {}
+
+/// 处理收到的区块后的结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockStatus {
+    AlreadyKnown, // 区块已存在
+    Connected,    // 延长了主链
+    SideChain,    // 保存在较短的分叉链上
+    Reorganized {
+        disconnected: usize,
+        connected: usize,
+    }, // 分叉链更长，切换了主链
+}
+
+/// 主链上未花费的交易输出
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Utxo {
+    pub txid: [u8; 32],
+    pub index: u32, // 输出在交易中的位置
+    pub value: u64,
+    pub height: u32, // 所在区块的高度
+}
+
+// 交易输入花费的输出：父交易哈希和签名公钥
+type Spend = ([u8; 32], Vec<u8>);
+
+fn spends(tx: &Transaction) -> impl Iterator<Item = Spend> + '_ {
+    tx.inputs.iter().filter_map(|input| {
+        input
+            .public_key()
+            .map(|key| (input.previous_output, key.to_vec()))
+    })
+}
+
+/// 不依赖链状态的交易校验：非 coinbase、有输入和输出、长度不超限且每个输入都签名正确
+///
+/// 父交易未知的交易也可以先做这部分校验。
+pub fn check_transaction_stateless(tx: &Transaction) -> Result<(), TransactionError> {
+    if tx.is_coinbase() {
+        return Err(TransactionError::Coinbase);
+    }
+    if tx.inputs.is_empty() || tx.outputs.is_empty() {
+        return Err(TransactionError::Empty);
+    }
+    if serialize_bc(tx).map_or(true, |data| data.len() > MAX_TRANSACTION_SIZE) {
+        return Err(TransactionError::TooLarge);
+    }
+    check_signatures(tx)
+}
+
+// 每个输入都必须签名，且签名能通过校验
+fn check_signatures(tx: &Transaction) -> Result<(), TransactionError> {
+    for (index, input) in tx.inputs.iter().enumerate() {
+        if input.public_key().is_none() {
+            return Err(TransactionError::MissingSignature);
+        }
+        if !tx.verify_signature(index) {
+            return Err(TransactionError::InvalidSignature);
+        }
+    }
+    Ok(())
+}
+
+/// 按交易池规则校验交易的输入，返回交易花费的输出
+///
+/// 调用方需先校验签名；输入只引用父交易的哈希，签名公钥花掉父交易中锁定给它的全部输出。
+/// `find_parent` 查找父交易，`is_spent` 判断父交易中锁定给某公钥的输出是否已被花费。
+fn check_inputs<'a>(
+    tx: &Transaction,
+    find_parent: impl Fn(&[u8; 32]) -> Option<&'a Transaction>,
+    is_spent: impl Fn(&[u8; 32], &[u8]) -> bool,
+) -> Result<Vec<Spend>, TransactionError> {
+    // 同一父交易和公钥的输出只计一次
+    let mut spends: Vec<Spend> = Vec::new();
+    let mut input_value: u64 = 0;
+    for input in &tx.inputs {
+        let Some(key) = input.public_key() else {
+            return Err(TransactionError::MissingSignature);
+        };
+        if spends.iter().any(|(previous_output, spent_key)| {
+            *previous_output == input.previous_output && spent_key == key
+        }) {
+            continue;
+        }
+        let parent = find_parent(&input.previous_output).ok_or(TransactionError::MissingParent)?;
+        let owned: Vec<u64> = parent
+            .outputs
+            .iter()
+            .filter(|output| output.script_pubkey == key)
+            .map(|output| output.value)
+            .collect();
+        if owned.is_empty() {
+            return Err(TransactionError::NoSpendableOutput);
+        }
+        input_value = owned
+            .into_iter()
+            .fold(input_value, |sum, value| sum.saturating_add(value));
+        spends.push((input.previous_output, key.to_vec()));
+    }
+
+    if spends
+        .iter()
+        .any(|(previous_output, key)| is_spent(previous_output, key))
+    {
+        return Err(TransactionError::DoubleSpend);
+    }
+
+    let output_value = tx
+        .outputs
+        .iter()
+        .try_fold(0u64, |sum, output| sum.checked_add(output.value));
+    if output_value.is_none_or(|value| value > input_value) {
+        return Err(TransactionError::InsufficientFunds);
+    }
+    Ok(spends)
+}
+
+// 某条分支在分叉点之后新增的交易和花费，分叉点及之前的部分从主链索引读取
+struct BranchState<'a> {
+    chain: &'a BlockChain,
+    fork_height: usize,
+    transactions: HashMap<[u8; 32], &'a Transaction>,
+    spent: HashSet<Spend>,
+}
+
+impl<'a> BranchState<'a> {
+    // 校验交易的输入，通过后把交易加入分支
+    fn connect(&mut self, tx: &'a Transaction) -> Result<(), TransactionError> {
+        check_signatures(tx)?;
+        let spent = check_inputs(
+            tx,
+            |hash| {
+                self.transactions.get(hash).copied().or_else(|| {
+                    self.chain
+                        .find_transaction(hash)
+                        .filter(|(_, height)| *height as usize <= self.fork_height)
+                        .map(|(parent, _)| parent)
+                })
+            },
+            |previous_output, key| {
+                let spend = (*previous_output, key.to_vec());
+                self.spent.contains(&spend)
+                    || self
+                        .chain
+                        .spent_index
+                        .get(&spend)
+                        .is_some_and(|height| *height <= self.fork_height)
+            },
+        )?;
+        self.transactions.insert(tx.hash(), tx);
+        self.spent.extend(spent);
+        Ok(())
+    }
+}
+
+/// 交易池：按加入顺序保存交易，并索引交易哈希和已花费的输出
+///
+/// 只读访问通过 `Deref` 得到交易队列，修改需使用下面的方法以保持索引一致。
+/// 队列中间的交易可能被移除，位置不固定，因此哈希索引保存交易的副本。
+#[derive(Debug, Clone, Default)]
+pub struct TransactionPool {
+    transactions: VecDeque<Transaction>,
+    txids: HashMap<[u8; 32], (usize, Transaction)>, // 交易哈希 -> 池中相同交易的个数和交易
+    spent: HashMap<Spend, usize>,                   // 已花费的输出 -> 花费它的池中交易数
+}
+
+impl TransactionPool {
+    pub fn new() -> Self {
+        TransactionPool::default()
+    }
+
+    fn index(&mut self, tx: &Transaction) {
+        self.txids
+            .entry(tx.hash())
+            .or_insert_with(|| (0, tx.clone()))
+            .0 += 1;
+        for spend in spends(tx) {
+            *self.spent.entry(spend).or_default() += 1;
+        }
+    }
+
+    fn unindex(&mut self, tx: &Transaction) {
+        fn decrement<K: std::hash::Hash + Eq>(map: &mut HashMap<K, usize>, key: K) {
+            if let Some(count) = map.get_mut(&key) {
+                *count -= 1;
+                if *count == 0 {
+                    map.remove(&key);
+                }
+            }
+        }
+        let hash = tx.hash();
+        if let Some((count, _)) = self.txids.get_mut(&hash) {
+            *count -= 1;
+            if *count == 0 {
+                self.txids.remove(&hash);
+            }
+        }
+        for spend in spends(tx) {
+            decrement(&mut self.spent, spend);
+        }
+    }
+
+    pub fn contains(&self, hash: &[u8; 32]) -> bool {
+        self.txids.contains_key(hash)
+    }
+
+    // 按哈希查找池中的交易
+    pub fn get_by_hash(&self, hash: &[u8; 32]) -> Option<&Transaction> {
+        self.txids.get(hash).map(|(_, tx)| tx)
+    }
+
+    // 池中是否已有交易花费了该父交易中锁定给该公钥的输出
+    pub fn is_spent(&self, previous_output: &[u8; 32], public_key: &[u8]) -> bool {
+        self.spent
+            .contains_key(&(*previous_output, public_key.to_vec()))
+    }
+
+    pub fn push_back(&mut self, tx: Transaction) {
+        self.index(&tx);
+        self.transactions.push_back(tx);
+    }
+
+    pub fn pop_front(&mut self) -> Option<Transaction> {
+        let tx = self.transactions.pop_front()?;
+        self.unindex(&tx);
+        Some(tx)
+    }
+
+    /// 移除所有满足条件的交易并按原顺序返回
+    pub fn remove_if(&mut self, mut remove: impl FnMut(&Transaction) -> bool) -> Vec<Transaction> {
+        let mut removed = Vec::new();
+        let mut kept = VecDeque::with_capacity(self.transactions.len());
+        for tx in self.transactions.drain(..) {
+            if remove(&tx) {
+                removed.push(tx);
+            } else {
+                kept.push_back(tx);
+            }
+        }
+        self.transactions = kept;
+        for tx in &removed {
+            self.unindex(tx);
+        }
+        removed
+    }
+
+    /// 取出全部交易
+    pub fn take_all(&mut self) -> Vec<Transaction> {
+        self.txids.clear();
+        self.spent.clear();
+        self.transactions.drain(..).collect()
+    }
+}
+
+impl std::ops::Deref for TransactionPool {
+    type Target = VecDeque<Transaction>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.transactions
+    }
+}
+
+impl FromIterator<Transaction> for TransactionPool {
+    fn from_iter<I: IntoIterator<Item = Transaction>>(iter: I) -> Self {
+        let mut pool = TransactionPool::new();
+        for tx in iter {
+            pool.push_back(tx);
+        }
+        pool
+    }
+}
+
+impl Serialize for TransactionPool {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.transactions.serialize(serializer)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct BlockChain {
     pub blocks: Vec<Block>, // 区块列表
-    pub transaction_pool: Arc<Mutex<VecDeque<Transaction>>>,
+    pub transaction_pool: Arc<Mutex<TransactionPool>>,
     difficulty: usize,
+    side_blocks: HashMap<[u8; 32], (Block, u32)>, // 不在主链上的分叉区块及其高度
+    pool_limit: Option<usize>,                    // 交易池最多保存的交易数，None 表示不限制
+    block_index: HashMap<[u8; 32], usize>,        // 主链区块哈希 -> 高度
+    tx_index: HashMap<[u8; 32], usize>,           // 主链交易哈希 -> 所在区块的高度
+    spent_index: HashMap<Spend, usize>,           // 主链交易花费的输出 -> 所在区块的高度
+    events: EventBus,                             // 区块和交易池事件，克隆的链共享同一总线
 }
 
 // 手动实现 Serialize 和 Deserialize
@@ -32,6 +420,24 @@ impl Serialize for BlockChain {
     }
 }
 
+// 与 Serialize 输出的字段一致
+impl JsonSchema for BlockChain {
+    fn schema_name() -> String {
+        "BlockChain".to_string()
+    }
+
+    fn json_schema(generator: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        #[derive(JsonSchema)]
+        #[allow(dead_code)]
+        struct BlockChain {
+            blocks: Vec<Block>,
+            transaction_pool: Vec<Transaction>,
+            difficulty: usize,
+        }
+        BlockChain::json_schema(generator)
+    }
+}
+
 impl<'de> Deserialize<'de> for BlockChain {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -74,8 +480,9 @@ impl<'de> Deserialize<'de> for BlockChain {
                             if transaction_pool.is_some() {
                                 return Err(de::Error::duplicate_field("transaction_pool"));
                             }
-                            let pool: VecDeque<Transaction> = map.next_value()?;
-                            transaction_pool = Some(Arc::new(Mutex::new(pool)));
+                            let pool: Vec<Transaction> = map.next_value()?;
+                            transaction_pool =
+                                Some(Arc::new(Mutex::new(pool.into_iter().collect())));
                         }
                         Field::Difficulty => {
                             if difficulty.is_some() {
@@ -92,20 +499,28 @@ impl<'de> Deserialize<'de> for BlockChain {
                 let difficulty =
                     difficulty.ok_or_else(|| de::Error::missing_field("difficulty"))?;
 
-                Ok(BlockChain {
+                let mut chain = BlockChain {
                     blocks,
                     transaction_pool,
                     difficulty,
-                })
+                    side_blocks: HashMap::new(),
+                    pool_limit: None,
+                    block_index: HashMap::new(),
+                    tx_index: HashMap::new(),
+                    spent_index: HashMap::new(),
+                    events: EventBus::new(),
+                };
+                chain.reindex();
+                Ok(chain)
             }
         }
 
-        const FIELDS: &'static [&'static str] = &["blocks", "transaction_pool", "difficulty"];
+        const FIELDS: &[&str] = &["blocks", "transaction_pool", "difficulty"];
         deserializer.deserialize_struct("BlockChain", FIELDS, BlockChainVisitor)
     }
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
 pub struct BlockHeader {
     pub version: u32,              // 版本号
     pub prev_block_hash: [u8; 32], // 前一个区块的哈希值
@@ -115,12 +530,18 @@ pub struct BlockHeader {
     pub nonce: u32,                // 随机数
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct Block {
     pub header: BlockHeader,
     pub transactions: Vec<Transaction>, // 交易列表
 }
 
+impl Default for Block {
+    fn default() -> Self {
+        Block::new()
+    }
+}
+
 impl Block {
     // 创建一个新的区块
     pub fn new() -> Block {
@@ -141,79 +562,631 @@ impl Block {
             transactions: vec![],
         }
     }
+
+    // 创世区块，内容固定，使所有节点的创世哈希一致
+    pub fn genesis() -> Block {
+        Block {
+            header: BlockHeader {
+                version: 1,
+                prev_block_hash: [0; 32],
+                merkle_root: [0; 32],
+                timestamp: GENESIS_TIMESTAMP,
+                bits: 0,
+                nonce: 0,
+            },
+            transactions: vec![],
+        }
+    }
 }
 
 impl BlockChain {
     // 创建一个新的区块链
     pub fn new(difficulty: usize) -> Self {
-        let genesis_block = Block::new();
-        BlockChain {
+        let genesis_block = Block::genesis();
+        let mut chain = BlockChain {
             blocks: vec![genesis_block],
-            transaction_pool: Arc::new(Mutex::new(VecDeque::new())),
+            transaction_pool: Arc::new(Mutex::new(TransactionPool::new())),
             difficulty,
+            side_blocks: HashMap::new(),
+            pool_limit: None,
+            block_index: HashMap::new(),
+            tx_index: HashMap::new(),
+            spent_index: HashMap::new(),
+            events: EventBus::new(),
+        };
+        chain.reindex();
+        chain
+    }
+
+    /// 重建主链的区块和交易索引，直接修改 `blocks` 后需要调用
+    pub fn reindex(&mut self) {
+        self.block_index.clear();
+        self.tx_index.clear();
+        self.spent_index.clear();
+        for height in 0..self.blocks.len() {
+            self.index_block(height);
+        }
+    }
+
+    fn index_block(&mut self, height: usize) {
+        let block = &self.blocks[height];
+        self.block_index
+            .insert(hash_block_header(&block.header), height);
+        for tx in &block.transactions {
+            self.tx_index.insert(tx.hash(), height);
+            for spend in spends(tx) {
+                self.spent_index.insert(spend, height);
+            }
         }
     }
-    pub fn broadcast_transaction(&self, tx: Transaction, peers: Vec<String>) {
-        for peer in peers {
-            let client = reqwest::Client::new();
-            let url = format!("http://{}/transaction", peer);
-            let tx_clone = tx.clone();
-            tokio::spawn(async move {
-                let res = client.post(&url).json(&tx_clone).send().await;
-                match res {
-                    Ok(_) => println!("Transaction broadcasted to {}", peer),
-                    Err(e) => println!("Failed to broadcast to {}: {}", peer, e),
+
+    // 主链断开区块时移除其索引
+    fn unindex_block(&mut self, block: &Block, height: usize) {
+        self.block_index.remove(&hash_block_header(&block.header));
+        for tx in &block.transactions {
+            let hash = tx.hash();
+            if self.tx_index.get(&hash) == Some(&height) {
+                self.tx_index.remove(&hash);
+            }
+            for spend in spends(tx) {
+                if self.spent_index.get(&spend) == Some(&height) {
+                    self.spent_index.remove(&spend);
                 }
-            });
+            }
         }
     }
+    /// 区块链和交易池的事件总线
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
 
-    // 添加交易到交易池
+    // 添加交易到交易池，超出上限时丢弃最早的交易
     pub fn add_transaction(&mut self, transaction: Transaction) {
         let mut pool = self.transaction_pool.lock().unwrap();
+        self.events
+            .publish(|| ChainEvent::TransactionAdded(Arc::new(transaction.clone())));
         pool.push_back(transaction);
+        if let Some(limit) = self.pool_limit {
+            while pool.len() > limit {
+                let evicted = pool.pop_front().unwrap();
+                self.events.publish(|| ChainEvent::TransactionRemoved {
+                    txid: evicted.hash(),
+                    reason: RemovalReason::Evicted,
+                });
+            }
+        }
     }
 
-    pub fn mine_block(&mut self) {
+    /// 按交易池规则完整校验交易，通过后加入交易池，返回交易哈希
+    pub fn accept_transaction(&mut self, tx: Transaction) -> Result<[u8; 32], TransactionError> {
+        self.check_transaction(&tx)?;
+        let hash = tx.hash();
+        self.add_transaction(tx);
+        Ok(hash)
+    }
+
+    /// 校验外部提交的交易能否进入交易池，父交易可以在主链或交易池中
+    pub fn check_transaction(&self, tx: &Transaction) -> Result<(), TransactionError> {
+        check_transaction_stateless(tx)?;
+        let hash = tx.hash();
+        let pool = self.transaction_pool.lock().unwrap();
+        if self.contains_transaction(&hash) || pool.contains(&hash) {
+            return Err(TransactionError::AlreadyKnown);
+        }
+        if !tx.is_final(self.height() + 1, Utc::now().timestamp() as u32) {
+            return Err(TransactionError::NonFinal);
+        }
+
+        check_inputs(
+            tx,
+            |hash| {
+                self.find_transaction(hash)
+                    .map(|(parent, _)| parent)
+                    .or_else(|| pool.get_by_hash(hash))
+            },
+            |previous_output, key| {
+                self.spent_index
+                    .contains_key(&(*previous_output, key.to_vec()))
+                    || pool.is_spent(previous_output, key)
+            },
+        )?;
+        Ok(())
+    }
+
+    // 设置交易池的容量上限
+    pub fn set_pool_limit(&mut self, limit: Option<usize>) {
+        self.pool_limit = limit;
+    }
+
+    pub fn difficulty(&self) -> usize {
+        self.difficulty
+    }
+
+    // 创世区块的哈希值
+    pub fn genesis_hash(&self) -> [u8; 32] {
+        self.blocks
+            .first()
+            .map(|block| hash_block_header(&block.header))
+            .unwrap_or([0; 32])
+    }
+
+    // 当前链顶高度（创世区块高度为 0）
+    pub fn height(&self) -> u32 {
+        self.blocks.len().saturating_sub(1) as u32
+    }
+
+    // 当前链顶区块的哈希值
+    pub fn tip_hash(&self) -> [u8; 32] {
+        self.blocks
+            .last()
+            .map(|block| hash_block_header(&block.header))
+            .unwrap_or([0; 32])
+    }
+
+    // 构造待挖矿的区块模板，不修改交易池；提供锁定脚本时在首位加入 coinbase 交易
+    pub fn block_template(&self, coinbase_script: Option<&[u8]>) -> Block {
+        self.block_template_at(
+            coinbase_script,
+            Utc::now().timestamp() as u32,
+            &mut rand::rng(),
+        )
+    }
+
+    /// 以指定的时间戳构造区块模板，起始 nonce 和 coinbase 的 extra_nonce 取自 `rng`
+    pub fn block_template_at(
+        &self,
+        coinbase_script: Option<&[u8]>,
+        current_timestamp: u32,
+        rng: &mut impl Rng,
+    ) -> Block {
         let mut new_block = Block::new();
+        new_block.header.timestamp = current_timestamp;
+        new_block.header.nonce = rng.random();
         // 将交易池中的交易添加到新区块的交易列表
-        // 获取当前区块高度和时间戳
         let current_height = self.blocks.len() as u32;
-        let current_timestamp = Utc::now().timestamp() as u32;
 
-        // 过滤交易池中的交易
-        let valid_transactions: Vec<Transaction> = self
-            .transaction_pool
-            .lock()
-            .unwrap()
+        // 过滤交易池中的交易：只保留已到期、且按区块校验规则能依次连接到链顶的交易
+        let pool = self.transaction_pool.lock().unwrap();
+        let mut branch = self.branch_state(&self.tip_hash()).unwrap();
+        let valid_transactions: Vec<Transaction> = pool
             .iter()
             .filter(|tx| {
-                if tx.lock_time == 0 {
-                    // lock_time 为 0，表示交易立即生效
-                    true
-                } else if tx.lock_time < 500_000_000 {
-                    // lock_time 表示区块高度
-                    current_height >= tx.lock_time
-                } else {
-                    // lock_time 表示时间戳
-                    current_timestamp >= tx.lock_time
-                }
+                !tx.is_coinbase()
+                    && !tx.outputs.is_empty()
+                    && tx.is_final(current_height, current_timestamp)
+                    && branch.connect(tx).is_ok()
             })
             .cloned()
             .collect();
+        drop(pool);
 
+        if let Some(script_pubkey) = coinbase_script {
+            let extra_nonce = rng.random();
+            new_block.transactions.push(Transaction::coinbase(
+                current_height,
+                extra_nonce,
+                BLOCK_REWARD,
+                script_pubkey.to_vec(),
+            ));
+        }
         // 将有效的交易添加到新区块的交易列表
-        new_block.transactions = valid_transactions;
-        // 清空交易池
-        self.transaction_pool.lock().unwrap().clear();
-        // 计算新区块的 Merkle Root（假设交易列表已经设置）
+        new_block.transactions.extend(valid_transactions);
+        // 设置前一个区块哈希和 Merkle Root，使挖出的哈希在上链后保持不变
+        new_block.header.prev_block_hash = self.tip_hash();
         new_block.header.merkle_root = calculate_merkle_root(&new_block.transactions);
-        println!("Mining block...");
-        // 计算新区块的哈希值
-        let mut hash = hash_block_header(&new_block.header);
-        while !hash.starts_with(&[0; 32][..self.difficulty]) {
-            new_block.header.nonce += 1;
-            hash = hash_block_header(&new_block.header);
+        new_block.header.bits = self.difficulty as u32;
+        new_block
+    }
+
+    // 从交易池取出交易并构造待挖矿的区块模板（会清空交易池）
+    pub fn take_block_template(&mut self) -> Block {
+        let new_block = self.block_template(None);
+        // 清空交易池
+        let taken = self.transaction_pool.lock().unwrap().take_all();
+        self.publish_removed(&taken, RemovalReason::Included);
+        new_block
+    }
+
+    // 提交挖出的区块；若期间链顶已变化则把不在交易池中的交易放回并返回 false
+    pub fn submit_mined_block(&mut self, block: Block) -> bool {
+        if block.header.prev_block_hash != self.tip_hash() {
+            let mut pool = self.transaction_pool.lock().unwrap();
+            for tx in block.transactions {
+                if tx.is_coinbase() || pool.contains(&tx.hash()) {
+                    continue;
+                }
+                self.events
+                    .publish(|| ChainEvent::TransactionAdded(Arc::new(tx.clone())));
+                pool.push_back(tx);
+            }
+            return false;
+        }
+        self.add_block(block);
+        true
+    }
+
+    // 完整校验一个待上链的区块（不修改区块内容）
+    pub fn validate_block(&self, block: &Block) -> Result<(), BlockError> {
+        self.check_block(block, self.blocks.len() as u32, self.tip_hash())
+    }
+
+    // 校验区块头的链接关系、难度和时间戳，不涉及交易内容
+    pub fn check_header(
+        &self,
+        header: &BlockHeader,
+        prev_hash: [u8; 32],
+    ) -> Result<(), BlockError> {
+        if header.prev_block_hash != prev_hash {
+            return Err(BlockError::PrevHashMismatch);
+        }
+        if header.bits != self.difficulty as u32 {
+            return Err(BlockError::BadBits);
+        }
+        if !meets_difficulty(&hash_block_header(header), self.difficulty) {
+            return Err(BlockError::InsufficientWork);
+        }
+        let now = Utc::now().timestamp() as u32;
+        if header.timestamp > now.saturating_add(MAX_FUTURE_BLOCK_TIME) {
+            return Err(BlockError::TimeTooNew);
+        }
+        Ok(())
+    }
+
+    // 以 prev_hash 所在分支的状态校验交易：分叉点及之前的主链，加上其后的分叉区块
+    fn branch_state(&self, prev_hash: &[u8; 32]) -> Option<BranchState<'_>> {
+        let (fork_height, path) = self.side_branch(prev_hash)?;
+        let mut branch = BranchState {
+            chain: self,
+            fork_height,
+            transactions: HashMap::new(),
+            spent: HashSet::new(),
+        };
+        for hash in &path {
+            for tx in &self.side_blocks[hash].0.transactions {
+                branch.transactions.insert(tx.hash(), tx);
+                branch.spent.extend(spends(tx));
+            }
+        }
+        Some(branch)
+    }
+
+    // 以指定的父区块和高度为上下文校验区块
+    fn check_block(
+        &self,
+        block: &Block,
+        height: u32,
+        prev_hash: [u8; 32],
+    ) -> Result<(), BlockError> {
+        let header = &block.header;
+        self.check_header(header, prev_hash)?;
+        if header.merkle_root != calculate_merkle_root(&block.transactions) {
+            return Err(BlockError::MerkleRootMismatch);
+        }
+
+        let mut branch = self
+            .branch_state(&prev_hash)
+            .ok_or(BlockError::UnknownParent)?;
+        let mut hashes = Vec::with_capacity(block.transactions.len());
+        for (index, tx) in block.transactions.iter().enumerate() {
+            if tx.is_coinbase() {
+                // coinbase 只能位于首位，记录正确的高度，且金额不超过奖励
+                let coinbase_height = tx.inputs[0].script_sig.get(..4);
+                // 对端构造的金额之和可能溢出
+                let value = tx
+                    .outputs
+                    .iter()
+                    .try_fold(0u64, |sum, out| sum.checked_add(out.value));
+                if index != 0
+                    || coinbase_height != Some(&height.to_le_bytes()[..])
+                    || value.is_none_or(|value| value > BLOCK_REWARD)
+                {
+                    return Err(BlockError::BadCoinbase);
+                }
+                continue;
+            }
+            if !tx.is_final(height, header.timestamp) {
+                return Err(BlockError::NonFinalTransaction);
+            }
+            if tx.inputs.is_empty() || tx.outputs.is_empty() {
+                return Err(TransactionError::Empty.into());
+            }
+            let hash = tx.hash();
+            if hashes.contains(&hash) {
+                return Err(BlockError::DuplicateTransaction);
+            }
+            // 父交易可以在分支上或本区块之前的交易中，输出不能已被花费
+            branch.connect(tx)?;
+            hashes.push(hash);
+        }
+        Ok(())
+    }
+
+    // 校验并添加外部提交的区块
+    pub fn submit_block(&mut self, block: Block) -> Result<(), BlockError> {
+        self.validate_block(&block)?;
+        self.add_block(block);
+        Ok(())
+    }
+
+    // 区块在主链上的高度，索引中的高度需与当前区块列表一致
+    fn main_chain_height(&self, hash: &[u8; 32]) -> Option<usize> {
+        let height = *self.block_index.get(hash)?;
+        let block = self.blocks.get(height)?;
+        (hash_block_header(&block.header) == *hash).then_some(height)
+    }
+
+    // 区块是否在主链上
+    pub fn is_main_chain(&self, hash: &[u8; 32]) -> bool {
+        self.main_chain_height(hash).is_some()
+    }
+
+    pub fn block_at(&self, height: u32) -> Option<&Block> {
+        self.blocks.get(height as usize)
+    }
+
+    // 从分叉区块回溯到主链：返回分叉点高度和从分叉点之后到该区块的哈希路径
+    fn side_branch(&self, hash: &[u8; 32]) -> Option<(usize, Vec<[u8; 32]>)> {
+        let mut path = Vec::new();
+        let mut current = *hash;
+        loop {
+            if let Some(height) = self.main_chain_height(&current) {
+                path.reverse();
+                return Some((height, path));
+            }
+            let (block, _) = self.side_blocks.get(&current)?;
+            path.push(current);
+            current = block.header.prev_block_hash;
+        }
+    }
+
+    // 主链或分叉链上是否已有该区块
+    pub fn contains_block(&self, hash: &[u8; 32]) -> bool {
+        self.side_blocks.contains_key(hash) || self.main_chain_height(hash).is_some()
+    }
+
+    // 区块的高度，主链和分叉链上的区块都可以查询
+    pub fn block_height(&self, hash: &[u8; 32]) -> Option<u32> {
+        self.side_branch(hash)
+            .map(|(fork_height, path)| (fork_height + path.len()) as u32)
+    }
+
+    /// 区块定位器：从链顶开始的区块哈希，最近 10 个逐个列出，之后间隔加倍，最后是创世区块
+    pub fn block_locator(&self) -> Vec<[u8; 32]> {
+        let mut locator = Vec::new();
+        let mut height = self.blocks.len() as isize - 1;
+        let mut step = 1;
+        while height > 0 {
+            locator.push(hash_block_header(&self.blocks[height as usize].header));
+            if locator.len() >= 10 {
+                step *= 2;
+            }
+            height -= step;
+        }
+        locator.push(self.genesis_hash());
+        locator
+    }
+
+    /// 根据对方的定位器找到共同的主链区块，返回其后最多 `max` 个区块头，遇到 `stop` 时停止
+    pub fn locate_headers(
+        &self,
+        locator: &[[u8; 32]],
+        stop: [u8; 32],
+        max: usize,
+    ) -> Vec<BlockHeader> {
+        let start = locator
+            .iter()
+            .find_map(|hash| self.main_chain_height(hash))
+            .map_or(1, |height| height + 1);
+        let mut headers = Vec::new();
+        for block in self.blocks.iter().skip(start).take(max) {
+            headers.push(block.header.clone());
+            if hash_block_header(&block.header) == stop {
+                break;
+            }
+        }
+        headers
+    }
+
+    // 主链区块中是否包含该交易
+    pub fn contains_transaction(&self, hash: &[u8; 32]) -> bool {
+        self.find_transaction(hash).is_some()
+    }
+
+    /// 在主链中查找交易，返回交易和所在区块的高度
+    pub fn find_transaction(&self, hash: &[u8; 32]) -> Option<(&Transaction, u32)> {
+        let height = *self.tx_index.get(hash)?;
+        let tx = self
+            .blocks
+            .get(height)?
+            .transactions
+            .iter()
+            .find(|tx| tx.hash() == *hash)?;
+        Some((tx, height as u32))
+    }
+
+    /// 主链上锁定给该脚本的未花费输出
+    ///
+    /// 交易输入只引用父交易的哈希：签名公钥等于锁定脚本的输入会花掉父交易中锁定给该脚本的全部输出。
+    pub fn unspent_outputs(&self, script_pubkey: &[u8]) -> Vec<Utxo> {
+        let mut spent = HashSet::new();
+        let mut outputs = Vec::new();
+        for (height, block) in self.blocks.iter().enumerate() {
+            for tx in &block.transactions {
+                for input in &tx.inputs {
+                    if input.public_key() == Some(script_pubkey) {
+                        spent.insert(input.previous_output);
+                    }
+                }
+                let txid = tx.hash();
+                for (index, output) in tx.outputs.iter().enumerate() {
+                    if output.script_pubkey == script_pubkey {
+                        outputs.push(Utxo {
+                            txid,
+                            index: index as u32,
+                            value: output.value,
+                            height: height as u32,
+                        });
+                    }
+                }
+            }
+        }
+        outputs.retain(|utxo| !spent.contains(&utxo.txid));
+        outputs
+    }
+
+    pub fn balance(&self, script_pubkey: &[u8]) -> u64 {
+        self.unspent_outputs(script_pubkey)
+            .iter()
+            .fold(0u64, |sum, utxo| sum.saturating_add(utxo.value))
+    }
+
+    // 按哈希查找主链或分叉链上的区块
+    pub fn get_block(&self, hash: &[u8; 32]) -> Option<&Block> {
+        self.main_chain_height(hash)
+            .map(|height| &self.blocks[height])
+            .or_else(|| self.side_blocks.get(hash).map(|(block, _)| block))
+    }
+
+    /// 处理从网络收到的区块：延长主链、保存为分叉，或在分叉链更长时重组
+    pub fn process_block(&mut self, block: Block) -> Result<BlockStatus, BlockError> {
+        let hash = hash_block_header(&block.header);
+        if self.contains_block(&hash) {
+            return Ok(BlockStatus::AlreadyKnown);
+        }
+        let prev_hash = block.header.prev_block_hash;
+        if prev_hash == self.tip_hash() {
+            self.submit_block(block)?;
+            return Ok(BlockStatus::Connected);
+        }
+
+        let (fork_height, path) = self
+            .side_branch(&prev_hash)
+            .ok_or(BlockError::UnknownParent)?;
+        let height = fork_height + path.len() + 1;
+        self.check_block(&block, height as u32, prev_hash)?;
+        self.side_blocks.insert(hash, (block, height as u32));
+
+        // 难度固定，最长链即工作量最大的链
+        let status = if height > self.height() as usize {
+            self.reorganize(&hash)
+        } else {
+            BlockStatus::SideChain
+        };
+        self.prune_side_blocks();
+        Ok(status)
+    }
+
+    // 丢弃分叉点过深的分叉区块，数量超出上限时从高度最低的开始丢弃
+    fn prune_side_blocks(&mut self) {
+        let tip_height = self.height();
+        self.side_blocks
+            .retain(|_, (_, height)| height.saturating_add(MAX_REORG_DEPTH) > tip_height);
+        while self.side_blocks.len() > MAX_SIDE_BLOCKS {
+            let Some(lowest) = self
+                .side_blocks
+                .iter()
+                .min_by_key(|(_, (_, height))| *height)
+                .map(|(hash, _)| *hash)
+            else {
+                break;
+            };
+            self.side_blocks.remove(&lowest);
+        }
+    }
+
+    // 切换到以 tip 结尾的分叉链，断开的区块中的交易放回交易池
+    fn reorganize(&mut self, tip: &[u8; 32]) -> BlockStatus {
+        let (fork_height, path) = self.side_branch(tip).unwrap();
+        let disconnected: Vec<Block> = self.blocks.drain(fork_height + 1..).collect();
+        let disconnected_count = disconnected.len();
+        for (offset, block) in disconnected.iter().enumerate() {
+            self.unindex_block(block, fork_height + 1 + offset);
+        }
+        // 从原链顶开始依次断开
+        for (offset, block) in disconnected.iter().enumerate().rev() {
+            self.events.publish(|| ChainEvent::BlockDisconnected {
+                block: Arc::new(block.clone()),
+                height: (fork_height + 1 + offset) as u32,
+            });
+        }
+        {
+            let mut pool = self.transaction_pool.lock().unwrap();
+            for tx in disconnected
+                .iter()
+                .flat_map(|block| &block.transactions)
+                .filter(|tx| !tx.is_coinbase())
+            {
+                self.events
+                    .publish(|| ChainEvent::TransactionAdded(Arc::new(tx.clone())));
+                pool.push_back(tx.clone());
+            }
+        }
+        for (offset, block) in disconnected.into_iter().enumerate() {
+            let height = (fork_height + 1 + offset) as u32;
+            self.side_blocks
+                .insert(hash_block_header(&block.header), (block, height));
+        }
+
+        for hash in &path {
+            let (block, _) = self.side_blocks.remove(hash).unwrap();
+            self.remove_from_pool(&block.transactions);
+            self.blocks.push(block);
+            self.index_block(self.blocks.len() - 1);
+            self.publish_connected();
+        }
+        self.publish_tip();
+        BlockStatus::Reorganized {
+            disconnected: disconnected_count,
+            connected: path.len(),
+        }
+    }
+
+    // 从交易池移除已被打包的交易
+    fn remove_from_pool(&self, transactions: &[Transaction]) {
+        let included: HashSet<[u8; 32]> = transactions.iter().map(|tx| tx.hash()).collect();
+        let removed = self
+            .transaction_pool
+            .lock()
+            .unwrap()
+            .remove_if(|tx| included.contains(&tx.hash()));
+        self.publish_removed(&removed, RemovalReason::Included);
+    }
+
+    fn publish_removed(&self, transactions: &[Transaction], reason: RemovalReason) {
+        for tx in transactions {
+            self.events.publish(|| ChainEvent::TransactionRemoved {
+                txid: tx.hash(),
+                reason,
+            });
+        }
+    }
+
+    // 发布主链最后一个区块的连接事件
+    fn publish_connected(&self) {
+        self.events.publish(|| ChainEvent::BlockConnected {
+            block: Arc::new(self.blocks.last().unwrap().clone()),
+            height: self.height(),
+        });
+    }
+
+    fn publish_tip(&self) {
+        self.events.publish(|| ChainEvent::NewTip {
+            hash: self.tip_hash(),
+            height: self.height(),
+        });
+    }
+
+    pub fn mine_block(&mut self) {
+        self.mine_block_with(&Miner::default());
+    }
+
+    // 使用指定的矿工挖矿
+    pub fn mine_block_with(&mut self, miner: &Miner) {
+        let mut new_block = self.take_block_template();
+        crate::log!(Debug, "Mining block...");
+        let cancel = AtomicBool::new(false);
+        if let Some(header) = miner.mine(&new_block.header, self.difficulty, &cancel) {
+            new_block.header = header;
         }
         // 将新区块添加到区块链
         self.add_block(new_block);
@@ -221,7 +1194,7 @@ impl BlockChain {
 
     pub fn add_block(&mut self, data: Block) {
         let mut new_block = data;
-        println!("{:?}", self.blocks.len());
+        crate::log!(Debug, "{:?}", self.blocks.len());
         if let Some(last_block) = self.blocks.last() {
             // 获取前一个区块的哈希值
             let prev_block_hash = hash_block_header(&last_block.header);
@@ -235,7 +1208,14 @@ impl BlockChain {
         // 计算新区块的 Merkle Root（假设交易列表已经设置）
         new_block.header.merkle_root = calculate_merkle_root(&new_block.transactions);
 
+        // 从交易池移除已被打包的交易
+        self.remove_from_pool(&new_block.transactions);
+
         // 将新区块添加到区块链
         self.blocks.push(new_block);
+        self.index_block(self.blocks.len() - 1);
+        self.prune_side_blocks();
+        self.publish_connected();
+        self.publish_tip();
     }
 }
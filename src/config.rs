@@ -0,0 +1,592 @@
+use crate::auth::{parse_password_hash, Group, COOKIE_USER};
+use crate::logging::LogLevel;
+use crate::network::{read_seeds, NetworkConfig, DEFAULT_MAGIC};
+use crate::transaction::decode_address;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+pub const USAGE: &str = "\
+Usage: node [OPTIONS] [PEER...]
+
+Options:
+  --config <FILE>          read settings from a TOML file
+  --network <NAME>         network preset: main, test or regtest
+  --datadir <DIR>          directory for the address book, ban list and node key
+  --rpc-listen <ADDR>      HTTP server address (repeatable, IPv6 as [::1]:3030)
+  --rpc-tls-cert <FILE>    serve HTTPS with this PEM certificate chain
+  --rpc-tls-key <FILE>     PEM private key for --rpc-tls-cert
+  --rpc-cookie             write a cookie file for local admin access to the HTTP server
+  --rpc-rate-limit <N>     maximum HTTP requests per second per client
+  --gen-rpcauth <NAME>     print a new password and its [[rpc.users]] entry, then exit
+  --p2p-listen <ADDR>      P2P listen address
+  --no-listen              do not accept inbound P2P connections
+  --peer <ADDR>            connect to this peer on startup (repeatable)
+  --mine                   start mining on startup
+  --mining-threads <N>     number of mining threads
+  --mining-address <HEX>   payout public key for mined blocks
+  --log-level <LEVEL>      off, error, warn, info or debug
+  --max-mempool <N>        maximum number of transactions in the pool
+  --wallet                 load the wallet and enable the wallet RPC methods
+  --wallet-file <FILE>     encrypted wallet file (default: <datadir>/wallet.json)
+  --wallet-password-file <FILE>
+                           file whose first line is the wallet password
+  --create-wallet          create a new wallet, print its mnemonic and exit
+  --print-config           print the effective configuration and exit
+  -h, --help               print this help
+
+Positional arguments are peer addresses, like --peer.";
+
+/// 网络预设：决定网络魔数、挖矿难度和默认端口
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NetworkPreset {
+    Main,
+    Test,
+    Regtest, // 本地测试网络，难度为 0
+}
+
+impl NetworkPreset {
+    pub fn magic(&self) -> [u8; 4] {
+        match self {
+            NetworkPreset::Main => DEFAULT_MAGIC,
+            NetworkPreset::Test => [0xb1, 0x0c, 0xc4, 0x02],
+            NetworkPreset::Regtest => [0xb1, 0x0c, 0xc4, 0x03],
+        }
+    }
+
+    pub fn difficulty(&self) -> usize {
+        match self {
+            NetworkPreset::Main | NetworkPreset::Test => 1,
+            NetworkPreset::Regtest => 0,
+        }
+    }
+
+    // 默认的 HTTP 和 P2P 端口
+    pub fn rpc_port(&self) -> u16 {
+        match self {
+            NetworkPreset::Main => 3030,
+            NetworkPreset::Test => 13030,
+            NetworkPreset::Regtest => 23030,
+        }
+    }
+
+    pub fn p2p_port(&self) -> u16 {
+        self.rpc_port() + 1
+    }
+}
+
+impl fmt::Display for NetworkPreset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            NetworkPreset::Main => "main",
+            NetworkPreset::Test => "test",
+            NetworkPreset::Regtest => "regtest",
+        })
+    }
+}
+
+impl FromStr for NetworkPreset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "main" => Ok(NetworkPreset::Main),
+            "test" => Ok(NetworkPreset::Test),
+            "regtest" => Ok(NetworkPreset::Regtest),
+            _ => Err(format!("unknown network: {}", s)),
+        }
+    }
+}
+
+/// 静态令牌凭据，请求头为 `Authorization: Bearer <令牌>`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TokenCredential {
+    pub name: String, // 用于限流和日志
+    pub token: String,
+    pub group: Group,
+}
+
+/// 用户名和密码凭据，使用 HTTP Basic 认证；配置中只保存 `盐$HMAC-SHA256(盐, 密码)`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UserCredential {
+    pub name: String,
+    pub hmac: String,
+    pub group: Group,
+}
+
+/// HTTPS 的证书链和私钥，均为 PEM 文件
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+// 监听地址可以写成单个地址或地址数组
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<SocketAddr>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(SocketAddr),
+        Many(Vec<SocketAddr>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(addr) => vec![addr],
+        OneOrMany::Many(addrs) => addrs,
+    })
+}
+
+/// HTTP 服务配置
+///
+/// 配置了令牌、用户或 cookie 之一时开启认证，否则所有请求都有管理员权限。
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RpcConfig {
+    #[serde(deserialize_with = "one_or_many")]
+    pub listen: Vec<SocketAddr>, // 每个地址一个监听器，为空时使用网络预设的默认端口
+    pub tls: Option<TlsConfig>,   // 设置后所有监听器都使用 HTTPS
+    pub cookie: bool,             // 启动时在数据目录写入 .cookie，供本地工具以管理员权限访问
+    pub anonymous: Option<Group>, // 开启认证后未携带凭据的请求的权限，None 表示拒绝
+    pub rate_limit: Option<u32>,  // 每个客户端每秒最多的请求数，None 表示不限制
+    pub tokens: Vec<TokenCredential>,
+    pub users: Vec<UserCredential>,
+}
+
+/// P2P 网络配置
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct P2pConfig {
+    pub listen: Option<SocketAddr>, // None 表示使用网络预设的默认端口
+    pub inbound: bool,              // 是否接受入站连接
+    pub peers: Vec<SocketAddr>,
+    pub seeds: Vec<String>,
+    pub seeds_file: Option<PathBuf>, // 种子节点文件，与 seeds 合并
+    pub max_outbound: usize,
+    pub encryption: bool,
+}
+
+impl Default for P2pConfig {
+    fn default() -> Self {
+        P2pConfig {
+            listen: None,
+            inbound: true,
+            peers: Vec::new(),
+            seeds: Vec::new(),
+            seeds_file: Some("seeds.txt".into()),
+            max_outbound: 8,
+            encryption: true,
+        }
+    }
+}
+
+/// 挖矿配置
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MiningConfig {
+    pub enabled: bool,           // 启动时开始后台挖矿
+    pub threads: Option<usize>,  // None 表示使用全部 CPU 核心
+    pub address: Option<String>, // 收款公钥的十六进制编码
+}
+
+/// 交易池配置
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MempoolConfig {
+    pub max_transactions: usize,
+}
+
+impl Default for MempoolConfig {
+    fn default() -> Self {
+        MempoolConfig {
+            max_transactions: 5_000,
+        }
+    }
+}
+
+/// 钱包配置
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WalletConfig {
+    pub enabled: bool,                  // 启动时打开钱包
+    pub file: Option<PathBuf>,          // None 表示数据目录下的 wallet.json
+    pub password_file: Option<PathBuf>, // 第一行为钱包密码
+}
+
+/// 节点配置，来自 TOML 文件和命令行参数，命令行参数优先
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NodeConfig {
+    pub network: NetworkPreset,
+    pub data_dir: PathBuf, // 地址簿、封禁列表和身份密钥所在的目录
+    pub log_level: LogLevel,
+    pub rpc: RpcConfig,
+    pub p2p: P2pConfig,
+    pub mining: MiningConfig,
+    pub mempool: MempoolConfig,
+    pub wallet: WalletConfig,
+}
+
+impl Default for NodeConfig {
+    fn default() -> Self {
+        NodeConfig {
+            network: NetworkPreset::Main,
+            data_dir: "data".into(),
+            log_level: LogLevel::Info,
+            rpc: RpcConfig::default(),
+            p2p: P2pConfig::default(),
+            mining: MiningConfig::default(),
+            mempool: MempoolConfig::default(),
+            wallet: WalletConfig::default(),
+        }
+    }
+}
+
+/// 读取或校验配置失败的原因
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, String),        // 配置文件格式错误
+    UnknownFlag(String),           // 未知的命令行参数
+    MissingValue(String),          // 命令行参数缺少值
+    InvalidValue(String, String),  // (参数, 原因)
+    Invalid(&'static str, String), // 校验失败：(配置项, 原因)
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "failed to read {}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "invalid config {}: {}", path.display(), e),
+            ConfigError::UnknownFlag(flag) => write!(f, "unknown option: {}", flag),
+            ConfigError::MissingValue(flag) => write!(f, "missing value for {}", flag),
+            ConfigError::InvalidValue(flag, e) => write!(f, "invalid value for {}: {}", flag, e),
+            ConfigError::Invalid(key, e) => write!(f, "invalid {}: {}", key, e),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// 命令行要执行的操作
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Run(NodeConfig),
+    PrintConfig(NodeConfig),
+    GenerateAuth(String), // 为该用户名生成密码和配置项
+    CreateWallet(NodeConfig),
+    Help,
+}
+
+// 解析命令行参数的值
+fn parse_value<T: FromStr>(flag: &str, value: Option<String>) -> Result<T, ConfigError>
+where
+    T::Err: fmt::Display,
+{
+    let value = value.ok_or_else(|| ConfigError::MissingValue(flag.to_string()))?;
+    value
+        .parse()
+        .map_err(|e: T::Err| ConfigError::InvalidValue(flag.to_string(), e.to_string()))
+}
+
+impl NodeConfig {
+    pub fn from_toml(content: &str) -> Result<Self, String> {
+        toml::from_str(content).map_err(|e| e.to_string())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let content =
+            fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+        Self::from_toml(&content).map_err(|e| ConfigError::Parse(path.to_path_buf(), e))
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string_pretty(self).unwrap()
+    }
+
+    /// 解析命令行参数（不含程序名）
+    ///
+    /// 先读取 `--config` 指定的文件，再依次应用其余参数，最后填入网络预设的默认地址并校验。
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Command, ConfigError> {
+        let args: Vec<String> = args.into_iter().collect();
+        let mut config = match args.iter().position(|arg| arg == "--config") {
+            Some(i) => {
+                let path = args
+                    .get(i + 1)
+                    .ok_or_else(|| ConfigError::MissingValue("--config".to_string()))?;
+                NodeConfig::load(Path::new(path))?
+            }
+            None => NodeConfig::default(),
+        };
+
+        let mut print = false;
+        let mut create_wallet = false;
+        // 命令行中的 --rpc-listen 替换配置文件中的地址
+        let mut rpc_listen = Vec::new();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let flag = arg.as_str();
+            match flag {
+                "-h" | "--help" => return Ok(Command::Help),
+                "--gen-rpcauth" => {
+                    return Ok(Command::GenerateAuth(parse_value(flag, args.next())?))
+                }
+                "--print-config" => print = true,
+                "--config" => {
+                    args.next();
+                }
+                "--network" => config.network = parse_value(flag, args.next())?,
+                "--datadir" => config.data_dir = parse_value(flag, args.next())?,
+                "--rpc-listen" => rpc_listen.push(parse_value(flag, args.next())?),
+                "--rpc-tls-cert" => {
+                    config.rpc.tls.get_or_insert_with(TlsConfig::default).cert =
+                        parse_value(flag, args.next())?
+                }
+                "--rpc-tls-key" => {
+                    config.rpc.tls.get_or_insert_with(TlsConfig::default).key =
+                        parse_value(flag, args.next())?
+                }
+                "--rpc-cookie" => config.rpc.cookie = true,
+                "--rpc-rate-limit" => config.rpc.rate_limit = Some(parse_value(flag, args.next())?),
+                "--p2p-listen" => config.p2p.listen = Some(parse_value(flag, args.next())?),
+                "--no-listen" => config.p2p.inbound = false,
+                "--peer" => config.p2p.peers.push(parse_value(flag, args.next())?),
+                "--mine" => config.mining.enabled = true,
+                "--mining-threads" => config.mining.threads = Some(parse_value(flag, args.next())?),
+                "--mining-address" => config.mining.address = Some(parse_value(flag, args.next())?),
+                "--log-level" => config.log_level = parse_value(flag, args.next())?,
+                "--max-mempool" => {
+                    config.mempool.max_transactions = parse_value(flag, args.next())?
+                }
+                "--wallet" => config.wallet.enabled = true,
+                "--wallet-file" => config.wallet.file = Some(parse_value(flag, args.next())?),
+                "--wallet-password-file" => {
+                    config.wallet.password_file = Some(parse_value(flag, args.next())?)
+                }
+                "--create-wallet" => create_wallet = true,
+                _ if flag.starts_with('-') => return Err(ConfigError::UnknownFlag(arg)),
+                _ => config.p2p.peers.push(parse_value("peer", Some(arg))?),
+            }
+        }
+
+        if !rpc_listen.is_empty() {
+            config.rpc.listen = rpc_listen;
+        }
+        config.resolve();
+        config.validate()?;
+        if create_wallet && config.wallet.password_file.is_none() {
+            return Err(ConfigError::Invalid(
+                "wallet.password_file",
+                "required to create a wallet".into(),
+            ));
+        }
+        Ok(if print {
+            Command::PrintConfig(config)
+        } else if create_wallet {
+            Command::CreateWallet(config)
+        } else {
+            Command::Run(config)
+        })
+    }
+
+    // 未指定的监听地址使用网络预设的默认端口
+    pub fn resolve(&mut self) {
+        if self.rpc.listen.is_empty() {
+            self.rpc.listen = self.rpc_addrs();
+        }
+        if self.p2p.listen.is_none() {
+            self.p2p.listen = Some(([127, 0, 0, 1], self.network.p2p_port()).into());
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.data_dir.as_os_str().is_empty() {
+            return Err(ConfigError::Invalid("data_dir", "must not be empty".into()));
+        }
+        // 端口 0 由系统分配，不会冲突
+        let rpc_addrs: Vec<SocketAddr> = self
+            .rpc
+            .listen
+            .iter()
+            .copied()
+            .filter(|addr| addr.port() != 0)
+            .collect();
+        if self.p2p.inbound
+            && self
+                .p2p
+                .listen
+                .is_some_and(|addr| rpc_addrs.contains(&addr))
+        {
+            return Err(ConfigError::Invalid(
+                "p2p.listen",
+                "must differ from rpc.listen".into(),
+            ));
+        }
+        for (i, addr) in rpc_addrs.iter().enumerate() {
+            if rpc_addrs[..i].contains(addr) {
+                return Err(ConfigError::Invalid(
+                    "rpc.listen",
+                    format!("duplicate address: {}", addr),
+                ));
+            }
+        }
+        if self.mining.threads == Some(0) {
+            return Err(ConfigError::Invalid(
+                "mining.threads",
+                "must be at least 1".into(),
+            ));
+        }
+        if let Some(address) = &self.mining.address {
+            if decode_address(address).is_none() {
+                return Err(ConfigError::Invalid(
+                    "mining.address",
+                    format!("not a 32-byte hex public key: {}", address),
+                ));
+            }
+        }
+        self.validate_rpc()?;
+        if self.mempool.max_transactions == 0 {
+            return Err(ConfigError::Invalid(
+                "mempool.max_transactions",
+                "must be at least 1".into(),
+            ));
+        }
+        if self.wallet.enabled && self.wallet.password_file.is_none() {
+            return Err(ConfigError::Invalid(
+                "wallet.password_file",
+                "required when the wallet is enabled".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn validate_rpc(&self) -> Result<(), ConfigError> {
+        if let Some(tls) = &self.rpc.tls {
+            if tls.cert.as_os_str().is_empty() || tls.key.as_os_str().is_empty() {
+                return Err(ConfigError::Invalid(
+                    "rpc.tls",
+                    "cert and key must both be set".into(),
+                ));
+            }
+        }
+        if self.rpc.rate_limit == Some(0) {
+            return Err(ConfigError::Invalid(
+                "rpc.rate_limit",
+                "must be at least 1".into(),
+            ));
+        }
+        let mut names = vec![COOKIE_USER];
+        let credentials = self
+            .rpc
+            .tokens
+            .iter()
+            .map(|token| ("rpc.tokens", token.name.as_str()))
+            .chain(
+                self.rpc
+                    .users
+                    .iter()
+                    .map(|user| ("rpc.users", user.name.as_str())),
+            );
+        for (key, name) in credentials {
+            if name.is_empty() || name.contains(':') {
+                return Err(ConfigError::Invalid(
+                    key,
+                    format!("invalid name: {:?}", name),
+                ));
+            }
+            if names.contains(&name) {
+                return Err(ConfigError::Invalid(
+                    key,
+                    format!("duplicate name: {}", name),
+                ));
+            }
+            names.push(name);
+        }
+        if self.rpc.tokens.iter().any(|token| token.token.is_empty()) {
+            return Err(ConfigError::Invalid(
+                "rpc.tokens",
+                "token must not be empty".into(),
+            ));
+        }
+        for user in &self.rpc.users {
+            if parse_password_hash(&user.hmac).is_none() {
+                return Err(ConfigError::Invalid(
+                    "rpc.users",
+                    format!("{}: hmac must be <salt>$<64 hex digits>", user.name),
+                ));
+            }
+        }
+        // 未开启认证时所有请求都有管理员权限，只允许本机访问
+        let authenticated =
+            self.rpc.cookie || !self.rpc.tokens.is_empty() || !self.rpc.users.is_empty();
+        if let Some(addr) = self.rpc.listen.iter().find(|addr| !addr.ip().is_loopback()) {
+            if !authenticated {
+                return Err(ConfigError::Invalid(
+                    "rpc.listen",
+                    format!(
+                        "{} is not a loopback address; configure rpc.tokens, rpc.users or rpc.cookie",
+                        addr
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // cookie 文件的路径
+    pub fn cookie_path(&self) -> PathBuf {
+        self.data_dir.join(".cookie")
+    }
+
+    // 钱包文件的路径
+    pub fn wallet_path(&self) -> PathBuf {
+        self.wallet
+            .file
+            .clone()
+            .unwrap_or_else(|| self.data_dir.join("wallet.json"))
+    }
+
+    /// HTTP 服务的全部监听地址
+    pub fn rpc_addrs(&self) -> Vec<SocketAddr> {
+        if self.rpc.listen.is_empty() {
+            vec![([127, 0, 0, 1], self.network.rpc_port()).into()]
+        } else {
+            self.rpc.listen.clone()
+        }
+    }
+
+    // 第一个监听地址
+    pub fn rpc_addr(&self) -> SocketAddr {
+        self.rpc_addrs()[0]
+    }
+
+    /// 由配置构造 P2P 网络配置，持久化文件放在数据目录下
+    pub fn network_config(&self) -> NetworkConfig {
+        let mut seeds = self.p2p.seeds.clone();
+        if let Some(path) = &self.p2p.seeds_file {
+            seeds.extend(read_seeds(path).unwrap_or_default());
+        }
+        let listen_addr = self
+            .p2p
+            .listen
+            .unwrap_or_else(|| ([127, 0, 0, 1], self.network.p2p_port()).into());
+        NetworkConfig {
+            magic: self.network.magic(),
+            listen_addr: self.p2p.inbound.then_some(listen_addr),
+            peers: self.p2p.peers.clone(),
+            max_outbound: self.p2p.max_outbound,
+            seeds,
+            address_book_path: Some(self.data_dir.join("peers.json")),
+            ban_list_path: Some(self.data_dir.join("bans.json")),
+            encryption: self.p2p.encryption,
+            identity_path: Some(self.data_dir.join("node_key")),
+            ..NetworkConfig::default()
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use crate::block_chain::Block;
+use crate::transaction::Transaction;
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+// 每个订阅者最多缓存的事件数，处理不及时的订阅者会丢失最早的事件
+pub const EVENT_BUFFER_SIZE: usize = 1024;
+
+/// 交易离开交易池的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RemovalReason {
+    Included, // 打包进区块
+    Evicted,  // 交易池已满，被丢弃
+}
+
+/// 区块链和交易池发生的变化
+#[derive(Debug, Clone)]
+pub enum ChainEvent {
+    NewTip {
+        hash: [u8; 32],
+        height: u32,
+    }, // 主链链顶变化
+    BlockConnected {
+        block: Arc<Block>,
+        height: u32,
+    }, // 区块连接到主链
+    BlockDisconnected {
+        block: Arc<Block>,
+        height: u32,
+    }, // 重组时从主链断开
+    TransactionAdded(Arc<Transaction>), // 交易进入交易池
+    TransactionRemoved {
+        txid: [u8; 32],
+        reason: RemovalReason,
+    }, // 交易离开交易池
+}
+
+/// 进程内的事件总线，每个订阅者都会收到订阅之后的全部事件
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<ChainEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        EventBus::new()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUFFER_SIZE);
+        EventBus { sender }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ChainEvent> {
+        self.sender.subscribe()
+    }
+
+    /// 发布事件，没有订阅者时不会构造事件
+    pub fn publish(&self, event: impl FnOnce() -> ChainEvent) {
+        if self.sender.receiver_count() > 0 {
+            let _ = self.sender.send(event());
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use crate::block_chain::{Block, BlockChain, BlockError, BlockStatus};
+use crate::network::message::MAX_PAYLOAD_SIZE;
+use crate::serialization::{deserialize_bc, serialize_bc};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::fmt;
+use std::str::FromStr;
+
+// 导出时每次持锁复制的区块数
+pub const EXPORT_BATCH_SIZE: usize = 100;
+// 导入时单个区块记录的最大长度
+pub const MAX_RECORD_SIZE: usize = MAX_PAYLOAD_SIZE;
+
+/// 区块导出和导入的格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExportFormat {
+    #[default]
+    Ndjson, // 每行一个 JSON 区块
+    Binary, // 每条记录为 长度(4, 小端) + bincode 区块
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Ndjson => "application/x-ndjson",
+            ExportFormat::Binary => "application/octet-stream",
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ndjson" => Ok(ExportFormat::Ndjson),
+            "binary" => Ok(ExportFormat::Binary),
+            _ => Err(format!("unknown format: {}", s)),
+        }
+    }
+}
+
+// 编码一个区块记录
+pub fn encode_block(format: ExportFormat, block: &Block) -> Vec<u8> {
+    match format {
+        ExportFormat::Ndjson => {
+            let mut line = serde_json::to_vec(block).unwrap();
+            line.push(b'\n');
+            line
+        }
+        ExportFormat::Binary => {
+            let data = serialize_bc(block).unwrap();
+            let mut record = (data.len() as u32).to_le_bytes().to_vec();
+            record.extend_from_slice(&data);
+            record
+        }
+    }
+}
+
+/// 导入失败的原因，序号为出错区块在导入数据中的位置（从 0 开始）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImportError {
+    Malformed(usize, String),    // 无法解析的记录
+    RecordTooLarge(usize),       // 记录超过长度上限
+    Truncated,                   // 数据在记录中间结束
+    Rejected(usize, BlockError), // 区块未通过校验
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImportError::Malformed(index, e) => write!(f, "malformed block #{}: {}", index, e),
+            ImportError::RecordTooLarge(index) => write!(f, "block #{} is too large", index),
+            ImportError::Truncated => f.write_str("data ends in the middle of a block"),
+            ImportError::Rejected(index, e) => write!(f, "block #{} rejected: {}", index, e),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+/// 导入结果的统计
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct ImportSummary {
+    pub connected: usize,     // 延长了主链
+    pub reorganized: usize,   // 触发了重组
+    pub side_chain: usize,    // 保存在分叉链上
+    pub already_known: usize, // 已经存在
+}
+
+/// 逐块解析导入数据，每个区块都走正常的区块校验
+///
+/// 数据可以分多次追加，调用方每次只需在处理解析出的区块时持有链的锁。
+pub struct Importer {
+    format: ExportFormat,
+    buffer: Vec<u8>,
+    start: usize,   // 缓冲区中尚未解析的数据的起点
+    scanned: usize, // NDJSON 当前行已查找过换行符的长度（相对 start）
+    decoded: usize, // 已解析的区块数
+    summary: ImportSummary,
+}
+
+impl Importer {
+    pub fn new(format: ExportFormat) -> Self {
+        Importer {
+            format,
+            buffer: Vec::new(),
+            start: 0,
+            scanned: 0,
+            decoded: 0,
+            summary: ImportSummary::default(),
+        }
+    }
+
+    pub fn summary(&self) -> &ImportSummary {
+        &self.summary
+    }
+
+    /// 追加数据，返回其中已经完整的区块
+    pub fn push(&mut self, data: &[u8]) -> Result<Vec<Block>, ImportError> {
+        // 已解析的数据超过一半时才移除，每个字节平均只复制常数次
+        if self.start > self.buffer.len() / 2 {
+            self.buffer.drain(..self.start);
+            self.start = 0;
+        }
+        self.buffer.extend_from_slice(data);
+        let mut blocks = Vec::new();
+        while let Some(block) = self.next_block(false)? {
+            blocks.push(block);
+        }
+        Ok(blocks)
+    }
+
+    // 从缓冲区解析下一个区块，数据不足时返回 None
+    fn next_block(&mut self, eof: bool) -> Result<Option<Block>, ImportError> {
+        let index = self.decoded;
+        let record = match self.format {
+            // 跳过空行；已查找过的部分不再重复查找
+            ExportFormat::Ndjson => loop {
+                let pending = &self.buffer[self.start..];
+                let end = match pending[self.scanned..].iter().position(|b| *b == b'\n') {
+                    Some(offset) => self.scanned + offset,
+                    None if eof && !pending.is_empty() => pending.len(),
+                    None if pending.len() > MAX_RECORD_SIZE => {
+                        return Err(ImportError::RecordTooLarge(index))
+                    }
+                    None => {
+                        self.scanned = pending.len();
+                        return Ok(None);
+                    }
+                };
+                let line = self.start..self.start + end;
+                self.start = (self.start + end + 1).min(self.buffer.len());
+                self.scanned = 0;
+                let line = &self.buffer[line];
+                if !line.iter().all(|b| b.is_ascii_whitespace()) {
+                    break serde_json::from_slice(line)
+                        .map_err(|e| ImportError::Malformed(index, e.to_string()))?;
+                }
+            },
+            ExportFormat::Binary => {
+                let pending = &self.buffer[self.start..];
+                let Some(length) = pending.get(..4) else {
+                    return Ok(None);
+                };
+                let length = u32::from_le_bytes(length.try_into().unwrap()) as usize;
+                if length > MAX_RECORD_SIZE {
+                    return Err(ImportError::RecordTooLarge(index));
+                }
+                let Some(data) = pending.get(4..4 + length) else {
+                    return Ok(None);
+                };
+                let block = deserialize_bc(data)
+                    .map_err(|e| ImportError::Malformed(index, e.to_string()))?;
+                self.start += 4 + length;
+                block
+            }
+        };
+        self.decoded += 1;
+        Ok(Some(record))
+    }
+
+    /// 校验并处理一个区块，按顺序调用
+    pub fn apply(&mut self, blockchain: &mut BlockChain, block: Block) -> Result<(), ImportError> {
+        let index = self.summary.connected
+            + self.summary.reorganized
+            + self.summary.side_chain
+            + self.summary.already_known;
+        match blockchain.process_block(block) {
+            Ok(BlockStatus::Connected) => self.summary.connected += 1,
+            Ok(BlockStatus::Reorganized { .. }) => self.summary.reorganized += 1,
+            Ok(BlockStatus::SideChain) => self.summary.side_chain += 1,
+            Ok(BlockStatus::AlreadyKnown) => self.summary.already_known += 1,
+            Err(e) => return Err(ImportError::Rejected(index, e)),
+        }
+        Ok(())
+    }
+
+    /// 数据结束：返回剩余的最后一个区块（NDJSON 最后一行可以没有换行），有残缺数据时出错
+    pub fn finish(&mut self) -> Result<Option<Block>, ImportError> {
+        let block = self.next_block(true)?;
+        if self.start < self.buffer.len() {
+            return Err(ImportError::Truncated);
+        }
+        Ok(block)
+    }
+}
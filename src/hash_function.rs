@@ -20,6 +20,38 @@ pub fn hash_pair(a: &[u8], b: &[u8]) -> Digest {
     context.finish()
 }
 
+/// 计算交易在 Merkle 树中的叶子哈希
+pub fn merkle_leaf(transaction: &Transaction) -> [u8; 32] {
+    let serialized = serialize_bc(transaction).unwrap(); // 序列化交易
+    sha256_hash(&serialized).as_ref().try_into().unwrap() // 计算哈希值
+}
+
+/// 计算第一笔交易（coinbase）到 Merkle Root 的路径上的兄弟节点
+pub fn merkle_branch(transactions: &[Transaction]) -> Vec<[u8; 32]> {
+    let mut hashes: Vec<[u8; 32]> = transactions.iter().map(merkle_leaf).collect();
+    let mut branch = Vec::new();
+
+    while hashes.len() > 1 {
+        branch.push(hashes[1]);
+        hashes = hashes
+            .chunks(2)
+            .map(|pair| {
+                let right = pair.get(1).unwrap_or(&pair[0]);
+                hash_pair(&pair[0], right).as_ref().try_into().unwrap()
+            })
+            .collect();
+    }
+
+    branch
+}
+
+/// 由第一笔交易的叶子哈希和 Merkle 路径计算 Merkle Root
+pub fn merkle_root_from_branch(leaf: [u8; 32], branch: &[[u8; 32]]) -> [u8; 32] {
+    branch.iter().fold(leaf, |acc, sibling| {
+        hash_pair(&acc, sibling).as_ref().try_into().unwrap()
+    })
+}
+
 /// 计算 Merkle Root
 pub fn calculate_merkle_root(transactions: &[Transaction]) -> [u8; 32] {
     if transactions.is_empty() {
@@ -27,13 +59,7 @@ pub fn calculate_merkle_root(transactions: &[Transaction]) -> [u8; 32] {
     }
 
     // 计算每个交易的哈希值
-    let mut hashes: Vec<[u8; 32]> = transactions
-        .iter()
-        .map(|tx| {
-            let serialized = serialize_bc(tx).unwrap(); // 序列化交易
-            sha256_hash(&serialized).as_ref().try_into().unwrap() // 计算哈希值
-        })
-        .collect();
+    let mut hashes: Vec<[u8; 32]> = transactions.iter().map(merkle_leaf).collect();
 
     // 递归计算 Merkle Root
     while hashes.len() > 1 {
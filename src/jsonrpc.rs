@@ -0,0 +1,383 @@
+use crate::auth::Group;
+use crate::block_chain::{Block, TransactionError};
+use crate::hash_function::hash_block_header;
+use crate::mining::MAX_GENERATE_BLOCKS;
+use crate::rpc::{BlockInfo, TransactionInfo};
+use crate::serialization::{deserialize_bc, serialize_bc};
+use crate::service::Node;
+use crate::stratum::decode_hash;
+use crate::transaction::{decode_address, Transaction};
+use crate::wallet::coin_selection::{DEFAULT_FEE_RATE, MAX_FEE_RATE};
+use crate::wallet::{Wallet, WalletError};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::fmt;
+use std::sync::Arc;
+use tokio::sync::Mutex as AsyncMutex;
+
+// JSON-RPC 2.0 规定的错误码
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+// 节点自定义的错误码，位于规范保留的 -32000 到 -32099 之间
+pub const NOT_FOUND: i64 = -32001; // 区块或交易不存在
+pub const TRANSACTION_REJECTED: i64 = -32002; // 交易未被交易池接受
+pub const FORBIDDEN: i64 = -32003; // 权限不足
+pub const WALLET_ERROR: i64 = -32004; // 未启用钱包、余额不足等钱包错误
+
+// 请求体的最大长度
+pub const MAX_REQUEST_SIZE: u64 = 1024 * 1024;
+
+/// JSON-RPC 错误对象
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>, // 附加信息，如交易被拒绝的原因
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        RpcError {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.code)
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+// 按位置（数组）或名称（对象）传入的参数
+struct Params(Value);
+
+impl Params {
+    fn get(&self, index: usize, name: &str) -> Option<&Value> {
+        let value = match &self.0 {
+            Value::Array(values) => values.get(index),
+            Value::Object(values) => values.get(name),
+            _ => None,
+        };
+        value.filter(|value| !value.is_null())
+    }
+
+    fn optional<T: DeserializeOwned>(
+        &self,
+        index: usize,
+        name: &str,
+    ) -> Result<Option<T>, RpcError> {
+        self.get(index, name)
+            .map(|value| {
+                serde_json::from_value(value.clone()).map_err(|e| {
+                    RpcError::new(INVALID_PARAMS, format!("invalid parameter {}: {}", name, e))
+                })
+            })
+            .transpose()
+    }
+
+    fn required<T: DeserializeOwned>(&self, index: usize, name: &str) -> Result<T, RpcError> {
+        self.optional(index, name)?
+            .ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("missing parameter: {}", name)))
+    }
+
+    fn hash(&self, index: usize, name: &str) -> Result<[u8; 32], RpcError> {
+        let hash: String = self.required(index, name)?;
+        decode_hash(&hash)
+            .ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("invalid hash: {}", hash)))
+    }
+
+    fn address(&self, index: usize, name: &str) -> Result<Option<Vec<u8>>, RpcError> {
+        match self.optional::<String>(index, name)? {
+            Some(address) => decode_address(&address).map(Some).ok_or_else(|| {
+                RpcError::new(INVALID_PARAMS, format!("invalid address: {}", address))
+            }),
+            None => Ok(None),
+        }
+    }
+}
+
+fn to_value<T: Serialize>(value: T) -> Result<Value, RpcError> {
+    serde_json::to_value(value).map_err(|e| RpcError::new(INTERNAL_ERROR, e.to_string()))
+}
+
+fn encode_hex<T: Serialize>(value: &T) -> Result<Value, RpcError> {
+    serialize_bc(value)
+        .map(|bytes| Value::String(hex::encode(bytes)))
+        .map_err(|e| RpcError::new(INTERNAL_ERROR, e.to_string()))
+}
+
+fn not_found(what: &str) -> RpcError {
+    RpcError::new(NOT_FOUND, format!("{} not found", what))
+}
+
+fn rejected(e: TransactionError) -> RpcError {
+    RpcError {
+        data: Some(json!(e)),
+        ..RpcError::new(TRANSACTION_REJECTED, e.to_string())
+    }
+}
+
+fn wallet(node: &Node) -> Result<&Arc<AsyncMutex<Wallet>>, RpcError> {
+    node.wallet
+        .as_ref()
+        .ok_or_else(|| RpcError::new(WALLET_ERROR, "wallet not loaded"))
+}
+
+fn wallet_error(e: WalletError) -> RpcError {
+    match e {
+        WalletError::InvalidAmount | WalletError::InvalidFeeRate(_) => {
+            RpcError::new(INVALID_PARAMS, e.to_string())
+        }
+        WalletError::Io(..) | WalletError::Format(_) => {
+            RpcError::new(INTERNAL_ERROR, e.to_string())
+        }
+        _ => RpcError::new(WALLET_ERROR, e.to_string()),
+    }
+}
+
+/// 方法所需的权限组，未知方法按只读处理，由调用时报告不存在
+pub fn method_group(method: &str) -> Group {
+    match method {
+        "sendrawtransaction" | "getnewaddress" | "sendtoaddress" | "listunspent" => Group::Wallet,
+        "generate" => Group::Admin,
+        _ => Group::Read,
+    }
+}
+
+// 执行一个方法
+async fn call(node: &Node, method: &str, params: &Params) -> Result<Value, RpcError> {
+    match method {
+        "getblockcount" => Ok(json!(node.blockchain.lock().await.height())),
+        "getblockhash" => {
+            let height: u32 = params.required(0, "height")?;
+            let blockchain = node.blockchain.lock().await;
+            let block = blockchain
+                .block_at(height)
+                .ok_or_else(|| not_found("Block"))?;
+            Ok(json!(hex::encode(hash_block_header(&block.header))))
+        }
+        // verbosity 为 0 时返回序列化区块的十六进制编码，默认为 1 返回 JSON
+        "getblock" => {
+            let hash = params.hash(0, "blockhash")?;
+            let verbosity: u8 = params.optional(1, "verbosity")?.unwrap_or(1);
+            let blockchain = node.blockchain.lock().await;
+            let block: &Block = blockchain
+                .get_block(&hash)
+                .ok_or_else(|| not_found("Block"))?;
+            match verbosity {
+                0 => encode_hex(block),
+                _ => to_value(BlockInfo::new(&blockchain, block)),
+            }
+        }
+        // verbose 为 false（默认）时返回序列化交易的十六进制编码
+        "getrawtransaction" => {
+            let hash = params.hash(0, "txid")?;
+            let verbose: bool = params.optional(1, "verbose")?.unwrap_or(false);
+            let blockchain = node.blockchain.lock().await;
+            let info = TransactionInfo::find(&blockchain, &hash)
+                .ok_or_else(|| not_found("Transaction"))?;
+            if verbose {
+                to_value(info)
+            } else {
+                encode_hex(&info.transaction)
+            }
+        }
+        "sendrawtransaction" => {
+            let raw: String = params.required(0, "hexstring")?;
+            let tx: Transaction = hex::decode(&raw)
+                .map_err(|e| e.to_string())
+                .and_then(|bytes| deserialize_bc(&bytes).map_err(|e| e.to_string()))
+                .map_err(|e| {
+                    RpcError::new(INVALID_PARAMS, format!("invalid transaction: {}", e))
+                })?;
+            let hash = node.submit_transaction(tx).await.map_err(rejected)?;
+            Ok(json!(hex::encode(hash)))
+        }
+        "getmempoolinfo" => {
+            let blockchain = node.blockchain.lock().await;
+            let pool = blockchain.transaction_pool.lock().unwrap();
+            let bytes: usize = pool
+                .iter()
+                .map(|tx| serialize_bc(tx).map_or(0, |data| data.len()))
+                .sum();
+            Ok(json!({
+                "size": pool.len(),
+                "bytes": bytes,
+                "max_transactions": node.config.mempool.max_transactions,
+            }))
+        }
+        "getbalance" => {
+            let script = params
+                .address(0, "address")?
+                .ok_or_else(|| RpcError::new(INVALID_PARAMS, "missing parameter: address"))?;
+            Ok(json!(node.blockchain.lock().await.balance(&script)))
+        }
+        "getnewaddress" => {
+            let address = wallet(node)?
+                .lock()
+                .await
+                .new_address()
+                .map_err(wallet_error)?;
+            Ok(json!(address))
+        }
+        // 从钱包的币中选币支付，找零到钱包的新地址；fee_rate 为每 1000 字节的手续费
+        "sendtoaddress" => {
+            let script = params
+                .address(0, "address")?
+                .ok_or_else(|| RpcError::new(INVALID_PARAMS, "missing parameter: address"))?;
+            let amount: u64 = params.required(1, "amount")?;
+            let fee_rate: u64 = params.optional(2, "fee_rate")?.unwrap_or(DEFAULT_FEE_RATE);
+            if fee_rate > MAX_FEE_RATE {
+                return Err(RpcError::new(
+                    INVALID_PARAMS,
+                    format!("fee_rate must not exceed {}", MAX_FEE_RATE),
+                ));
+            }
+            // 持有钱包锁直到交易进入交易池，避免并发的转账选中同一个币
+            let mut wallet = wallet(node)?.lock().await;
+            let tx = {
+                let blockchain = node.blockchain.lock().await;
+                wallet.scan(&blockchain).map_err(wallet_error)?;
+                wallet
+                    .create_transaction(&script, amount, fee_rate)
+                    .map_err(wallet_error)?
+            };
+            let hash = node.submit_transaction(tx).await.map_err(rejected)?;
+            Ok(json!(hex::encode(hash)))
+        }
+        // 不包括已被交易池中的交易花费的币
+        "listunspent" => {
+            let min_confirmations: u32 = params.optional(0, "minconf")?.unwrap_or(1);
+            let mut wallet = wallet(node)?.lock().await;
+            wallet
+                .scan(&*node.blockchain.lock().await)
+                .map_err(wallet_error)?;
+            let coins: Vec<Value> = wallet
+                .list_unspent(min_confirmations)
+                .into_iter()
+                .map(|coin| {
+                    json!({
+                        "txid": hex::encode(coin.txid),
+                        "vout": coin.outputs,
+                        "address": coin.address(),
+                        "amount": coin.value,
+                        "confirmations": wallet.confirmations(coin),
+                    })
+                })
+                .collect();
+            Ok(json!(coins))
+        }
+        // 立即挖出指定数量的区块，主要用于 regtest
+        "generate" => {
+            let count: usize = params.required(0, "nblocks")?;
+            if count > MAX_GENERATE_BLOCKS {
+                return Err(RpcError::new(
+                    INVALID_PARAMS,
+                    format!("nblocks must not exceed {}", MAX_GENERATE_BLOCKS),
+                ));
+            }
+            // 先校验地址，避免把参数错误报告成挖矿失败
+            params.address(1, "address")?;
+            let address: Option<String> = params.optional(1, "address")?;
+            let hashes = node
+                .mining
+                .generate(count, address)
+                .await
+                .map_err(|e| RpcError::new(INTERNAL_ERROR, e))?;
+            Ok(json!(hashes.iter().map(hex::encode).collect::<Vec<_>>()))
+        }
+        _ => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("method not found: {}", method),
+        )),
+    }
+}
+
+fn response(id: Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({"jsonrpc": "2.0", "result": result, "id": id}),
+        Err(error) => json!({"jsonrpc": "2.0", "error": error, "id": id}),
+    }
+}
+
+// 处理单个请求，通知（没有 id）不返回响应
+async fn handle_request(node: &Node, group: Group, request: Value) -> Option<Value> {
+    let invalid = |message: &str| {
+        Some(response(
+            Value::Null,
+            Err(RpcError::new(INVALID_REQUEST, message)),
+        ))
+    };
+    let Value::Object(request) = request else {
+        return invalid("request must be an object");
+    };
+    let id = request.get("id").cloned();
+    if !matches!(
+        id,
+        None | Some(Value::Null | Value::Number(_) | Value::String(_))
+    ) {
+        return invalid("id must be a string, number or null");
+    }
+    if request.get("jsonrpc").and_then(Value::as_str) != Some("2.0") {
+        return invalid("jsonrpc must be \"2.0\"");
+    }
+    let Some(method) = request.get("method").and_then(Value::as_str) else {
+        return invalid("method must be a string");
+    };
+    let params = request.get("params").cloned().unwrap_or(Value::Null);
+    let required = method_group(method);
+    let result = match params {
+        _ if group < required => Err(RpcError::new(
+            FORBIDDEN,
+            format!("{} requires {} permission", method, required),
+        )),
+        Value::Null | Value::Array(_) | Value::Object(_) => {
+            call(node, method, &Params(params)).await
+        }
+        _ => Err(RpcError::new(
+            INVALID_REQUEST,
+            "params must be an array or an object",
+        )),
+    };
+    id.map(|id| response(id, result))
+}
+
+/// 处理一个 HTTP 请求体，可以是单个请求或批量请求
+///
+/// `group` 为调用方的权限组，批量请求中每个方法分别检查权限。请求全部是通知时没有响应，返回 None。
+pub async fn handle(node: &Node, group: Group, body: &[u8]) -> Option<Value> {
+    let request: Value = match serde_json::from_slice(body) {
+        Ok(request) => request,
+        Err(e) => {
+            return Some(response(
+                Value::Null,
+                Err(RpcError::new(PARSE_ERROR, format!("parse error: {}", e))),
+            ))
+        }
+    };
+    match request {
+        Value::Array(requests) if requests.is_empty() => Some(response(
+            Value::Null,
+            Err(RpcError::new(INVALID_REQUEST, "empty batch")),
+        )),
+        // 批量请求按顺序执行，响应中省略通知
+        Value::Array(requests) => {
+            let mut responses = Vec::new();
+            for request in requests {
+                responses.extend(handle_request(node, group, request).await);
+            }
+            (!responses.is_empty()).then_some(Value::Array(responses))
+        }
+        request => handle_request(node, group, request).await,
+    }
+}
@@ -1,4 +1,19 @@
+pub mod auth;
 pub mod block_chain;
+pub mod config;
+pub mod events;
+pub mod export;
 pub mod hash_function;
+pub mod jsonrpc;
+pub mod logging;
+pub mod miner;
+pub mod mining;
+pub mod network;
+pub mod openapi;
+pub mod rpc;
 pub mod serialization;
+pub mod service;
+pub mod stratum;
 pub mod transaction;
+pub mod wallet;
+pub mod websocket;
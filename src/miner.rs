@@ -0,0 +1,119 @@
+use crate::block_chain::BlockHeader;
+use crate::hash_function::hash_block_header;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+// 每尝试多少个 nonce 检查一次取消/完成标志
+const CHECK_INTERVAL: u32 = 1024;
+
+/// 判断哈希是否满足难度要求（前 difficulty 个字节为 0）
+pub fn meets_difficulty(hash: &[u8; 32], difficulty: usize) -> bool {
+    hash.iter().take(difficulty).all(|byte| *byte == 0)
+}
+
+/// 哈希的前导零比特数，用于比区块难度更细粒度的份额难度
+pub fn leading_zero_bits(hash: &[u8; 32]) -> u32 {
+    let mut bits = 0;
+    for byte in hash {
+        bits += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    bits
+}
+
+/// 多线程工作量证明矿工
+#[derive(Debug, Clone)]
+pub struct Miner {
+    threads: usize,         // 工作线程数
+    hashes: Arc<AtomicU64>, // 累计计算的哈希次数（克隆的矿工共享）
+}
+
+impl Default for Miner {
+    fn default() -> Self {
+        let threads = thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1);
+        Miner::new(threads)
+    }
+}
+
+impl Miner {
+    // 创建一个矿工，线程数至少为 1
+    pub fn new(threads: usize) -> Self {
+        Miner {
+            threads: threads.max(1),
+            hashes: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    // 累计计算的哈希次数，用于统计算力
+    pub fn hashes(&self) -> u64 {
+        self.hashes.load(Ordering::Relaxed)
+    }
+
+    /// 搜索满足难度的区块头
+    ///
+    /// nonce 空间按线程数交错划分：第 i 个线程从 `header.nonce + i` 开始，每次递增 n；
+    /// 当 32 位 nonce 用尽时递增时间戳并重新开始。
+    /// `cancel` 被置位（例如收到新的链顶）时返回 `None`。
+    pub fn mine(
+        &self,
+        header: &BlockHeader,
+        difficulty: usize,
+        cancel: &AtomicBool,
+    ) -> Option<BlockHeader> {
+        let found = AtomicBool::new(false);
+        let result: Mutex<Option<BlockHeader>> = Mutex::new(None);
+        let stride = self.threads as u32;
+
+        thread::scope(|scope| {
+            for worker in 0..stride {
+                let found = &found;
+                let result = &result;
+                let hashes = &self.hashes;
+                let mut candidate = header.clone();
+                scope.spawn(move || {
+                    candidate.nonce = header.nonce.wrapping_add(worker);
+                    let mut attempts: u32 = 0;
+                    loop {
+                        attempts += 1;
+                        if attempts == CHECK_INTERVAL {
+                            hashes.fetch_add(attempts as u64, Ordering::Relaxed);
+                            attempts = 0;
+                            if found.load(Ordering::Relaxed) || cancel.load(Ordering::Relaxed) {
+                                return;
+                            }
+                        }
+
+                        let hash = hash_block_header(&candidate);
+                        if meets_difficulty(&hash, difficulty) {
+                            hashes.fetch_add(attempts as u64, Ordering::Relaxed);
+                            if !found.swap(true, Ordering::AcqRel) {
+                                *result.lock().unwrap() = Some(candidate);
+                            }
+                            return;
+                        }
+
+                        candidate.nonce = match candidate.nonce.checked_add(stride) {
+                            Some(nonce) => nonce,
+                            None => {
+                                // nonce 空间用尽，滚动时间戳
+                                candidate.timestamp = candidate.timestamp.wrapping_add(1);
+                                worker
+                            }
+                        };
+                    }
+                });
+            }
+        });
+
+        result.into_inner().unwrap()
+    }
+}
@@ -0,0 +1,295 @@
+use crate::block_chain::{Block, BlockChain, BLOCK_REWARD};
+use crate::hash_function::hash_block_header;
+use crate::miner::Miner;
+use crate::serialization::serialize_bc;
+use crate::transaction::decode_address;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex as AsyncMutex;
+
+// 检查链顶和交易池是否变化的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+// generate 一次最多挖出的区块数
+pub const MAX_GENERATE_BLOCKS: usize = 1000;
+
+/// 后台挖矿状态
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct MiningStatus {
+    pub running: bool,
+    pub threads: usize,
+    pub payout_address: Option<String>,
+    pub hashrate: f64,     // 每秒哈希次数
+    pub difficulty: usize, // 前导零字节数
+    pub target: String,    // 目标值的十六进制表示
+    pub blocks_found: u64,
+}
+
+// 将难度转换为目标值：前 difficulty 个字节为 0，其余为 0xff
+pub fn difficulty_to_target(difficulty: usize) -> String {
+    let zeros = difficulty.min(32);
+    format!("{}{}", "00".repeat(zeros), "ff".repeat(32 - zeros))
+}
+
+/// 区块模板中的候选交易
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct TemplateTransaction {
+    pub hash: String, // 交易哈希的十六进制编码
+    pub data: String, // 序列化交易的十六进制编码
+}
+
+/// 提供给外部矿工的区块模板（getblocktemplate）
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct BlockTemplate {
+    pub version: u32,
+    pub prev_hash: String,
+    pub bits: u32,
+    pub target: String,
+    pub height: u32,
+    pub curtime: u32,
+    pub coinbase_value: u64,
+    pub transactions: Vec<TemplateTransaction>,
+}
+
+impl BlockTemplate {
+    // 根据当前链顶和交易池生成模板，coinbase 由矿工自行构造
+    pub fn new(blockchain: &BlockChain) -> Self {
+        let block = blockchain.block_template(None);
+        let transactions = block
+            .transactions
+            .iter()
+            .map(|tx| TemplateTransaction {
+                hash: hex::encode(tx.hash()),
+                data: hex::encode(serialize_bc(tx).unwrap()),
+            })
+            .collect();
+        BlockTemplate {
+            version: block.header.version,
+            prev_hash: hex::encode(block.header.prev_block_hash),
+            bits: block.header.bits,
+            target: difficulty_to_target(blockchain.difficulty()),
+            height: blockchain.blocks.len() as u32,
+            curtime: block.header.timestamp,
+            coinbase_value: BLOCK_REWARD,
+            transactions,
+        }
+    }
+}
+
+/// 后台挖矿服务：持续构造区块模板并挖矿，链顶或交易池变化时重新开始
+pub struct MiningService {
+    blockchain: Arc<AsyncMutex<BlockChain>>,
+    miner: Mutex<Miner>,
+    payout_address: Mutex<Option<String>>,
+    running: Mutex<Option<Arc<AtomicBool>>>, // 当前挖矿任务的运行标志
+    blocks_found: Arc<AtomicU64>,
+    started: Mutex<Option<(Instant, u64)>>, // 启动时间和当时的哈希计数
+}
+
+impl MiningService {
+    pub fn new(
+        blockchain: Arc<AsyncMutex<BlockChain>>,
+        miner: Miner,
+        payout_address: Option<String>,
+    ) -> Self {
+        MiningService {
+            blockchain,
+            miner: Mutex::new(miner),
+            payout_address: Mutex::new(payout_address),
+            running: Mutex::new(None),
+            blocks_found: Arc::new(AtomicU64::new(0)),
+            started: Mutex::new(None),
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.lock().unwrap().is_some()
+    }
+
+    // 修改收款地址，下一个模板生效
+    pub fn set_payout_address(&self, address: Option<String>) -> Result<(), String> {
+        if let Some(address) = &address {
+            decode_address(address).ok_or_else(|| format!("invalid address: {}", address))?;
+        }
+        *self.payout_address.lock().unwrap() = address;
+        Ok(())
+    }
+
+    // 修改线程数，仅在未运行时生效
+    pub fn set_threads(&self, threads: usize) {
+        if !self.is_running() {
+            *self.miner.lock().unwrap() = Miner::new(threads);
+        }
+    }
+
+    // 启动后台挖矿，已在运行时返回 false
+    pub fn start(&self) -> bool {
+        let mut running = self.running.lock().unwrap();
+        if running.is_some() {
+            return false;
+        }
+        // 每次启动使用新的标志，避免旧任务在快速重启后继续运行
+        let flag = Arc::new(AtomicBool::new(true));
+        *running = Some(flag.clone());
+        let miner = self.miner.lock().unwrap().clone();
+        *self.started.lock().unwrap() = Some((Instant::now(), miner.hashes()));
+        let payout_script = self
+            .payout_address
+            .lock()
+            .unwrap()
+            .as_deref()
+            .and_then(decode_address);
+        tokio::spawn(mining_loop(
+            self.blockchain.clone(),
+            miner,
+            payout_script,
+            flag,
+            self.blocks_found.clone(),
+        ));
+        true
+    }
+
+    // 停止后台挖矿，当前任务会在下一次检查时取消
+    pub fn stop(&self) -> bool {
+        match self.running.lock().unwrap().take() {
+            Some(flag) => {
+                flag.store(false, Ordering::SeqCst);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 立即挖出 `count` 个区块并上链，返回区块哈希；`address` 省略时使用当前的收款地址
+    ///
+    /// `count` 不能超过 MAX_GENERATE_BLOCKS。
+    pub async fn generate(
+        &self,
+        count: usize,
+        address: Option<String>,
+    ) -> Result<Vec<[u8; 32]>, String> {
+        if count > MAX_GENERATE_BLOCKS {
+            return Err(format!(
+                "cannot generate more than {} blocks at once",
+                MAX_GENERATE_BLOCKS
+            ));
+        }
+        let address = address.or_else(|| self.payout_address.lock().unwrap().clone());
+        let payout_script = match address {
+            Some(address) => Some(
+                decode_address(&address).ok_or_else(|| format!("invalid address: {}", address))?,
+            ),
+            None => None,
+        };
+        let miner = self.miner.lock().unwrap().clone();
+        let mut hashes = Vec::with_capacity(count);
+        for _ in 0..count {
+            let (template, difficulty) = {
+                let blockchain = self.blockchain.lock().await;
+                (
+                    blockchain.block_template(payout_script.as_deref()),
+                    blockchain.difficulty(),
+                )
+            };
+            let mined = {
+                let miner = miner.clone();
+                let header = template.header.clone();
+                tokio::task::spawn_blocking(move || {
+                    miner.mine(&header, difficulty, &AtomicBool::new(false))
+                })
+                .await
+                .unwrap()
+            };
+            let header = mined.ok_or("no valid nonce found")?;
+            let hash = hash_block_header(&header);
+            self.blockchain
+                .lock()
+                .await
+                .process_block(Block { header, ..template })
+                .map_err(|e| e.to_string())?;
+            self.blocks_found.fetch_add(1, Ordering::SeqCst);
+            hashes.push(hash);
+        }
+        Ok(hashes)
+    }
+
+    pub async fn status(&self) -> MiningStatus {
+        let miner = self.miner.lock().unwrap().clone();
+        let hashrate = match *self.started.lock().unwrap() {
+            Some((started, base)) if self.is_running() => {
+                let elapsed = started.elapsed().as_secs_f64();
+                if elapsed > 0.0 {
+                    (miner.hashes() - base) as f64 / elapsed
+                } else {
+                    0.0
+                }
+            }
+            _ => 0.0,
+        };
+        let difficulty = self.blockchain.lock().await.difficulty();
+        MiningStatus {
+            running: self.is_running(),
+            threads: miner.threads(),
+            payout_address: self.payout_address.lock().unwrap().clone(),
+            hashrate,
+            difficulty,
+            target: difficulty_to_target(difficulty),
+            blocks_found: self.blocks_found.load(Ordering::SeqCst),
+        }
+    }
+}
+
+// 链顶哈希和交易池大小，变化时需要重新构造模板
+async fn template_key(blockchain: &AsyncMutex<BlockChain>) -> ([u8; 32], usize) {
+    let blockchain = blockchain.lock().await;
+    let pool_len = blockchain.transaction_pool.lock().unwrap().len();
+    (blockchain.tip_hash(), pool_len)
+}
+
+async fn mining_loop(
+    blockchain: Arc<AsyncMutex<BlockChain>>,
+    miner: Miner,
+    payout_script: Option<Vec<u8>>,
+    running: Arc<AtomicBool>,
+    blocks_found: Arc<AtomicU64>,
+) {
+    while running.load(Ordering::SeqCst) {
+        let (template, difficulty) = {
+            let blockchain = blockchain.lock().await;
+            (
+                blockchain.block_template(payout_script.as_deref()),
+                blockchain.difficulty(),
+            )
+        };
+        let key = template_key(&blockchain).await;
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let mut task = {
+            let miner = miner.clone();
+            let cancel = cancel.clone();
+            let header = template.header.clone();
+            tokio::task::spawn_blocking(move || miner.mine(&header, difficulty, &cancel))
+        };
+
+        let mined = loop {
+            tokio::select! {
+                result = &mut task => break result.unwrap(),
+                _ = tokio::time::sleep(POLL_INTERVAL) => {
+                    if !running.load(Ordering::SeqCst) || template_key(&blockchain).await != key {
+                        cancel.store(true, Ordering::SeqCst);
+                    }
+                }
+            }
+        };
+
+        if let Some(header) = mined {
+            let mut blockchain = blockchain.lock().await;
+            // 链顶已变化的区块被丢弃，交易仍在交易池中
+            if blockchain.submit_mined_block(Block { header, ..template }) {
+                blocks_found.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    }
+}
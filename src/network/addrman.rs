@@ -0,0 +1,318 @@
+use rand::seq::IndexedRandom;
+use rand::Rng;
+use ring::digest::{Context, SHA256};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+
+// "new" 表保存听说过但未成功连接的地址，"tried" 表保存成功连接过的地址
+pub const NEW_BUCKET_COUNT: usize = 256;
+pub const TRIED_BUCKET_COUNT: usize = 64;
+pub const BUCKET_SIZE: usize = 64;
+// 同一来源的地址最多分布在多少个 new 桶中
+pub const NEW_BUCKETS_PER_SOURCE_GROUP: u64 = 16;
+// 同一网段的地址最多分布在多少个 tried 桶中
+pub const TRIED_BUCKETS_PER_GROUP: u64 = 8;
+// 两次尝试连接同一地址的最小间隔
+const RETRY_INTERVAL_MS: u64 = 60_000;
+// 从未成功且失败次数达到该值的地址不再选择
+const MAX_FAILURES: u32 = 10;
+
+/// 地址簿中的一条记录
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct AddressEntry {
+    pub addr: SocketAddr,
+    pub source: Option<IpAddr>, // 告诉我们这个地址的节点，种子地址为 None
+    pub last_seen: u64,         // 最近一次听说该地址的时间（毫秒）
+    pub last_success: Option<u64>, // 最近一次握手成功的时间
+    pub last_attempt: Option<u64>, // 最近一次尝试连接的时间
+    pub attempts: u32,          // 上次成功后的失败次数
+    pub tried: bool,            // 是否在 tried 表中
+}
+
+// 持久化到磁盘的格式
+#[derive(Serialize, Deserialize)]
+struct AddressBookFile {
+    key: String, // 桶计算用的密钥，十六进制
+    entries: Vec<AddressEntry>,
+}
+
+/// 节点地址簿
+///
+/// 地址按网段和来源分到带密钥哈希的桶中，每个桶容量有限，
+/// 单个来源或单个网段只能占据少数几个桶，攻击者难以用大量地址挤占整个地址簿。
+/// 选择出站连接时还会避开已有出站连接的网段。
+#[derive(Debug, Clone)]
+pub struct AddressBook {
+    key: [u8; 32],
+    entries: HashMap<SocketAddr, AddressEntry>,
+    new_buckets: Vec<Vec<SocketAddr>>,
+    tried_buckets: Vec<Vec<SocketAddr>>,
+}
+
+// 地址所在的网段：IPv4 取 /16，IPv6 取 /32
+pub fn address_group(ip: &IpAddr) -> Vec<u8> {
+    match ip {
+        IpAddr::V4(v4) => {
+            let ip = v4.octets();
+            vec![4, ip[0], ip[1]]
+        }
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => address_group(&IpAddr::V4(v4)),
+            None => {
+                let ip = v6.octets();
+                vec![6, ip[0], ip[1], ip[2], ip[3]]
+            }
+        },
+    }
+}
+
+impl AddressBook {
+    pub fn new(key: [u8; 32]) -> Self {
+        AddressBook {
+            key,
+            entries: HashMap::new(),
+            new_buckets: vec![Vec::new(); NEW_BUCKET_COUNT],
+            tried_buckets: vec![Vec::new(); TRIED_BUCKET_COUNT],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, addr: &SocketAddr) -> Option<&AddressEntry> {
+        self.entries.get(addr)
+    }
+
+    // 所有记录，按地址排序
+    pub fn entries(&self) -> Vec<AddressEntry> {
+        let mut entries: Vec<AddressEntry> = self.entries.values().cloned().collect();
+        entries.sort_by_key(|entry| entry.addr);
+        entries
+    }
+
+    // 带密钥的哈希，取前 8 个字节
+    fn keyed_hash(&self, parts: &[&[u8]]) -> u64 {
+        let mut context = Context::new(&SHA256);
+        context.update(&self.key);
+        for part in parts {
+            context.update(&(part.len() as u32).to_le_bytes());
+            context.update(part);
+        }
+        u64::from_le_bytes(context.finish().as_ref()[..8].try_into().unwrap())
+    }
+
+    // new 表的桶：由地址网段和来源网段决定，同一来源只能落在少数几个桶中
+    fn new_bucket(&self, addr: &SocketAddr, source: Option<IpAddr>) -> usize {
+        let group = address_group(&addr.ip());
+        let source_group = source.map(|ip| address_group(&ip)).unwrap_or_default();
+        let slot = self.keyed_hash(&[&group, &source_group]) % NEW_BUCKETS_PER_SOURCE_GROUP;
+        (self.keyed_hash(&[&source_group, &slot.to_le_bytes()]) % NEW_BUCKET_COUNT as u64) as usize
+    }
+
+    // tried 表的桶：由地址本身和网段决定，同一网段只能落在少数几个桶中
+    fn tried_bucket(&self, addr: &SocketAddr) -> usize {
+        let group = address_group(&addr.ip());
+        let slot = self.keyed_hash(&[addr.to_string().as_bytes()]) % TRIED_BUCKETS_PER_GROUP;
+        (self.keyed_hash(&[&group, &slot.to_le_bytes()]) % TRIED_BUCKET_COUNT as u64) as usize
+    }
+
+    // 把地址放进 new 表，桶满时淘汰其中最久没有听说的地址
+    fn insert_new(&mut self, addr: SocketAddr, source: Option<IpAddr>) {
+        let bucket = self.new_bucket(&addr, source);
+        if self.new_buckets[bucket].len() >= BUCKET_SIZE {
+            let oldest = *self.new_buckets[bucket]
+                .iter()
+                .min_by_key(|a| self.entries[*a].last_seen)
+                .unwrap();
+            self.new_buckets[bucket].retain(|a| *a != oldest);
+            self.entries.remove(&oldest);
+        }
+        self.new_buckets[bucket].push(addr);
+    }
+
+    /// 记录听说的地址，返回是否为新地址
+    pub fn add(&mut self, addr: SocketAddr, source: Option<IpAddr>, last_seen: u64) -> bool {
+        if addr.port() == 0 || addr.ip().is_unspecified() {
+            return false;
+        }
+        if let Some(entry) = self.entries.get_mut(&addr) {
+            entry.last_seen = entry.last_seen.max(last_seen);
+            return false;
+        }
+        self.entries.insert(
+            addr,
+            AddressEntry {
+                addr,
+                source,
+                last_seen,
+                last_success: None,
+                last_attempt: None,
+                attempts: 0,
+                tried: false,
+            },
+        );
+        self.insert_new(addr, source);
+        true
+    }
+
+    // 尝试连接前调用
+    pub fn mark_attempt(&mut self, addr: &SocketAddr, now: u64) {
+        if let Some(entry) = self.entries.get_mut(addr) {
+            entry.attempts += 1;
+            entry.last_attempt = Some(now);
+        }
+    }
+
+    /// 握手成功：记录成功时间，地址移入 tried 表
+    ///
+    /// tried 桶满时，其中最久没有成功连接的地址被移回 new 表。
+    pub fn mark_good(&mut self, addr: &SocketAddr, now: u64) {
+        let Some(entry) = self.entries.get_mut(addr) else {
+            return;
+        };
+        entry.last_success = Some(now);
+        entry.last_seen = entry.last_seen.max(now);
+        entry.attempts = 0;
+        if entry.tried {
+            return;
+        }
+        entry.tried = true;
+        let source = entry.source;
+        let new_bucket = self.new_bucket(addr, source);
+        self.new_buckets[new_bucket].retain(|a| a != addr);
+
+        let bucket = self.tried_bucket(addr);
+        if self.tried_buckets[bucket].len() >= BUCKET_SIZE {
+            let evicted = *self.tried_buckets[bucket]
+                .iter()
+                .min_by_key(|a| self.entries[*a].last_success)
+                .unwrap();
+            self.tried_buckets[bucket].retain(|a| *a != evicted);
+            let entry = self.entries.get_mut(&evicted).unwrap();
+            entry.tried = false;
+            let source = entry.source;
+            self.insert_new(evicted, source);
+        }
+        self.tried_buckets[bucket].push(*addr);
+    }
+
+    /// 选择一个出站连接地址
+    ///
+    /// tried 表和 new 表各占一半机会，先随机选桶再随机选地址；
+    /// 跳过 `exclude` 中的地址、`exclude_groups` 中的网段、刚尝试过的地址和多次失败的地址。
+    pub fn select<R: Rng>(
+        &self,
+        rng: &mut R,
+        exclude: &HashSet<SocketAddr>,
+        exclude_groups: &HashSet<Vec<u8>>,
+        now: u64,
+    ) -> Option<SocketAddr> {
+        let usable = |addr: &SocketAddr| {
+            let entry = &self.entries[addr];
+            let recently_tried = entry
+                .last_attempt
+                .is_some_and(|at| now.saturating_sub(at) < RETRY_INTERVAL_MS);
+            let terrible = entry.last_success.is_none() && entry.attempts >= MAX_FAILURES;
+            !exclude.contains(addr)
+                && !exclude_groups.contains(&address_group(&addr.ip()))
+                && !recently_tried
+                && !terrible
+        };
+        let candidates = |buckets: &[Vec<SocketAddr>]| -> Vec<Vec<SocketAddr>> {
+            buckets
+                .iter()
+                .map(|bucket| bucket.iter().copied().filter(usable).collect::<Vec<_>>())
+                .filter(|bucket| !bucket.is_empty())
+                .collect()
+        };
+        let tried = candidates(&self.tried_buckets);
+        let new = candidates(&self.new_buckets);
+        let table = match (tried.is_empty(), new.is_empty()) {
+            (true, true) => return None,
+            (false, true) => &tried,
+            (true, false) => &new,
+            (false, false) => {
+                if rng.random_bool(0.5) {
+                    &tried
+                } else {
+                    &new
+                }
+            }
+        };
+        table
+            .choose(rng)
+            .and_then(|bucket| bucket.choose(rng))
+            .copied()
+    }
+
+    // 随机取最多 count 个地址，用于回复 getaddr
+    pub fn sample<R: Rng>(&self, rng: &mut R, count: usize) -> Vec<AddressEntry> {
+        let mut entries = self.entries();
+        let (chosen, _) = rand::seq::SliceRandom::partial_shuffle(&mut entries[..], rng, count);
+        chosen.to_vec()
+    }
+
+    /// 从文件读取地址簿，文件不存在时返回使用新密钥的空地址簿
+    pub fn load(path: &Path, new_key: [u8; 32]) -> io::Result<Self> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(AddressBook::new(new_key)),
+            Err(e) => return Err(e),
+        };
+        let file: AddressBookFile = serde_json::from_slice(&data)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let key = hex::decode(&file.key)
+            .ok()
+            .and_then(|key| key.try_into().ok())
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "invalid address book key")
+            })?;
+
+        let mut book = AddressBook::new(key);
+        // tried 表中的地址先放入，使其重新占据 tried 桶
+        let (tried, new): (Vec<AddressEntry>, Vec<AddressEntry>) =
+            file.entries.into_iter().partition(|entry| entry.tried);
+        for mut entry in new.into_iter().chain(tried) {
+            let addr = entry.addr;
+            let tried = entry.tried;
+            entry.tried = false;
+            if book.entries.contains_key(&addr) {
+                continue;
+            }
+            let source = entry.source;
+            book.entries.insert(addr, entry.clone());
+            book.insert_new(addr, source);
+            if tried {
+                let last_success = entry.last_success.unwrap_or(entry.last_seen);
+                book.mark_good(&addr, last_success);
+                let restored = book.entries.get_mut(&addr).unwrap();
+                restored.last_seen = entry.last_seen;
+                restored.attempts = entry.attempts;
+            }
+        }
+        Ok(book)
+    }
+
+    // 写入临时文件后再替换，避免写到一半时留下损坏的文件
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let file = AddressBookFile {
+            key: hex::encode(self.key),
+            entries: self.entries(),
+        };
+        let data = serde_json::to_vec_pretty(&file)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, data)?;
+        fs::rename(tmp, path)
+    }
+}
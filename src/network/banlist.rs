@@ -0,0 +1,102 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::path::Path;
+
+/// 一条封禁记录
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct BanEntry {
+    pub ip: IpAddr,
+    pub created: u64, // 封禁时间（毫秒）
+    pub until: u64,   // 解封时间（毫秒）
+    pub reason: String,
+}
+
+/// 有时限的封禁列表
+///
+/// 被封禁的地址不能建立入站连接，也不会被选为出站连接。
+/// 修改后 `take_changed` 返回 true，由传输层负责写回磁盘。
+#[derive(Debug, Clone, Default)]
+pub struct BanList {
+    entries: HashMap<IpAddr, BanEntry>,
+    changed: bool,
+}
+
+impl BanList {
+    pub fn new() -> Self {
+        BanList::default()
+    }
+
+    // 封禁地址，已封禁时延长到较晚的解封时间
+    pub fn ban(&mut self, ip: IpAddr, now: u64, duration_ms: u64, reason: &str) {
+        let until = now.saturating_add(duration_ms);
+        let entry = self.entries.entry(ip).or_insert_with(|| BanEntry {
+            ip,
+            created: now,
+            until,
+            reason: reason.to_string(),
+        });
+        if until > entry.until {
+            entry.until = until;
+            entry.reason = reason.to_string();
+        }
+        self.changed = true;
+    }
+
+    // 解除封禁，返回地址之前是否被封禁
+    pub fn unban(&mut self, ip: &IpAddr) -> bool {
+        let removed = self.entries.remove(ip).is_some();
+        self.changed |= removed;
+        removed
+    }
+
+    pub fn is_banned(&self, ip: &IpAddr, now: u64) -> bool {
+        self.entries.get(ip).is_some_and(|entry| entry.until > now)
+    }
+
+    // 移除已过期的封禁
+    pub fn sweep(&mut self, now: u64) {
+        let before = self.entries.len();
+        self.entries.retain(|_, entry| entry.until > now);
+        self.changed |= self.entries.len() != before;
+    }
+
+    // 所有封禁记录，按地址排序
+    pub fn entries(&self) -> Vec<BanEntry> {
+        let mut entries: Vec<BanEntry> = self.entries.values().cloned().collect();
+        entries.sort_by_key(|entry| entry.ip);
+        entries
+    }
+
+    // 上次调用后是否有修改
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::take(&mut self.changed)
+    }
+
+    /// 从文件读取封禁列表，文件不存在时返回空列表
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(BanList::new()),
+            Err(e) => return Err(e),
+        };
+        let entries: Vec<BanEntry> = serde_json::from_slice(&data)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(BanList {
+            entries: entries.into_iter().map(|entry| (entry.ip, entry)).collect(),
+            changed: false,
+        })
+    }
+
+    // 写入临时文件后再替换，避免写到一半时留下损坏的文件
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let data = serde_json::to_vec_pretty(&self.entries())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, data)?;
+        fs::rename(tmp, path)
+    }
+}
@@ -0,0 +1,232 @@
+use crate::block_chain::{Block, BlockHeader};
+use crate::hash_function::{calculate_merkle_root, hash_block_header, sha256_hash};
+use crate::serialization::serialize_bc;
+use crate::transaction::Transaction;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// 交易的短 id：加盐 SipHash 的低 6 个字节
+pub type ShortId = [u8; 6];
+
+/// 紧凑区块中直接附带的完整交易
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrefilledTransaction {
+    pub index: u32, // 交易在区块中的位置
+    pub tx: Transaction,
+}
+
+/// 紧凑区块：区块头加交易短 id，接收方用交易池中的交易还原区块
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompactBlock {
+    pub header: BlockHeader,
+    pub nonce: u64,                           // 与区块头一起决定 SipHash 的密钥
+    pub short_ids: Vec<ShortId>,              // 未附带的交易的短 id，按区块中的顺序
+    pub prefilled: Vec<PrefilledTransaction>, // 附带的完整交易（coinbase）
+}
+
+/// 还原紧凑区块失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompactError {
+    BadIndex,         // 附带交易的位置越界或重复
+    DuplicateShortId, // 区块内有相同的短 id，无法还原
+}
+
+// SipHash-2-4
+pub fn siphash24(k0: u64, k1: u64, data: &[u8]) -> u64 {
+    let mut v0 = k0 ^ 0x736f6d6570736575;
+    let mut v1 = k1 ^ 0x646f72616e646f6d;
+    let mut v2 = k0 ^ 0x6c7967656e657261;
+    let mut v3 = k1 ^ 0x7465646279746573;
+
+    macro_rules! round {
+        () => {
+            v0 = v0.wrapping_add(v1);
+            v1 = v1.rotate_left(13);
+            v1 ^= v0;
+            v0 = v0.rotate_left(32);
+            v2 = v2.wrapping_add(v3);
+            v3 = v3.rotate_left(16);
+            v3 ^= v2;
+            v0 = v0.wrapping_add(v3);
+            v3 = v3.rotate_left(21);
+            v3 ^= v0;
+            v2 = v2.wrapping_add(v1);
+            v1 = v1.rotate_left(17);
+            v1 ^= v2;
+            v2 = v2.rotate_left(32);
+        };
+    }
+
+    let chunks = data.chunks_exact(8);
+    let tail = chunks.remainder();
+    for chunk in chunks {
+        let m = u64::from_le_bytes(chunk.try_into().unwrap());
+        v3 ^= m;
+        round!();
+        round!();
+        v0 ^= m;
+    }
+    // 最后一个分组：剩余字节加上长度的低 8 位
+    let mut last = [0u8; 8];
+    last[..tail.len()].copy_from_slice(tail);
+    last[7] = data.len() as u8;
+    let m = u64::from_le_bytes(last);
+    v3 ^= m;
+    round!();
+    round!();
+    v0 ^= m;
+
+    v2 ^= 0xff;
+    round!();
+    round!();
+    round!();
+    round!();
+    v0 ^ v1 ^ v2 ^ v3
+}
+
+impl CompactBlock {
+    /// 由完整区块构造紧凑区块，coinbase 交易直接附带
+    pub fn new(block: &Block, nonce: u64) -> Self {
+        let mut compact = CompactBlock {
+            header: block.header.clone(),
+            nonce,
+            short_ids: Vec::new(),
+            prefilled: Vec::new(),
+        };
+        for (index, tx) in block.transactions.iter().enumerate() {
+            if tx.is_coinbase() {
+                compact.prefilled.push(PrefilledTransaction {
+                    index: index as u32,
+                    tx: tx.clone(),
+                });
+            } else {
+                compact.short_ids.push(compact.short_id(&tx.hash()));
+            }
+        }
+        compact
+    }
+
+    pub fn block_hash(&self) -> [u8; 32] {
+        hash_block_header(&self.header)
+    }
+
+    pub fn transaction_count(&self) -> usize {
+        self.short_ids.len() + self.prefilled.len()
+    }
+
+    // SipHash 密钥：区块头和 nonce 的 SHA-256 的前 16 个字节
+    fn keys(&self) -> (u64, u64) {
+        let mut data = serialize_bc(&self.header).unwrap();
+        data.extend_from_slice(&self.nonce.to_le_bytes());
+        let digest = sha256_hash(&data);
+        let bytes = digest.as_ref();
+        (
+            u64::from_le_bytes(bytes[..8].try_into().unwrap()),
+            u64::from_le_bytes(bytes[8..16].try_into().unwrap()),
+        )
+    }
+
+    pub fn short_id(&self, txid: &[u8; 32]) -> ShortId {
+        let (k0, k1) = self.keys();
+        short_id_with_keys(k0, k1, txid)
+    }
+}
+
+fn short_id_with_keys(k0: u64, k1: u64, txid: &[u8; 32]) -> ShortId {
+    siphash24(k0, k1, txid).to_le_bytes()[..6]
+        .try_into()
+        .unwrap()
+}
+
+/// 正在还原的区块：每个位置是已经找到的交易或缺失
+#[derive(Debug, Clone)]
+pub struct PartialBlock {
+    header: BlockHeader,
+    slots: Vec<Option<Transaction>>,
+}
+
+impl PartialBlock {
+    /// 用附带的交易和交易池中短 id 匹配的交易填充区块
+    ///
+    /// 交易池中多笔交易的短 id 相同时，该位置视为缺失，由后续的 getblocktxn 补齐。
+    pub fn reconstruct(compact: &CompactBlock, pool: &[Transaction]) -> Result<Self, CompactError> {
+        let count = compact.transaction_count();
+        let mut slots: Vec<Option<Transaction>> = vec![None; count];
+        let mut prefilled = vec![false; count];
+        for item in &compact.prefilled {
+            let index = item.index as usize;
+            if index >= count || prefilled[index] {
+                return Err(CompactError::BadIndex);
+            }
+            prefilled[index] = true;
+            slots[index] = Some(item.tx.clone());
+        }
+
+        // 短 id 到区块中位置的映射
+        let mut positions: HashMap<ShortId, usize> = HashMap::new();
+        let mut short_ids = compact.short_ids.iter();
+        for (index, _) in prefilled.iter().enumerate().filter(|(_, p)| !**p) {
+            let id = *short_ids.next().unwrap();
+            if positions.insert(id, index).is_some() {
+                return Err(CompactError::DuplicateShortId);
+            }
+        }
+
+        let (k0, k1) = compact.keys();
+        let mut collided = Vec::new();
+        for tx in pool {
+            let id = short_id_with_keys(k0, k1, &tx.hash());
+            if let Some(&index) = positions.get(&id) {
+                if slots[index].is_some() {
+                    collided.push(index);
+                } else {
+                    slots[index] = Some(tx.clone());
+                }
+            }
+        }
+        for index in collided {
+            slots[index] = None;
+        }
+        Ok(PartialBlock {
+            header: compact.header.clone(),
+            slots,
+        })
+    }
+
+    // 仍然缺失的交易位置
+    pub fn missing(&self) -> Vec<u32> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter(|(_, slot)| slot.is_none())
+            .map(|(index, _)| index as u32)
+            .collect()
+    }
+
+    /// 按缺失位置的顺序填入 blocktxn 返回的交易，数量不符时返回 false
+    pub fn fill(&mut self, transactions: Vec<Transaction>) -> bool {
+        let missing = self.missing();
+        if missing.len() != transactions.len() {
+            return false;
+        }
+        for (index, tx) in missing.into_iter().zip(transactions) {
+            self.slots[index as usize] = Some(tx);
+        }
+        true
+    }
+
+    /// 所有交易齐全且 Merkle Root 一致时返回完整区块
+    ///
+    /// Merkle Root 不一致说明短 id 碰撞选错了交易，调用方应改为请求完整区块。
+    pub fn into_block(self) -> Option<Block> {
+        let transactions: Option<Vec<Transaction>> = self.slots.into_iter().collect();
+        let transactions = transactions?;
+        if calculate_merkle_root(&transactions) != self.header.merkle_root {
+            return None;
+        }
+        Some(Block {
+            header: self.header,
+            transactions,
+        })
+    }
+}
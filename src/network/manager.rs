@@ -0,0 +1,1072 @@
+use super::addrman::{address_group, AddressBook};
+use super::banlist::BanList;
+use super::compact::{CompactBlock, CompactError, PartialBlock};
+use super::message::{
+    FrameError, Inventory, Message, NetAddress, VersionMessage, MAX_ADDR_SIZE, MAX_HEADERS,
+    MAX_INV_SIZE, MAX_LOCATOR_SIZE,
+};
+use super::orphan::{OrphanBlocks, OrphanPool};
+use super::peer::{Peer, PeerInfo, PeerState};
+use super::sync::{BlockSync, HeadersOutcome};
+use super::{
+    Action, Direction, NetworkConfig, PeerId, BAN_THRESHOLD, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+};
+use crate::block_chain::{
+    Block, BlockChain, BlockError, BlockHeader, BlockStatus, TransactionError,
+};
+use crate::hash_function::{calculate_merkle_root, hash_block_header};
+use crate::transaction::Transaction;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
+
+// getdata 请求的超时时间，超时后可以向其他节点重新请求
+const REQUEST_TIMEOUT_MS: u64 = 30_000;
+// 地址数不超过该值的 addr 消息视为新地址通告，会继续转发
+const MAX_ADDR_RELAY: usize = 10;
+// 每个新地址转发给多少个节点
+const ADDR_RELAY_FANOUT: usize = 2;
+// 每批最多通告的交易数，其余留到下一批
+const MAX_TX_PER_RELAY: usize = 100;
+// 各类不当行为增加的分数
+const SCORE_INVALID: u32 = 100; // 无效的区块、区块头或交易
+const SCORE_OVERSIZED: u32 = 20; // 超过上限的消息
+const SCORE_UNCONNECTED_HEADERS: u32 = 20; // 接不上已知链的区块头
+const SCORE_MALFORMED: u32 = 10; // 无法解析的消息
+
+/// 与传输层无关的 P2P 协议状态机
+///
+/// 传输层在连接建立、收到消息、连接断开以及定时器触发时调用对应方法，
+/// 再通过 `take_actions` 取出需要发送的消息和需要断开的连接。
+/// 时间以毫秒传入，便于在测试中使用虚拟时钟。
+pub struct PeerManager {
+    config: NetworkConfig,
+    local_nonce: u64,
+    peers: HashMap<PeerId, Peer>,
+    actions: Vec<Action>,
+    rng: StdRng,
+    in_flight: HashMap<Inventory, (PeerId, u64)>, // 已请求尚未收到的数据：(节点, 请求时间)
+    orphan_blocks: OrphanBlocks,                  // 父区块未知的区块
+    announced_tip: Option<[u8; 32]>,              // 最近一次通告的链顶
+    sync: BlockSync,                              // 区块头优先的区块同步
+    addresses: AddressBook,                       // 已知的节点地址
+    connecting: HashMap<SocketAddr, u64>,         // 正在建立的出站连接：(地址, 发起时间)
+    bans: BanList,                                // 被封禁的地址
+    partial_blocks: HashMap<[u8; 32], (PeerId, u64, PartialBlock)>, // 等待缺失交易的紧凑区块
+    orphan_txs: OrphanPool,                       // 父交易未知的交易
+}
+
+impl PeerManager {
+    pub fn new(config: NetworkConfig, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let addresses = AddressBook::new(rng.random());
+        PeerManager {
+            config,
+            local_nonce: rng.random(),
+            peers: HashMap::new(),
+            actions: Vec::new(),
+            rng,
+            in_flight: HashMap::new(),
+            orphan_blocks: OrphanBlocks::new(),
+            announced_tip: None,
+            sync: BlockSync::new(),
+            addresses,
+            connecting: HashMap::new(),
+            bans: BanList::new(),
+            partial_blocks: HashMap::new(),
+            orphan_txs: OrphanPool::new(),
+        }
+    }
+
+    pub fn bans(&self) -> &BanList {
+        &self.bans
+    }
+
+    pub fn bans_mut(&mut self) -> &mut BanList {
+        &mut self.bans
+    }
+
+    /// 封禁地址并断开该地址的所有连接，`duration_ms` 为 None 时使用配置的时长
+    pub fn ban(&mut self, ip: IpAddr, duration_ms: Option<u64>, reason: &str, now: u64) {
+        let duration_ms = duration_ms.unwrap_or(self.config.ban_duration_ms);
+        self.bans.ban(ip, now, duration_ms, reason);
+        let mut banned: Vec<PeerId> = self
+            .peers
+            .values()
+            .filter(|peer| Self::peer_ip(peer) == Some(ip))
+            .map(|peer| peer.id)
+            .collect();
+        banned.sort();
+        for id in banned {
+            self.disconnect(id, "banned");
+        }
+    }
+
+    // 解除封禁，返回地址之前是否被封禁
+    pub fn unban(&mut self, ip: &IpAddr) -> bool {
+        self.bans.unban(ip)
+    }
+
+    fn peer_ip(peer: &Peer) -> Option<IpAddr> {
+        peer.addr.parse::<SocketAddr>().ok().map(|addr| addr.ip())
+    }
+
+    // 增加节点的不当行为分数，达到阈值时封禁其地址并断开连接
+    fn misbehaving(&mut self, id: PeerId, score: u32, reason: &str, now: u64) {
+        let Some(peer) = self.peers.get_mut(&id) else {
+            return;
+        };
+        peer.ban_score = peer.ban_score.saturating_add(score);
+        crate::log!(
+            Info,
+            "Peer {} misbehaving (+{} -> {}): {}",
+            id,
+            score,
+            peer.ban_score,
+            reason
+        );
+        if peer.ban_score < BAN_THRESHOLD {
+            return;
+        }
+        if let Some(ip) = Self::peer_ip(peer) {
+            self.bans.ban(ip, now, self.config.ban_duration_ms, reason);
+        }
+        self.disconnect(id, reason);
+    }
+
+    // 传输层解析消息帧失败
+    pub fn on_malformed(&mut self, id: PeerId, error: &FrameError, now: u64) {
+        let score = match error {
+            FrameError::PayloadTooLarge(_) => SCORE_OVERSIZED,
+            _ => SCORE_MALFORMED,
+        };
+        self.misbehaving(id, score, &error.to_string(), now);
+    }
+
+    pub fn addresses(&self) -> &AddressBook {
+        &self.addresses
+    }
+
+    pub fn addresses_mut(&mut self) -> &mut AddressBook {
+        &mut self.addresses
+    }
+
+    pub fn config(&self) -> &NetworkConfig {
+        &self.config
+    }
+
+    // 取出待执行的动作
+    pub fn take_actions(&mut self) -> Vec<Action> {
+        std::mem::take(&mut self.actions)
+    }
+
+    pub fn peers(&self) -> Vec<PeerInfo> {
+        let mut peers: Vec<PeerInfo> = self.peers.values().map(Peer::info).collect();
+        peers.sort_by_key(|peer| peer.id);
+        peers
+    }
+
+    // 已完成握手的连接
+    pub fn ready_peers(&self) -> Vec<PeerId> {
+        let mut ready: Vec<PeerId> = self
+            .peers
+            .values()
+            .filter(|peer| peer.is_ready())
+            .map(|peer| peer.id)
+            .collect();
+        ready.sort();
+        ready
+    }
+
+    fn send(&mut self, peer: PeerId, message: Message) {
+        self.actions.push(Action::Send(peer, message));
+    }
+
+    fn disconnect(&mut self, peer: PeerId, reason: &str) {
+        self.in_flight
+            .retain(|_, (requested_from, _)| *requested_from != peer);
+        self.sync.remove_peer(peer);
+        self.partial_blocks.retain(|_, (from, _, _)| *from != peer);
+        self.orphan_txs.remove_peer(peer);
+        if self.peers.remove(&peer).is_some() {
+            self.actions
+                .push(Action::Disconnect(peer, reason.to_string()));
+        }
+    }
+
+    fn version_message(&self, blockchain: &BlockChain, now: u64) -> Message {
+        Message::Version(VersionMessage {
+            version: PROTOCOL_VERSION,
+            services: 0,
+            timestamp: now,
+            nonce: self.local_nonce,
+            user_agent: self.config.user_agent.clone(),
+            genesis_hash: blockchain.genesis_hash(),
+            best_height: blockchain.height(),
+            listen_port: self.config.listen_addr.map(|a| a.port()).unwrap_or(0),
+        })
+    }
+
+    // 新连接建立，主动连接的一方先发送 version
+    pub fn on_connected(
+        &mut self,
+        id: PeerId,
+        addr: String,
+        direction: Direction,
+        blockchain: &BlockChain,
+        now: u64,
+    ) {
+        let mut peer = Peer::new(id, addr, direction, now);
+        if let Ok(addr) = peer.addr.parse::<SocketAddr>() {
+            self.connecting.remove(&addr);
+            if direction == Direction::Outbound {
+                peer.listen_addr = Some(addr);
+            }
+        }
+        if direction == Direction::Outbound {
+            peer.version_sent = true;
+            let version = self.version_message(blockchain, now);
+            self.send(id, version);
+        }
+        let banned = Self::peer_ip(&peer).is_some_and(|ip| self.bans.is_banned(&ip, now));
+        self.peers.insert(id, peer);
+        if banned {
+            self.disconnect(id, "banned");
+        }
+    }
+
+    // 加密握手验证过的对方身份
+    pub fn set_identity(&mut self, id: PeerId, identity: [u8; 32]) {
+        if let Some(peer) = self.peers.get_mut(&id) {
+            peer.identity = Some(identity);
+        }
+    }
+
+    // 出站连接建立失败
+    pub fn on_connect_failed(&mut self, addr: SocketAddr) {
+        self.connecting.remove(&addr);
+    }
+
+    pub fn on_disconnected(&mut self, id: PeerId) {
+        self.peers.remove(&id);
+        self.in_flight.retain(|_, (peer, _)| *peer != id);
+        self.sync.remove_peer(id);
+        self.partial_blocks.retain(|_, (peer, _, _)| *peer != id);
+        self.orphan_txs.remove_peer(id);
+    }
+
+    pub fn on_message(
+        &mut self,
+        id: PeerId,
+        message: Message,
+        blockchain: &mut BlockChain,
+        now: u64,
+    ) {
+        let Some(peer) = self.peers.get_mut(&id) else {
+            return;
+        };
+        peer.last_recv = now;
+
+        match message {
+            Message::Version(version) => self.handle_version(id, version, blockchain, now),
+            Message::Verack => {
+                peer.verack_received = true;
+                if peer.state == PeerState::VersionReceived {
+                    peer.state = PeerState::Ready;
+                    self.handshake_completed(id, now);
+                }
+            }
+            // 握手完成前只允许 version 和 verack
+            _ if !peer.is_ready() => self.disconnect(id, "message before handshake"),
+            Message::Ping(nonce) => self.send(id, Message::Pong(nonce)),
+            Message::Pong(nonce) => {
+                if let Some((expected, sent_at)) = peer.ping {
+                    if expected == nonce {
+                        peer.ping = None;
+                        peer.latency_ms = Some(now.saturating_sub(sent_at));
+                    }
+                }
+            }
+            Message::Inv(items) => self.handle_inv(id, items, blockchain, now),
+            Message::GetData(items) => self.handle_getdata(id, items, blockchain, now),
+            Message::NotFound(items) => {
+                self.sync.on_not_found(id, &items);
+                for item in items {
+                    self.in_flight.remove(&item);
+                }
+            }
+            Message::Tx(tx) => {
+                let item = Inventory::Tx(tx.hash());
+                peer.known_inventory.insert(item);
+                self.in_flight.remove(&item);
+                self.handle_transaction(id, tx, blockchain, now);
+            }
+            Message::Block(block) => {
+                let item = Inventory::Block(hash_block_header(&block.header));
+                peer.known_inventory.insert(item);
+                self.in_flight.remove(&item);
+                match self.sync.on_block(id, block) {
+                    Some(block) => self.handle_block(id, block, blockchain, now),
+                    None => self.connect_synced_blocks(blockchain, now),
+                }
+            }
+            Message::GetHeaders { locator, stop } => {
+                if locator.len() > MAX_LOCATOR_SIZE {
+                    return self.misbehaving(id, SCORE_OVERSIZED, "oversized locator", now);
+                }
+                let headers = blockchain.locate_headers(&locator, stop, MAX_HEADERS);
+                self.send(id, Message::Headers(headers));
+            }
+            Message::Headers(headers) => self.handle_headers(id, headers, blockchain, now),
+            Message::GetAddr => {
+                // 每个连接只回复一次，避免对方反复获取整个地址簿
+                if !peer.getaddr_answered {
+                    peer.getaddr_answered = true;
+                    let addresses = self
+                        .addresses
+                        .sample(&mut self.rng, MAX_ADDR_SIZE)
+                        .into_iter()
+                        .map(|entry| NetAddress {
+                            addr: entry.addr,
+                            last_seen: entry.last_seen,
+                        })
+                        .collect();
+                    self.send(id, Message::Addr(addresses));
+                }
+            }
+            Message::Addr(addresses) => self.handle_addr(id, addresses, now),
+            Message::SendCmpct(enabled) => peer.compact_blocks = enabled,
+            Message::CmpctBlock(compact) => self.handle_compact_block(id, compact, blockchain, now),
+            Message::GetBlockTxn {
+                block_hash,
+                indexes,
+            } => self.handle_getblocktxn(id, block_hash, indexes, blockchain, now),
+            Message::BlockTxn {
+                block_hash,
+                transactions,
+            } => {
+                let Some((from, _, mut partial)) = self.partial_blocks.remove(&block_hash) else {
+                    return;
+                };
+                if from != id {
+                    self.partial_blocks.insert(block_hash, (from, now, partial));
+                    return;
+                }
+                if !partial.fill(transactions) {
+                    return self.misbehaving(id, SCORE_INVALID, "invalid blocktxn", now);
+                }
+                self.complete_compact_block(id, block_hash, partial, blockchain, now);
+            }
+        }
+        self.sync_blocks(blockchain, now);
+    }
+
+    fn handle_headers(
+        &mut self,
+        id: PeerId,
+        headers: Vec<BlockHeader>,
+        blockchain: &BlockChain,
+        now: u64,
+    ) {
+        if headers.len() > MAX_HEADERS {
+            return self.misbehaving(id, SCORE_OVERSIZED, "oversized headers", now);
+        }
+        let last = headers.last().map(hash_block_header);
+        match self.sync.on_headers(id, headers, blockchain) {
+            Ok(outcome) => {
+                let peer = self.peers.get_mut(&id).unwrap();
+                // 对方链顶的高度：空回复说明对方没有比共同区块更新的区块
+                let height = match last {
+                    Some(hash) => self
+                        .sync
+                        .height_of(&hash)
+                        .or_else(|| blockchain.block_height(&hash)),
+                    None => Some(peer.best_height.min(blockchain.height())),
+                };
+                if let Some(height) = height {
+                    peer.best_height = height;
+                }
+                if outcome == HeadersOutcome::More {
+                    self.request_headers(id, blockchain, now);
+                }
+            }
+            Err(BlockError::UnknownParent) => {
+                self.misbehaving(id, SCORE_UNCONNECTED_HEADERS, "unconnected headers", now)
+            }
+            // 时间戳超前可能只是本地时钟偏差，不计分；不再把对方视为链更长的节点，
+            // 停止向其同步，直到对方通告新的链顶
+            Err(BlockError::TimeTooNew) => {
+                crate::log!(Warn, "Headers from peer {} are too far in the future", id);
+                let peer = self.peers.get_mut(&id).unwrap();
+                peer.best_height = peer.best_height.min(blockchain.height());
+            }
+            Err(e) => {
+                crate::log!(Warn, "Invalid headers from peer {}: {}", id, e);
+                self.misbehaving(id, SCORE_INVALID, "invalid headers", now);
+            }
+        }
+    }
+
+    fn request_headers(&mut self, id: PeerId, blockchain: &BlockChain, now: u64) {
+        let locator = self.sync.locator(blockchain);
+        self.sync.headers_requested(id, now);
+        self.send(
+            id,
+            Message::GetHeaders {
+                locator,
+                stop: [0; 32],
+            },
+        );
+    }
+
+    // 按顺序连接同步下载到的区块，提供无效区块的节点被断开
+    fn connect_synced_blocks(&mut self, blockchain: &mut BlockChain, now: u64) {
+        for (peer, e) in self.sync.connect_blocks(blockchain) {
+            self.block_rejected(peer, e, now);
+        }
+        self.announce_tip(blockchain);
+    }
+
+    // 有节点的链更长时向其请求区块头，并把待下载的区块分配给各节点
+    fn sync_blocks(&mut self, blockchain: &BlockChain, now: u64) {
+        let best_known = blockchain.height().max(self.sync.header_height());
+        // 未连接的区块头已满时，等区块连接后再继续请求
+        if self.sync.header_peer().is_none() && self.sync.wants_headers() {
+            let ahead = self
+                .peers
+                .values()
+                .filter(|peer| peer.is_ready() && peer.best_height > best_known)
+                .map(|peer| peer.id)
+                .min();
+            if let Some(id) = ahead {
+                self.request_headers(id, blockchain, now);
+            }
+        }
+
+        let mut candidates: Vec<(PeerId, u32)> = self
+            .peers
+            .values()
+            .filter(|peer| peer.is_ready())
+            .map(|peer| (peer.id, peer.best_height))
+            .collect();
+        candidates.sort();
+        for (id, items) in self.sync.schedule(&candidates, blockchain, now) {
+            self.send(id, Message::GetData(items));
+        }
+    }
+
+    // 本地是否已有该条目
+    fn has_inventory(item: &Inventory, blockchain: &BlockChain) -> bool {
+        match item {
+            Inventory::Block(hash) => blockchain.contains_block(hash),
+            Inventory::Tx(hash) => blockchain.transaction_pool.lock().unwrap().contains(hash),
+        }
+    }
+
+    fn pooled_transaction(hash: &[u8; 32], blockchain: &BlockChain) -> Option<Transaction> {
+        blockchain
+            .transaction_pool
+            .lock()
+            .unwrap()
+            .get_by_hash(hash)
+            .cloned()
+    }
+
+    // 请求尚未拥有且未在请求中的条目
+    fn request(&mut self, id: PeerId, items: Vec<Inventory>, now: u64) {
+        let mut wanted = Vec::new();
+        for item in items {
+            let pending = self
+                .in_flight
+                .get(&item)
+                .is_some_and(|(_, at)| now.saturating_sub(*at) < REQUEST_TIMEOUT_MS);
+            if !pending {
+                self.in_flight.insert(item, (id, now));
+                wanted.push(item);
+            }
+        }
+        if !wanted.is_empty() {
+            self.send(id, Message::GetData(wanted));
+        }
+    }
+
+    fn handle_inv(&mut self, id: PeerId, items: Vec<Inventory>, blockchain: &BlockChain, now: u64) {
+        if items.len() > MAX_INV_SIZE {
+            return self.misbehaving(id, SCORE_OVERSIZED, "oversized inv", now);
+        }
+        let peer = self.peers.get_mut(&id).unwrap();
+        for item in &items {
+            peer.known_inventory.insert(*item);
+        }
+        let missing = items
+            .into_iter()
+            .filter(|item| !Self::has_inventory(item, blockchain))
+            .collect();
+        self.request(id, missing, now);
+    }
+
+    fn handle_getdata(
+        &mut self,
+        id: PeerId,
+        items: Vec<Inventory>,
+        blockchain: &BlockChain,
+        now: u64,
+    ) {
+        if items.len() > MAX_INV_SIZE {
+            return self.misbehaving(id, SCORE_OVERSIZED, "oversized getdata", now);
+        }
+        let mut not_found = Vec::new();
+        for item in items {
+            let message = match &item {
+                Inventory::Block(hash) => blockchain.get_block(hash).cloned().map(Message::Block),
+                Inventory::Tx(hash) => Self::pooled_transaction(hash, blockchain).map(Message::Tx),
+            };
+            match message {
+                Some(message) => {
+                    self.peers
+                        .get_mut(&id)
+                        .unwrap()
+                        .known_inventory
+                        .insert(item);
+                    self.send(id, message);
+                }
+                None => not_found.push(item),
+            }
+        }
+        if !not_found.is_empty() {
+            self.send(id, Message::NotFound(not_found));
+        }
+    }
+
+    // 处理收到的区块，父区块未知时缓存为孤块并向对方请求父区块
+    fn handle_block(&mut self, id: PeerId, block: Block, blockchain: &mut BlockChain, now: u64) {
+        let received = hash_block_header(&block.header);
+        let mut queue = vec![block];
+        while let Some(block) = queue.pop() {
+            let hash = hash_block_header(&block.header);
+            let prev_hash = block.header.prev_block_hash;
+            match blockchain.process_block(block.clone()) {
+                Ok(BlockStatus::AlreadyKnown) => {}
+                Ok(status) => {
+                    if status != BlockStatus::SideChain {
+                        let txids = block.transactions.iter().map(Transaction::hash).collect();
+                        self.process_orphans(txids, blockchain, now);
+                    }
+                    if let (Some(peer), Some(height)) =
+                        (self.peers.get_mut(&id), blockchain.block_height(&hash))
+                    {
+                        peer.best_height = peer.best_height.max(height);
+                    }
+                    // 父区块已到达的孤块可以继续连接，父区块在分叉链上时也一样
+                    for child in self.orphan_blocks.children(&hash) {
+                        queue.extend(self.orphan_blocks.remove(&child));
+                    }
+                }
+                Err(BlockError::UnknownParent) => {
+                    // 无法连接的区块至少要满足工作量，且交易与 Merkle Root 一致，才值得缓存
+                    if let Err(e) = Self::check_orphan_block(&block, blockchain) {
+                        if hash == received {
+                            self.block_rejected(id, e, now);
+                        }
+                        continue;
+                    }
+                    self.orphan_blocks.add(&mut self.rng, block, id, now);
+                    if self.peers.contains_key(&id) {
+                        self.request(id, vec![Inventory::Block(prev_hash)], now);
+                    }
+                }
+                // 缓存的孤块可能来自其他节点，只为对方直接发来的区块计分
+                Err(e) if hash == received => self.block_rejected(id, e, now),
+                Err(e) => crate::log!(Warn, "Rejected orphan block {}: {}", hex::encode(hash), e),
+            }
+        }
+        self.announce_tip(blockchain);
+    }
+
+    // 孤块的父区块未知，只能校验不依赖父区块的部分
+    fn check_orphan_block(block: &Block, blockchain: &BlockChain) -> Result<(), BlockError> {
+        blockchain.check_header(&block.header, block.header.prev_block_hash)?;
+        if block.header.merkle_root != calculate_merkle_root(&block.transactions) {
+            return Err(BlockError::MerkleRootMismatch);
+        }
+        Ok(())
+    }
+
+    // 收到紧凑区块：校验区块头后用交易池还原区块，缺失的交易向对方请求
+    fn handle_compact_block(
+        &mut self,
+        id: PeerId,
+        compact: CompactBlock,
+        blockchain: &mut BlockChain,
+        now: u64,
+    ) {
+        let hash = compact.block_hash();
+        let item = Inventory::Block(hash);
+        self.peers
+            .get_mut(&id)
+            .unwrap()
+            .known_inventory
+            .insert(item);
+        if blockchain.contains_block(&hash) || self.partial_blocks.contains_key(&hash) {
+            return;
+        }
+        // 父区块未知时请求完整区块，按孤块处理
+        let prev_hash = compact.header.prev_block_hash;
+        if !blockchain.contains_block(&prev_hash) {
+            return self.request(id, vec![item], now);
+        }
+        if let Err(e) = blockchain.check_header(&compact.header, prev_hash) {
+            return self.block_rejected(id, e, now);
+        }
+
+        let pool: Vec<Transaction> = blockchain
+            .transaction_pool
+            .lock()
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect();
+        match PartialBlock::reconstruct(&compact, &pool) {
+            Ok(partial) => {
+                let missing = partial.missing();
+                if missing.is_empty() {
+                    self.complete_compact_block(id, hash, partial, blockchain, now);
+                } else {
+                    self.partial_blocks.insert(hash, (id, now, partial));
+                    self.send(
+                        id,
+                        Message::GetBlockTxn {
+                            block_hash: hash,
+                            indexes: missing,
+                        },
+                    );
+                }
+            }
+            // 区块内短 id 碰撞无法还原，改为请求完整区块
+            Err(CompactError::DuplicateShortId) => self.request(id, vec![item], now),
+            Err(CompactError::BadIndex) => {
+                self.misbehaving(id, SCORE_INVALID, "invalid compact block", now)
+            }
+        }
+    }
+
+    // 交易齐全的紧凑区块按普通区块处理，Merkle Root 不符时改为请求完整区块
+    fn complete_compact_block(
+        &mut self,
+        id: PeerId,
+        hash: [u8; 32],
+        partial: PartialBlock,
+        blockchain: &mut BlockChain,
+        now: u64,
+    ) {
+        match partial.into_block() {
+            Some(block) => {
+                self.in_flight.remove(&Inventory::Block(hash));
+                self.handle_block(id, block, blockchain, now);
+            }
+            None => self.request(id, vec![Inventory::Block(hash)], now),
+        }
+    }
+
+    // 回复紧凑区块中对方缺失的交易
+    fn handle_getblocktxn(
+        &mut self,
+        id: PeerId,
+        block_hash: [u8; 32],
+        indexes: Vec<u32>,
+        blockchain: &BlockChain,
+        now: u64,
+    ) {
+        let Some(block) = blockchain.get_block(&block_hash) else {
+            return self.send(id, Message::NotFound(vec![Inventory::Block(block_hash)]));
+        };
+        if indexes.len() > block.transactions.len() {
+            return self.misbehaving(id, SCORE_OVERSIZED, "oversized getblocktxn", now);
+        }
+        let transactions: Option<Vec<Transaction>> = indexes
+            .iter()
+            .map(|index| block.transactions.get(*index as usize).cloned())
+            .collect();
+        match transactions {
+            Some(transactions) => self.send(
+                id,
+                Message::BlockTxn {
+                    block_hash,
+                    transactions,
+                },
+            ),
+            None => self.misbehaving(id, SCORE_INVALID, "invalid getblocktxn", now),
+        }
+    }
+
+    // 区块校验失败：时间戳超前可能只是时钟偏差，不计分
+    fn block_rejected(&mut self, id: PeerId, error: BlockError, now: u64) {
+        crate::log!(Warn, "Rejected block from peer {}: {}", id, error);
+        if error != BlockError::TimeTooNew {
+            self.misbehaving(id, SCORE_INVALID, "invalid block", now);
+        }
+    }
+
+    /// 链顶变化时通告新的链顶区块
+    ///
+    /// 要求紧凑区块的节点直接收到紧凑区块，其他节点收到 inv。
+    pub fn announce_tip(&mut self, blockchain: &BlockChain) {
+        let tip = blockchain.tip_hash();
+        if self.announced_tip == Some(tip) {
+            return;
+        }
+        self.announced_tip = Some(tip);
+        let item = Inventory::Block(tip);
+        let compact = blockchain
+            .get_block(&tip)
+            .map(|block| CompactBlock::new(block, self.rng.random()));
+        let mut targets = Vec::new();
+        for peer in self.peers.values_mut() {
+            if peer.is_ready() && peer.known_inventory.insert(item) {
+                targets.push((peer.id, peer.compact_blocks));
+            }
+        }
+        targets.sort();
+        for (id, wants_compact) in targets {
+            match &compact {
+                Some(compact) if wants_compact => {
+                    self.send(id, Message::CmpctBlock(compact.clone()))
+                }
+                _ => self.send(id, Message::Inv(vec![item])),
+            }
+        }
+    }
+
+    fn handle_version(
+        &mut self,
+        id: PeerId,
+        version: VersionMessage,
+        blockchain: &BlockChain,
+        now: u64,
+    ) {
+        let peer = &self.peers[&id];
+        if peer.version.is_some() {
+            return self.disconnect(id, "duplicate version");
+        }
+        if version.nonce == self.local_nonce {
+            return self.disconnect(id, "connected to self");
+        }
+        if version.version < MIN_PROTOCOL_VERSION {
+            return self.disconnect(id, "obsolete protocol version");
+        }
+        if version.genesis_hash != blockchain.genesis_hash() {
+            return self.disconnect(id, "genesis hash mismatch");
+        }
+
+        let reply_version = !peer.version_sent;
+        let our_version = self.version_message(blockchain, now);
+        let peer = self.peers.get_mut(&id).unwrap();
+        peer.best_height = version.best_height;
+        if peer.direction == Direction::Inbound && version.listen_port != 0 {
+            peer.listen_addr = peer
+                .addr
+                .parse::<SocketAddr>()
+                .ok()
+                .map(|addr| SocketAddr::new(addr.ip(), version.listen_port));
+        }
+        peer.version = Some(version);
+        let ready = peer.verack_received;
+        peer.state = if ready {
+            PeerState::Ready
+        } else {
+            PeerState::VersionReceived
+        };
+        if reply_version {
+            peer.version_sent = true;
+            self.send(id, our_version);
+        }
+        self.send(id, Message::Verack);
+        if ready {
+            self.handshake_completed(id, now);
+        }
+    }
+
+    // 握手完成：要求紧凑区块通告，出站连接记为成功并请求地址，入站节点通告的监听地址加入地址簿
+    fn handshake_completed(&mut self, id: PeerId, now: u64) {
+        self.send(id, Message::SendCmpct(true));
+        let peer = &self.peers[&id];
+        let Some(listen_addr) = peer.listen_addr else {
+            return;
+        };
+        if peer.direction == Direction::Outbound {
+            self.addresses.add(listen_addr, None, now);
+            self.addresses.mark_good(&listen_addr, now);
+            self.send(id, Message::GetAddr);
+        } else {
+            let address = NetAddress {
+                addr: listen_addr,
+                last_seen: now,
+            };
+            self.handle_addr(id, vec![address], now);
+        }
+    }
+
+    // 记录收到的地址，少量的新地址继续随机转发给其他节点
+    fn handle_addr(&mut self, id: PeerId, addresses: Vec<NetAddress>, now: u64) {
+        if addresses.len() > MAX_ADDR_SIZE {
+            return self.misbehaving(id, SCORE_OVERSIZED, "oversized addr", now);
+        }
+        let source = self.peers[&id]
+            .addr
+            .parse::<SocketAddr>()
+            .ok()
+            .map(|a| a.ip());
+        let relay = addresses.len() <= MAX_ADDR_RELAY;
+        let mut fresh = Vec::new();
+        for address in addresses {
+            // 不接受来自未来的时间
+            let last_seen = address.last_seen.min(now);
+            if self.addresses.add(address.addr, source, last_seen) {
+                fresh.push(NetAddress {
+                    addr: address.addr,
+                    last_seen,
+                });
+            }
+        }
+        if !relay || fresh.is_empty() {
+            return;
+        }
+        let mut targets: Vec<PeerId> = self
+            .ready_peers()
+            .into_iter()
+            .filter(|peer| *peer != id)
+            .collect();
+        targets.shuffle(&mut self.rng);
+        for target in targets.into_iter().take(ADDR_RELAY_FANOUT) {
+            self.send(target, Message::Addr(fresh.clone()));
+        }
+    }
+
+    // 出站连接不足时从地址簿选择新地址，避开已有出站连接的网段
+    fn maintain_outbound(&mut self, now: u64) {
+        let timeout = self.config.handshake_timeout_ms;
+        self.connecting
+            .retain(|_, started| now.saturating_sub(*started) < timeout);
+
+        let mut exclude: HashSet<SocketAddr> = self.connecting.keys().copied().collect();
+        let mut groups = HashSet::new();
+        let mut outbound = self.connecting.len();
+        for peer in self.peers.values() {
+            exclude.extend(peer.listen_addr);
+            if peer.direction == Direction::Outbound {
+                outbound += 1;
+                if let Some(addr) = peer.listen_addr {
+                    groups.insert(address_group(&addr.ip()));
+                }
+            }
+        }
+        for addr in self.connecting.keys() {
+            groups.insert(address_group(&addr.ip()));
+        }
+        if let Some(addr) = self.config.listen_addr {
+            exclude.insert(addr);
+        }
+
+        while outbound < self.config.max_outbound {
+            let Some(addr) = self.addresses.select(&mut self.rng, &exclude, &groups, now) else {
+                break;
+            };
+            if self.bans.is_banned(&addr.ip(), now) {
+                exclude.insert(addr);
+                continue;
+            }
+            self.addresses.mark_attempt(&addr, now);
+            self.connecting.insert(addr, now);
+            exclude.insert(addr);
+            groups.insert(address_group(&addr.ip()));
+            outbound += 1;
+            self.actions.push(Action::Connect(addr));
+        }
+    }
+
+    // 交易校验失败：已知的交易不计分，其余按无效交易计分
+    fn transaction_rejected(&mut self, id: PeerId, error: TransactionError, now: u64) {
+        if error != TransactionError::AlreadyKnown {
+            crate::log!(Warn, "Rejected transaction from peer {}: {}", id, error);
+            self.misbehaving(id, SCORE_INVALID, "invalid transaction", now);
+        }
+    }
+
+    // 交易引用的、既不在交易池也不在主链上的父交易
+    fn missing_parents(tx: &Transaction, blockchain: &BlockChain) -> Vec<[u8; 32]> {
+        let mut missing = Vec::new();
+        for input in &tx.inputs {
+            let parent = input.previous_output;
+            if parent == [0; 32] || missing.contains(&parent) {
+                continue;
+            }
+            if Self::pooled_transaction(&parent, blockchain).is_none()
+                && !blockchain.contains_transaction(&parent)
+            {
+                missing.push(parent);
+            }
+        }
+        missing
+    }
+
+    // 通过交易池校验的交易进入交易池，父交易未知时放入孤立交易池并向对方请求父交易
+    fn handle_transaction(
+        &mut self,
+        id: PeerId,
+        tx: Transaction,
+        blockchain: &mut BlockChain,
+        now: u64,
+    ) {
+        let hash = tx.hash();
+        if self.orphan_txs.contains(&hash) {
+            return;
+        }
+        match blockchain.accept_transaction(tx.clone()) {
+            Ok(_) => {
+                self.announce_transaction(hash);
+                self.process_orphans(vec![hash], blockchain, now);
+                return;
+            }
+            Err(TransactionError::MissingParent) => {}
+            Err(e) => return self.transaction_rejected(id, e, now),
+        }
+        let missing = Self::missing_parents(&tx, blockchain);
+        let wanted = missing
+            .iter()
+            .filter(|parent| !self.orphan_txs.contains(parent))
+            .map(|parent| Inventory::Tx(*parent))
+            .collect();
+        self.orphan_txs.add(&mut self.rng, tx, missing, id, now);
+        self.request(id, wanted, now);
+    }
+
+    // 新交易进入交易池或上链后，父交易已齐全的孤立交易进入交易池
+    fn process_orphans(&mut self, parents: Vec<[u8; 32]>, blockchain: &mut BlockChain, now: u64) {
+        let mut queue = parents;
+        while let Some(parent) = queue.pop() {
+            for child in self.orphan_txs.children(&parent) {
+                let ready = self
+                    .orphan_txs
+                    .get(&child)
+                    .is_some_and(|tx| Self::missing_parents(tx, blockchain).is_empty());
+                if !ready {
+                    continue;
+                }
+                let (tx, from) = self.orphan_txs.remove(&child).unwrap();
+                match blockchain.accept_transaction(tx) {
+                    Ok(_) => {
+                        self.announce_transaction(child);
+                        queue.push(child);
+                    }
+                    Err(e) => self.transaction_rejected(from, e, now),
+                }
+            }
+        }
+    }
+
+    // 把交易加入各已握手节点的通告队列，在下一批中随机排序后通告
+    pub fn announce_transaction(&mut self, hash: [u8; 32]) {
+        for peer in self.peers.values_mut() {
+            if peer.is_ready() {
+                peer.tx_queue.push(hash);
+            }
+        }
+    }
+
+    // 泊松分布的批量通告间隔，使通告时间无法用来推断交易的来源
+    fn next_relay_delay(&mut self, direction: Direction) -> u64 {
+        let mean = match direction {
+            Direction::Inbound => self.config.tx_relay_interval_ms,
+            Direction::Outbound => self.config.tx_relay_interval_ms / 2,
+        };
+        let uniform: f64 = self.rng.random();
+        (-(1.0 - uniform).ln() * mean as f64) as u64
+    }
+
+    // 到达通告时间的节点收到一批随机排序的交易通告
+    fn relay_transactions(&mut self, blockchain: &BlockChain, now: u64) {
+        let mut due: Vec<PeerId> = self
+            .peers
+            .values()
+            .filter(|peer| peer.is_ready() && now >= peer.next_tx_relay)
+            .map(|peer| peer.id)
+            .collect();
+        due.sort();
+        for id in due {
+            let direction = self.peers[&id].direction;
+            let delay = self.next_relay_delay(direction);
+            let peer = self.peers.get_mut(&id).unwrap();
+            peer.next_tx_relay = now + delay;
+            let mut queue = std::mem::take(&mut peer.tx_queue);
+            queue.shuffle(&mut self.rng);
+            let mut items = Vec::new();
+            for hash in queue {
+                let item = Inventory::Tx(hash);
+                if items.len() >= MAX_TX_PER_RELAY {
+                    peer.tx_queue.push(hash);
+                } else if Self::has_inventory(&item, blockchain)
+                    && peer.known_inventory.insert(item)
+                {
+                    items.push(item);
+                }
+            }
+            if !items.is_empty() {
+                self.send(id, Message::Inv(items));
+            }
+        }
+    }
+
+    // 定时器：通告新的链顶和交易，推进区块同步，发送 ping，断开超时的连接
+    pub fn on_tick(&mut self, blockchain: &BlockChain, now: u64) {
+        self.bans.sweep(now);
+        self.announce_tip(blockchain);
+        for (id, reason) in self.sync.timed_out_peers(now) {
+            self.disconnect(id, reason);
+        }
+        self.sync_blocks(blockchain, now);
+        self.maintain_outbound(now);
+        self.in_flight
+            .retain(|_, (_, at)| now.saturating_sub(*at) < REQUEST_TIMEOUT_MS);
+        self.partial_blocks
+            .retain(|_, (_, at, _)| now.saturating_sub(*at) < REQUEST_TIMEOUT_MS);
+        self.orphan_txs.expire(now);
+        self.relay_transactions(blockchain, now);
+
+        let mut timed_out = Vec::new();
+        let mut to_ping = Vec::new();
+        for peer in self.peers.values() {
+            if !peer.is_ready() {
+                if now.saturating_sub(peer.connected_at) > self.config.handshake_timeout_ms {
+                    timed_out.push((peer.id, "handshake timeout"));
+                }
+                continue;
+            }
+            match peer.ping {
+                Some((_, sent_at)) => {
+                    if now.saturating_sub(sent_at) > self.config.ping_timeout_ms {
+                        timed_out.push((peer.id, "ping timeout"));
+                    }
+                }
+                None => {
+                    if now.saturating_sub(peer.last_ping) >= self.config.ping_interval_ms {
+                        to_ping.push(peer.id);
+                    }
+                }
+            }
+        }
+
+        for (id, reason) in timed_out {
+            self.disconnect(id, reason);
+        }
+        to_ping.sort();
+        for id in to_ping {
+            let nonce = self.rng.random();
+            let peer = self.peers.get_mut(&id).unwrap();
+            peer.ping = Some((nonce, now));
+            peer.last_ping = now;
+            self.send(id, Message::Ping(nonce));
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use super::compact::CompactBlock;
+use crate::block_chain::{Block, BlockHeader};
+use crate::hash_function::sha256_hash;
+use crate::serialization::{deserialize_bc, serialize_bc};
+use crate::transaction::Transaction;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::net::SocketAddr;
+
+// 帧头：魔数(4) + 命令(12) + 负载长度(4) + 校验和(4)
+pub const HEADER_SIZE: usize = 24;
+const COMMAND_SIZE: usize = 12;
+// 单条消息负载的最大字节数
+pub const MAX_PAYLOAD_SIZE: usize = 4 * 1024 * 1024;
+
+/// 握手时交换的版本信息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VersionMessage {
+    pub version: u32,           // 协议版本
+    pub services: u64,          // 节点提供的服务
+    pub timestamp: u64,         // 发送时间（毫秒）
+    pub nonce: u64,             // 随机数，用于检测连接到自己
+    pub user_agent: String,     // 客户端标识
+    pub genesis_hash: [u8; 32], // 创世区块哈希，不同链的节点拒绝握手
+    pub best_height: u32,       // 当前链顶高度
+    pub listen_port: u16,       // 节点监听的 P2P 端口，0 表示不接受入站连接
+}
+
+// 单条 inv/getdata 消息最多包含的条目数
+pub const MAX_INV_SIZE: usize = 50_000;
+
+// 单条 headers 消息最多包含的区块头数
+pub const MAX_HEADERS: usize = 2_000;
+// 区块定位器最多包含的哈希数
+pub const MAX_LOCATOR_SIZE: usize = 101;
+
+// 单条 addr 消息最多包含的地址数
+pub const MAX_ADDR_SIZE: usize = 1_000;
+
+/// addr 消息中的节点地址
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetAddress {
+    pub addr: SocketAddr,
+    pub last_seen: u64, // 最近一次听说该地址的时间（毫秒）
+}
+
+/// 库存条目：用哈希标识的交易或区块
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Inventory {
+    Tx([u8; 32]),
+    Block([u8; 32]),
+}
+
+/// 节点之间传递的消息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Message {
+    Version(VersionMessage),
+    Verack,
+    Ping(u64),
+    Pong(u64),
+    Inv(Vec<Inventory>),      // 通告新的交易或区块
+    GetData(Vec<Inventory>),  // 请求完整的交易或区块
+    NotFound(Vec<Inventory>), // 请求的数据不存在
+    Tx(Transaction),
+    Block(Block),
+    // 请求定位器中第一个共同区块之后的区块头，直到 stop（全零表示不限）
+    GetHeaders {
+        locator: Vec<[u8; 32]>,
+        stop: [u8; 32],
+    },
+    Headers(Vec<BlockHeader>),
+    GetAddr,               // 请求对方地址簿中的地址
+    Addr(Vec<NetAddress>), // 通告节点地址
+    SendCmpct(bool),       // 请求对方直接用紧凑区块通告新区块
+    CmpctBlock(CompactBlock),
+    // 请求紧凑区块中缺失的交易，indexes 为交易在区块中的位置
+    GetBlockTxn {
+        block_hash: [u8; 32],
+        indexes: Vec<u32>,
+    },
+    // getblocktxn 的回复，交易按请求的顺序排列
+    BlockTxn {
+        block_hash: [u8; 32],
+        transactions: Vec<Transaction>,
+    },
+}
+
+impl Message {
+    // 帧头中的命令名
+    pub fn command(&self) -> &'static str {
+        match self {
+            Message::Version(_) => "version",
+            Message::Verack => "verack",
+            Message::Ping(_) => "ping",
+            Message::Pong(_) => "pong",
+            Message::Inv(_) => "inv",
+            Message::GetData(_) => "getdata",
+            Message::NotFound(_) => "notfound",
+            Message::Tx(_) => "tx",
+            Message::Block(_) => "block",
+            Message::GetHeaders { .. } => "getheaders",
+            Message::Headers(_) => "headers",
+            Message::GetAddr => "getaddr",
+            Message::Addr(_) => "addr",
+            Message::SendCmpct(_) => "sendcmpct",
+            Message::CmpctBlock(_) => "cmpctblock",
+            Message::GetBlockTxn { .. } => "getblocktxn",
+            Message::BlockTxn { .. } => "blocktxn",
+        }
+    }
+}
+
+/// 解析消息帧失败的原因
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameError {
+    BadMagic,
+    BadCommand,
+    PayloadTooLarge(usize),
+    BadChecksum,
+    Malformed(String),
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FrameError::BadMagic => f.write_str("network magic mismatch"),
+            FrameError::BadCommand => f.write_str("command does not match payload"),
+            FrameError::PayloadTooLarge(len) => write!(f, "payload too large: {} bytes", len),
+            FrameError::BadChecksum => f.write_str("payload checksum mismatch"),
+            FrameError::Malformed(e) => write!(f, "malformed payload: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+impl FrameError {
+    // 帧长度可信、可以跳过这一帧继续解析后续数据的错误
+    pub fn is_recoverable(&self) -> bool {
+        matches!(
+            self,
+            FrameError::BadCommand | FrameError::BadChecksum | FrameError::Malformed(_)
+        )
+    }
+}
+
+// 缓冲区中第一帧的总长度，帧头不完整时返回 None
+pub fn frame_length(buffer: &[u8]) -> Option<usize> {
+    let length = buffer.get(16..20)?;
+    Some(HEADER_SIZE + u32::from_le_bytes(length.try_into().unwrap()) as usize)
+}
+
+// 负载两次 SHA-256 后的前 4 个字节
+fn checksum(payload: &[u8]) -> [u8; 4] {
+    let first = sha256_hash(payload);
+    let second = sha256_hash(first.as_ref());
+    second.as_ref()[..4].try_into().unwrap()
+}
+
+/// 将消息编码为带魔数和校验和的帧
+pub fn encode_frame(magic: [u8; 4], message: &Message) -> Vec<u8> {
+    let payload = serialize_bc(message).unwrap();
+    let mut command = [0u8; COMMAND_SIZE];
+    let name = message.command().as_bytes();
+    command[..name.len()].copy_from_slice(name);
+
+    let mut frame = Vec::with_capacity(HEADER_SIZE + payload.len());
+    frame.extend_from_slice(&magic);
+    frame.extend_from_slice(&command);
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    frame.extend_from_slice(&checksum(&payload));
+    frame.extend_from_slice(&payload);
+    frame
+}
+
+/// 从缓冲区解析一帧，数据不足时返回 `Ok(None)`，成功时返回消息和消耗的字节数
+pub fn decode_frame(magic: [u8; 4], buffer: &[u8]) -> Result<Option<(Message, usize)>, FrameError> {
+    if buffer.len() < HEADER_SIZE {
+        return Ok(None);
+    }
+    if buffer[..4] != magic {
+        return Err(FrameError::BadMagic);
+    }
+    let length = u32::from_le_bytes(buffer[16..20].try_into().unwrap()) as usize;
+    if length > MAX_PAYLOAD_SIZE {
+        return Err(FrameError::PayloadTooLarge(length));
+    }
+    if buffer.len() < HEADER_SIZE + length {
+        return Ok(None);
+    }
+
+    let payload = &buffer[HEADER_SIZE..HEADER_SIZE + length];
+    if buffer[20..24] != checksum(payload) {
+        return Err(FrameError::BadChecksum);
+    }
+    let message: Message =
+        deserialize_bc(payload).map_err(|e| FrameError::Malformed(e.to_string()))?;
+
+    let command = &buffer[4..4 + COMMAND_SIZE];
+    let name = message.command().as_bytes();
+    if &command[..name.len()] != name || command[name.len()..].iter().any(|b| *b != 0) {
+        return Err(FrameError::BadCommand);
+    }
+    Ok(Some((message, HEADER_SIZE + length)))
+}
@@ -0,0 +1,97 @@
+pub mod addrman;
+pub mod banlist;
+pub mod compact;
+pub mod manager;
+pub mod message;
+pub mod orphan;
+pub mod peer;
+pub mod sim;
+pub mod sync;
+pub mod tcp;
+pub mod transport;
+
+use message::Message;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+// 当前协议版本和可接受的最低版本
+pub const PROTOCOL_VERSION: u32 = 1;
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+// 不当行为分数达到该值时封禁节点
+pub const BAN_THRESHOLD: u32 = 100;
+// 默认网络魔数
+pub const DEFAULT_MAGIC: [u8; 4] = [0xb1, 0x0c, 0xc4, 0x01];
+
+pub type PeerId = u64;
+
+/// P2P 网络配置
+#[derive(Debug, Clone)]
+pub struct NetworkConfig {
+    pub magic: [u8; 4],                  // 网络魔数，不同网络的节点无法通信
+    pub listen_addr: Option<SocketAddr>, // P2P 监听地址，None 表示不接受入站连接
+    pub peers: Vec<SocketAddr>,          // 启动时主动连接的节点
+    pub user_agent: String,
+    pub ping_interval_ms: u64,              // 发送 ping 的间隔
+    pub ping_timeout_ms: u64,               // 等待 pong 的超时时间
+    pub handshake_timeout_ms: u64,          // 完成握手的超时时间
+    pub max_outbound: usize,                // 从地址簿自动建立的出站连接数
+    pub seeds: Vec<String>,                 // 种子节点（host:port），启动时解析后加入地址簿
+    pub address_book_path: Option<PathBuf>, // 地址簿文件，None 表示不持久化
+    pub ban_duration_ms: u64,               // 自动封禁的时长
+    pub ban_list_path: Option<PathBuf>,     // 封禁列表文件，None 表示不持久化
+    pub tx_relay_interval_ms: u64,          // 向入站节点批量通告交易的平均间隔，出站节点减半
+    pub encryption: bool,                   // 是否加密并认证连接，所有节点需一致
+    pub identity_path: Option<PathBuf>,     // 身份密钥文件，None 表示每次启动生成新身份
+    pub trusted_identities: Vec<[u8; 32]>,  // 只接受这些身份的节点，为空表示不限制
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        NetworkConfig {
+            magic: DEFAULT_MAGIC,
+            listen_addr: None,
+            peers: Vec::new(),
+            user_agent: format!("/block_chain:{}/", env!("CARGO_PKG_VERSION")),
+            ping_interval_ms: 30_000,
+            ping_timeout_ms: 20_000,
+            handshake_timeout_ms: 10_000,
+            max_outbound: 8,
+            seeds: Vec::new(),
+            address_book_path: None,
+            ban_duration_ms: 24 * 60 * 60 * 1000,
+            ban_list_path: None,
+            tx_relay_interval_ms: 5_000,
+            encryption: false,
+            identity_path: None,
+            trusted_identities: Vec::new(),
+        }
+    }
+}
+
+/// 读取种子节点列表文件：每行一个 host:port，`#` 之后为注释
+pub fn read_seeds(path: &Path) -> io::Result<Vec<String>> {
+    let content = fs::read_to_string(path)?;
+    Ok(content
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// 连接方向
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, schemars::JsonSchema)]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+/// 状态机产生的需要由传输层执行的动作
+#[derive(Debug, Clone)]
+pub enum Action {
+    Send(PeerId, Message),
+    Disconnect(PeerId, String),
+    Connect(SocketAddr), // 建立出站连接
+}
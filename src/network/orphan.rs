@@ -0,0 +1,266 @@
+use super::PeerId;
+use crate::block_chain::{check_transaction_stateless, Block};
+use crate::hash_function::hash_block_header;
+use crate::serialization::serialize_bc;
+use crate::transaction::Transaction;
+use rand::Rng;
+use std::collections::{HashMap, HashSet};
+
+// 孤立交易池最多保存的交易数
+pub const MAX_ORPHAN_TRANSACTIONS: usize = 100;
+// 孤立交易的最长保存时间
+pub const ORPHAN_TX_EXPIRY_MS: u64 = 20 * 60 * 1000;
+// 孤块缓存最多保存的区块数
+pub const MAX_ORPHAN_BLOCKS: usize = 100;
+// 孤块缓存中区块序列化后的总字节数上限
+pub const MAX_ORPHAN_BLOCK_BYTES: usize = 32 * 1024 * 1024;
+// 单个节点发来的孤块最多占用的字节数
+pub const MAX_ORPHAN_BLOCK_BYTES_PER_PEER: usize = 8 * 1024 * 1024;
+
+#[derive(Debug, Clone)]
+struct OrphanTx {
+    tx: Transaction,
+    from: PeerId,  // 发来该交易的节点
+    received: u64, // 收到的时间（毫秒）
+    parents: Vec<[u8; 32]>,
+}
+
+/// 父交易未知的交易
+///
+/// 容量有限，满时随机淘汰；超时或发送节点断开时移除。
+/// 父交易到达后由调用方取出子交易重新处理。
+#[derive(Debug, Clone, Default)]
+pub struct OrphanPool {
+    orphans: HashMap<[u8; 32], OrphanTx>,
+    by_parent: HashMap<[u8; 32], HashSet<[u8; 32]>>, // 父交易 -> 等待它的孤立交易
+}
+
+impl OrphanPool {
+    pub fn new() -> Self {
+        OrphanPool::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.orphans.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.orphans.is_empty()
+    }
+
+    pub fn contains(&self, hash: &[u8; 32]) -> bool {
+        self.orphans.contains_key(hash)
+    }
+
+    pub fn get(&self, hash: &[u8; 32]) -> Option<&Transaction> {
+        self.orphans.get(hash).map(|orphan| &orphan.tx)
+    }
+
+    /// 加入孤立交易，`parents` 为缺失的父交易
+    ///
+    /// 已存在、超过长度上限或未通过无需父交易的校验（如签名）时返回 false。
+    pub fn add<R: Rng>(
+        &mut self,
+        rng: &mut R,
+        tx: Transaction,
+        parents: Vec<[u8; 32]>,
+        from: PeerId,
+        now: u64,
+    ) -> bool {
+        let hash = tx.hash();
+        if self.orphans.contains_key(&hash) || check_transaction_stateless(&tx).is_err() {
+            return false;
+        }
+        while self.orphans.len() >= MAX_ORPHAN_TRANSACTIONS {
+            let mut hashes: Vec<[u8; 32]> = self.orphans.keys().copied().collect();
+            hashes.sort();
+            let evicted = hashes[rng.random_range(0..hashes.len())];
+            self.remove(&evicted);
+        }
+        for parent in &parents {
+            self.by_parent.entry(*parent).or_default().insert(hash);
+        }
+        self.orphans.insert(
+            hash,
+            OrphanTx {
+                tx,
+                from,
+                received: now,
+                parents,
+            },
+        );
+        true
+    }
+
+    // 移除孤立交易，返回交易和发来它的节点
+    pub fn remove(&mut self, hash: &[u8; 32]) -> Option<(Transaction, PeerId)> {
+        let orphan = self.orphans.remove(hash)?;
+        for parent in &orphan.parents {
+            if let Some(children) = self.by_parent.get_mut(parent) {
+                children.remove(hash);
+                if children.is_empty() {
+                    self.by_parent.remove(parent);
+                }
+            }
+        }
+        Some((orphan.tx, orphan.from))
+    }
+
+    // 等待该父交易的孤立交易，按哈希排序
+    pub fn children(&self, parent: &[u8; 32]) -> Vec<[u8; 32]> {
+        let mut children: Vec<[u8; 32]> = self
+            .by_parent
+            .get(parent)
+            .map(|children| children.iter().copied().collect())
+            .unwrap_or_default();
+        children.sort();
+        children
+    }
+
+    // 移除超时的孤立交易，返回移除的数量
+    pub fn expire(&mut self, now: u64) -> usize {
+        let expired: Vec<[u8; 32]> = self
+            .orphans
+            .iter()
+            .filter(|(_, orphan)| now.saturating_sub(orphan.received) >= ORPHAN_TX_EXPIRY_MS)
+            .map(|(hash, _)| *hash)
+            .collect();
+        for hash in &expired {
+            self.remove(hash);
+        }
+        expired.len()
+    }
+
+    // 移除某个节点发来的所有孤立交易
+    pub fn remove_peer(&mut self, peer: PeerId) {
+        let hashes: Vec<[u8; 32]> = self
+            .orphans
+            .iter()
+            .filter(|(_, orphan)| orphan.from == peer)
+            .map(|(hash, _)| *hash)
+            .collect();
+        for hash in &hashes {
+            self.remove(hash);
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct OrphanBlock {
+    block: Block,
+    from: PeerId,  // 发来该区块的节点
+    received: u64, // 收到的时间（毫秒）
+    size: usize,   // 序列化后的字节数
+}
+
+/// 父区块未知的区块
+///
+/// 按区块数和总字节数限制容量，满时随机淘汰；
+/// 单个节点的孤块超过字节上限时淘汰该节点最早发来的孤块。
+#[derive(Debug, Clone, Default)]
+pub struct OrphanBlocks {
+    blocks: HashMap<[u8; 32], OrphanBlock>,
+    by_parent: HashMap<[u8; 32], HashSet<[u8; 32]>>, // 父区块 -> 等待它的孤块
+    bytes: usize,
+    peer_bytes: HashMap<PeerId, usize>,
+}
+
+impl OrphanBlocks {
+    pub fn new() -> Self {
+        OrphanBlocks::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    // 缓存的孤块序列化后的总字节数
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    // 某个节点发来的孤块占用的字节数
+    pub fn peer_bytes(&self, peer: PeerId) -> usize {
+        self.peer_bytes.get(&peer).copied().unwrap_or(0)
+    }
+
+    pub fn contains(&self, hash: &[u8; 32]) -> bool {
+        self.blocks.contains_key(hash)
+    }
+
+    /// 加入孤块；已存在或单个区块就超过节点的字节上限时返回 false
+    pub fn add<R: Rng>(&mut self, rng: &mut R, block: Block, from: PeerId, now: u64) -> bool {
+        let hash = hash_block_header(&block.header);
+        let size = serialize_bc(&block).map_or(usize::MAX, |data| data.len());
+        if self.blocks.contains_key(&hash) || size > MAX_ORPHAN_BLOCK_BYTES_PER_PEER {
+            return false;
+        }
+        while self.peer_bytes(from) + size > MAX_ORPHAN_BLOCK_BYTES_PER_PEER {
+            let oldest = self
+                .blocks
+                .iter()
+                .filter(|(_, orphan)| orphan.from == from)
+                .min_by_key(|(hash, orphan)| (orphan.received, **hash))
+                .map(|(hash, _)| *hash)
+                .unwrap();
+            self.remove(&oldest);
+        }
+        while self.blocks.len() >= MAX_ORPHAN_BLOCKS || self.bytes + size > MAX_ORPHAN_BLOCK_BYTES {
+            let mut hashes: Vec<[u8; 32]> = self.blocks.keys().copied().collect();
+            hashes.sort();
+            let evicted = hashes[rng.random_range(0..hashes.len())];
+            self.remove(&evicted);
+        }
+        self.by_parent
+            .entry(block.header.prev_block_hash)
+            .or_default()
+            .insert(hash);
+        self.bytes += size;
+        *self.peer_bytes.entry(from).or_default() += size;
+        self.blocks.insert(
+            hash,
+            OrphanBlock {
+                block,
+                from,
+                received: now,
+                size,
+            },
+        );
+        true
+    }
+
+    // 移除孤块
+    pub fn remove(&mut self, hash: &[u8; 32]) -> Option<Block> {
+        let orphan = self.blocks.remove(hash)?;
+        let parent = orphan.block.header.prev_block_hash;
+        if let Some(children) = self.by_parent.get_mut(&parent) {
+            children.remove(hash);
+            if children.is_empty() {
+                self.by_parent.remove(&parent);
+            }
+        }
+        self.bytes -= orphan.size;
+        if let Some(bytes) = self.peer_bytes.get_mut(&orphan.from) {
+            *bytes -= orphan.size;
+            if *bytes == 0 {
+                self.peer_bytes.remove(&orphan.from);
+            }
+        }
+        Some(orphan.block)
+    }
+
+    // 等待该父区块的孤块，按哈希排序
+    pub fn children(&self, parent: &[u8; 32]) -> Vec<[u8; 32]> {
+        let mut children: Vec<[u8; 32]> = self
+            .by_parent
+            .get(parent)
+            .map(|children| children.iter().copied().collect())
+            .unwrap_or_default();
+        children.sort();
+        children
+    }
+}
@@ -0,0 +1,132 @@
+use super::message::{Inventory, VersionMessage};
+use super::{Direction, PeerId};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::{HashSet, VecDeque};
+use std::net::SocketAddr;
+
+// 每个连接最多记录的已知库存条目数
+const MAX_KNOWN_INVENTORY: usize = 50_000;
+
+/// 对方已经拥有的库存条目，超过上限时淘汰最早的条目
+#[derive(Debug, Clone, Default)]
+pub struct KnownInventory {
+    items: HashSet<Inventory>,
+    order: VecDeque<Inventory>,
+}
+
+impl KnownInventory {
+    // 记录条目，已存在时返回 false
+    pub fn insert(&mut self, item: Inventory) -> bool {
+        if !self.items.insert(item) {
+            return false;
+        }
+        self.order.push_back(item);
+        if self.order.len() > MAX_KNOWN_INVENTORY {
+            if let Some(oldest) = self.order.pop_front() {
+                self.items.remove(&oldest);
+            }
+        }
+        true
+    }
+
+    pub fn contains(&self, item: &Inventory) -> bool {
+        self.items.contains(item)
+    }
+}
+
+/// 连接的握手状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+pub enum PeerState {
+    Connected,       // 已建立连接，尚未收到对方的 version
+    VersionReceived, // 已收到 version，等待对方的 verack
+    Ready,           // 握手完成
+}
+
+/// 单个连接的状态
+#[derive(Debug, Clone)]
+pub struct Peer {
+    pub id: PeerId,
+    pub addr: String,
+    pub direction: Direction,
+    pub state: PeerState,
+    pub version: Option<VersionMessage>, // 对方的版本信息
+    pub version_sent: bool,              // 是否已发送我方的 version
+    pub verack_received: bool,
+    pub connected_at: u64,
+    pub last_recv: u64,
+    pub ping: Option<(u64, u64)>, // 未回复的 ping：(nonce, 发送时间)
+    pub last_ping: u64,
+    pub latency_ms: Option<u64>,
+    pub known_inventory: KnownInventory, // 对方已知的交易和区块
+    pub best_height: u32,                // 对方已知的最高区块高度
+    pub listen_addr: Option<SocketAddr>, // 对方接受入站连接的地址
+    pub getaddr_answered: bool,          // 是否已回复过对方的 getaddr
+    pub ban_score: u32,                  // 不当行为分数，达到阈值时封禁
+    pub compact_blocks: bool,            // 对方是否要求用紧凑区块通告新区块
+    pub tx_queue: Vec<[u8; 32]>,         // 等待下一批通告的交易
+    pub next_tx_relay: u64,              // 下一次批量通告交易的时间
+    pub identity: Option<[u8; 32]>,      // 加密连接中对方证明持有的身份公钥
+}
+
+impl Peer {
+    pub fn new(id: PeerId, addr: String, direction: Direction, now: u64) -> Self {
+        Peer {
+            id,
+            addr,
+            direction,
+            state: PeerState::Connected,
+            version: None,
+            version_sent: false,
+            verack_received: false,
+            connected_at: now,
+            last_recv: now,
+            ping: None,
+            last_ping: now,
+            latency_ms: None,
+            known_inventory: KnownInventory::default(),
+            best_height: 0,
+            listen_addr: None,
+            getaddr_answered: false,
+            ban_score: 0,
+            compact_blocks: false,
+            tx_queue: Vec::new(),
+            next_tx_relay: 0,
+            identity: None,
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.state == PeerState::Ready
+    }
+
+    pub fn info(&self) -> PeerInfo {
+        PeerInfo {
+            id: self.id,
+            addr: self.addr.clone(),
+            direction: self.direction,
+            state: self.state,
+            user_agent: self.version.as_ref().map(|v| v.user_agent.clone()),
+            protocol_version: self.version.as_ref().map(|v| v.version),
+            best_height: self.version.as_ref().map(|_| self.best_height),
+            latency_ms: self.latency_ms,
+            ban_score: self.ban_score,
+            identity: self.identity.map(hex::encode),
+        }
+    }
+}
+
+/// 对外展示的连接信息
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct PeerInfo {
+    pub id: PeerId,
+    pub addr: String,
+    pub direction: Direction,
+    pub state: PeerState,
+    pub user_agent: Option<String>,
+    pub protocol_version: Option<u32>,
+    pub best_height: Option<u32>,
+    pub latency_ms: Option<u64>,
+    pub ban_score: u32,
+    pub identity: Option<String>,
+}
@@ -0,0 +1,381 @@
+use super::manager::PeerManager;
+use super::message::{decode_frame, encode_frame};
+use super::{Action, Direction, NetworkConfig, PeerId};
+use crate::block_chain::{
+    BlockChain, BlockError, BlockStatus, TransactionError, GENESIS_TIMESTAMP,
+};
+use crate::miner::Miner;
+use crate::transaction::Transaction;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::atomic::AtomicBool;
+
+// 模拟网络中节点的 P2P 端口
+const SIM_PORT: u16 = 3031;
+// 默认的定时器间隔
+const DEFAULT_TICK_INTERVAL_MS: u64 = 1_000;
+
+/// 模拟网络中的一个节点
+pub struct SimNode {
+    pub manager: PeerManager,
+    pub chain: BlockChain,
+    pub addr: SocketAddr, // 节点的监听地址
+    next_peer_id: PeerId,
+    key_pair: Ed25519KeyPair, // 挖矿收款的密钥，公钥即 coinbase 的锁定脚本
+}
+
+impl SimNode {
+    // 节点的收款密钥，可用于签名花费该节点挖出的 coinbase
+    pub fn key_pair(&self) -> &Ed25519KeyPair {
+        &self.key_pair
+    }
+}
+
+// 等待送达的消息帧
+struct Delivery {
+    to: usize,
+    conn: PeerId, // 接收方上的连接编号
+    frame: Vec<u8>,
+}
+
+/// 单进程内的多节点网络模拟器
+///
+/// 每个节点是一个 `PeerManager` 加一条本地链，节点之间通过内存中的连接传递编码后的消息帧。
+/// 时间使用虚拟时钟，消息按 `[min, max]` 之间的随机延迟送达，同一连接上的消息保持顺序；
+/// 可以设置丢包率和网络分区，分区之间的消息和新连接都会失败。
+/// 所有随机性来自构造时传入的种子。
+pub struct Simulator {
+    nodes: Vec<SimNode>,
+    rng: StdRng,
+    now: u64,
+    seq: u64,
+    queue: BTreeMap<(u64, u64), Delivery>, // (送达时间, 序号) -> 消息
+    links: HashMap<(usize, PeerId), (usize, PeerId)>, // (节点, 连接) -> 对端的 (节点, 连接)
+    link_clock: HashMap<(usize, PeerId), u64>, // 连接上最后一条消息的送达时间
+    latency: (u64, u64),
+    drop_rate: f64,
+    partition: Option<Vec<usize>>, // 每个节点所在的分区
+    tick_interval_ms: u64,
+    next_tick: u64,
+    delivered: u64,
+    dropped: u64,
+}
+
+impl Simulator {
+    pub fn new(seed: u64) -> Self {
+        Simulator {
+            nodes: Vec::new(),
+            rng: StdRng::seed_from_u64(seed),
+            now: 0,
+            seq: 0,
+            queue: BTreeMap::new(),
+            links: HashMap::new(),
+            link_clock: HashMap::new(),
+            latency: (10, 100),
+            drop_rate: 0.0,
+            partition: None,
+            tick_interval_ms: DEFAULT_TICK_INTERVAL_MS,
+            next_tick: DEFAULT_TICK_INTERVAL_MS,
+            delivered: 0,
+            dropped: 0,
+        }
+    }
+
+    // 当前虚拟时间（毫秒）
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn node(&self, index: usize) -> &SimNode {
+        &self.nodes[index]
+    }
+
+    pub fn node_mut(&mut self, index: usize) -> &mut SimNode {
+        &mut self.nodes[index]
+    }
+
+    // 已送达和被丢弃的消息数
+    pub fn delivered(&self) -> u64 {
+        self.delivered
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    // 消息延迟的范围（毫秒）
+    pub fn set_latency(&mut self, min: u64, max: u64) {
+        self.latency = (min, max.max(min));
+    }
+
+    // 每条消息被丢弃的概率
+    pub fn set_drop_rate(&mut self, rate: f64) {
+        self.drop_rate = rate.clamp(0.0, 1.0);
+    }
+
+    pub fn set_tick_interval(&mut self, interval_ms: u64) {
+        self.tick_interval_ms = interval_ms.max(1);
+        self.next_tick = self.now + self.tick_interval_ms;
+    }
+
+    /// 把节点划分为若干分区，未列出的节点组成另一个分区
+    pub fn partition(&mut self, groups: &[&[usize]]) {
+        let mut partition = vec![groups.len(); self.nodes.len()];
+        for (group, members) in groups.iter().enumerate() {
+            for node in members.iter() {
+                partition[*node] = group;
+            }
+        }
+        self.partition = Some(partition);
+    }
+
+    // 取消分区
+    pub fn heal(&mut self) {
+        self.partition = None;
+    }
+
+    fn reachable(&self, a: usize, b: usize) -> bool {
+        self.partition
+            .as_ref()
+            .is_none_or(|partition| partition[a] == partition[b])
+    }
+
+    /// 加入一个使用默认配置的节点，返回节点编号
+    pub fn add_node(&mut self, difficulty: usize) -> usize {
+        self.add_node_with(NetworkConfig::default(), difficulty)
+    }
+
+    /// 加入一个节点，监听地址由模拟器分配
+    pub fn add_node_with(&mut self, config: NetworkConfig, difficulty: usize) -> usize {
+        let index = self.nodes.len();
+        let n = index + 1;
+        let ip = Ipv4Addr::new(10, (n >> 16) as u8, (n >> 8) as u8, n as u8);
+        let addr = SocketAddr::new(IpAddr::V4(ip), SIM_PORT);
+        let config = NetworkConfig {
+            listen_addr: Some(addr),
+            ..config
+        };
+        let manager = PeerManager::new(config, self.rng.random());
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(&self.rng.random::<[u8; 32]>()).unwrap();
+        self.nodes.push(SimNode {
+            manager,
+            chain: BlockChain::new(difficulty),
+            addr,
+            next_peer_id: 1,
+            key_pair,
+        });
+        if let Some(partition) = &mut self.partition {
+            partition.push(usize::MAX);
+        }
+        index
+    }
+
+    fn node_by_addr(&self, addr: &SocketAddr) -> Option<usize> {
+        self.nodes.iter().position(|node| node.addr == *addr)
+    }
+
+    /// 建立从 `from` 到 `to` 的出站连接，两端不可达时返回 false
+    pub fn connect(&mut self, from: usize, to: usize) -> bool {
+        if from == to || !self.reachable(from, to) {
+            return false;
+        }
+        let outbound = self.nodes[from].next_peer_id;
+        self.nodes[from].next_peer_id += 1;
+        let inbound = self.nodes[to].next_peer_id;
+        self.nodes[to].next_peer_id += 1;
+        self.links.insert((from, outbound), (to, inbound));
+        self.links.insert((to, inbound), (from, outbound));
+
+        let now = self.now;
+        // 入站一方看到的是对方的临时端口
+        let remote = SocketAddr::new(self.nodes[from].addr.ip(), 40_000 + inbound as u16);
+        let to_addr = self.nodes[to].addr.to_string();
+        let node = &mut self.nodes[to];
+        node.manager.on_connected(
+            inbound,
+            remote.to_string(),
+            Direction::Inbound,
+            &node.chain,
+            now,
+        );
+        let node = &mut self.nodes[from];
+        node.manager
+            .on_connected(outbound, to_addr, Direction::Outbound, &node.chain, now);
+        self.process_actions();
+        true
+    }
+
+    // 关闭连接，另一端收到断开通知
+    fn close(&mut self, node: usize, conn: PeerId) {
+        if let Some(remote) = self.links.remove(&(node, conn)) {
+            self.links.remove(&remote);
+            self.link_clock.remove(&(node, conn));
+            self.link_clock.remove(&remote);
+            self.nodes[remote.0].manager.on_disconnected(remote.1);
+        }
+    }
+
+    // 发送一条消息：分区之间或按丢包率丢弃，否则按延迟排队
+    fn send(&mut self, from: usize, conn: PeerId, frame: Vec<u8>) {
+        let Some(&(to, remote_conn)) = self.links.get(&(from, conn)) else {
+            return;
+        };
+        if !self.reachable(from, to) || self.rng.random_bool(self.drop_rate) {
+            self.dropped += 1;
+            return;
+        }
+        let delay = self.rng.random_range(self.latency.0..=self.latency.1);
+        // 同一连接上的消息按发送顺序送达
+        let last = self.link_clock.entry((from, conn)).or_insert(0);
+        let at = (self.now + delay).max(*last);
+        *last = at;
+        self.seq += 1;
+        self.queue.insert(
+            (at, self.seq),
+            Delivery {
+                to,
+                conn: remote_conn,
+                frame,
+            },
+        );
+    }
+
+    // 执行所有节点产生的动作，直到没有新的动作
+    fn process_actions(&mut self) {
+        loop {
+            let mut any = false;
+            for index in 0..self.nodes.len() {
+                let magic = self.nodes[index].manager.config().magic;
+                for action in self.nodes[index].manager.take_actions() {
+                    any = true;
+                    match action {
+                        Action::Send(conn, message) => {
+                            self.send(index, conn, encode_frame(magic, &message))
+                        }
+                        Action::Disconnect(conn, _) => self.close(index, conn),
+                        Action::Connect(addr) => {
+                            let connected = match self.node_by_addr(&addr) {
+                                Some(to) => self.connect(index, to),
+                                None => false,
+                            };
+                            if !connected {
+                                self.nodes[index].manager.on_connect_failed(addr);
+                            }
+                        }
+                    }
+                }
+            }
+            if !any {
+                return;
+            }
+        }
+    }
+
+    /// 推进虚拟时钟，按时间顺序送达消息并触发各节点的定时器
+    pub fn run_for(&mut self, duration_ms: u64) {
+        let end = self.now + duration_ms;
+        loop {
+            let next_delivery = self.queue.keys().next().map(|(at, _)| *at);
+            let next = match next_delivery {
+                Some(at) if at < self.next_tick => at,
+                _ => self.next_tick,
+            };
+            if next > end {
+                break;
+            }
+            self.now = next;
+            if next_delivery == Some(next) {
+                let (_, delivery) = self.queue.pop_first().unwrap();
+                self.deliver(delivery);
+            } else {
+                self.next_tick += self.tick_interval_ms;
+                for node in &mut self.nodes {
+                    node.manager.on_tick(&node.chain, next);
+                }
+            }
+            self.process_actions();
+        }
+        self.now = end;
+    }
+
+    /// 推进虚拟时钟直到条件成立，最多 `max_ms` 毫秒，返回条件是否成立
+    pub fn run_until<F: Fn(&Simulator) -> bool>(&mut self, condition: F, max_ms: u64) -> bool {
+        let end = self.now + max_ms;
+        while !condition(self) {
+            if self.now >= end {
+                return false;
+            }
+            self.run_for(self.tick_interval_ms.min(end - self.now));
+        }
+        true
+    }
+
+    fn deliver(&mut self, delivery: Delivery) {
+        let node = &mut self.nodes[delivery.to];
+        // 连接在消息途中被关闭
+        if !self.links.contains_key(&(delivery.to, delivery.conn)) {
+            return;
+        }
+        let magic = node.manager.config().magic;
+        match decode_frame(magic, &delivery.frame) {
+            Ok(Some((message, _))) => {
+                self.delivered += 1;
+                node.manager
+                    .on_message(delivery.conn, message, &mut node.chain, self.now);
+            }
+            Ok(None) => unreachable!("frames are delivered whole"),
+            Err(e) => node.manager.on_malformed(delivery.conn, &e, self.now),
+        }
+    }
+
+    /// 节点挖出一个包含其交易池中交易的区块并通告，返回处理结果
+    ///
+    /// 区块的时间戳取自虚拟时钟，nonce 取自模拟器的随机数生成器，相同种子挖出相同的区块。
+    pub fn mine(&mut self, index: usize) -> Result<BlockStatus, BlockError> {
+        let timestamp = GENESIS_TIMESTAMP.saturating_add((self.now / 1000) as u32);
+        let node = &mut self.nodes[index];
+        let coinbase_script = node.key_pair.public_key().as_ref();
+        let mut block =
+            node.chain
+                .block_template_at(Some(coinbase_script), timestamp, &mut self.rng);
+        let cancel = AtomicBool::new(false);
+        if let Some(header) = Miner::new(1).mine(&block.header, node.chain.difficulty(), &cancel) {
+            block.header = header;
+        }
+        let status = node.chain.process_block(block)?;
+        node.manager.announce_tip(&node.chain);
+        self.process_actions();
+        Ok(status)
+    }
+
+    /// 按交易池规则校验交易，通过后加入节点的交易池并通告
+    pub fn submit_transaction(
+        &mut self,
+        index: usize,
+        tx: Transaction,
+    ) -> Result<(), TransactionError> {
+        let node = &mut self.nodes[index];
+        let hash = node.chain.accept_transaction(tx)?;
+        node.manager.announce_transaction(hash);
+        Ok(())
+    }
+
+    // 所有节点的链顶是否一致
+    pub fn converged(&self) -> bool {
+        let tip = self.nodes.first().map(|node| node.chain.tip_hash());
+        self.nodes
+            .iter()
+            .all(|node| Some(node.chain.tip_hash()) == tip)
+    }
+}
@@ -0,0 +1,321 @@
+use super::message::{Inventory, MAX_HEADERS};
+use super::PeerId;
+use crate::block_chain::{Block, BlockChain, BlockError, BlockHeader};
+use crate::hash_function::hash_block_header;
+use std::collections::HashMap;
+
+// 等待 headers 回复的超时时间
+pub const HEADERS_TIMEOUT_MS: u64 = 20_000;
+// 单个区块下载的超时时间
+pub const BLOCK_DOWNLOAD_TIMEOUT_MS: u64 = 20_000;
+// 后续区块已下载而最早的区块仍未到达时，负责该区块的节点被视为停滞的时间
+pub const BLOCK_STALL_TIMEOUT_MS: u64 = 5_000;
+// 每个节点同时下载的最大区块数
+pub const MAX_BLOCKS_IN_FLIGHT_PER_PEER: usize = 16;
+// 下载窗口：只下载已连接位置之后这么多个区块，限制缓存的区块数
+pub const DOWNLOAD_WINDOW: usize = 1_024;
+// 已校验但尚未连接的区块头数上限，达到后等区块连接腾出空间再继续请求
+pub const MAX_PENDING_HEADERS: usize = 100_000;
+
+/// 处理 headers 消息后的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeadersOutcome {
+    Complete, // 对方没有更多区块头
+    More,     // 消息已满，对方可能还有更多区块头
+}
+
+/// 先同步区块头、再并行下载区块体的初始区块下载
+///
+/// 与 `PeerManager` 一样不做 IO：调用方根据返回值发送请求或断开连接。
+/// 已校验的区块头链从本地已有的锚点区块开始，区块体按高度顺序
+/// 通过 `BlockChain::process_block` 连接，与网络上收到的普通区块走同一条校验路径。
+#[derive(Debug, Default)]
+pub struct BlockSync {
+    base_height: u32,                             // 锚点区块的高度
+    hashes: Vec<[u8; 32]>,                        // 锚点之后已校验的区块头的哈希
+    positions: HashMap<[u8; 32], usize>,          // 区块头哈希在 hashes 中的位置
+    connected: usize,                             // 已经连接到本地链的区块头数
+    header_peer: Option<(PeerId, u64)>,           // 正在提供区块头的节点和请求时间
+    requested: HashMap<[u8; 32], (PeerId, u64)>,  // 下载中的区块：(节点, 请求时间)
+    received: HashMap<[u8; 32], (PeerId, Block)>, // 已下载但尚未连接的区块
+}
+
+impl BlockSync {
+    pub fn new() -> Self {
+        BlockSync::default()
+    }
+
+    // 已知的最高区块头高度
+    pub fn header_height(&self) -> u32 {
+        self.base_height + self.hashes.len() as u32
+    }
+
+    // 正在提供区块头的节点
+    pub fn header_peer(&self) -> Option<PeerId> {
+        self.header_peer.map(|(peer, _)| peer)
+    }
+
+    // 区块头和区块体都已同步完成
+    pub fn is_idle(&self) -> bool {
+        self.header_peer.is_none() && self.connected == self.hashes.len()
+    }
+
+    // 区块头链还能容纳更多未连接的区块头
+    pub fn wants_headers(&self) -> bool {
+        self.hashes.len() - self.connected < MAX_PENDING_HEADERS
+    }
+
+    // 请求区块头用的定位器：已保存的区块头链顶在前，之后是本地链的定位器
+    pub fn locator(&self, blockchain: &BlockChain) -> Vec<[u8; 32]> {
+        let mut locator: Vec<[u8; 32]> = self.hashes.last().copied().into_iter().collect();
+        locator.extend(blockchain.block_locator());
+        locator
+    }
+
+    // 区块头链上某个区块的高度
+    pub fn height_of(&self, hash: &[u8; 32]) -> Option<u32> {
+        self.positions
+            .get(hash)
+            .map(|index| self.base_height + *index as u32 + 1)
+    }
+
+    // 向节点请求区块头前调用
+    pub fn headers_requested(&mut self, peer: PeerId, now: u64) {
+        self.header_peer = Some((peer, now));
+    }
+
+    /// 校验并保存节点发来的区块头
+    ///
+    /// 已知的区块头会被跳过；第一个未知区块头必须接在本地链或已保存的区块头链上，
+    /// 之后逐个检查链接关系和工作量。新的分支只有比当前区块头链更长时才会替换它。
+    /// 未连接的区块头最多保存 `MAX_PENDING_HEADERS` 个，超出的部分留到之后再请求。
+    pub fn on_headers(
+        &mut self,
+        peer: PeerId,
+        headers: Vec<BlockHeader>,
+        blockchain: &BlockChain,
+    ) -> Result<HeadersOutcome, BlockError> {
+        let full = headers.len() >= MAX_HEADERS;
+        if self.header_peer() == Some(peer) {
+            self.header_peer = None;
+        }
+
+        let hashes: Vec<[u8; 32]> = headers.iter().map(hash_block_header).collect();
+        let skip = hashes
+            .iter()
+            .take_while(|hash| {
+                blockchain.contains_block(hash) || self.positions.contains_key(*hash)
+            })
+            .count();
+        let Some(first) = headers.get(skip) else {
+            return Ok(Self::outcome(full));
+        };
+
+        // 新区块头接在哪里：已保存的区块头链上或本地链上
+        let prev_hash = first.prev_block_hash;
+        let (base_height, kept) = if let Some(index) = self.positions.get(&prev_hash) {
+            (self.base_height, index + 1)
+        } else if let Some(height) = blockchain.block_height(&prev_hash) {
+            (height, 0)
+        } else {
+            return Err(BlockError::UnknownParent);
+        };
+
+        // 只接收未连接区块头上限以内的部分
+        let pending = kept - self.connected.min(kept);
+        let accepted = (headers.len() - skip).min(MAX_PENDING_HEADERS.saturating_sub(pending));
+        let new_hashes = &hashes[skip..skip + accepted];
+        let mut prev = prev_hash;
+        for (header, hash) in headers[skip..].iter().zip(new_hashes) {
+            blockchain.check_header(header, prev)?;
+            prev = *hash;
+        }
+
+        let new_height = base_height + (kept + accepted) as u32;
+        if new_height > self.header_height() {
+            if kept == 0 {
+                self.reset();
+                self.base_height = base_height;
+            } else {
+                // 分支在已保存的区块头链中间分叉，丢弃分叉点之后的部分
+                self.truncate(kept);
+            }
+            for hash in new_hashes {
+                self.positions.insert(*hash, self.hashes.len());
+                self.hashes.push(*hash);
+            }
+        }
+        Ok(Self::outcome(full && accepted == headers.len() - skip))
+    }
+
+    fn outcome(full: bool) -> HeadersOutcome {
+        if full {
+            HeadersOutcome::More
+        } else {
+            HeadersOutcome::Complete
+        }
+    }
+
+    // 清空区块头链和下载状态
+    fn reset(&mut self) {
+        self.hashes.clear();
+        self.positions.clear();
+        self.connected = 0;
+        self.requested.clear();
+        self.received.clear();
+    }
+
+    // 丢弃区块头链上 len 之后的部分及其下载状态
+    fn truncate(&mut self, len: usize) {
+        for hash in self.hashes.drain(len..) {
+            self.positions.remove(&hash);
+            self.requested.remove(&hash);
+            self.received.remove(&hash);
+        }
+        self.connected = self.connected.min(len);
+    }
+
+    /// 为下载窗口中尚未请求的区块分配节点
+    ///
+    /// `peers` 为可用节点及其已知高度，只向高度足够的节点请求，
+    /// 每个节点同时下载的区块数不超过 `MAX_BLOCKS_IN_FLIGHT_PER_PEER`。
+    pub fn schedule(
+        &mut self,
+        peers: &[(PeerId, u32)],
+        blockchain: &BlockChain,
+        now: u64,
+    ) -> Vec<(PeerId, Vec<Inventory>)> {
+        let mut load: HashMap<PeerId, usize> = HashMap::new();
+        for (peer, _) in self.requested.values() {
+            *load.entry(*peer).or_default() += 1;
+        }
+
+        let mut requests: Vec<(PeerId, Vec<Inventory>)> = Vec::new();
+        let end = self.hashes.len().min(self.connected + DOWNLOAD_WINDOW);
+        let mut next_peer = 0;
+        for index in self.connected..end {
+            let hash = self.hashes[index];
+            if self.requested.contains_key(&hash)
+                || self.received.contains_key(&hash)
+                || blockchain.contains_block(&hash)
+            {
+                continue;
+            }
+            let height = self.base_height + index as u32 + 1;
+            // 轮流分配给有空闲的节点，使下载分散到多个节点上
+            let candidate = (0..peers.len())
+                .map(|offset| peers[(next_peer + offset) % peers.len()])
+                .find(|(peer, best_height)| {
+                    *best_height >= height
+                        && load.get(peer).copied().unwrap_or(0) < MAX_BLOCKS_IN_FLIGHT_PER_PEER
+                });
+            let Some((peer, _)) = candidate else {
+                continue;
+            };
+            next_peer = peers.iter().position(|(p, _)| *p == peer).unwrap() + 1;
+            *load.entry(peer).or_default() += 1;
+            self.requested.insert(hash, (peer, now));
+            match requests.iter_mut().find(|(p, _)| *p == peer) {
+                Some((_, items)) => items.push(Inventory::Block(hash)),
+                None => requests.push((peer, vec![Inventory::Block(hash)])),
+            }
+        }
+        requests
+    }
+
+    // 收到区块：属于同步中的区块时保存，否则原样返回交给普通的区块处理
+    pub fn on_block(&mut self, peer: PeerId, block: Block) -> Option<Block> {
+        let hash = hash_block_header(&block.header);
+        if self
+            .positions
+            .get(&hash)
+            .is_none_or(|index| *index < self.connected)
+        {
+            return Some(block);
+        }
+        self.requested.remove(&hash);
+        self.received.insert(hash, (peer, block));
+        None
+    }
+
+    // 对方没有请求的区块，释放请求以便分配给其他节点
+    pub fn on_not_found(&mut self, peer: PeerId, items: &[Inventory]) {
+        for item in items {
+            if let Inventory::Block(hash) = item {
+                if self.requested.get(hash).is_some_and(|(p, _)| *p == peer) {
+                    self.requested.remove(hash);
+                }
+            }
+        }
+    }
+
+    /// 按高度顺序连接已下载的区块，返回提供了无效区块的节点
+    ///
+    /// 区块校验失败时，该区块之后的区块头链也不再可信，会被整体丢弃。
+    pub fn connect_blocks(&mut self, blockchain: &mut BlockChain) -> Vec<(PeerId, BlockError)> {
+        let mut invalid = Vec::new();
+        while self.connected < self.hashes.len() {
+            let hash = self.hashes[self.connected];
+            if blockchain.contains_block(&hash) {
+                self.connected += 1;
+                continue;
+            }
+            let Some((peer, block)) = self.received.remove(&hash) else {
+                break;
+            };
+            match blockchain.process_block(block) {
+                Ok(_) => self.connected += 1,
+                Err(e) => {
+                    invalid.push((peer, e));
+                    self.truncate(self.connected);
+                    break;
+                }
+            }
+        }
+        if self.is_idle() {
+            // 同步完成，下一次同步从新的链顶开始
+            self.base_height = blockchain.height();
+            self.reset();
+        }
+        invalid
+    }
+
+    /// 检查超时：区块头请求超时、区块下载超时，以及卡住下载窗口的停滞节点
+    pub fn timed_out_peers(&self, now: u64) -> Vec<(PeerId, &'static str)> {
+        let mut peers = Vec::new();
+        if let Some((peer, at)) = self.header_peer {
+            if now.saturating_sub(at) > HEADERS_TIMEOUT_MS {
+                peers.push((peer, "headers timeout"));
+            }
+        }
+        for (peer, at) in self.requested.values() {
+            if now.saturating_sub(*at) > BLOCK_DOWNLOAD_TIMEOUT_MS {
+                peers.push((*peer, "block download timeout"));
+            }
+        }
+
+        // 之后的区块已经下载完毕，只差最早的区块，连接被这个节点卡住
+        if !self.received.is_empty() {
+            if let Some((peer, at)) = self
+                .hashes
+                .get(self.connected)
+                .and_then(|hash| self.requested.get(hash))
+            {
+                if now.saturating_sub(*at) > BLOCK_STALL_TIMEOUT_MS {
+                    peers.push((*peer, "block download stalled"));
+                }
+            }
+        }
+        peers.sort();
+        peers.dedup_by_key(|(peer, _)| *peer);
+        peers
+    }
+
+    // 连接断开时释放该节点的所有请求
+    pub fn remove_peer(&mut self, peer: PeerId) {
+        if self.header_peer() == Some(peer) {
+            self.header_peer = None;
+        }
+        self.requested.retain(|_, (p, _)| *p != peer);
+    }
+}
@@ -0,0 +1,547 @@
+use super::addrman::{AddressBook, AddressEntry};
+use super::banlist::{BanEntry, BanList};
+use super::manager::PeerManager;
+use super::message::{decode_frame, encode_frame, frame_length, FrameError, Message};
+use super::peer::PeerInfo;
+use super::transport::{self, Identity, Opener, Sealer, SecureChannel, TransportError};
+use super::{Action, Direction, NetworkConfig, PeerId};
+use crate::block_chain::BlockChain;
+use crate::transaction::Transaction;
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot, Mutex as AsyncMutex};
+use tokio::task::JoinHandle;
+
+// 定时器间隔
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+// 地址簿写入磁盘的间隔
+const ADDRESS_BOOK_SAVE_INTERVAL_MS: u64 = 60_000;
+
+// 当前时间（毫秒）
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+// 网络任务处理的事件
+enum Event {
+    Connected(TcpStream, Direction, Option<Box<SecureChannel>>),
+    Message(PeerId, Message),
+    Malformed(PeerId, FrameError),
+    Disconnected(PeerId),
+    Connect(SocketAddr),
+    ConnectFailed(SocketAddr),
+    AddPeer(SocketAddr),
+    BroadcastTransaction(Transaction),
+    AnnounceTip,
+    Peers(oneshot::Sender<Vec<PeerInfo>>),
+    Addresses(oneshot::Sender<Vec<AddressEntry>>),
+    Bans(oneshot::Sender<Vec<BanEntry>>),
+    Ban(IpAddr, Option<u64>, String),
+    Unban(IpAddr, oneshot::Sender<bool>),
+    Shutdown(oneshot::Sender<()>),
+}
+
+// 单个连接的读写任务
+struct Connection {
+    writer: mpsc::UnboundedSender<Vec<u8>>,
+    reader: JoinHandle<()>,
+}
+
+// 建立连接后、交给状态机之前的传输层设置
+#[derive(Clone)]
+struct Transport {
+    magic: [u8; 4],
+    identity: Option<Arc<Identity>>, // None 表示明文传输
+    trusted: Arc<Vec<[u8; 32]>>,
+    handshake_timeout: Duration,
+}
+
+impl Transport {
+    // 启用加密时完成加密握手，超时或失败时返回错误
+    async fn secure(
+        &self,
+        mut stream: TcpStream,
+        direction: Direction,
+    ) -> Result<(TcpStream, Option<Box<SecureChannel>>), TransportError> {
+        let Some(identity) = &self.identity else {
+            return Ok((stream, None));
+        };
+        let initiator = direction == Direction::Outbound;
+        let handshake =
+            transport::handshake(&mut stream, identity, initiator, self.magic, &self.trusted);
+        let channel = tokio::time::timeout(self.handshake_timeout, handshake)
+            .await
+            .map_err(|_| TransportError::Io(io::ErrorKind::TimedOut.into()))??;
+        Ok((stream, Some(Box::new(channel))))
+    }
+}
+
+/// 运行中的 P2P 网络的句柄
+#[derive(Clone)]
+pub struct NetworkHandle {
+    events: mpsc::UnboundedSender<Event>,
+    local_addr: Option<SocketAddr>,
+    identity: Option<[u8; 32]>,
+}
+
+impl NetworkHandle {
+    // 实际监听的 P2P 地址
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    // 本节点的身份公钥，未启用加密时为 None
+    pub fn identity(&self) -> Option<[u8; 32]> {
+        self.identity
+    }
+
+    // 主动连接一个节点
+    pub fn connect(&self, addr: SocketAddr) {
+        let _ = self.events.send(Event::Connect(addr));
+    }
+
+    // 向所有已握手的节点通告交易，交易需已在本地交易池中
+    pub fn broadcast_transaction(&self, tx: Transaction) {
+        let _ = self.events.send(Event::BroadcastTransaction(tx));
+    }
+
+    // 立即向已握手的节点通告当前链顶，不必等待下一次定时器
+    pub fn announce_tip(&self) {
+        let _ = self.events.send(Event::AnnounceTip);
+    }
+
+    // 把地址加入地址簿并立即连接
+    pub fn add_peer(&self, addr: SocketAddr) {
+        let _ = self.events.send(Event::AddPeer(addr));
+    }
+
+    pub async fn peers(&self) -> Vec<PeerInfo> {
+        let (sender, receiver) = oneshot::channel();
+        if self.events.send(Event::Peers(sender)).is_err() {
+            return Vec::new();
+        }
+        receiver.await.unwrap_or_default()
+    }
+
+    // 地址簿中的所有地址
+    pub async fn addresses(&self) -> Vec<AddressEntry> {
+        let (sender, receiver) = oneshot::channel();
+        if self.events.send(Event::Addresses(sender)).is_err() {
+            return Vec::new();
+        }
+        receiver.await.unwrap_or_default()
+    }
+
+    pub async fn bans(&self) -> Vec<BanEntry> {
+        let (sender, receiver) = oneshot::channel();
+        if self.events.send(Event::Bans(sender)).is_err() {
+            return Vec::new();
+        }
+        receiver.await.unwrap_or_default()
+    }
+
+    // 封禁地址并断开其连接，duration_ms 为 None 时使用配置的时长
+    pub fn ban(&self, ip: IpAddr, duration_ms: Option<u64>, reason: String) {
+        let _ = self.events.send(Event::Ban(ip, duration_ms, reason));
+    }
+
+    // 解除封禁，返回地址之前是否被封禁
+    pub async fn unban(&self, ip: IpAddr) -> bool {
+        let (sender, receiver) = oneshot::channel();
+        if self.events.send(Event::Unban(ip, sender)).is_err() {
+            return false;
+        }
+        receiver.await.unwrap_or(false)
+    }
+
+    /// 停止网络：关闭监听和所有连接，保存地址簿和封禁列表后返回
+    pub async fn shutdown(&self) {
+        let (sender, receiver) = oneshot::channel();
+        if self.events.send(Event::Shutdown(sender)).is_ok() {
+            let _ = receiver.await;
+        }
+    }
+}
+
+/// 启动 TCP 上的 P2P 网络：监听入站连接并连接配置中的节点
+pub async fn start(
+    config: NetworkConfig,
+    blockchain: Arc<AsyncMutex<BlockChain>>,
+) -> io::Result<NetworkHandle> {
+    let (events, receiver) = mpsc::unbounded_channel();
+    let identity = match (config.encryption, &config.identity_path) {
+        (false, _) => None,
+        (true, Some(path)) => Some(Arc::new(Identity::load_or_generate(path)?)),
+        (true, None) => Some(Arc::new(Identity::generate())),
+    };
+    let transport = Transport {
+        magic: config.magic,
+        identity: identity.clone(),
+        trusted: Arc::new(config.trusted_identities.clone()),
+        handshake_timeout: Duration::from_millis(config.handshake_timeout_ms),
+    };
+
+    let mut local_addr = None;
+    let mut accept_task = None;
+    if let Some(addr) = config.listen_addr {
+        let listener = TcpListener::bind(addr).await?;
+        local_addr = Some(listener.local_addr()?);
+        let events = events.clone();
+        let transport = transport.clone();
+        accept_task = Some(tokio::spawn(async move {
+            while let Ok((stream, addr)) = listener.accept().await {
+                if events.is_closed() {
+                    break;
+                }
+                let events = events.clone();
+                let transport = transport.clone();
+                tokio::spawn(async move {
+                    match transport.secure(stream, Direction::Inbound).await {
+                        Ok((stream, channel)) => {
+                            let _ =
+                                events.send(Event::Connected(stream, Direction::Inbound, channel));
+                        }
+                        Err(e) => {
+                            crate::log!(Warn, "Encrypted handshake with {} failed: {}", addr, e)
+                        }
+                    }
+                });
+            }
+        }));
+    }
+
+    let mut config = config;
+    config.listen_addr = local_addr;
+    for peer in &config.peers {
+        let _ = events.send(Event::Connect(*peer));
+    }
+
+    let mut manager = PeerManager::new(config, rand::random());
+    if let Some(path) = &manager.config().address_book_path {
+        match AddressBook::load(path, rand::random()) {
+            Ok(book) => *manager.addresses_mut() = book,
+            Err(e) => crate::log!(
+                Warn,
+                "Failed to load address book {}: {}",
+                path.display(),
+                e
+            ),
+        }
+    }
+    if let Some(path) = &manager.config().ban_list_path {
+        match BanList::load(path) {
+            Ok(bans) => *manager.bans_mut() = bans,
+            Err(e) => crate::log!(Warn, "Failed to load ban list {}: {}", path.display(), e),
+        }
+    }
+    // 解析种子节点，加入地址簿后由出站连接管理选择连接
+    let now = now_ms();
+    for seed in manager.config().seeds.clone() {
+        match tokio::net::lookup_host(seed.as_str()).await {
+            Ok(addrs) => {
+                for addr in addrs {
+                    manager.addresses_mut().add(addr, None, now);
+                }
+            }
+            Err(e) => crate::log!(Warn, "Failed to resolve seed {}: {}", seed, e),
+        }
+    }
+    tokio::spawn(run(
+        manager,
+        blockchain,
+        transport,
+        events.clone(),
+        receiver,
+        accept_task,
+    ));
+    Ok(NetworkHandle {
+        events,
+        local_addr,
+        identity: identity.map(|identity| identity.public_key()),
+    })
+}
+
+async fn run(
+    mut manager: PeerManager,
+    blockchain: Arc<AsyncMutex<BlockChain>>,
+    transport: Transport,
+    events: mpsc::UnboundedSender<Event>,
+    mut receiver: mpsc::UnboundedReceiver<Event>,
+    accept_task: Option<JoinHandle<()>>,
+) {
+    let magic = transport.magic;
+    let mut connections: HashMap<PeerId, Connection> = HashMap::new();
+    let mut next_id: PeerId = 1;
+    let mut ticker = tokio::time::interval(TICK_INTERVAL);
+    let mut last_save = now_ms();
+    let mut shutdown = None;
+
+    loop {
+        let event = tokio::select! {
+            event = receiver.recv() => match event {
+                Some(event) => event,
+                None => break,
+            },
+            _ = ticker.tick() => {
+                let now = now_ms();
+                let blockchain = blockchain.lock().await;
+                manager.on_tick(&blockchain, now);
+                apply_actions(&mut manager, &mut connections, &events, &transport);
+                save_ban_list(&mut manager);
+                if now.saturating_sub(last_save) >= ADDRESS_BOOK_SAVE_INTERVAL_MS {
+                    last_save = now;
+                    save_address_book(&manager);
+                }
+                continue;
+            }
+        };
+
+        match event {
+            Event::Connect(addr) => dial(addr, &events, &transport),
+            Event::ConnectFailed(addr) => manager.on_connect_failed(addr),
+            Event::AddPeer(addr) => {
+                manager.addresses_mut().add(addr, None, now_ms());
+                dial(addr, &events, &transport);
+            }
+            Event::Connected(stream, direction, channel) => {
+                let id = next_id;
+                next_id += 1;
+                let addr = stream
+                    .peer_addr()
+                    .map(|a| a.to_string())
+                    .unwrap_or_default();
+                let identity = channel.as_ref().map(|channel| channel.remote_identity);
+                connections.insert(
+                    id,
+                    spawn_connection(id, stream, magic, channel, events.clone()),
+                );
+                let blockchain = blockchain.lock().await;
+                manager.on_connected(id, addr, direction, &blockchain, now_ms());
+                if let Some(identity) = identity {
+                    manager.set_identity(id, identity);
+                }
+            }
+            Event::Message(id, message) => {
+                let mut blockchain = blockchain.lock().await;
+                manager.on_message(id, message, &mut blockchain, now_ms());
+            }
+            Event::Malformed(id, error) => manager.on_malformed(id, &error, now_ms()),
+            Event::Disconnected(id) => {
+                if let Some(connection) = connections.remove(&id) {
+                    connection.reader.abort();
+                }
+                manager.on_disconnected(id);
+            }
+            Event::BroadcastTransaction(tx) => manager.announce_transaction(tx.hash()),
+            Event::AnnounceTip => {
+                let blockchain = blockchain.lock().await;
+                manager.announce_tip(&blockchain);
+            }
+            Event::Peers(reply) => {
+                let _ = reply.send(manager.peers());
+            }
+            Event::Addresses(reply) => {
+                let _ = reply.send(manager.addresses().entries());
+            }
+            Event::Bans(reply) => {
+                let _ = reply.send(manager.bans().entries());
+            }
+            Event::Ban(ip, duration_ms, reason) => manager.ban(ip, duration_ms, &reason, now_ms()),
+            Event::Unban(ip, reply) => {
+                let _ = reply.send(manager.unban(&ip));
+            }
+            Event::Shutdown(reply) => {
+                shutdown = Some(reply);
+                break;
+            }
+        }
+        apply_actions(&mut manager, &mut connections, &events, &transport);
+        save_ban_list(&mut manager);
+    }
+
+    if let Some(task) = accept_task {
+        task.abort();
+    }
+    // 丢弃发送端后写任务结束并关闭连接
+    for (_, connection) in connections.drain() {
+        connection.reader.abort();
+    }
+    save_ban_list(&mut manager);
+    save_address_book(&manager);
+    if let Some(reply) = shutdown {
+        let _ = reply.send(());
+    }
+}
+
+// 封禁列表有变化时写回磁盘
+fn save_ban_list(manager: &mut PeerManager) {
+    if !manager.bans_mut().take_changed() {
+        return;
+    }
+    if let Some(path) = &manager.config().ban_list_path {
+        if let Err(e) = manager.bans().save(path) {
+            crate::log!(Error, "Failed to save ban list {}: {}", path.display(), e);
+        }
+    }
+}
+
+fn save_address_book(manager: &PeerManager) {
+    if let Some(path) = &manager.config().address_book_path {
+        if let Err(e) = manager.addresses().save(path) {
+            crate::log!(
+                Error,
+                "Failed to save address book {}: {}",
+                path.display(),
+                e
+            );
+        }
+    }
+}
+
+// 建立出站连接，结果作为事件交给网络任务
+fn dial(addr: SocketAddr, events: &mpsc::UnboundedSender<Event>, transport: &Transport) {
+    let events = events.clone();
+    let transport = transport.clone();
+    tokio::spawn(async move {
+        let stream = match TcpStream::connect(addr).await {
+            Ok(stream) => stream,
+            Err(e) => {
+                crate::log!(Debug, "Failed to connect to {}: {}", addr, e);
+                let _ = events.send(Event::ConnectFailed(addr));
+                return;
+            }
+        };
+        match transport.secure(stream, Direction::Outbound).await {
+            Ok((stream, channel)) => {
+                let _ = events.send(Event::Connected(stream, Direction::Outbound, channel));
+            }
+            Err(e) => {
+                crate::log!(Warn, "Encrypted handshake with {} failed: {}", addr, e);
+                let _ = events.send(Event::ConnectFailed(addr));
+            }
+        }
+    });
+}
+
+// 执行状态机产生的动作
+fn apply_actions(
+    manager: &mut PeerManager,
+    connections: &mut HashMap<PeerId, Connection>,
+    events: &mpsc::UnboundedSender<Event>,
+    transport: &Transport,
+) {
+    for action in manager.take_actions() {
+        match action {
+            Action::Send(id, message) => {
+                if let Some(connection) = connections.get(&id) {
+                    let _ = connection
+                        .writer
+                        .send(encode_frame(transport.magic, &message));
+                }
+            }
+            Action::Disconnect(id, reason) => {
+                if let Some(connection) = connections.remove(&id) {
+                    crate::log!(Info, "Disconnecting peer {}: {}", id, reason);
+                    // 丢弃发送端后写任务结束并关闭连接
+                    connection.reader.abort();
+                }
+            }
+            Action::Connect(addr) => dial(addr, events, transport),
+        }
+    }
+}
+
+// 启动连接的读写任务，读到的完整消息转发给网络任务
+//
+// 加密连接中每个消息帧单独加密为一条记录，读任务先解密记录再解析其中的帧。
+fn spawn_connection(
+    id: PeerId,
+    stream: TcpStream,
+    magic: [u8; 4],
+    channel: Option<Box<SecureChannel>>,
+    events: mpsc::UnboundedSender<Event>,
+) -> Connection {
+    let (mut read_half, mut write_half) = stream.into_split();
+    let (writer, mut frames) = mpsc::unbounded_channel::<Vec<u8>>();
+    let (mut sealer, mut opener): (Option<Sealer>, Option<Opener>) = match channel {
+        Some(channel) => {
+            let SecureChannel { sealer, opener, .. } = *channel;
+            (Some(sealer), Some(opener))
+        }
+        None => (None, None),
+    };
+
+    tokio::spawn(async move {
+        while let Some(frame) = frames.recv().await {
+            let data = match &mut sealer {
+                Some(sealer) => sealer.seal(&frame),
+                None => frame,
+            };
+            if write_half.write_all(&data).await.is_err() {
+                break;
+            }
+        }
+        let _ = write_half.shutdown().await;
+    });
+
+    let reader = tokio::spawn(async move {
+        let mut records = Vec::new();
+        let mut buffer = Vec::new();
+        let mut chunk = [0u8; 8192];
+        'read: loop {
+            let data = match read_half.read(&mut chunk).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => &chunk[..n],
+            };
+            match &mut opener {
+                Some(opener) => {
+                    records.extend_from_slice(data);
+                    loop {
+                        match opener.open(&mut records) {
+                            Ok(Some(plaintext)) => buffer.extend_from_slice(&plaintext),
+                            Ok(None) => break,
+                            Err(e) => {
+                                // 记录无法认证时连接已不可信，直接断开
+                                crate::log!(Warn, "Invalid record from peer {}: {}", id, e);
+                                break 'read;
+                            }
+                        }
+                    }
+                }
+                None => buffer.extend_from_slice(data),
+            }
+            loop {
+                match decode_frame(magic, &buffer) {
+                    Ok(Some((message, used))) => {
+                        buffer.drain(..used);
+                        if events.send(Event::Message(id, message)).is_err() {
+                            break 'read;
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(e) => {
+                        crate::log!(Warn, "Invalid frame from peer {}: {}", id, e);
+                        let recoverable = e.is_recoverable();
+                        if events.send(Event::Malformed(id, e)).is_err() || !recoverable {
+                            break 'read;
+                        }
+                        // 帧长度可信时跳过这一帧，由状态机根据分数决定是否断开
+                        let used = frame_length(&buffer).unwrap_or(buffer.len());
+                        buffer.drain(..used.min(buffer.len()));
+                    }
+                }
+            }
+        }
+        let _ = events.send(Event::Disconnected(id));
+    });
+
+    Connection { writer, reader }
+}
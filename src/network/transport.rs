@@ -0,0 +1,269 @@
+use super::message::{HEADER_SIZE, MAX_PAYLOAD_SIZE};
+use crate::hash_function::sha256_hash;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::agreement::{self, EphemeralPrivateKey, X25519};
+use ring::hkdf::{Salt, HKDF_SHA256};
+use ring::rand::SystemRandom;
+use ring::signature::{self, Ed25519KeyPair, KeyPair, ED25519};
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+// hello：临时 X25519 公钥(32) + 身份公钥(32)
+const HELLO_SIZE: usize = 64;
+// 认证标签长度
+const TAG_SIZE: usize = 16;
+// 单条加密记录的最大长度：一个完整的消息帧加认证标签
+const MAX_RECORD_SIZE: usize = HEADER_SIZE + MAX_PAYLOAD_SIZE + TAG_SIZE;
+
+/// 节点的长期身份密钥（Ed25519），握手时用来证明身份
+pub struct Identity {
+    key_pair: Ed25519KeyPair,
+}
+
+impl Identity {
+    pub fn generate() -> Self {
+        let document = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        Identity {
+            key_pair: Ed25519KeyPair::from_pkcs8(document.as_ref()).unwrap(),
+        }
+    }
+
+    /// 从文件读取身份密钥（PKCS#8 的十六进制编码），文件不存在时生成并保存
+    pub fn load_or_generate(path: &Path) -> io::Result<Self> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid identity key");
+        match fs::read_to_string(path) {
+            Ok(content) => {
+                let document = hex::decode(content.trim()).map_err(|_| invalid())?;
+                let key_pair = Ed25519KeyPair::from_pkcs8(&document).map_err(|_| invalid())?;
+                Ok(Identity { key_pair })
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let document = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+                    .map_err(|_| io::Error::other("failed to generate identity key"))?;
+                // 私钥文件只允许本用户读写；create_new 保证不会跟随或覆盖已有的文件
+                let mut options = fs::OpenOptions::new();
+                options.write(true).create_new(true);
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::OpenOptionsExt;
+                    options.mode(0o600);
+                }
+                let mut file = options.open(path)?;
+                file.write_all(hex::encode(document.as_ref()).as_bytes())?;
+                file.sync_all()?;
+                let key_pair = Ed25519KeyPair::from_pkcs8(document.as_ref()).unwrap();
+                Ok(Identity { key_pair })
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn public_key(&self) -> [u8; 32] {
+        self.key_pair.public_key().as_ref().try_into().unwrap()
+    }
+}
+
+/// 加密传输失败的原因
+#[derive(Debug)]
+pub enum TransportError {
+    Io(io::Error),
+    KeyExchange,                 // 临时公钥无效
+    BadSignature,                // 对方无法证明持有声明的身份密钥
+    UntrustedIdentity([u8; 32]), // 对方的身份不在信任列表中
+    RecordTooLarge(usize),
+    Decrypt, // 记录被篡改或密钥不一致
+}
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransportError::Io(e) => write!(f, "io error: {}", e),
+            TransportError::KeyExchange => f.write_str("key exchange failed"),
+            TransportError::BadSignature => f.write_str("invalid identity signature"),
+            TransportError::UntrustedIdentity(key) => {
+                write!(f, "untrusted identity {}", hex::encode(key))
+            }
+            TransportError::RecordTooLarge(len) => write!(f, "record too large: {} bytes", len),
+            TransportError::Decrypt => f.write_str("record authentication failed"),
+        }
+    }
+}
+
+impl std::error::Error for TransportError {}
+
+impl From<io::Error> for TransportError {
+    fn from(e: io::Error) -> Self {
+        TransportError::Io(e)
+    }
+}
+
+// 一个方向上的密钥和递增的 nonce 计数器
+struct CipherState {
+    key: LessSafeKey,
+    counter: u64,
+}
+
+impl CipherState {
+    fn next_nonce(&mut self) -> Nonce {
+        let mut nonce = [0u8; NONCE_LEN];
+        nonce[..8].copy_from_slice(&self.counter.to_le_bytes());
+        self.counter += 1;
+        Nonce::assume_unique_for_key(nonce)
+    }
+}
+
+/// 发送方向：把明文加密为 长度(4) + 密文 的记录
+pub struct Sealer(CipherState);
+
+impl Sealer {
+    pub fn seal(&mut self, plaintext: &[u8]) -> Vec<u8> {
+        let nonce = self.0.next_nonce();
+        let mut in_out = plaintext.to_vec();
+        self.0
+            .key
+            .seal_in_place_append_tag(nonce, Aad::empty(), &mut in_out)
+            .unwrap();
+        let mut record = Vec::with_capacity(4 + in_out.len());
+        record.extend_from_slice(&(in_out.len() as u32).to_le_bytes());
+        record.extend_from_slice(&in_out);
+        record
+    }
+}
+
+/// 接收方向：从缓冲区解出并校验一条记录
+pub struct Opener(CipherState);
+
+impl Opener {
+    /// 数据不足一条记录时返回 `Ok(None)`，成功时从缓冲区移除该记录并返回明文
+    pub fn open(&mut self, buffer: &mut Vec<u8>) -> Result<Option<Vec<u8>>, TransportError> {
+        let Some(length) = buffer.get(..4) else {
+            return Ok(None);
+        };
+        let length = u32::from_le_bytes(length.try_into().unwrap()) as usize;
+        if length > MAX_RECORD_SIZE {
+            return Err(TransportError::RecordTooLarge(length));
+        }
+        if buffer.len() < 4 + length {
+            return Ok(None);
+        }
+        let mut in_out: Vec<u8> = buffer.drain(..4 + length).skip(4).collect();
+        let nonce = self.0.next_nonce();
+        let plaintext = self
+            .0
+            .key
+            .open_in_place(nonce, Aad::empty(), &mut in_out)
+            .map_err(|_| TransportError::Decrypt)?;
+        Ok(Some(plaintext.to_vec()))
+    }
+}
+
+/// 握手完成后的加密通道
+pub struct SecureChannel {
+    pub sealer: Sealer,
+    pub opener: Opener,
+    pub remote_identity: [u8; 32],
+}
+
+// 由共享密钥和握手记录派生一个方向的密钥
+fn derive_key(secret: &[u8], transcript: &[u8; 32], label: &[u8]) -> CipherState {
+    let prk = Salt::new(HKDF_SHA256, transcript).extract(secret);
+    let info = [label];
+    let okm = prk.expand(&info, &CHACHA20_POLY1305).unwrap();
+    CipherState {
+        key: LessSafeKey::new(UnboundKey::from(okm)),
+        counter: 0,
+    }
+}
+
+// 身份签名的内容：角色和握手记录，防止把对方的签名反射回去
+fn auth_payload(initiator: bool, transcript: &[u8; 32]) -> Vec<u8> {
+    let mut payload = b"block_chain auth".to_vec();
+    payload.push(initiator as u8);
+    payload.extend_from_slice(transcript);
+    payload
+}
+
+/// 在已建立的连接上完成加密握手
+///
+/// 双方先交换临时 X25519 公钥和身份公钥，用 X25519 共享密钥和握手记录经 HKDF
+/// 派生两个方向的 ChaCha20-Poly1305 密钥，再各自发送加密的 Ed25519 签名证明持有身份私钥。
+/// `trusted` 非空时只接受其中的身份。
+pub async fn handshake<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    identity: &Identity,
+    initiator: bool,
+    magic: [u8; 4],
+    trusted: &[[u8; 32]],
+) -> Result<SecureChannel, TransportError> {
+    let rng = SystemRandom::new();
+    let ephemeral =
+        EphemeralPrivateKey::generate(&X25519, &rng).map_err(|_| TransportError::KeyExchange)?;
+    let ephemeral_public = ephemeral
+        .compute_public_key()
+        .map_err(|_| TransportError::KeyExchange)?;
+    let mut hello = [0u8; HELLO_SIZE];
+    hello[..32].copy_from_slice(ephemeral_public.as_ref());
+    hello[32..].copy_from_slice(&identity.public_key());
+    stream.write_all(&hello).await?;
+
+    let mut remote_hello = [0u8; HELLO_SIZE];
+    stream.read_exact(&mut remote_hello).await?;
+    let remote_identity: [u8; 32] = remote_hello[32..].try_into().unwrap();
+    if !trusted.is_empty() && !trusted.contains(&remote_identity) {
+        return Err(TransportError::UntrustedIdentity(remote_identity));
+    }
+
+    // 握手记录：网络魔数、发起方 hello、响应方 hello
+    let (initiator_hello, responder_hello) = if initiator {
+        (&hello, &remote_hello)
+    } else {
+        (&remote_hello, &hello)
+    };
+    let mut data = magic.to_vec();
+    data.extend_from_slice(initiator_hello);
+    data.extend_from_slice(responder_hello);
+    let transcript: [u8; 32] = sha256_hash(&data).as_ref().try_into().unwrap();
+
+    let remote_ephemeral = agreement::UnparsedPublicKey::new(&X25519, &remote_hello[..32]);
+    let (send, recv) = agreement::agree_ephemeral(ephemeral, &remote_ephemeral, |secret| {
+        let to_responder = derive_key(secret, &transcript, b"initiator");
+        let to_initiator = derive_key(secret, &transcript, b"responder");
+        if initiator {
+            (to_responder, to_initiator)
+        } else {
+            (to_initiator, to_responder)
+        }
+    })
+    .map_err(|_| TransportError::KeyExchange)?;
+    let mut channel = SecureChannel {
+        sealer: Sealer(send),
+        opener: Opener(recv),
+        remote_identity,
+    };
+
+    let signature = identity
+        .key_pair
+        .sign(&auth_payload(initiator, &transcript));
+    stream
+        .write_all(&channel.sealer.seal(signature.as_ref()))
+        .await?;
+
+    // 只读取认证记录本身，之后的数据留给连接的读任务
+    let mut length = [0u8; 4];
+    stream.read_exact(&mut length).await?;
+    let size = u32::from_le_bytes(length) as usize;
+    if size > MAX_RECORD_SIZE {
+        return Err(TransportError::RecordTooLarge(size));
+    }
+    let mut buffer = length.to_vec();
+    buffer.resize(4 + size, 0);
+    stream.read_exact(&mut buffer[4..]).await?;
+    let remote_signature = channel.opener.open(&mut buffer)?.unwrap();
+    signature::UnparsedPublicKey::new(&ED25519, remote_identity)
+        .verify(&auth_payload(!initiator, &transcript), &remote_signature)
+        .map_err(|_| TransportError::BadSignature)?;
+    Ok(channel)
+}
@@ -0,0 +1,363 @@
+use crate::auth::Group;
+use crate::block_chain::{BlockChain, TransactionError};
+use crate::export::ExportFormat;
+use crate::mining::{BlockTemplate, MiningStatus};
+use crate::network::banlist::BanEntry;
+use crate::rpc::{
+    route_group, AddPeerRequest, BanRequest, BlockInfo, BlockPage, BlocksQuery,
+    CreateTransactionRequest, ExportQuery, HeaderInfo, HeadersQuery, ImportResult, PeersInfo,
+    StartMiningRequest, SubmitBlockRequest, SubmitTransactionRequest, SubmittedTransaction,
+    TipInfo, TransactionInfo, TransactionRejection,
+};
+use crate::transaction::Transaction;
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::JsonSchema;
+use serde_json::{json, Map, Value};
+use std::net::IpAddr;
+
+// 文档遵循的 OpenAPI 版本
+pub const OPENAPI_VERSION: &str = "3.0.3";
+
+// 文档中的一个接口：路径参数写作 {name}
+struct Operation {
+    method: &'static str,
+    path: &'static str,
+    group: Group,
+    summary: &'static str,
+    parameters: Vec<Value>,
+    request: Option<Value>, // 请求体的 content 对象
+    responses: Map<String, Value>,
+}
+
+impl Operation {
+    // 权限组取自 rpc::ROUTES
+    fn new(method: &'static str, path: &'static str, summary: &'static str) -> Self {
+        Operation {
+            method,
+            path,
+            group: route_group(method, path),
+            summary,
+            parameters: Vec::new(),
+            request: None,
+            responses: Map::new(),
+        }
+    }
+
+    fn parameters(mut self, parameters: Vec<Value>) -> Self {
+        self.parameters.extend(parameters);
+        self
+    }
+
+    fn json_body(self, schema: Value) -> Self {
+        self.body(json!({ "application/json": { "schema": schema } }))
+    }
+
+    fn body(mut self, content: Value) -> Self {
+        self.request = Some(json!({ "required": true, "content": content }));
+        self
+    }
+
+    fn json_response(self, status: u16, description: &str, schema: Value) -> Self {
+        self.response(
+            status,
+            description,
+            Some(json!({ "application/json": { "schema": schema } })),
+        )
+    }
+
+    fn response(mut self, status: u16, description: &str, content: Option<Value>) -> Self {
+        let mut response = json!({ "description": description });
+        if let Some(content) = content {
+            response["content"] = content;
+        }
+        self.responses.insert(status.to_string(), response);
+        self
+    }
+
+    // 认证开启后每个接口都可能返回的错误
+    fn into_value(mut self) -> Value {
+        let message = json!({ "application/json": { "schema": { "type": "string" } } });
+        for (status, description) in [
+            (401, "Missing or invalid credentials".to_string()),
+            (403, format!("Requires {} permission", self.group)),
+            (429, "Rate limit exceeded".to_string()),
+        ] {
+            self = self.response(status, &description, Some(message.clone()));
+        }
+        let mut operation = json!({
+            "summary": self.summary,
+            "x-permission-group": self.group,
+            "responses": self.responses,
+        });
+        if !self.parameters.is_empty() {
+            operation["parameters"] = Value::Array(self.parameters);
+        }
+        if let Some(request) = self.request {
+            operation["requestBody"] = request;
+        }
+        operation
+    }
+}
+
+// 生成类型的 schema，结构体和枚举放入 components 中并返回引用
+struct Schemas {
+    generator: SchemaGenerator,
+}
+
+impl Schemas {
+    fn of<T: JsonSchema>(&mut self) -> Value {
+        json!(self.generator.subschema_for::<T>())
+    }
+
+    fn string(&self) -> Value {
+        json!({ "type": "string" })
+    }
+
+    fn path<T: JsonSchema>(&mut self, name: &str, description: &str) -> Vec<Value> {
+        vec![json!({
+            "name": name,
+            "in": "path",
+            "required": true,
+            "description": description,
+            "schema": self.of::<T>(),
+        })]
+    }
+
+    // 查询参数结构体的每个字段是一个参数
+    fn query<T: JsonSchema>(&self) -> Vec<Value> {
+        let root = SchemaSettings::openapi3()
+            .into_generator()
+            .into_root_schema_for::<T>();
+        let object = root.schema.object.unwrap_or_default();
+        object
+            .properties
+            .into_iter()
+            .map(|(name, schema)| {
+                json!({
+                    "name": name,
+                    "in": "query",
+                    "required": object.required.contains(&name),
+                    "schema": schema,
+                })
+            })
+            .collect()
+    }
+}
+
+// 全部接口，与 rpc::ROUTES 一一对应
+fn operations(schemas: &mut Schemas) -> Vec<Operation> {
+    let string = schemas.string();
+    let hash = "Hex-encoded 32-byte hash";
+    // 导出和导入的两种格式
+    let streams = |schema: Value| {
+        let mut content = Map::new();
+        content.insert(
+            ExportFormat::Ndjson.content_type().to_string(),
+            json!({ "schema": schema }),
+        );
+        content.insert(
+            ExportFormat::Binary.content_type().to_string(),
+            json!({ "schema": { "type": "string", "format": "binary" } }),
+        );
+        Value::Object(content)
+    };
+    // 单个 JSON-RPC 消息或批量消息
+    let messages = json!({
+        "oneOf": [{ "type": "object" }, { "type": "array", "items": { "type": "object" } }],
+    });
+    vec![
+        Operation::new(
+            "post",
+            "/transaction",
+            "Create and broadcast an unsigned transaction",
+        )
+        .json_body(schemas.of::<CreateTransactionRequest>())
+        .json_response(200, "Transaction added to the pool", string.clone()),
+        Operation::new("post", "/tx/raw", "Submit a signed, serialized transaction")
+            .json_body(schemas.of::<SubmitTransactionRequest>())
+            .json_response(
+                200,
+                "Transaction accepted",
+                schemas.of::<SubmittedTransaction>(),
+            )
+            .json_response(
+                400,
+                "Transaction could not be decoded",
+                schemas.of::<TransactionRejection<String>>(),
+            )
+            .json_response(
+                409,
+                "Transaction already known or double spend",
+                schemas.of::<TransactionRejection<TransactionError>>(),
+            )
+            .json_response(
+                422,
+                "Transaction rejected by the mempool",
+                schemas.of::<TransactionRejection<TransactionError>>(),
+            ),
+        Operation::new(
+            "get",
+            "/tx/{txid}",
+            "Look up a transaction in the chain or the mempool",
+        )
+        .parameters(schemas.path::<String>("txid", hash))
+        .json_response(200, "Transaction", schemas.of::<TransactionInfo>())
+        .json_response(400, "Invalid hash", string.clone())
+        .json_response(404, "Transaction not found", string.clone()),
+        Operation::new("get", "/pool", "Transactions in the mempool").json_response(
+            200,
+            "Mempool transactions, oldest first",
+            schemas.of::<Vec<Transaction>>(),
+        ),
+        Operation::new("get", "/chain", "Full main chain and mempool").json_response(
+            200,
+            "Chain state",
+            schemas.of::<BlockChain>(),
+        ),
+        Operation::new("get", "/tip", "Current main chain tip").json_response(
+            200,
+            "Chain tip",
+            schemas.of::<TipInfo>(),
+        ),
+        Operation::new(
+            "get",
+            "/block/{hash}",
+            "Look up a main chain or side chain block by hash",
+        )
+        .parameters(schemas.path::<String>("hash", hash))
+        .json_response(200, "Block", schemas.of::<BlockInfo>())
+        .json_response(400, "Invalid hash", string.clone())
+        .json_response(404, "Block not found", string.clone()),
+        Operation::new(
+            "get",
+            "/block/height/{height}",
+            "Look up a main chain block by height",
+        )
+        .parameters(schemas.path::<u32>("height", "Block height"))
+        .json_response(200, "Block", schemas.of::<BlockInfo>())
+        .json_response(404, "Block not found", string.clone()),
+        Operation::new("get", "/headers", "Main chain headers starting at a height")
+            .parameters(schemas.query::<HeadersQuery>())
+            .json_response(200, "Block headers", schemas.of::<Vec<HeaderInfo>>()),
+        Operation::new("get", "/blocks", "Page through main chain blocks")
+            .parameters(schemas.query::<BlocksQuery>())
+            .json_response(200, "One page of blocks", schemas.of::<BlockPage>())
+            .json_response(400, "Invalid cursor", string.clone())
+            .json_response(409, "Cursor is no longer on the main chain", string.clone()),
+        Operation::new("get", "/export", "Stream main chain blocks")
+            .parameters(schemas.query::<ExportQuery>())
+            .response(
+                200,
+                "One block per record",
+                Some(streams(schemas.of::<crate::block_chain::Block>())),
+            )
+            .json_response(400, "Unknown format", string.clone()),
+        Operation::new("post", "/import", "Import and validate a block stream")
+            .parameters(schemas.query::<ExportQuery>())
+            .body(streams(schemas.of::<crate::block_chain::Block>()))
+            .json_response(200, "All blocks imported", schemas.of::<ImportResult>())
+            .json_response(
+                400,
+                "Import stopped at an invalid block",
+                schemas.of::<ImportResult>(),
+            ),
+        Operation::new(
+            "get",
+            "/mining/template",
+            "Block template for external miners",
+        )
+        .json_response(200, "Block template", schemas.of::<BlockTemplate>()),
+        Operation::new("post", "/mining/submit", "Submit a mined, serialized block")
+            .json_body(schemas.of::<SubmitBlockRequest>())
+            .json_response(200, "Hash of the accepted block", string.clone())
+            .json_response(
+                400,
+                "Block could not be decoded or was rejected",
+                string.clone(),
+            ),
+        Operation::new("post", "/mining/start", "Start background mining")
+            .json_body(schemas.of::<StartMiningRequest>())
+            .json_response(200, "Mining started or already running", string.clone())
+            .json_response(400, "Invalid payout address", string.clone()),
+        Operation::new("post", "/mining/stop", "Stop background mining").json_response(
+            200,
+            "Mining stopped or not running",
+            string.clone(),
+        ),
+        Operation::new("get", "/mining/status", "Background mining status").json_response(
+            200,
+            "Mining status",
+            schemas.of::<MiningStatus>(),
+        ),
+        Operation::new("get", "/peers", "Connected peers and the address book").json_response(
+            200,
+            "Peers",
+            schemas.of::<PeersInfo>(),
+        ),
+        Operation::new("post", "/peers", "Add a peer address and connect to it")
+            .json_body(schemas.of::<AddPeerRequest>())
+            .json_response(200, "Connecting", string.clone())
+            .json_response(400, "Invalid address", string.clone()),
+        Operation::new("get", "/bans", "Banned addresses").json_response(
+            200,
+            "Ban list",
+            schemas.of::<Vec<BanEntry>>(),
+        ),
+        Operation::new("post", "/bans", "Ban an address and disconnect it")
+            .json_body(schemas.of::<BanRequest>())
+            .json_response(200, "Address banned", string.clone())
+            .json_response(400, "Duration is too large", string.clone()),
+        Operation::new("delete", "/bans/{ip}", "Remove a ban")
+            .parameters(schemas.path::<IpAddr>("ip", "Banned IP address"))
+            .json_response(200, "Ban removed", string.clone())
+            .json_response(404, "Address is not banned", string.clone()),
+        Operation::new(
+            "post",
+            "/rpc",
+            "JSON-RPC 2.0; each method checks its own permission group",
+        )
+        .json_body(messages.clone())
+        .json_response(200, "Response or batch of responses", messages)
+        .response(204, "Request consisted only of notifications", None),
+        Operation::new(
+            "get",
+            "/ws",
+            "WebSocket subscription to chain and mempool events",
+        )
+        .response(101, "Switching to the WebSocket protocol", None),
+        Operation::new("get", "/openapi.json", "This document").json_response(
+            200,
+            "OpenAPI document",
+            json!({ "type": "object" }),
+        ),
+    ]
+}
+
+/// 生成 HTTP 接口的 OpenAPI 文档，schema 由请求和响应类型生成
+pub fn document() -> Value {
+    let mut schemas = Schemas {
+        generator: SchemaSettings::openapi3().into_generator(),
+    };
+    let mut paths = Map::new();
+    for operation in operations(&mut schemas) {
+        let (path, method) = (operation.path, operation.method);
+        paths.entry(path).or_insert_with(|| json!({}))[method] = operation.into_value();
+    }
+    json!({
+        "openapi": OPENAPI_VERSION,
+        "info": {
+            "title": "Block chain node API",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": paths,
+        "components": {
+            "schemas": schemas.generator.take_definitions(),
+            "securitySchemes": {
+                "bearer": { "type": "http", "scheme": "bearer" },
+                "basic": { "type": "http", "scheme": "basic" },
+            },
+        },
+        // 没有配置凭据的节点不需要认证
+        "security": [{}, { "bearer": [] }, { "basic": [] }],
+    })
+}
@@ -0,0 +1,895 @@
+use crate::auth::{self, Group};
+use crate::block_chain::{Block, BlockChain, BlockHeader, TransactionError};
+use crate::export::{encode_block, ExportFormat, ImportSummary, Importer, EXPORT_BATCH_SIZE};
+use crate::hash_function::hash_block_header;
+use crate::jsonrpc;
+use crate::mining::{BlockTemplate, MiningService};
+use crate::network::addrman::AddressEntry;
+use crate::network::message::MAX_HEADERS;
+use crate::network::peer::PeerInfo;
+use crate::network::tcp::NetworkHandle;
+use crate::openapi;
+use crate::serialization::deserialize_bc;
+use crate::service::Node;
+use crate::stratum::decode_hash;
+use crate::transaction::Transaction;
+use crate::websocket;
+use futures_util::{Stream, StreamExt};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use tokio::sync::Mutex as AsyncMutex;
+use warp::http::header::CONTENT_TYPE;
+use warp::http::{Response, StatusCode};
+use warp::hyper::body::{Buf, Bytes};
+use warp::hyper::Body;
+use warp::{Filter, Rejection, Reply};
+
+/// 全部 HTTP 接口的方法、路径和所需的权限组，路径参数写作 {name}
+///
+/// 路由的权限检查和 OpenAPI 文档都从这张表取权限组。
+pub const ROUTES: &[(&str, &str, Group)] = &[
+    ("post", "/transaction", Group::Wallet),
+    ("post", "/tx/raw", Group::Wallet),
+    ("get", "/tx/{txid}", Group::Read),
+    ("get", "/pool", Group::Read),
+    ("get", "/chain", Group::Read),
+    ("get", "/tip", Group::Read),
+    ("get", "/block/{hash}", Group::Read),
+    ("get", "/block/height/{height}", Group::Read),
+    ("get", "/headers", Group::Read),
+    ("get", "/blocks", Group::Read),
+    ("get", "/export", Group::Read),
+    ("post", "/import", Group::Admin),
+    ("get", "/mining/template", Group::Admin),
+    ("post", "/mining/submit", Group::Admin),
+    ("post", "/mining/start", Group::Admin),
+    ("post", "/mining/stop", Group::Admin),
+    ("get", "/mining/status", Group::Read),
+    ("get", "/peers", Group::Read),
+    ("post", "/peers", Group::Admin),
+    ("get", "/bans", Group::Read),
+    ("post", "/bans", Group::Admin),
+    ("delete", "/bans/{ip}", Group::Admin),
+    ("post", "/rpc", Group::Read),
+    ("get", "/ws", Group::Read),
+    ("get", "/openapi.json", Group::Read),
+];
+
+/// 表中声明的接口所需的权限组；未声明的接口会 panic，新增路由时必须先加入 `ROUTES`
+pub fn route_group(method: &str, path: &str) -> Group {
+    ROUTES
+        .iter()
+        .find(|(m, p, _)| *m == method && *p == path)
+        .map(|&(_, _, group)| group)
+        .unwrap_or_else(|| panic!("undeclared route: {} {}", method, path))
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct CreateTransactionRequest {
+    lock_time: u32,
+    value: u64,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct SubmitBlockRequest {
+    block: String, // 序列化区块的十六进制编码
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct SubmitTransactionRequest {
+    tx: String, // 签名后序列化交易的十六进制编码
+}
+
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct TransactionRejection<E> {
+    error: E, // 拒绝原因的类型，如 double_spend；无法解析时为 malformed
+    message: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct SubmittedTransaction {
+    txid: String,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct AddPeerRequest {
+    addr: String, // 节点的 P2P 地址，如 192.168.1.2:3031
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct BanRequest {
+    ip: IpAddr,
+    duration: Option<u64>, // 封禁秒数，省略时使用默认时长
+    reason: Option<String>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct StartMiningRequest {
+    address: Option<String>,
+    threads: Option<usize>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct HeadersQuery {
+    from: Option<u32>,    // 起始高度，默认 0
+    count: Option<usize>, // 区块头数量，默认且最多 MAX_HEADERS
+}
+
+// 每页默认和最多的区块数
+const DEFAULT_PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: usize = 1_000;
+
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct BlocksQuery {
+    cursor: Option<String>, // 上一页最后一个区块的哈希，省略时从创世区块开始
+    limit: Option<usize>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct BlockPage<'a> {
+    blocks: &'a [Block],
+    next_cursor: Option<String>, // 没有更多区块时为 null
+}
+
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct PeersInfo {
+    connected: Vec<PeerInfo>,
+    known: Vec<AddressEntry>, // 地址簿
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct ExportQuery {
+    format: Option<String>, // ndjson（默认）或 binary
+    from: Option<u32>,      // 起始高度，默认 0
+}
+
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct ImportResult<'a> {
+    imported: &'a ImportSummary,
+    error: Option<String>, // 导入中止的原因，全部成功时为 null
+}
+
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct TipInfo {
+    height: u32,
+    hash: String,
+    timestamp: u32,
+    difficulty: usize,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct BlockInfo<'a> {
+    hash: String,
+    height: u32,
+    confirmations: u32, // 不在主链上时为 0
+    main_chain: bool,
+    header: &'a BlockHeader,
+    transactions: &'a [Transaction],
+}
+
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct HeaderInfo<'a> {
+    hash: String,
+    height: u32,
+    header: &'a BlockHeader,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct TransactionInfo {
+    txid: String,
+    status: &'static str,       // mempool 或 confirmed
+    block_hash: Option<String>, // 所在的主链区块
+    height: Option<u32>,
+    confirmations: u32,
+    pub(crate) transaction: Transaction,
+}
+
+impl TransactionInfo {
+    // 依次在主链和交易池中查找交易
+    pub(crate) fn find(blockchain: &BlockChain, hash: &[u8; 32]) -> Option<Self> {
+        if let Some((tx, height)) = blockchain.find_transaction(hash) {
+            let block_hash = hash_block_header(&blockchain.blocks[height as usize].header);
+            return Some(TransactionInfo {
+                txid: hex::encode(hash),
+                status: "confirmed",
+                block_hash: Some(hex::encode(block_hash)),
+                height: Some(height),
+                confirmations: blockchain.height() - height + 1,
+                transaction: tx.clone(),
+            });
+        }
+        let pool = blockchain.transaction_pool.lock().unwrap();
+        let tx = pool.get_by_hash(hash)?;
+        Some(TransactionInfo {
+            txid: hex::encode(hash),
+            status: "mempool",
+            block_hash: None,
+            height: None,
+            confirmations: 0,
+            transaction: tx.clone(),
+        })
+    }
+}
+
+impl<'a> BlockInfo<'a> {
+    pub(crate) fn new(blockchain: &BlockChain, block: &'a Block) -> Self {
+        let hash = hash_block_header(&block.header);
+        let main_chain = blockchain.is_main_chain(&hash);
+        let height = blockchain.block_height(&hash).unwrap_or(0);
+        BlockInfo {
+            hash: hex::encode(hash),
+            height,
+            confirmations: if main_chain {
+                blockchain.height() - height + 1
+            } else {
+                0
+            },
+            main_chain,
+            header: &block.header,
+            transactions: &block.transactions,
+        }
+    }
+}
+
+// 查询结果：找到时返回 JSON，否则返回 404
+fn found_or_404<T: Serialize>(
+    value: Option<T>,
+    what: &str,
+) -> warp::reply::WithStatus<warp::reply::Json> {
+    match value {
+        Some(value) => warp::reply::with_status(warp::reply::json(&value), StatusCode::OK),
+        None => warp::reply::with_status(
+            warp::reply::json(&format!("{} not found", what)),
+            StatusCode::NOT_FOUND,
+        ),
+    }
+}
+
+fn bad_request(message: String) -> warp::reply::WithStatus<warp::reply::Json> {
+    warp::reply::with_status(warp::reply::json(&message), StatusCode::BAD_REQUEST)
+}
+
+fn parse_format(format: Option<&str>) -> Result<ExportFormat, String> {
+    format.map_or(Ok(ExportFormat::default()), str::parse)
+}
+
+/// 分批发送主链区块：只在复制每一批区块时持锁，发送时不持锁
+///
+/// 两批之间如果已发送的区块被重组出主链，中止响应，客户端会收到不完整的数据。
+async fn export_blocks(
+    blockchain: Arc<AsyncMutex<BlockChain>>,
+    format: ExportFormat,
+    from: u32,
+    mut sender: warp::hyper::body::Sender,
+) {
+    let mut height = from as usize;
+    let mut last_hash = None;
+    loop {
+        let batch: Vec<Block> = {
+            let blockchain = blockchain.lock().await;
+            if let Some(hash) = &last_hash {
+                if !blockchain.is_main_chain(hash) {
+                    sender.abort();
+                    return;
+                }
+            }
+            blockchain
+                .blocks
+                .iter()
+                .skip(height)
+                .take(EXPORT_BATCH_SIZE)
+                .cloned()
+                .collect()
+        };
+        let Some(last) = batch.last() else {
+            return;
+        };
+        height += batch.len();
+        last_hash = Some(hash_block_header(&last.header));
+        for block in &batch {
+            let record = Bytes::from(encode_block(format, block));
+            if sender.send_data(record).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+// 导入请求体中的区块，返回导入统计和遇到的错误
+async fn import_blocks<S, B>(
+    query: ExportQuery,
+    body: S,
+    blockchain: Arc<AsyncMutex<BlockChain>>,
+) -> Result<warp::reply::WithStatus<warp::reply::Json>, warp::Rejection>
+where
+    S: Stream<Item = Result<B, warp::Error>>,
+    B: Buf,
+{
+    let format = match parse_format(query.format.as_deref()) {
+        Ok(format) => format,
+        Err(e) => return Ok::<_, warp::Rejection>(bad_request(e)),
+    };
+    let mut body = Box::pin(body);
+    let mut importer = Importer::new(format);
+    let result = async {
+        while let Some(chunk) = body.next().await {
+            let mut chunk = chunk.map_err(|e| e.to_string())?;
+            while chunk.has_remaining() {
+                let data = chunk.chunk();
+                let len = data.len();
+                let blocks = importer.push(data).map_err(|e| e.to_string())?;
+                chunk.advance(len);
+                let mut blockchain = blockchain.lock().await;
+                for block in blocks {
+                    importer
+                        .apply(&mut blockchain, block)
+                        .map_err(|e| e.to_string())?;
+                }
+            }
+        }
+        if let Some(block) = importer.finish().map_err(|e| e.to_string())? {
+            let mut blockchain = blockchain.lock().await;
+            importer
+                .apply(&mut blockchain, block)
+                .map_err(|e| e.to_string())?;
+        }
+        Ok::<_, String>(())
+    }
+    .await;
+    let (error, status) = match result {
+        Ok(()) => (None, StatusCode::OK),
+        Err(e) => (Some(e), StatusCode::BAD_REQUEST),
+    };
+    Ok(warp::reply::with_status(
+        warp::reply::json(&ImportResult {
+            imported: importer.summary(),
+            error,
+        }),
+        status,
+    ))
+}
+
+fn invalid_hash(hash: &str) -> warp::reply::WithStatus<warp::reply::Json> {
+    warp::reply::with_status(
+        warp::reply::json(&format!("Invalid hash: {}", hash)),
+        StatusCode::BAD_REQUEST,
+    )
+}
+
+/// 由节点构造 HTTP 接口的全部路由
+pub fn routes(node: &Node) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let mining_service = node.mining.clone();
+    let mining_service = warp::any().map(move || mining_service.clone());
+    let blockchain = node.blockchain.clone();
+    let blockchain = warp::any().map(move || blockchain.clone());
+    let network = node.network.clone();
+    let network = warp::any().map(move || network.clone());
+    // 按 ROUTES 中声明的权限组限制访问
+    let access = node.access.clone();
+    let require =
+        move |method: &str, path: &str| auth::require(access.clone(), route_group(method, path));
+    let identify = auth::identify(node.access.clone());
+    let node = node.clone();
+    let node = warp::any().map(move || node.clone());
+
+    // 创建交易
+    let create_transaction = warp::path("transaction")
+        .and(warp::post())
+        .and(require("post", "/transaction"))
+        .and(warp::body::json())
+        .and(blockchain.clone())
+        .and(network.clone())
+        .and_then(
+            |req: CreateTransactionRequest,
+             blockchain: Arc<AsyncMutex<BlockChain>>,
+             network: NetworkHandle| async move {
+                let tx = Transaction::new(req.value, req.lock_time);
+                let mut blockchain = blockchain.lock().await;
+                blockchain.add_transaction(tx.clone());
+                network.broadcast_transaction(tx);
+                Ok::<_, warp::Rejection>(warp::reply::json(&"Transaction created and broadcasted"))
+            },
+        );
+
+    // 获取区块模板
+    let get_template = warp::path!("mining" / "template")
+        .and(warp::get())
+        .and(require("get", "/mining/template"))
+        .and(blockchain.clone())
+        .and_then(|blockchain: Arc<AsyncMutex<BlockChain>>| async move {
+            let blockchain = blockchain.lock().await;
+            Ok::<_, warp::Rejection>(warp::reply::json(&BlockTemplate::new(&blockchain)))
+        });
+
+    // 提交序列化后的区块，校验通过后上链
+    let submit_block = warp::path!("mining" / "submit")
+        .and(warp::post())
+        .and(require("post", "/mining/submit"))
+        .and(warp::body::json())
+        .and(blockchain.clone())
+        .and(network.clone())
+        .and_then(
+            |req: SubmitBlockRequest,
+             blockchain: Arc<AsyncMutex<BlockChain>>,
+             network: NetworkHandle| async move {
+                let block = hex::decode(&req.block)
+                    .map_err(|e| e.to_string())
+                    .and_then(|bytes| deserialize_bc::<Block>(&bytes).map_err(|e| e.to_string()));
+                let block = match block {
+                    Ok(block) => block,
+                    Err(e) => {
+                        return Ok::<_, warp::Rejection>(warp::reply::with_status(
+                            warp::reply::json(&format!("Invalid block: {}", e)),
+                            StatusCode::BAD_REQUEST,
+                        ))
+                    }
+                };
+                let hash = hex::encode(hash_block_header(&block.header));
+                let result = blockchain.lock().await.submit_block(block);
+                match result {
+                    Ok(()) => {
+                        // 外部矿工挖出的区块立即通告给其他节点
+                        network.announce_tip();
+                        Ok(warp::reply::with_status(
+                            warp::reply::json(&hash),
+                            StatusCode::OK,
+                        ))
+                    }
+                    Err(e) => Ok(warp::reply::with_status(
+                        warp::reply::json(&format!("Block rejected: {}", e)),
+                        StatusCode::BAD_REQUEST,
+                    )),
+                }
+            },
+        );
+
+    // 启动后台挖矿
+    let start_mining = warp::path!("mining" / "start")
+        .and(warp::post())
+        .and(require("post", "/mining/start"))
+        .and(warp::body::json())
+        .and(mining_service.clone())
+        .and_then(
+            |req: StartMiningRequest, mining_service: Arc<MiningService>| async move {
+                if let Some(address) = req.address {
+                    if let Err(e) = mining_service.set_payout_address(Some(address)) {
+                        return Ok::<_, warp::Rejection>(warp::reply::with_status(
+                            warp::reply::json(&e),
+                            StatusCode::BAD_REQUEST,
+                        ));
+                    }
+                }
+                if let Some(threads) = req.threads {
+                    mining_service.set_threads(threads);
+                }
+                let message = if mining_service.start() {
+                    "Mining started"
+                } else {
+                    "Mining already running"
+                };
+                Ok(warp::reply::with_status(
+                    warp::reply::json(&message),
+                    StatusCode::OK,
+                ))
+            },
+        );
+
+    // 停止后台挖矿
+    let stop_mining = warp::path!("mining" / "stop")
+        .and(warp::post())
+        .and(require("post", "/mining/stop"))
+        .and(mining_service.clone())
+        .and_then(|mining_service: Arc<MiningService>| async move {
+            let message = if mining_service.stop() {
+                "Mining stopped"
+            } else {
+                "Mining not running"
+            };
+            Ok::<_, warp::Rejection>(warp::reply::json(&message))
+        });
+
+    // 查看挖矿状态
+    let mining_status = warp::path!("mining" / "status")
+        .and(warp::get())
+        .and(require("get", "/mining/status"))
+        .and(mining_service.clone())
+        .and_then(|mining_service: Arc<MiningService>| async move {
+            Ok::<_, warp::Rejection>(warp::reply::json(&mining_service.status().await))
+        });
+
+    // 查看区块链
+    let get_chain = warp::path("chain")
+        .and(warp::get())
+        .and(require("get", "/chain"))
+        .and(blockchain.clone())
+        .and_then(|blockchain: Arc<AsyncMutex<BlockChain>>| async move {
+            let blockchain = blockchain.lock().await;
+            Ok::<_, warp::Rejection>(warp::reply::json(&*blockchain))
+        });
+
+    // 当前链顶
+    let get_tip = warp::path("tip")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(require("get", "/tip"))
+        .and(blockchain.clone())
+        .and_then(|blockchain: Arc<AsyncMutex<BlockChain>>| async move {
+            let blockchain = blockchain.lock().await;
+            let tip = blockchain.blocks.last().unwrap();
+            Ok::<_, warp::Rejection>(warp::reply::json(&TipInfo {
+                height: blockchain.height(),
+                hash: hex::encode(blockchain.tip_hash()),
+                timestamp: tip.header.timestamp,
+                difficulty: blockchain.difficulty(),
+            }))
+        });
+
+    // 按哈希查询主链或分叉链上的区块
+    let get_block = warp::path!("block" / String)
+        .and(warp::get())
+        .and(require("get", "/block/{hash}"))
+        .and(blockchain.clone())
+        .and_then(
+            |hash: String, blockchain: Arc<AsyncMutex<BlockChain>>| async move {
+                let Some(hash) = decode_hash(&hash) else {
+                    return Ok::<_, warp::Rejection>(invalid_hash(&hash));
+                };
+                let blockchain = blockchain.lock().await;
+                let block = blockchain
+                    .get_block(&hash)
+                    .map(|block| BlockInfo::new(&blockchain, block));
+                Ok(found_or_404(block, "Block"))
+            },
+        );
+
+    // 按高度查询主链区块
+    let get_block_by_height = warp::path!("block" / "height" / u32)
+        .and(warp::get())
+        .and(require("get", "/block/height/{height}"))
+        .and(blockchain.clone())
+        .and_then(
+            |height: u32, blockchain: Arc<AsyncMutex<BlockChain>>| async move {
+                let blockchain = blockchain.lock().await;
+                let block = blockchain
+                    .block_at(height)
+                    .map(|block| BlockInfo::new(&blockchain, block));
+                Ok::<_, warp::Rejection>(found_or_404(block, "Block"))
+            },
+        );
+
+    // 从指定高度开始的主链区块头
+    let get_headers = warp::path("headers")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(require("get", "/headers"))
+        .and(warp::query::<HeadersQuery>())
+        .and(blockchain.clone())
+        .and_then(
+            |query: HeadersQuery, blockchain: Arc<AsyncMutex<BlockChain>>| async move {
+                let blockchain = blockchain.lock().await;
+                let from = query.from.unwrap_or(0) as usize;
+                let count = query.count.unwrap_or(MAX_HEADERS).min(MAX_HEADERS);
+                let headers: Vec<HeaderInfo> = blockchain
+                    .blocks
+                    .iter()
+                    .enumerate()
+                    .skip(from)
+                    .take(count)
+                    .map(|(height, block)| HeaderInfo {
+                        hash: hex::encode(hash_block_header(&block.header)),
+                        height: height as u32,
+                        header: &block.header,
+                    })
+                    .collect();
+                Ok::<_, warp::Rejection>(warp::reply::json(&headers))
+            },
+        );
+
+    // 按交易哈希查询交易池或主链中的交易
+    let get_tx = warp::path!("tx" / String)
+        .and(warp::get())
+        .and(require("get", "/tx/{txid}"))
+        .and(blockchain.clone())
+        .and_then(
+            |txid: String, blockchain: Arc<AsyncMutex<BlockChain>>| async move {
+                let Some(hash) = decode_hash(&txid) else {
+                    return Ok::<_, warp::Rejection>(invalid_hash(&txid));
+                };
+                let blockchain = blockchain.lock().await;
+                let tx = TransactionInfo::find(&blockchain, &hash);
+                Ok(found_or_404(tx, "Transaction"))
+            },
+        );
+
+    // 提交客户端自行构造并签名的交易，校验通过后加入交易池并通告给其他节点
+    let submit_raw_tx = warp::path!("tx" / "raw")
+        .and(warp::post())
+        .and(require("post", "/tx/raw"))
+        .and(warp::body::json())
+        .and(node.clone())
+        .and_then(|req: SubmitTransactionRequest, node: Node| async move {
+            let tx = hex::decode(&req.tx)
+                .map_err(|e| e.to_string())
+                .and_then(|bytes| deserialize_bc::<Transaction>(&bytes).map_err(|e| e.to_string()));
+            let (body, status) = match tx {
+                Err(e) => (
+                    warp::reply::json(&TransactionRejection {
+                        error: "malformed",
+                        message: format!("Invalid transaction: {}", e),
+                    }),
+                    StatusCode::BAD_REQUEST,
+                ),
+                Ok(tx) => match node.submit_transaction(tx).await {
+                    Ok(txid) => (
+                        warp::reply::json(&SubmittedTransaction {
+                            txid: hex::encode(txid),
+                        }),
+                        StatusCode::OK,
+                    ),
+                    Err(e) => {
+                        let status = match e {
+                            TransactionError::AlreadyKnown | TransactionError::DoubleSpend => {
+                                StatusCode::CONFLICT
+                            }
+                            _ => StatusCode::UNPROCESSABLE_ENTITY,
+                        };
+                        (
+                            warp::reply::json(&TransactionRejection {
+                                error: e,
+                                message: e.to_string(),
+                            }),
+                            status,
+                        )
+                    }
+                },
+            };
+            Ok::<_, warp::Rejection>(warp::reply::with_status(body, status))
+        });
+
+    // 分页查看主链区块，用上一页返回的 next_cursor 继续
+    let get_blocks = warp::path("blocks")
+        .and(warp::get())
+        .and(require("get", "/blocks"))
+        .and(warp::query::<BlocksQuery>())
+        .and(blockchain.clone())
+        .and_then(
+            |query: BlocksQuery, blockchain: Arc<AsyncMutex<BlockChain>>| async move {
+                let blockchain = blockchain.lock().await;
+                let start = match &query.cursor {
+                    None => 0,
+                    Some(cursor) => {
+                        let Some(hash) = decode_hash(cursor) else {
+                            return Ok::<_, warp::Rejection>(invalid_hash(cursor));
+                        };
+                        if !blockchain.is_main_chain(&hash) {
+                            return Ok(warp::reply::with_status(
+                                warp::reply::json(&"Cursor is no longer on the main chain"),
+                                StatusCode::CONFLICT,
+                            ));
+                        }
+                        blockchain.block_height(&hash).unwrap() as usize + 1
+                    }
+                };
+                let limit = query
+                    .limit
+                    .unwrap_or(DEFAULT_PAGE_SIZE)
+                    .clamp(1, MAX_PAGE_SIZE);
+                let end = (start + limit).min(blockchain.blocks.len());
+                let blocks = &blockchain.blocks[start.min(end)..end];
+                let next_cursor = match blocks.last() {
+                    Some(last) if end < blockchain.blocks.len() => {
+                        Some(hex::encode(hash_block_header(&last.header)))
+                    }
+                    _ => None,
+                };
+                Ok(warp::reply::with_status(
+                    warp::reply::json(&BlockPage {
+                        blocks,
+                        next_cursor,
+                    }),
+                    StatusCode::OK,
+                ))
+            },
+        );
+
+    // 流式导出主链区块
+    let export = warp::path("export")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(require("get", "/export"))
+        .and(warp::query::<ExportQuery>())
+        .and(blockchain.clone())
+        .and_then(
+            |query: ExportQuery, blockchain: Arc<AsyncMutex<BlockChain>>| async move {
+                let format = match parse_format(query.format.as_deref()) {
+                    Ok(format) => format,
+                    Err(e) => return Ok::<_, warp::Rejection>(bad_request(e).into_response()),
+                };
+                let (sender, body) = Body::channel();
+                tokio::spawn(export_blocks(
+                    blockchain,
+                    format,
+                    query.from.unwrap_or(0),
+                    sender,
+                ));
+                Ok(Response::builder()
+                    .header(CONTENT_TYPE, format.content_type())
+                    .body(body)
+                    .unwrap()
+                    .into_response())
+            },
+        );
+
+    // 导入区块：边接收边解析，每个区块都经过完整校验，遇到错误时停止
+    let import = warp::path("import")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(require("post", "/import"))
+        .and(warp::query::<ExportQuery>())
+        .and(warp::body::stream())
+        .and(blockchain.clone())
+        .and_then(
+            |query: ExportQuery, body, blockchain: Arc<AsyncMutex<BlockChain>>| {
+                import_blocks(query, body, blockchain)
+            },
+        );
+
+    // 查看区块链交易池
+    let get_transaction_pool = warp::path("pool")
+        .and(warp::get())
+        .and(require("get", "/pool"))
+        .and(blockchain.clone())
+        .and_then(|blockchain: Arc<AsyncMutex<BlockChain>>| async move {
+            let blockchain = blockchain.lock().await;
+            let pool = blockchain.transaction_pool.lock().unwrap();
+            Ok::<_, warp::Rejection>(warp::reply::json(&*pool))
+        });
+
+    // 查看当前连接和地址簿
+    let get_peers = warp::path("peers")
+        .and(warp::get())
+        .and(require("get", "/peers"))
+        .and(network.clone())
+        .and_then(|network: NetworkHandle| async move {
+            Ok::<_, warp::Rejection>(warp::reply::json(&PeersInfo {
+                connected: network.peers().await,
+                known: network.addresses().await,
+            }))
+        });
+
+    // 添加节点地址并立即连接
+    let add_peer = warp::path("peers")
+        .and(warp::post())
+        .and(require("post", "/peers"))
+        .and(warp::body::json())
+        .and(network.clone())
+        .and_then(|req: AddPeerRequest, network: NetworkHandle| async move {
+            match req.addr.parse::<SocketAddr>() {
+                Ok(addr) => {
+                    network.add_peer(addr);
+                    Ok::<_, warp::Rejection>(warp::reply::with_status(
+                        warp::reply::json(&format!("Connecting to {}", addr)),
+                        StatusCode::OK,
+                    ))
+                }
+                Err(e) => Ok(warp::reply::with_status(
+                    warp::reply::json(&format!("Invalid address: {}", e)),
+                    StatusCode::BAD_REQUEST,
+                )),
+            }
+        });
+
+    // 查看封禁列表
+    let get_bans = warp::path("bans")
+        .and(warp::get())
+        .and(require("get", "/bans"))
+        .and(network.clone())
+        .and_then(|network: NetworkHandle| async move {
+            Ok::<_, warp::Rejection>(warp::reply::json(&network.bans().await))
+        });
+
+    // 封禁地址并断开其连接
+    let add_ban = warp::path("bans")
+        .and(warp::post())
+        .and(require("post", "/bans"))
+        .and(warp::body::json())
+        .and(network.clone())
+        .and_then(|req: BanRequest, network: NetworkHandle| async move {
+            let duration_ms = match req.duration.map(|secs| secs.checked_mul(1000)) {
+                Some(None) => {
+                    return Ok::<_, warp::Rejection>(bad_request(
+                        "duration is too large".to_string(),
+                    ))
+                }
+                Some(Some(ms)) => Some(ms),
+                None => None,
+            };
+            let reason = req.reason.unwrap_or_else(|| "manually banned".to_string());
+            network.ban(req.ip, duration_ms, reason);
+            Ok(warp::reply::with_status(
+                warp::reply::json(&format!("Banned {}", req.ip)),
+                StatusCode::OK,
+            ))
+        });
+
+    // 解除封禁
+    let remove_ban = warp::path!("bans" / IpAddr)
+        .and(warp::delete())
+        .and(require("delete", "/bans/{ip}"))
+        .and(network.clone())
+        .and_then(|ip: IpAddr, network: NetworkHandle| async move {
+            let (message, status) = if network.unban(ip).await {
+                (format!("Unbanned {}", ip), StatusCode::OK)
+            } else {
+                (format!("{} is not banned", ip), StatusCode::NOT_FOUND)
+            };
+            Ok::<_, warp::Rejection>(warp::reply::with_status(
+                warp::reply::json(&message),
+                status,
+            ))
+        });
+
+    // WebSocket 事件订阅
+    let subscribe = warp::path("ws")
+        .and(warp::path::end())
+        .and(require("get", "/ws"))
+        .and(warp::ws())
+        .and(node.clone())
+        .map(|ws: warp::ws::Ws, node: Node| {
+            ws.on_upgrade(move |socket| websocket::serve(socket, node.events))
+        });
+
+    // JSON-RPC 2.0 接口，支持批量请求
+    let json_rpc = warp::path("rpc")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(identify)
+        .and(warp::body::content_length_limit(jsonrpc::MAX_REQUEST_SIZE))
+        .and(warp::body::bytes())
+        .and(node.clone())
+        .and_then(|group: Group, body: Bytes, node: Node| async move {
+            let reply = match jsonrpc::handle(&node, group, &body).await {
+                Some(response) => warp::reply::json(&response).into_response(),
+                None => StatusCode::NO_CONTENT.into_response(),
+            };
+            Ok::<_, warp::Rejection>(reply)
+        });
+
+    // 接口的 OpenAPI 文档
+    let document = Arc::new(openapi::document());
+    let get_openapi = warp::path("openapi.json")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(require("get", "/openapi.json"))
+        .map(move || warp::reply::json(&*document));
+
+    // 合并路由
+    create_transaction
+        .or(get_template)
+        .or(submit_block)
+        .or(get_chain)
+        .or(get_blocks)
+        .or(export)
+        .or(import)
+        .or(get_tip)
+        .or(get_block)
+        .or(get_block_by_height)
+        .or(get_headers)
+        .or(submit_raw_tx)
+        .or(get_tx)
+        .or(get_transaction_pool)
+        .or(start_mining)
+        .or(stop_mining)
+        .or(mining_status)
+        .or(get_peers)
+        .or(add_peer)
+        .or(get_bans)
+        .or(add_ban)
+        .or(remove_ban)
+        .or(json_rpc)
+        .or(subscribe)
+        .or(get_openapi)
+        .recover(auth::recover)
+}
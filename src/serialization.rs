@@ -6,7 +6,8 @@ pub fn serialize_bc<T>(blockchain: &T) -> Result<Vec<u8>, io::Error>
 where
     T: Serialize,
 {
-    let encoded: Vec<u8> = bincode::serialize(blockchain).unwrap();
+    let encoded: Vec<u8> = bincode::serialize(blockchain)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
     Ok(encoded)
 }
 
@@ -14,8 +15,7 @@ pub fn deserialize_bc<'a, T>(bytes: &'a [u8]) -> Result<T, io::Error>
 where
     T: Deserialize<'a> + Clone,
 {
-    let decoded: T = bincode::deserialize(bytes).unwrap();
+    let decoded: T =
+        bincode::deserialize(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
     Ok(decoded)
 }
-
-
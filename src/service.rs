@@ -1,91 +1,240 @@
-use warp::Filter;
-
-use ::block_chain::block_chain::BlockChain;
-use ::block_chain::transaction::Transaction;
-use serde::Deserialize;
+use crate::auth::{self, Access};
+use crate::block_chain::{BlockChain, TransactionError};
+use crate::config::{ConfigError, NodeConfig};
+use crate::events::EventBus;
+use crate::miner::Miner;
+use crate::mining::MiningService;
+use crate::network::tcp::{self, NetworkHandle};
+use crate::rpc;
+use crate::transaction::Transaction;
+use crate::wallet::{self, Wallet, WalletError};
+use futures_util::future::join_all;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::sync::Mutex as AsyncMutex;
+use std::time::Duration;
+use tokio::sync::{watch, Mutex as AsyncMutex};
+use tokio::task::JoinHandle;
+
+// 关闭时等待进行中的 HTTP 请求的最长时间，之后直接断开剩余连接
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 启动节点失败的原因
+#[derive(Debug)]
+pub enum NodeError {
+    Config(ConfigError),
+    DataDir(PathBuf, io::Error), // 无法创建数据目录
+    Cookie(PathBuf, io::Error),  // 无法写入 cookie 文件
+    Network(io::Error),          // P2P 网络无法启动
+    Rpc(SocketAddr, String),     // HTTP 服务无法监听
+    Wallet(WalletError),         // 无法打开钱包
+}
+
+impl fmt::Display for NodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NodeError::Config(e) => write!(f, "{}", e),
+            NodeError::DataDir(path, e) => {
+                write!(
+                    f,
+                    "failed to create data directory {}: {}",
+                    path.display(),
+                    e
+                )
+            }
+            NodeError::Cookie(path, e) => {
+                write!(f, "failed to write cookie {}: {}", path.display(), e)
+            }
+            NodeError::Network(e) => write!(f, "failed to start P2P network: {}", e),
+            NodeError::Rpc(addr, e) => write!(f, "failed to listen on {}: {}", addr, e),
+            NodeError::Wallet(e) => write!(f, "failed to open wallet: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for NodeError {}
+
+impl From<ConfigError> for NodeError {
+    fn from(e: ConfigError) -> Self {
+        NodeError::Config(e)
+    }
+}
+
+/// 运行中的节点的共享状态，HTTP 接口通过它访问链、网络和挖矿服务
+#[derive(Clone)]
+pub struct Node {
+    pub config: Arc<NodeConfig>,
+    pub blockchain: Arc<AsyncMutex<BlockChain>>,
+    pub network: NetworkHandle,
+    pub mining: Arc<MiningService>,
+    pub events: EventBus, // 区块链和交易池的事件总线
+    pub access: Arc<Access>,
+    pub wallet: Option<Arc<AsyncMutex<Wallet>>>, // 未启用钱包时为 None
+}
+
+impl Node {
+    /// 按配置启动节点：创建数据目录和区块链，启动 P2P 网络、挖矿服务和 HTTP 服务
+    ///
+    /// 监听地址的端口为 0 时由系统分配，实际地址从返回的句柄读取。
+    pub async fn start(config: NodeConfig) -> Result<NodeHandle, NodeError> {
+        config.validate()?;
+        fs::create_dir_all(&config.data_dir)
+            .map_err(|e| NodeError::DataDir(config.data_dir.clone(), e))?;
+        let cookie = if config.rpc.cookie {
+            let path = config.cookie_path();
+            Some(auth::write_cookie(&path).map_err(|e| NodeError::Cookie(path, e))?)
+        } else {
+            None
+        };
+        let access = Arc::new(Access::new(&config.rpc, cookie));
+        let wallet = match &config.wallet.password_file {
+            Some(password_file) if config.wallet.enabled => {
+                let password =
+                    wallet::read_password_file(password_file).map_err(NodeError::Wallet)?;
+                let wallet =
+                    Wallet::open(&config.wallet_path(), &password).map_err(NodeError::Wallet)?;
+                Some(Arc::new(AsyncMutex::new(wallet)))
+            }
+            _ => None,
+        };
+
+        let mut chain = BlockChain::new(config.network.difficulty());
+        chain.set_pool_limit(Some(config.mempool.max_transactions));
+        let events = chain.events().clone();
+        let blockchain = Arc::new(AsyncMutex::new(chain));
+
+        let network = tcp::start(config.network_config(), blockchain.clone())
+            .await
+            .map_err(NodeError::Network)?;
+
+        let miner = match config.mining.threads {
+            Some(threads) => Miner::new(threads),
+            None => Miner::default(),
+        };
+        let mining = Arc::new(MiningService::new(
+            blockchain.clone(),
+            miner,
+            config.mining.address.clone(),
+        ));
 
-#[derive(Deserialize)]
-struct CreateTransactionRequest {
-    lock_time: u32,
-    value: u64,
+        let node = Node {
+            config: Arc::new(config),
+            blockchain,
+            network,
+            mining,
+            events,
+            access,
+            wallet,
+        };
+        let (shutdown, _) = watch::channel(());
+        let routes = rpc::routes(&node);
+        let mut rpc_addrs = Vec::new();
+        let mut servers = Vec::new();
+        // 每个地址一个监听器，共用同一组路由；配置了证书时全部使用 HTTPS
+        for addr in node.config.rpc_addrs() {
+            let mut signal = shutdown.subscribe();
+            let graceful = async move {
+                let _ = signal.changed().await;
+            };
+            let bound = match &node.config.rpc.tls {
+                Some(tls) => warp::serve(routes.clone())
+                    .tls()
+                    .cert_path(&tls.cert)
+                    .key_path(&tls.key)
+                    .try_bind_with_graceful_shutdown(addr, graceful)
+                    .map(|(local, server)| (local, tokio::spawn(server))),
+                None => warp::serve(routes.clone())
+                    .try_bind_with_graceful_shutdown(addr, graceful)
+                    .map(|(local, server)| (local, tokio::spawn(server))),
+            };
+            match bound {
+                Ok((local, server)) => {
+                    rpc_addrs.push(local);
+                    servers.push(server);
+                }
+                Err(e) => {
+                    // 关闭已经启动的监听器
+                    let _ = shutdown.send(());
+                    join_all(servers).await;
+                    node.network.shutdown().await;
+                    if node.config.rpc.cookie {
+                        let _ = fs::remove_file(node.config.cookie_path());
+                    }
+                    return Err(NodeError::Rpc(addr, e.to_string()));
+                }
+            }
+        }
+
+        if node.config.mining.enabled {
+            node.mining.start();
+        }
+        Ok(NodeHandle {
+            node,
+            rpc_addrs,
+            shutdown,
+            servers,
+        })
+    }
+
+    /// 完整校验外部提交的交易，加入交易池后通告给其他节点，返回交易哈希
+    pub async fn submit_transaction(&self, tx: Transaction) -> Result<[u8; 32], TransactionError> {
+        let hash = self
+            .blockchain
+            .lock()
+            .await
+            .accept_transaction(tx.clone())?;
+        self.network.broadcast_transaction(tx);
+        Ok(hash)
+    }
 }
-async fn start_server(blockchain: Arc<AsyncMutex<BlockChain>>, port: u16) {
-    let blockchain = warp::any().map(move || blockchain.clone());
-
-    // 创建交易
-    let create_transaction = warp::path("transaction")
-        .and(warp::post())
-        .and(warp::body::json())
-        .and(blockchain.clone())
-        .and_then(
-            |req: CreateTransactionRequest, blockchain: Arc<AsyncMutex<BlockChain>>| async move {
-                let tx = Transaction::new(req.value, req.lock_time);
-                let mut blockchain = blockchain.lock().await;
-                blockchain.add_transaction(tx.clone());
-                blockchain.broadcast_transaction(tx, vec!["127.0.0.1:3031".to_string()]);
-                Ok::<_, warp::Rejection>(warp::reply::json(&"Transaction created and broadcasted"))
-            },
-        );
-
-    // 挖矿
-    let mine = warp::path("mine")
-        .and(warp::post())
-        .and(blockchain.clone())
-        .and_then(|blockchain: Arc<AsyncMutex<BlockChain>>| async move {
-            let mut blockchain = blockchain.lock().await;
-            blockchain.mine_block();
-            Ok::<_, warp::Rejection>(warp::reply::json(&"New block mined"))
-        });
-
-    // 查看区块链
-    let get_chain = warp::path("chain")
-        .and(warp::get())
-        .and(blockchain.clone())
-        .and_then(|blockchain: Arc<AsyncMutex<BlockChain>>| async move {
-            let blockchain = blockchain.lock().await;
-            Ok::<_, warp::Rejection>(warp::reply::json(&*blockchain))
-        });
-
-    // 查看区块链区块部分
-    let get_blocks = warp::path("blocks")
-        .and(warp::get())
-        .and(blockchain.clone())
-        .and_then(|blockchain: Arc<AsyncMutex<BlockChain>>| async move {
-            let blockchain = blockchain.lock().await;
-            Ok::<_, warp::Rejection>(warp::reply::json(&blockchain.blocks))
-        });
-
-    // 查看区块链交易池
-    let get_transaction_pool = warp::path("pool")
-        .and(warp::get())
-        .and(blockchain.clone())
-        .and_then(|blockchain: Arc<AsyncMutex<BlockChain>>| async move {
-            let blockchain = blockchain.lock().await;
-            let pool = blockchain.transaction_pool.lock().unwrap();
-            Ok::<_, warp::Rejection>(warp::reply::json(&*pool))
-        });
-
-    // 合并路由
-    let routes = create_transaction
-        .or(mine)
-        .or(get_chain)
-        .or(get_blocks)
-        .or(get_transaction_pool);
-
-    // 启动服务器
-    warp::serve(routes).run(([127, 0, 0, 1], port)).await;
+
+/// 节点的句柄，用来读取实际的监听地址和关闭节点
+pub struct NodeHandle {
+    node: Node,
+    rpc_addrs: Vec<SocketAddr>,
+    shutdown: watch::Sender<()>,
+    servers: Vec<JoinHandle<()>>,
 }
 
-#[tokio::main]
-async fn main() {
-    // 创建区块链
-    let blockchain = Arc::new(AsyncMutex::new(BlockChain::new(1)));
+impl NodeHandle {
+    pub fn node(&self) -> &Node {
+        &self.node
+    }
+
+    // 第一个监听器实际监听的 HTTP 地址
+    pub fn rpc_addr(&self) -> SocketAddr {
+        self.rpc_addrs[0]
+    }
+
+    // 全部监听器实际监听的 HTTP 地址，顺序与配置相同
+    pub fn rpc_addrs(&self) -> &[SocketAddr] {
+        &self.rpc_addrs
+    }
 
-    // 启动 HTTP 服务器
-    let server_handle = tokio::spawn(start_server(blockchain.clone(), 3030));
+    // 实际监听的 P2P 地址，不接受入站连接时为 None
+    pub fn p2p_addr(&self) -> Option<SocketAddr> {
+        self.node.network.local_addr()
+    }
 
-    // 等待服务器关闭
-    server_handle.await.unwrap();
+    /// 关闭节点：停止挖矿，等待 HTTP 服务处理完进行中的请求，再关闭 P2P 网络并删除 cookie 文件
+    pub async fn shutdown(mut self) {
+        self.node.mining.stop();
+        let _ = self.shutdown.send(());
+        // 客户端保持的空闲连接可能让服务一直等待
+        if tokio::time::timeout(SHUTDOWN_TIMEOUT, join_all(&mut self.servers))
+            .await
+            .is_err()
+        {
+            for server in &self.servers {
+                server.abort();
+            }
+        }
+        self.node.network.shutdown().await;
+        if self.node.config.rpc.cookie {
+            let _ = fs::remove_file(self.node.config.cookie_path());
+        }
+    }
 }
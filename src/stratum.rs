@@ -0,0 +1,477 @@
+use crate::block_chain::{Block, BlockChain, BlockHeader, BLOCK_REWARD};
+use crate::hash_function::{
+    hash_block_header, merkle_branch, merkle_root_from_branch, sha256_hash,
+};
+use crate::miner::{leading_zero_bits, meets_difficulty};
+use crate::serialization::serialize_bc;
+use crate::transaction::{decode_address, Transaction};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::sync::Mutex as AsyncMutex;
+
+// 检查链顶和交易池是否变化的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+// extranonce1 和 extranonce2 的字节数，合起来即 coinbase 的 8 字节额外随机数
+pub const EXTRANONCE1_SIZE: usize = 4;
+pub const EXTRANONCE2_SIZE: usize = 4;
+/// 同一链顶下保留的任务数，更早的任务被淘汰
+pub const MAX_JOBS: usize = 16;
+/// 单行请求的最大字节数，超出即断开连接
+pub const MAX_LINE_LENGTH: usize = 16 * 1024;
+
+// Stratum 错误码
+const ERR_OTHER: i64 = 20;
+const ERR_JOB_NOT_FOUND: i64 = 21;
+const ERR_DUPLICATE_SHARE: i64 = 22;
+const ERR_LOW_DIFFICULTY: i64 = 23;
+const ERR_UNAUTHORIZED: i64 = 24;
+const ERR_NOT_SUBSCRIBED: i64 = 25;
+
+/// Stratum 服务器配置
+#[derive(Debug, Clone)]
+pub struct StratumConfig {
+    pub payout_address: String, // coinbase 收款地址
+    pub share_difficulty: u32,  // 份额难度（前导零比特数），不超过网络难度
+}
+
+/// mining.notify 下发的挖矿任务
+#[derive(Debug, Clone, PartialEq)]
+pub struct Job {
+    pub job_id: String,
+    pub prev_hash: [u8; 32],
+    pub coinb1: Vec<u8>, // 序列化 coinbase 中 extranonce 之前的部分
+    pub coinb2: Vec<u8>, // 序列化 coinbase 中 extranonce 之后的部分
+    pub merkle_branch: Vec<[u8; 32]>,
+    pub version: u32,
+    pub bits: u32,
+    pub ntime: u32,
+    pub clean_jobs: bool,
+}
+
+impl Job {
+    // 转换为 mining.notify 的参数数组
+    pub fn to_params(&self) -> Value {
+        json!([
+            self.job_id,
+            hex::encode(self.prev_hash),
+            hex::encode(&self.coinb1),
+            hex::encode(&self.coinb2),
+            self.merkle_branch
+                .iter()
+                .map(hex::encode)
+                .collect::<Vec<_>>(),
+            format!("{:08x}", self.version),
+            format!("{:08x}", self.bits),
+            format!("{:08x}", self.ntime),
+            self.clean_jobs,
+        ])
+    }
+
+    // 从 mining.notify 的参数数组解析
+    pub fn from_params(params: &Value) -> Option<Job> {
+        let params = params.as_array()?;
+        let merkle_branch = params
+            .get(4)?
+            .as_array()?
+            .iter()
+            .map(|h| decode_hash(h.as_str()?))
+            .collect::<Option<Vec<_>>>()?;
+        Some(Job {
+            job_id: params.first()?.as_str()?.to_string(),
+            prev_hash: decode_hash(params.get(1)?.as_str()?)?,
+            coinb1: hex::decode(params.get(2)?.as_str()?).ok()?,
+            coinb2: hex::decode(params.get(3)?.as_str()?).ok()?,
+            merkle_branch,
+            version: decode_u32(params.get(5)?.as_str()?)?,
+            bits: decode_u32(params.get(6)?.as_str()?)?,
+            ntime: decode_u32(params.get(7)?.as_str()?)?,
+            clean_jobs: params.get(8)?.as_bool()?,
+        })
+    }
+
+    // 拼接出完整的序列化 coinbase 交易
+    pub fn coinbase(&self, extranonce1: &[u8], extranonce2: &[u8]) -> Vec<u8> {
+        [&self.coinb1[..], extranonce1, extranonce2, &self.coinb2[..]].concat()
+    }
+
+    // 根据矿工提交的参数构造区块头
+    pub fn header(
+        &self,
+        extranonce1: &[u8],
+        extranonce2: &[u8],
+        ntime: u32,
+        nonce: u32,
+    ) -> BlockHeader {
+        let coinbase = self.coinbase(extranonce1, extranonce2);
+        let leaf = sha256_hash(&coinbase).as_ref().try_into().unwrap();
+        BlockHeader {
+            version: self.version,
+            prev_block_hash: self.prev_hash,
+            merkle_root: merkle_root_from_branch(leaf, &self.merkle_branch),
+            timestamp: ntime,
+            bits: self.bits,
+            nonce,
+        }
+    }
+}
+
+pub fn decode_hash(s: &str) -> Option<[u8; 32]> {
+    hex::decode(s).ok()?.try_into().ok()
+}
+
+pub fn decode_u32(s: &str) -> Option<u32> {
+    u32::from_str_radix(s, 16).ok()
+}
+
+// 服务器保存的任务及其对应的区块模板
+struct JobEntry {
+    job: Job,
+    template: Block,
+    height: u32,
+    submitted: HashSet<(Vec<u8>, u32, u32)>, // 已提交的 (extranonce, ntime, nonce)
+}
+
+#[derive(Default)]
+struct ServerState {
+    jobs: HashMap<String, JobEntry>,
+    job_order: VecDeque<String>, // 任务按生成顺序排列，用于淘汰最早的任务
+    current_job: Option<String>,
+    clients: HashMap<u64, mpsc::UnboundedSender<String>>, // 已订阅的客户端
+    last_key: Option<([u8; 32], usize)>,
+    next_job_id: u64,
+}
+
+// 单个连接的会话状态
+struct Session {
+    client_id: u64,
+    extranonce1: [u8; EXTRANONCE1_SIZE],
+    subscribed: bool,
+    workers: HashSet<String>,
+}
+
+type RpcError = (i64, &'static str);
+
+/// 行分隔 JSON 的 Stratum 挖矿服务器
+pub struct StratumServer {
+    blockchain: Arc<AsyncMutex<BlockChain>>,
+    payout_script: Vec<u8>,
+    share_difficulty: u32,
+    state: Mutex<ServerState>,
+    next_client_id: AtomicU64,
+    shares_accepted: AtomicU64,
+    blocks_found: AtomicU64,
+}
+
+impl StratumServer {
+    pub fn new(
+        blockchain: Arc<AsyncMutex<BlockChain>>,
+        config: StratumConfig,
+    ) -> Result<Arc<Self>, String> {
+        let payout_script = decode_address(&config.payout_address)
+            .ok_or_else(|| format!("invalid address: {}", config.payout_address))?;
+        Ok(Arc::new(StratumServer {
+            blockchain,
+            payout_script,
+            share_difficulty: config.share_difficulty,
+            state: Mutex::new(ServerState::default()),
+            next_client_id: AtomicU64::new(1),
+            shares_accepted: AtomicU64::new(0),
+            blocks_found: AtomicU64::new(0),
+        }))
+    }
+
+    pub fn shares_accepted(&self) -> u64 {
+        self.shares_accepted.load(Ordering::SeqCst)
+    }
+
+    pub fn blocks_found(&self) -> u64 {
+        self.blocks_found.load(Ordering::SeqCst)
+    }
+
+    // 绑定地址并在后台接受连接和刷新任务，返回实际监听的地址
+    pub async fn listen(self: &Arc<Self>, addr: SocketAddr) -> io::Result<SocketAddr> {
+        let listener = TcpListener::bind(addr).await?;
+        let local_addr = listener.local_addr()?;
+        self.refresh_job().await;
+
+        let server = Arc::downgrade(self);
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let Some(server) = server.upgrade() else {
+                    break;
+                };
+                tokio::spawn(server.handle_client(stream));
+            }
+        });
+
+        let server = Arc::downgrade(self);
+        tokio::spawn(refresh_loop(server));
+        Ok(local_addr)
+    }
+
+    // 链顶或交易池变化时生成新任务并通知所有客户端
+    async fn refresh_job(&self) {
+        let (template, height, key) = {
+            let blockchain = self.blockchain.lock().await;
+            let pool_len = blockchain.transaction_pool.lock().unwrap().len();
+            let key = (blockchain.tip_hash(), pool_len);
+            if self.state.lock().unwrap().last_key == Some(key) {
+                return;
+            }
+            let template = blockchain.block_template(Some(&self.payout_script));
+            (template, blockchain.blocks.len() as u32, key)
+        };
+
+        // 以不同的额外随机数序列化 coinbase，找到 extranonce 在字节中的位置
+        let coinbase = |extra_nonce| {
+            let tx = Transaction::coinbase(
+                height,
+                extra_nonce,
+                BLOCK_REWARD,
+                self.payout_script.clone(),
+            );
+            serialize_bc(&tx).unwrap()
+        };
+        let low = coinbase(0);
+        let high = coinbase(u64::MAX);
+        let offset = low.iter().zip(&high).position(|(a, b)| a != b).unwrap();
+        let extranonce_size = EXTRANONCE1_SIZE + EXTRANONCE2_SIZE;
+
+        let mut state = self.state.lock().unwrap();
+        let clean_jobs = state.last_key.map(|(tip, _)| tip) != Some(key.0);
+        if clean_jobs {
+            state.jobs.clear();
+            state.job_order.clear();
+        }
+        while state.job_order.len() >= MAX_JOBS {
+            let oldest = state.job_order.pop_front().unwrap();
+            state.jobs.remove(&oldest);
+        }
+        state.next_job_id += 1;
+        let job = Job {
+            job_id: format!("{:x}", state.next_job_id),
+            prev_hash: template.header.prev_block_hash,
+            coinb1: low[..offset].to_vec(),
+            coinb2: low[offset + extranonce_size..].to_vec(),
+            merkle_branch: merkle_branch(&template.transactions),
+            version: template.header.version,
+            bits: template.header.bits,
+            ntime: template.header.timestamp,
+            clean_jobs,
+        };
+        let notify = notification("mining.notify", job.to_params());
+        state
+            .clients
+            .retain(|_, client| client.send(notify.clone()).is_ok());
+        state.current_job = Some(job.job_id.clone());
+        state.last_key = Some(key);
+        state.job_order.push_back(job.job_id.clone());
+        state.jobs.insert(
+            job.job_id.clone(),
+            JobEntry {
+                job,
+                template,
+                height,
+                submitted: HashSet::new(),
+            },
+        );
+    }
+
+    async fn handle_client(self: Arc<Self>, stream: TcpStream) {
+        let (reader, mut writer) = stream.into_split();
+        let (sender, mut receiver) = mpsc::unbounded_channel::<String>();
+        tokio::spawn(async move {
+            while let Some(line) = receiver.recv().await {
+                if writer
+                    .write_all(format!("{}\n", line).as_bytes())
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        let client_id = self.next_client_id.fetch_add(1, Ordering::SeqCst);
+        let mut session = Session {
+            client_id,
+            extranonce1: (client_id as u32).to_le_bytes(),
+            subscribed: false,
+            workers: HashSet::new(),
+        };
+
+        let mut reader = BufReader::new(reader);
+        let mut line = String::new();
+        loop {
+            line.clear();
+            // 最多读入 MAX_LINE_LENGTH 字节加换行符，避免无换行的超长输入占满内存
+            let limit = MAX_LINE_LENGTH as u64 + 1;
+            match (&mut reader).take(limit).read_line(&mut line).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) if n as u64 == limit && !line.ends_with('\n') => break,
+                Ok(_) => {}
+            }
+            let request: Value = match serde_json::from_str(&line) {
+                Ok(request) => request,
+                Err(_) => break,
+            };
+            let id = request.get("id").cloned().unwrap_or(Value::Null);
+            let method = request.get("method").and_then(Value::as_str).unwrap_or("");
+            let params = request.get("params").cloned().unwrap_or(json!([]));
+
+            let was_subscribed = session.subscribed;
+            let result = self.handle_request(&mut session, method, &params).await;
+            let response = match result {
+                Ok(result) => json!({"id": id, "result": result, "error": null}),
+                Err((code, message)) => {
+                    json!({"id": id, "result": null, "error": [code, message, null]})
+                }
+            };
+            if sender.send(response.to_string()).is_err() {
+                break;
+            }
+            if session.subscribed && !was_subscribed {
+                self.register_client(client_id, &sender);
+            }
+        }
+
+        self.state.lock().unwrap().clients.remove(&client_id);
+    }
+
+    // 订阅成功后下发份额难度和当前任务，并加入通知列表
+    fn register_client(&self, client_id: u64, sender: &mpsc::UnboundedSender<String>) {
+        let mut state = self.state.lock().unwrap();
+        let _ = sender.send(notification(
+            "mining.set_difficulty",
+            json!([self.share_difficulty]),
+        ));
+        if let Some(entry) = state.current_job.as_ref().and_then(|id| state.jobs.get(id)) {
+            let mut job = entry.job.clone();
+            job.clean_jobs = true;
+            let _ = sender.send(notification("mining.notify", job.to_params()));
+        }
+        state.clients.insert(client_id, sender.clone());
+    }
+
+    async fn handle_request(
+        &self,
+        session: &mut Session,
+        method: &str,
+        params: &Value,
+    ) -> Result<Value, RpcError> {
+        match method {
+            "mining.subscribe" => {
+                session.subscribed = true;
+                Ok(json!([
+                    session.client_id.to_string(),
+                    hex::encode(session.extranonce1),
+                    EXTRANONCE2_SIZE
+                ]))
+            }
+            "mining.authorize" => {
+                let worker = params
+                    .get(0)
+                    .and_then(Value::as_str)
+                    .ok_or((ERR_OTHER, "Missing worker name"))?;
+                session.workers.insert(worker.to_string());
+                Ok(json!(true))
+            }
+            "mining.submit" => self.submit_share(session, params).await,
+            _ => Err((ERR_OTHER, "Unknown method")),
+        }
+    }
+
+    async fn submit_share(&self, session: &Session, params: &Value) -> Result<Value, RpcError> {
+        if !session.subscribed {
+            return Err((ERR_NOT_SUBSCRIBED, "Not subscribed"));
+        }
+        let field = |i: usize| params.get(i).and_then(Value::as_str);
+        let worker = field(0).ok_or((ERR_OTHER, "Invalid params"))?;
+        if !session.workers.contains(worker) {
+            return Err((ERR_UNAUTHORIZED, "Unauthorized worker"));
+        }
+        let job_id = field(1).ok_or((ERR_OTHER, "Invalid params"))?;
+        let extranonce2 = field(2)
+            .and_then(|s| hex::decode(s).ok())
+            .filter(|e| e.len() == EXTRANONCE2_SIZE)
+            .ok_or((ERR_OTHER, "Invalid extranonce2"))?;
+        let ntime = field(3)
+            .and_then(decode_u32)
+            .ok_or((ERR_OTHER, "Invalid ntime"))?;
+        let nonce = field(4)
+            .and_then(decode_u32)
+            .ok_or((ERR_OTHER, "Invalid nonce"))?;
+
+        let block = {
+            let mut state = self.state.lock().unwrap();
+            let entry = state
+                .jobs
+                .get_mut(job_id)
+                .ok_or((ERR_JOB_NOT_FOUND, "Job not found"))?;
+            let extranonce = [&session.extranonce1[..], &extranonce2[..]].concat();
+            let share = (extranonce, ntime, nonce);
+            if entry.submitted.contains(&share) {
+                return Err((ERR_DUPLICATE_SHARE, "Duplicate share"));
+            }
+
+            let header = entry
+                .job
+                .header(&session.extranonce1, &extranonce2, ntime, nonce);
+            let hash = hash_block_header(&header);
+            // 份额难度不超过网络难度
+            let share_bits = self.share_difficulty.min(entry.job.bits.saturating_mul(8));
+            if leading_zero_bits(&hash) < share_bits {
+                return Err((ERR_LOW_DIFFICULTY, "Low difficulty share"));
+            }
+            // 只记录达到份额难度的提交，无效提交不占用内存
+            entry.submitted.insert(share.clone());
+            self.shares_accepted.fetch_add(1, Ordering::SeqCst);
+
+            if !meets_difficulty(&hash, entry.job.bits as usize) {
+                return Ok(json!(true));
+            }
+            // 达到网络难度：用矿工的 extranonce 还原 coinbase 并组装区块
+            let extra_nonce = u64::from_le_bytes(share.0.try_into().unwrap());
+            let mut transactions = entry.template.transactions.clone();
+            transactions[0] = Transaction::coinbase(
+                entry.height,
+                extra_nonce,
+                BLOCK_REWARD,
+                self.payout_script.clone(),
+            );
+            Block {
+                header,
+                transactions,
+            }
+        };
+
+        if self.blockchain.lock().await.submit_mined_block(block) {
+            self.blocks_found.fetch_add(1, Ordering::SeqCst);
+            crate::log!(Info, "Stratum share found a block");
+        }
+        self.refresh_job().await;
+        Ok(json!(true))
+    }
+}
+
+fn notification(method: &str, params: Value) -> String {
+    json!({"id": null, "method": method, "params": params}).to_string()
+}
+
+async fn refresh_loop(server: Weak<StratumServer>) {
+    loop {
+        tokio::time::sleep(POLL_INTERVAL).await;
+        match server.upgrade() {
+            Some(server) => server.refresh_job().await,
+            None => break,
+        }
+    }
+}
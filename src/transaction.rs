@@ -1,15 +1,21 @@
 use crate::hash_function::sha256_hash;
-use reqwest;
 use ring::signature::{Ed25519KeyPair, KeyPair};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct TxIn {
     pub previous_output: [u8; 32], // 交易输入的哈希值
     pub script_sig: Vec<u8>,       // 解锁脚本
     pub sequence: u32,             // 序列号
 }
 
+impl Default for TxIn {
+    fn default() -> Self {
+        TxIn::new()
+    }
+}
+
 impl TxIn {
     pub fn new() -> Self {
         let previous_output: [u8; 32] = [0; 32];
@@ -21,9 +27,13 @@ impl TxIn {
             sequence,
         }
     }
+    // 签名输入的公钥（script_sig 的第 64..96 字节），未签名时为 None
+    pub fn public_key(&self) -> Option<&[u8]> {
+        self.script_sig.get(64..96)
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct TxOut {
     pub value: u64,             // 交易输出金额
     pub script_pubkey: Vec<u8>, // 锁定脚本
@@ -38,7 +48,21 @@ impl TxOut {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+// 地址即公钥（锁定脚本）的十六进制编码
+pub fn encode_address(script_pubkey: &[u8]) -> String {
+    hex::encode(script_pubkey)
+}
+
+// 将地址解码为锁定脚本，只接受 32 字节的 Ed25519 公钥
+pub fn decode_address(address: &str) -> Option<Vec<u8>> {
+    let script_pubkey = hex::decode(address).ok()?;
+    if script_pubkey.len() != 32 {
+        return None;
+    }
+    Some(script_pubkey)
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct Transaction {
     pub version: u32,        // 版本号
     pub inputs: Vec<TxIn>,   // 交易输入
@@ -60,6 +84,46 @@ impl Transaction {
         }
     }
 
+    // 创建 coinbase 交易，输入中记录区块高度和额外随机数
+    pub fn coinbase(height: u32, extra_nonce: u64, value: u64, script_pubkey: Vec<u8>) -> Self {
+        let mut script_sig = height.to_le_bytes().to_vec();
+        script_sig.extend_from_slice(&extra_nonce.to_le_bytes());
+        Transaction {
+            version: 0,
+            inputs: vec![TxIn {
+                previous_output: [0; 32],
+                script_sig,
+                sequence: u32::MAX,
+            }],
+            outputs: vec![TxOut {
+                value,
+                script_pubkey,
+            }],
+            lock_time: 0,
+        }
+    }
+
+    // 是否为 coinbase 交易
+    pub fn is_coinbase(&self) -> bool {
+        self.inputs.len() == 1
+            && self.inputs[0].previous_output == [0; 32]
+            && self.inputs[0].sequence == u32::MAX
+    }
+
+    // 判断交易在给定高度和时间是否已生效
+    pub fn is_final(&self, height: u32, timestamp: u32) -> bool {
+        if self.lock_time == 0 {
+            // lock_time 为 0，表示交易立即生效
+            true
+        } else if self.lock_time < 500_000_000 {
+            // lock_time 表示区块高度
+            height >= self.lock_time
+        } else {
+            // lock_time 表示时间戳
+            timestamp >= self.lock_time
+        }
+    }
+
     // 计算交易的哈希值
     pub fn hash(&self) -> [u8; 32] {
         let serialized = serde_json::to_vec(self).unwrap();
@@ -72,22 +136,23 @@ impl Transaction {
         serde_json::from_slice(data)
     }
 
+    // 签名消息：清空全部输入的 script_sig 后的序列化数据，
+    // 这样多个输入可以按任意顺序签名，签名之间互不影响
+    fn signature_message(&self) -> Vec<u8> {
+        let mut unsigned = self.clone();
+        for input in &mut unsigned.inputs {
+            input.script_sig = Vec::new();
+        }
+        serde_json::to_vec(&unsigned).unwrap()
+    }
+
     // 签名交易
     pub fn sign(&mut self, key_pair: &Ed25519KeyPair, input_index: usize) {
         if input_index >= self.inputs.len() {
             panic!("Input index out of bounds");
         }
 
-        // 在签名前，清空 script_sig
-        let original_script_sig = self.inputs[input_index].script_sig.clone();
-        self.inputs[input_index].script_sig = Vec::new();
-
-        // 使用交易的序列化数据作为签名消息
-        let message = serde_json::to_vec(self).unwrap();
-        let signature = key_pair.sign(&message);
-
-        // 恢复 script_sig
-        self.inputs[input_index].script_sig = original_script_sig;
+        let signature = key_pair.sign(&self.signature_message());
 
         // 将签名和公钥存储在 script_sig 中
         let mut script_sig = Vec::new();
@@ -109,26 +174,9 @@ impl Transaction {
 
         let signature = &script_sig[..64]; // 前 64 字节是签名
         let public_key = &script_sig[64..96]; // 后面的 32 字节是公钥
-                                              // 克隆交易并清空 script_sig
-        let mut tx_clone = self.clone();
-        tx_clone.inputs[input_index].script_sig = Vec::new();
-
-        // 使用克隆后的交易序列化数据作为验证消息
-        let message = serde_json::to_vec(&tx_clone).unwrap();
+        let message = self.signature_message();
         let public_key =
             ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, public_key);
         public_key.verify(&message, signature.as_ref()).is_ok()
     }
-
-    // 广播交易到其他节点
-    pub async fn broadcast_transaction(&self, node_url: &str) -> Result<(), reqwest::Error> {
-        let client = reqwest::Client::new();
-        let res = client
-            .post(&format!("{}/transactions", node_url))
-            .json(&self)
-            .send()
-            .await?;
-        res.error_for_status()?;
-        Ok(())
-    }
 }
@@ -0,0 +1,219 @@
+use rand::Rng;
+
+// bincode 序列化后的交易大小：版本号、输入和输出数组的长度、lock_time
+pub const TX_BASE_SIZE: u64 = 4 + 8 + 8 + 4;
+// 已签名输入：父交易哈希、script_sig（长度 + 64 字节签名 + 32 字节公钥）、序列号
+pub const INPUT_SIZE: u64 = 32 + 8 + 96 + 4;
+// 输出：金额、script_pubkey（长度 + 32 字节公钥）
+pub const OUTPUT_SIZE: u64 = 8 + 8 + 32;
+// 默认费率：每 1000 字节 1 个单位
+pub const DEFAULT_FEE_RATE: u64 = 1;
+// 允许的最高费率，防止误填的费率把全部余额作为手续费
+pub const MAX_FEE_RATE: u64 = 1_000_000;
+// 分支定界最多尝试的次数
+const BNB_MAX_TRIES: usize = 100_000;
+// 随机近似求和的轮数
+const KNAPSACK_ITERATIONS: usize = 1000;
+
+/// 按费率（每 1000 字节）计算指定大小的手续费，向上取整；溢出时返回 None
+pub fn fee_for_size(fee_rate: u64, size: u64) -> Option<u64> {
+    Some(fee_rate.checked_mul(size)?.div_ceil(1000))
+}
+
+/// 选币结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selection {
+    pub inputs: Vec<usize>, // 选中的币在候选列表中的位置，按升序排列
+    pub change: u64,        // 找零金额，为 0 时不需要找零输出
+    pub fee: u64,
+}
+
+/// 从候选币中选出支付 `amount` 和手续费所需的币
+///
+/// 先用分支定界寻找不需要找零的组合（超出部分不多于一次找零的成本，直接作为手续费），
+/// 找不到时用随机近似求和的背包算法，并把多余的金额找零。余额不足或金额计算溢出时返回 None。
+pub fn select_coins(
+    values: &[u64],
+    amount: u64,
+    fee_rate: u64,
+    rng: &mut impl Rng,
+) -> Option<Selection> {
+    // 全部币值之和不溢出时，下面任意组合的金额之和也不会溢出
+    values
+        .iter()
+        .try_fold(0u64, |sum, &value| sum.checked_add(value))?;
+    let input_fee = fee_for_size(fee_rate, INPUT_SIZE)?;
+    let change_fee = fee_for_size(fee_rate, OUTPUT_SIZE)?;
+    // 增加找零输出并在以后花掉它的成本
+    let cost_of_change = change_fee.checked_add(input_fee)?;
+    // 不含输入的交易本身需要的金额
+    let target = amount.checked_add(fee_for_size(fee_rate, TX_BASE_SIZE + OUTPUT_SIZE)?)?;
+
+    // 有效值：币值扣除花费它的手续费，不足以支付手续费的币不参与选择
+    let mut coins: Vec<(usize, u64)> = values
+        .iter()
+        .enumerate()
+        .filter_map(|(index, &value)| Some((index, value.checked_sub(input_fee)?)))
+        .filter(|&(_, effective)| effective > 0)
+        .collect();
+    coins.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    let (mut inputs, change) = match branch_and_bound(&coins, target, cost_of_change) {
+        Some(inputs) => (inputs, 0),
+        None => {
+            // 找零至少要值得以后花掉
+            let min_change = change_fee.checked_add(cost_of_change)?;
+            let inputs = target
+                .checked_add(min_change)
+                .and_then(|with_change| knapsack(&coins, with_change, rng))
+                .or_else(|| knapsack(&coins, target, rng))?;
+            let excess = inputs.iter().map(|&i| coins[i].1).sum::<u64>() - target;
+            let change = if excess >= min_change {
+                excess - change_fee
+            } else {
+                0
+            };
+            (inputs, change)
+        }
+    };
+    let mut selected: Vec<usize> = inputs.drain(..).map(|i| coins[i].0).collect();
+    selected.sort_unstable();
+    let total: u64 = selected.iter().map(|&index| values[index]).sum();
+    Some(Selection {
+        fee: total - amount - change,
+        inputs: selected,
+        change,
+    })
+}
+
+// 深度优先搜索有效值之和落在 [target, target + cost_of_change] 内的组合，
+// 返回超出部分最小的一个；`coins` 按有效值降序排列，返回其中的位置
+fn branch_and_bound(
+    coins: &[(usize, u64)],
+    target: u64,
+    cost_of_change: u64,
+) -> Option<Vec<usize>> {
+    // remaining[i] 为第 i 个及之后的币的有效值之和
+    let mut remaining = vec![0u64; coins.len() + 1];
+    for i in (0..coins.len()).rev() {
+        remaining[i] = remaining[i + 1] + coins[i].1;
+    }
+    if remaining[0] < target {
+        return None;
+    }
+    let mut best: Option<(u64, Vec<usize>)> = None;
+    let mut selected: Vec<usize> = Vec::new();
+    let mut sum = 0u64;
+    let mut index = 0;
+    for _ in 0..BNB_MAX_TRIES {
+        // 当前组合超出上限或剩下的币不够时回溯
+        let backtrack =
+            if sum > target.saturating_add(cost_of_change) || sum + remaining[index] < target {
+                true
+            } else if sum >= target {
+                let waste = sum - target;
+                if best
+                    .as_ref()
+                    .is_none_or(|(best_waste, _)| waste < *best_waste)
+                {
+                    best = Some((waste, selected.clone()));
+                }
+                if waste == 0 {
+                    break;
+                }
+                true
+            } else {
+                index == coins.len()
+            };
+        if backtrack {
+            // 撤销最近选中的币，改为不选它，继续搜索后面的币
+            let Some(last) = selected.pop() else {
+                break;
+            };
+            sum -= coins[last].1;
+            index = last + 1;
+        } else {
+            selected.push(index);
+            sum += coins[index].1;
+            index += 1;
+        }
+    }
+    best.map(|(_, selected)| selected)
+}
+
+// 背包算法：优先使用单个恰好相等的币，否则在比目标小的币中随机近似求和，
+// 与比目标大的最小的币比较，取总额较小者
+fn knapsack(coins: &[(usize, u64)], target: u64, rng: &mut impl Rng) -> Option<Vec<usize>> {
+    if let Some(exact) = coins.iter().position(|&(_, value)| value == target) {
+        return Some(vec![exact]);
+    }
+    let smaller: Vec<usize> = (0..coins.len()).filter(|&i| coins[i].1 < target).collect();
+    let lowest_larger = (0..coins.len())
+        .filter(|&i| coins[i].1 > target)
+        .min_by_key(|&i| coins[i].1);
+    let total_smaller: u64 = smaller.iter().map(|&i| coins[i].1).sum();
+    if total_smaller == target {
+        return Some(smaller);
+    }
+    if total_smaller < target {
+        return lowest_larger.map(|i| vec![i]);
+    }
+
+    let values: Vec<u64> = smaller.iter().map(|&i| coins[i].1).collect();
+    let (best_sum, included) = approximate_best_subset(&values, target, rng);
+    if let Some(larger) = lowest_larger {
+        if best_sum != target && coins[larger].1 <= best_sum {
+            return Some(vec![larger]);
+        }
+    }
+    Some(
+        included
+            .iter()
+            .zip(&smaller)
+            .filter(|(&included, _)| included)
+            .map(|(_, &i)| i)
+            .collect(),
+    )
+}
+
+// 多轮随机尝试：第一遍随机选币，第二遍依次补上未选的币，
+// 每次达到目标后记录总额并撤销最后一个币，继续寻找更接近目标的组合
+fn approximate_best_subset(values: &[u64], target: u64, rng: &mut impl Rng) -> (u64, Vec<bool>) {
+    let mut best = vec![true; values.len()];
+    let mut best_sum: u64 = values.iter().sum();
+    for _ in 0..KNAPSACK_ITERATIONS {
+        if best_sum == target {
+            break;
+        }
+        let mut included = vec![false; values.len()];
+        let mut sum = 0;
+        let mut reached = false;
+        for pass in 0..2 {
+            if reached {
+                break;
+            }
+            for i in 0..values.len() {
+                let take = if pass == 0 {
+                    rng.random_bool(0.5)
+                } else {
+                    !included[i]
+                };
+                if !take {
+                    continue;
+                }
+                sum += values[i];
+                included[i] = true;
+                if sum >= target {
+                    reached = true;
+                    if sum < best_sum {
+                        best_sum = sum;
+                        best = included.clone();
+                    }
+                    sum -= values[i];
+                    included[i] = false;
+                }
+            }
+        }
+    }
+    (best_sum, best)
+}
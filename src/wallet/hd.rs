@@ -0,0 +1,246 @@
+use crate::transaction::encode_address;
+use ring::hmac;
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use std::fmt;
+use std::str::FromStr;
+
+// 硬化派生的索引下限，Ed25519 只支持硬化派生
+pub const HARDENED: u32 = 0x8000_0000;
+// BIP44 路径 m/44'/币种'/账户'/链'/索引' 中的用途和币种
+pub const PURPOSE: u32 = 44;
+pub const COIN_TYPE: u32 = 1;
+// SLIP-10 为 Ed25519 规定的主密钥 HMAC 密钥
+const ED25519_SEED_KEY: &[u8] = b"ed25519 seed";
+
+/// 派生路径无效的原因
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathError {
+    Syntax(String),   // 不是 m/a'/b'/... 的形式
+    NotHardened(u32), // Ed25519 不支持非硬化派生
+    Overflow(u32),    // 索引不小于 2^31
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PathError::Syntax(path) => write!(f, "invalid derivation path: {}", path),
+            PathError::NotHardened(index) => {
+                write!(f, "ed25519 only supports hardened derivation: {}", index)
+            }
+            PathError::Overflow(index) => write!(f, "derivation index out of range: {}", index),
+        }
+    }
+}
+
+impl std::error::Error for PathError {}
+
+/// 派生路径，保存的索引都已加上 HARDENED
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct DerivationPath(Vec<u32>);
+
+impl DerivationPath {
+    /// 由未硬化的索引构造，如 [44, 1, 0] 表示 m/44'/1'/0'
+    pub fn hardened(indexes: &[u32]) -> Result<Self, PathError> {
+        indexes
+            .iter()
+            .map(|&index| {
+                if index >= HARDENED {
+                    Err(PathError::Overflow(index))
+                } else {
+                    Ok(index | HARDENED)
+                }
+            })
+            .collect::<Result<_, _>>()
+            .map(DerivationPath)
+    }
+
+    pub fn indexes(&self) -> &[u32] {
+        &self.0
+    }
+
+    pub fn child(&self, index: u32) -> Result<Self, PathError> {
+        let child = DerivationPath::hardened(&[index])?;
+        Ok(DerivationPath([self.0.as_slice(), &child.0].concat()))
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = PathError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut segments = s.split('/');
+        if segments.next() != Some("m") {
+            return Err(PathError::Syntax(s.to_string()));
+        }
+        let mut indexes = Vec::new();
+        for segment in segments {
+            let (digits, hardened) = match segment
+                .strip_suffix('\'')
+                .or_else(|| segment.strip_suffix('h'))
+                .or_else(|| segment.strip_suffix('H'))
+            {
+                Some(digits) => (digits, true),
+                None => (segment, false),
+            };
+            let index: u32 = digits
+                .parse()
+                .map_err(|_| PathError::Syntax(s.to_string()))?;
+            if !hardened {
+                return Err(PathError::NotHardened(index));
+            }
+            indexes.push(index);
+        }
+        DerivationPath::hardened(&indexes)
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("m")?;
+        for index in &self.0 {
+            write!(f, "/{}'", index - HARDENED)?;
+        }
+        Ok(())
+    }
+}
+
+/// SLIP-10 扩展私钥：32 字节私钥种子和 32 字节链码
+#[derive(Clone, PartialEq, Eq)]
+pub struct ExtendedKey {
+    secret: [u8; 32],
+    chain_code: [u8; 32],
+}
+
+// 不在日志中输出私钥
+impl fmt::Debug for ExtendedKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ExtendedKey({})", hex::encode(self.public_key()))
+    }
+}
+
+impl ExtendedKey {
+    // HMAC-SHA512 的结果左半部分为私钥，右半部分为链码
+    fn from_hmac(key: &[u8], data: &[u8]) -> Self {
+        let tag = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA512, key), data);
+        let (secret, chain_code) = tag.as_ref().split_at(32);
+        ExtendedKey {
+            secret: secret.try_into().unwrap(),
+            chain_code: chain_code.try_into().unwrap(),
+        }
+    }
+
+    /// 由种子生成主密钥
+    pub fn master(seed: &[u8]) -> Self {
+        ExtendedKey::from_hmac(ED25519_SEED_KEY, seed)
+    }
+
+    /// 硬化派生子密钥，`index` 为已加上 HARDENED 的索引
+    pub fn child(&self, index: u32) -> Self {
+        debug_assert!(index >= HARDENED);
+        let mut data = Vec::with_capacity(37);
+        data.push(0);
+        data.extend_from_slice(&self.secret);
+        data.extend_from_slice(&(index | HARDENED).to_be_bytes());
+        ExtendedKey::from_hmac(&self.chain_code, &data)
+    }
+
+    pub fn derive(&self, path: &DerivationPath) -> Self {
+        path.indexes()
+            .iter()
+            .fold(self.clone(), |key, &index| key.child(index))
+    }
+
+    pub fn secret(&self) -> &[u8; 32] {
+        &self.secret
+    }
+
+    pub fn chain_code(&self) -> &[u8; 32] {
+        &self.chain_code
+    }
+
+    pub fn key_pair(&self) -> Ed25519KeyPair {
+        Ed25519KeyPair::from_seed_unchecked(&self.secret).unwrap()
+    }
+
+    pub fn public_key(&self) -> [u8; 32] {
+        self.key_pair().public_key().as_ref().try_into().unwrap()
+    }
+}
+
+/// 账户下的两条密钥链
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyChain {
+    Receive, // 收款地址
+    Change,  // 找零地址
+}
+
+impl KeyChain {
+    pub fn index(self) -> u32 {
+        match self {
+            KeyChain::Receive => 0,
+            KeyChain::Change => 1,
+        }
+    }
+}
+
+/// 由账户派生出的一个密钥
+#[derive(Debug, Clone)]
+pub struct DerivedKey {
+    pub path: DerivationPath,
+    pub chain: KeyChain,
+    pub index: u32,
+    pub key: ExtendedKey,
+}
+
+impl DerivedKey {
+    pub fn key_pair(&self) -> Ed25519KeyPair {
+        self.key.key_pair()
+    }
+
+    pub fn public_key(&self) -> [u8; 32] {
+        self.key.public_key()
+    }
+
+    pub fn address(&self) -> String {
+        encode_address(&self.public_key())
+    }
+}
+
+/// BIP44 账户 m/44'/币种'/账户'，其下为收款链和找零链
+#[derive(Debug, Clone)]
+pub struct Account {
+    path: DerivationPath,
+    key: ExtendedKey,
+}
+
+impl Account {
+    pub fn new(seed: &[u8], account: u32) -> Result<Self, PathError> {
+        let path = DerivationPath::hardened(&[PURPOSE, COIN_TYPE, account])?;
+        let key = ExtendedKey::master(seed).derive(&path);
+        Ok(Account { path, key })
+    }
+
+    pub fn path(&self) -> &DerivationPath {
+        &self.path
+    }
+
+    /// 派生 m/44'/币种'/账户'/链'/索引'
+    pub fn derive(&self, chain: KeyChain, index: u32) -> Result<DerivedKey, PathError> {
+        let path = self.path.child(chain.index())?.child(index)?;
+        let key = self
+            .key
+            .child(chain.index() | HARDENED)
+            .child(index | HARDENED);
+        Ok(DerivedKey {
+            path,
+            chain,
+            index,
+            key,
+        })
+    }
+
+    /// 从索引 `start` 开始依次派生一条链上的密钥
+    pub fn keys(&self, chain: KeyChain, start: u32) -> impl Iterator<Item = DerivedKey> + '_ {
+        (start..HARDENED).map(move |index| self.derive(chain, index).unwrap())
+    }
+}
@@ -0,0 +1,158 @@
+use crate::hash_function::sha256_hash;
+use rand::Rng;
+use ring::pbkdf2;
+use std::fmt;
+use std::num::NonZeroU32;
+use std::sync::OnceLock;
+
+// BIP39 英文词表，共 2048 个词，按字母顺序排列
+const ENGLISH: &str = include_str!("english.txt");
+// 由助记词生成种子时 PBKDF2 的迭代次数
+const SEED_ITERATIONS: u32 = 2048;
+// 种子长度（字节）
+pub const SEED_SIZE: usize = 64;
+
+fn wordlist() -> &'static [&'static str] {
+    static WORDS: OnceLock<Vec<&'static str>> = OnceLock::new();
+    WORDS.get_or_init(|| ENGLISH.lines().collect())
+}
+
+/// 助记词无效的原因
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MnemonicError {
+    WordCount(usize),     // 词数不是 12、15、18、21 或 24
+    EntropyLength(usize), // 熵不是 16 到 32 之间 4 的倍数个字节
+    UnknownWord(String),  // 不在词表中
+    Checksum,             // 校验和不匹配，通常是抄错了词或顺序
+}
+
+impl fmt::Display for MnemonicError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MnemonicError::WordCount(count) => {
+                write!(
+                    f,
+                    "mnemonic must have 12, 15, 18, 21 or 24 words, got {}",
+                    count
+                )
+            }
+            MnemonicError::EntropyLength(len) => write!(f, "invalid entropy length: {} bytes", len),
+            MnemonicError::UnknownWord(word) => write!(f, "unknown mnemonic word: {}", word),
+            MnemonicError::Checksum => f.write_str("invalid mnemonic checksum"),
+        }
+    }
+}
+
+impl std::error::Error for MnemonicError {}
+
+/// BIP39 助记词：熵加上 SHA-256 校验和，每 11 位对应词表中的一个词
+///
+/// 备份助记词即可恢复由它派生的全部密钥。
+#[derive(Clone, PartialEq, Eq)]
+pub struct Mnemonic {
+    entropy: Vec<u8>,
+}
+
+// 不在日志中输出助记词
+impl fmt::Debug for Mnemonic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Mnemonic({} words)", self.word_count())
+    }
+}
+
+impl Mnemonic {
+    /// 生成指定词数的随机助记词
+    pub fn generate(word_count: usize) -> Result<Self, MnemonicError> {
+        if !matches!(word_count, 12 | 15 | 18 | 21 | 24) {
+            return Err(MnemonicError::WordCount(word_count));
+        }
+        let mut entropy = vec![0u8; word_count * 4 / 3];
+        rand::rng().fill(&mut entropy[..]);
+        Mnemonic::from_entropy(&entropy)
+    }
+
+    pub fn from_entropy(entropy: &[u8]) -> Result<Self, MnemonicError> {
+        if entropy.len() < 16 || entropy.len() > 32 || !entropy.len().is_multiple_of(4) {
+            return Err(MnemonicError::EntropyLength(entropy.len()));
+        }
+        Ok(Mnemonic {
+            entropy: entropy.to_vec(),
+        })
+    }
+
+    /// 解析助记词，忽略大小写和多余的空白，并检查校验和
+    pub fn from_phrase(phrase: &str) -> Result<Self, MnemonicError> {
+        let words: Vec<String> = phrase.split_whitespace().map(str::to_lowercase).collect();
+        if !matches!(words.len(), 12 | 15 | 18 | 21 | 24) {
+            return Err(MnemonicError::WordCount(words.len()));
+        }
+        // 每个词 11 位，拼接后前面是熵，最后 词数/3 位是校验和
+        let mut bits = Vec::with_capacity(words.len() * 11);
+        for word in &words {
+            let index = wordlist()
+                .binary_search(&word.as_str())
+                .map_err(|_| MnemonicError::UnknownWord(word.clone()))?;
+            bits.extend((0..11).rev().map(|bit| (index >> bit) & 1 == 1));
+        }
+        let entropy_bits = words.len() * 32 / 3;
+        let entropy: Vec<u8> = bits[..entropy_bits]
+            .chunks(8)
+            .map(|byte| byte.iter().fold(0u8, |acc, &bit| (acc << 1) | bit as u8))
+            .collect();
+        let mnemonic = Mnemonic { entropy };
+        if mnemonic.bits() != bits {
+            return Err(MnemonicError::Checksum);
+        }
+        Ok(mnemonic)
+    }
+
+    // 熵和校验和拼接后的位序列
+    fn bits(&self) -> Vec<bool> {
+        let checksum = sha256_hash(&self.entropy);
+        let checksum_bits = self.entropy.len() / 4;
+        let bytes = self.entropy.iter().chain(checksum.as_ref());
+        let mut bits: Vec<bool> = bytes
+            .flat_map(|byte| (0..8).rev().map(move |bit| (byte >> bit) & 1 == 1))
+            .collect();
+        bits.truncate(self.entropy.len() * 8 + checksum_bits);
+        bits
+    }
+
+    pub fn entropy(&self) -> &[u8] {
+        &self.entropy
+    }
+
+    pub fn word_count(&self) -> usize {
+        self.entropy.len() * 3 / 4
+    }
+
+    /// 以空格分隔的助记词
+    pub fn phrase(&self) -> String {
+        let words: Vec<&str> = self
+            .bits()
+            .chunks(11)
+            .map(|chunk| {
+                let index = chunk
+                    .iter()
+                    .fold(0usize, |acc, &bit| (acc << 1) | bit as usize);
+                wordlist()[index]
+            })
+            .collect();
+        words.join(" ")
+    }
+
+    /// 由助记词和可选的口令生成 64 字节种子（PBKDF2-HMAC-SHA512）
+    ///
+    /// 口令不做 Unicode 规范化，非 ASCII 口令需要调用方自行保证输入一致。
+    pub fn to_seed(&self, passphrase: &str) -> [u8; SEED_SIZE] {
+        let mut seed = [0u8; SEED_SIZE];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA512,
+            NonZeroU32::new(SEED_ITERATIONS).unwrap(),
+            format!("mnemonic{}", passphrase).as_bytes(),
+            self.phrase().as_bytes(),
+            &mut seed,
+        );
+        seed
+    }
+}
@@ -0,0 +1,428 @@
+pub mod coin_selection;
+pub mod hd;
+pub mod mnemonic;
+pub mod store;
+
+use crate::block_chain::BlockChain;
+use crate::hash_function::hash_block_header;
+use crate::transaction::{encode_address, Transaction, TxIn, TxOut};
+use coin_selection::{
+    fee_for_size, select_coins, INPUT_SIZE, MAX_FEE_RATE, OUTPUT_SIZE, TX_BASE_SIZE,
+};
+use hd::{Account, DerivedKey, KeyChain};
+use mnemonic::{Mnemonic, MnemonicError};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use store::{FileKey, DEFAULT_KDF_ITERATIONS};
+
+// 每条密钥链上在最后一个已使用的地址之后预先派生的地址数，扫描区块时只识别这些地址
+pub const GAP_LIMIT: u32 = 20;
+// 新钱包的助记词词数
+pub const DEFAULT_WORD_COUNT: usize = 24;
+
+/// 钱包操作失败的原因
+#[derive(Debug)]
+pub enum WalletError {
+    Io(PathBuf, io::Error),
+    Format(String),  // 钱包文件损坏或格式不支持
+    WrongPassword,   // 密码错误或文件被篡改
+    Exists(PathBuf), // 创建钱包时文件已存在
+    Mnemonic(MnemonicError),
+    InvalidAmount,       // 转账金额为 0
+    InvalidFeeRate(u64), // 费率超过 MAX_FEE_RATE
+    InsufficientFunds { available: u64, required: u64 },
+}
+
+impl fmt::Display for WalletError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WalletError::Io(path, e) => write!(f, "wallet file {}: {}", path.display(), e),
+            WalletError::Format(e) => write!(f, "invalid wallet file: {}", e),
+            WalletError::WrongPassword => f.write_str("wrong wallet password"),
+            WalletError::Exists(path) => write!(f, "wallet already exists: {}", path.display()),
+            WalletError::Mnemonic(e) => write!(f, "{}", e),
+            WalletError::InvalidAmount => f.write_str("amount must be positive"),
+            WalletError::InvalidFeeRate(rate) => {
+                write!(f, "fee rate {} exceeds maximum {}", rate, MAX_FEE_RATE)
+            }
+            WalletError::InsufficientFunds {
+                available,
+                required,
+            } => write!(
+                f,
+                "insufficient funds: {} available, at least {} required",
+                available, required
+            ),
+        }
+    }
+}
+
+impl std::error::Error for WalletError {}
+
+impl From<MnemonicError> for WalletError {
+    fn from(e: MnemonicError) -> Self {
+        WalletError::Mnemonic(e)
+    }
+}
+
+/// 读取密码文件的第一行作为钱包密码
+pub fn read_password_file(path: &Path) -> Result<String, WalletError> {
+    let content = fs::read_to_string(path).map_err(|e| WalletError::Io(path.to_path_buf(), e))?;
+    Ok(content.lines().next().unwrap_or_default().to_string())
+}
+
+// 加密保存的钱包内容
+#[derive(Serialize, Deserialize)]
+struct WalletData {
+    mnemonic: String,
+    passphrase: String,
+    account: u32,
+    next_receive: u32, // 下一个要分配的收款地址索引
+    next_change: u32,
+}
+
+/// 钱包拥有的未花费输出
+///
+/// 交易输入只引用父交易，同一父交易中锁定给同一公钥的输出只能一起花费，因此按 (父交易, 公钥) 归为一个币。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coin {
+    pub txid: [u8; 32],
+    pub public_key: [u8; 32],
+    pub outputs: Vec<u32>, // 输出在交易中的位置
+    pub value: u64,        // 这些输出的总金额
+    pub height: u32,       // 所在区块的高度
+    pub chain: KeyChain,
+    pub pending: bool, // 已被交易池中的交易花费
+}
+
+impl Coin {
+    pub fn address(&self) -> String {
+        encode_address(&self.public_key)
+    }
+}
+
+// 一条密钥链的使用情况
+#[derive(Debug, Clone, Copy, Default)]
+struct ChainState {
+    next: u32,    // 下一个未分配的索引
+    derived: u32, // 已派生的密钥数
+}
+
+/// 加密保存在磁盘上的 HD 钱包
+///
+/// 通过扫描主链区块跟踪钱包地址收到的币，用选币算法构造带找零的交易并签名。
+/// 磁盘上只保存助记词和地址索引，币在打开钱包后重新扫描得到。
+pub struct Wallet {
+    path: PathBuf,
+    file_key: FileKey,
+    mnemonic: Mnemonic,
+    passphrase: String,
+    account_index: u32,
+    account: Account,
+    chains: [ChainState; 2],                    // 按 KeyChain::index 排列
+    keys: HashMap<[u8; 32], DerivedKey>,        // 已派生的密钥：公钥 -> 密钥
+    coins: HashMap<([u8; 32], [u8; 32]), Coin>, // (父交易, 公钥) -> 币
+    synced: Option<(u32, [u8; 32])>,            // 已扫描到的区块高度和哈希
+}
+
+impl Wallet {
+    fn new(
+        path: &Path,
+        file_key: FileKey,
+        mnemonic: Mnemonic,
+        data: WalletData,
+    ) -> Result<Self, WalletError> {
+        let account = Account::new(&mnemonic.to_seed(&data.passphrase), data.account)
+            .map_err(|e| WalletError::Format(e.to_string()))?;
+        let mut wallet = Wallet {
+            path: path.to_path_buf(),
+            file_key,
+            mnemonic,
+            passphrase: data.passphrase,
+            account_index: data.account,
+            account,
+            chains: [
+                ChainState {
+                    next: data.next_receive,
+                    derived: 0,
+                },
+                ChainState {
+                    next: data.next_change,
+                    derived: 0,
+                },
+            ],
+            keys: HashMap::new(),
+            coins: HashMap::new(),
+            synced: None,
+        };
+        wallet.derive_lookahead();
+        Ok(wallet)
+    }
+
+    /// 由助记词创建钱包并用密码加密保存，文件已存在时返回错误
+    pub fn create(
+        path: &Path,
+        password: &str,
+        mnemonic: &Mnemonic,
+        passphrase: &str,
+    ) -> Result<Self, WalletError> {
+        if path.exists() {
+            return Err(WalletError::Exists(path.to_path_buf()));
+        }
+        let data = WalletData {
+            mnemonic: mnemonic.phrase(),
+            passphrase: passphrase.to_string(),
+            account: 0,
+            next_receive: 0,
+            next_change: 0,
+        };
+        let file_key = FileKey::new(password, DEFAULT_KDF_ITERATIONS);
+        let wallet = Wallet::new(path, file_key, mnemonic.clone(), data)?;
+        wallet.save()?;
+        Ok(wallet)
+    }
+
+    /// 用密码打开已有的钱包
+    pub fn open(path: &Path, password: &str) -> Result<Self, WalletError> {
+        let content = fs::read(path).map_err(|e| WalletError::Io(path.to_path_buf(), e))?;
+        let (file_key, plaintext) = FileKey::open(&content, password)?;
+        let data: WalletData =
+            serde_json::from_slice(&plaintext).map_err(|e| WalletError::Format(e.to_string()))?;
+        let mnemonic = Mnemonic::from_phrase(&data.mnemonic)?;
+        Wallet::new(path, file_key, mnemonic, data)
+    }
+
+    /// 加密保存助记词和地址索引，每次保存使用新的 nonce
+    pub fn save(&self) -> Result<(), WalletError> {
+        let data = WalletData {
+            mnemonic: self.mnemonic.phrase(),
+            passphrase: self.passphrase.clone(),
+            account: self.account_index,
+            next_receive: self.chains[0].next,
+            next_change: self.chains[1].next,
+        };
+        let plaintext = serde_json::to_vec(&data).unwrap();
+        store::write(&self.path, &self.file_key.seal(&plaintext))
+            .map_err(|e| WalletError::Io(self.path.clone(), e))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // 用于备份
+    pub fn mnemonic(&self) -> &Mnemonic {
+        &self.mnemonic
+    }
+
+    // 每条链都派生到最后一个已分配的索引之后 GAP_LIMIT 个
+    fn derive_lookahead(&mut self) {
+        for chain in [KeyChain::Receive, KeyChain::Change] {
+            let state = &mut self.chains[chain.index() as usize];
+            let target = state.next.saturating_add(GAP_LIMIT);
+            for key in self
+                .account
+                .keys(chain, state.derived)
+                .take((target - state.derived) as usize)
+            {
+                self.keys.insert(key.public_key(), key);
+            }
+            state.derived = target;
+        }
+    }
+
+    // 分配链上的下一个地址
+    fn next_key(&mut self, chain: KeyChain) -> Result<DerivedKey, WalletError> {
+        let index = self.chains[chain.index() as usize].next;
+        self.chains[chain.index() as usize].next += 1;
+        self.derive_lookahead();
+        self.save()?;
+        let key = self.account.derive(chain, index).unwrap();
+        Ok(key)
+    }
+
+    /// 分配一个新的收款地址
+    pub fn new_address(&mut self) -> Result<String, WalletError> {
+        Ok(self.next_key(KeyChain::Receive)?.address())
+    }
+
+    /// 扫描主链上新增的区块，更新钱包的币，并标记被交易池中的交易花费的币
+    ///
+    /// 上次扫描到的区块不在主链上时（发生了重组）从创世区块重新扫描。
+    /// 区块中用到了预先派生的地址时分配到该地址并保存索引。
+    pub fn scan(&mut self, chain: &BlockChain) -> Result<(), WalletError> {
+        let start = match self.synced {
+            Some((height, hash))
+                if chain
+                    .block_at(height)
+                    .is_some_and(|block| hash_block_header(&block.header) == hash) =>
+            {
+                height + 1
+            }
+            _ => {
+                self.coins.clear();
+                0
+            }
+        };
+        let next = self.chains.map(|state| state.next);
+        for height in start..=chain.height() {
+            let Some(block) = chain.block_at(height) else {
+                break;
+            };
+            for tx in &block.transactions {
+                self.apply(tx, height);
+            }
+        }
+        self.synced = Some((chain.height(), chain.tip_hash()));
+
+        let pool = chain.transaction_pool.lock().unwrap();
+        for coin in self.coins.values_mut() {
+            coin.pending = pool.iter().flat_map(|tx| &tx.inputs).any(|input| {
+                input.previous_output == coin.txid
+                    && input.public_key() == Some(&coin.public_key[..])
+            });
+        }
+        drop(pool);
+        if self.chains.map(|state| state.next) != next {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    // 处理主链上的一笔交易：删除被它花费的币，记录它支付给钱包地址的输出
+    fn apply(&mut self, tx: &Transaction, height: u32) {
+        for input in &tx.inputs {
+            if let Some(key) = input.public_key().and_then(|key| key.try_into().ok()) {
+                self.coins.remove(&(input.previous_output, key));
+            }
+        }
+        let txid = tx.hash();
+        for (index, output) in tx.outputs.iter().enumerate() {
+            let Ok(public_key) = <[u8; 32]>::try_from(output.script_pubkey.as_slice()) else {
+                continue;
+            };
+            let Some(key) = self.keys.get(&public_key) else {
+                continue;
+            };
+            let (chain, key_index) = (key.chain, key.index);
+            let coin = self
+                .coins
+                .entry((txid, public_key))
+                .or_insert_with(|| Coin {
+                    txid,
+                    public_key,
+                    outputs: Vec::new(),
+                    value: 0,
+                    height,
+                    chain,
+                    pending: false,
+                });
+            coin.outputs.push(index as u32);
+            // 无效区块中的输出金额之和可能溢出
+            coin.value = coin.value.saturating_add(output.value);
+            let state = &mut self.chains[chain.index() as usize];
+            if key_index >= state.next {
+                state.next = key_index + 1;
+                self.derive_lookahead();
+            }
+        }
+    }
+
+    // 已扫描到的主链高度
+    pub fn height(&self) -> Option<u32> {
+        self.synced.map(|(height, _)| height)
+    }
+
+    pub fn confirmations(&self, coin: &Coin) -> u32 {
+        self.height().map_or(0, |height| height + 1 - coin.height)
+    }
+
+    /// 至少有 `min_confirmations` 个确认、且未被交易池中的交易花费的币，按高度排列
+    pub fn list_unspent(&self, min_confirmations: u32) -> Vec<&Coin> {
+        let mut coins: Vec<&Coin> = self
+            .coins
+            .values()
+            .filter(|coin| !coin.pending && self.confirmations(coin) >= min_confirmations)
+            .collect();
+        coins.sort_by_key(|coin| (coin.height, coin.txid, coin.public_key));
+        coins
+    }
+
+    // 可以花费的余额
+    pub fn balance(&self) -> u64 {
+        self.list_unspent(0)
+            .iter()
+            .fold(0u64, |sum, coin| sum.saturating_add(coin.value))
+    }
+
+    /// 构造并签名一笔向 `script_pubkey` 支付 `amount` 的交易
+    ///
+    /// `fee_rate` 为每 1000 字节的手续费。找零支付到找零链上的新地址，
+    /// 选中的币标记为已花费，交易未被接受时下次扫描会恢复。
+    pub fn create_transaction(
+        &mut self,
+        script_pubkey: &[u8],
+        amount: u64,
+        fee_rate: u64,
+    ) -> Result<Transaction, WalletError> {
+        if amount == 0 {
+            return Err(WalletError::InvalidAmount);
+        }
+        if fee_rate > MAX_FEE_RATE {
+            return Err(WalletError::InvalidFeeRate(fee_rate));
+        }
+        let coins: Vec<Coin> = self.list_unspent(1).into_iter().cloned().collect();
+        let values: Vec<u64> = coins.iter().map(|coin| coin.value).collect();
+        let selection =
+            select_coins(&values, amount, fee_rate, &mut rand::rng()).ok_or_else(|| {
+                WalletError::InsufficientFunds {
+                    available: values
+                        .iter()
+                        .fold(0u64, |sum, &value| sum.saturating_add(value)),
+                    required: amount.saturating_add(
+                        fee_for_size(fee_rate, TX_BASE_SIZE + INPUT_SIZE + OUTPUT_SIZE)
+                            .unwrap_or(u64::MAX),
+                    ),
+                }
+            })?;
+
+        let selected: Vec<&Coin> = selection.inputs.iter().map(|&i| &coins[i]).collect();
+        let mut outputs = vec![TxOut {
+            value: amount,
+            script_pubkey: script_pubkey.to_vec(),
+        }];
+        if selection.change > 0 {
+            let change = self.next_key(KeyChain::Change)?;
+            outputs.push(TxOut {
+                value: selection.change,
+                script_pubkey: change.public_key().to_vec(),
+            });
+        }
+        let mut tx = Transaction {
+            version: 0,
+            inputs: selected
+                .iter()
+                .map(|coin| TxIn {
+                    previous_output: coin.txid,
+                    script_sig: Vec::new(),
+                    sequence: 0,
+                })
+                .collect(),
+            outputs,
+            lock_time: 0,
+        };
+        for (index, coin) in selected.iter().enumerate() {
+            tx.sign(&self.keys[&coin.public_key].key_pair(), index);
+        }
+        for coin in selected {
+            if let Some(coin) = self.coins.get_mut(&(coin.txid, coin.public_key)) {
+                coin.pending = true;
+            }
+        }
+        Ok(tx)
+    }
+}
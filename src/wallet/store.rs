@@ -0,0 +1,163 @@
+use super::WalletError;
+use rand::Rng;
+use ring::aead::{self, Aad, LessSafeKey, Nonce, UnboundKey, NONCE_LEN};
+use ring::pbkdf2;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Write};
+use std::num::NonZeroU32;
+use std::path::Path;
+
+// 钱包文件格式的版本
+const VERSION: u32 = 1;
+const KDF_ALGORITHM: &str = "pbkdf2-hmac-sha256";
+const CIPHER: &str = "chacha20-poly1305";
+const SALT_SIZE: usize = 16;
+// 由密码派生加密密钥时 PBKDF2 的默认迭代次数
+pub const DEFAULT_KDF_ITERATIONS: u32 = 100_000;
+
+#[derive(Serialize, Deserialize)]
+struct KdfParams {
+    algorithm: String,
+    iterations: u32,
+    salt: String,
+}
+
+// 钱包文件的内容，除版本和参数外都是十六进制编码
+#[derive(Serialize, Deserialize)]
+struct EncryptedFile {
+    version: u32,
+    kdf: KdfParams,
+    cipher: String,
+    nonce: String,
+    ciphertext: String, // 密文和认证标签
+}
+
+/// 由密码派生的文件加密密钥
+///
+/// 保存在内存中的是派生后的密钥而不是密码，每次保存只需生成新的随机 nonce。
+pub struct FileKey {
+    key: LessSafeKey,
+    salt: [u8; SALT_SIZE],
+    iterations: u32,
+}
+
+impl FileKey {
+    fn derive(password: &str, salt: [u8; SALT_SIZE], iterations: u32) -> Self {
+        let mut key = [0u8; 32];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            NonZeroU32::new(iterations).unwrap(),
+            &salt,
+            password.as_bytes(),
+            &mut key,
+        );
+        FileKey {
+            key: LessSafeKey::new(UnboundKey::new(&aead::CHACHA20_POLY1305, &key).unwrap()),
+            salt,
+            iterations,
+        }
+    }
+
+    /// 用随机盐从密码派生新密钥
+    pub fn new(password: &str, iterations: u32) -> Self {
+        FileKey::derive(password, rand::rng().random(), iterations.max(1))
+    }
+
+    // 附加认证数据：版本和密钥派生参数，篡改它们会导致解密失败
+    fn aad(&self) -> String {
+        format!(
+            "{}:{}:{}:{}",
+            VERSION,
+            KDF_ALGORITHM,
+            self.iterations,
+            hex::encode(self.salt)
+        )
+    }
+
+    /// 加密并返回钱包文件的内容
+    pub fn seal(&self, plaintext: &[u8]) -> Vec<u8> {
+        let nonce: [u8; NONCE_LEN] = rand::rng().random();
+        let mut data = plaintext.to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(self.aad()),
+                &mut data,
+            )
+            .unwrap();
+        let file = EncryptedFile {
+            version: VERSION,
+            kdf: KdfParams {
+                algorithm: KDF_ALGORITHM.to_string(),
+                iterations: self.iterations,
+                salt: hex::encode(self.salt),
+            },
+            cipher: CIPHER.to_string(),
+            nonce: hex::encode(nonce),
+            ciphertext: hex::encode(data),
+        };
+        serde_json::to_vec_pretty(&file).unwrap()
+    }
+
+    /// 用密码解密钱包文件，返回派生的密钥和明文
+    pub fn open(data: &[u8], password: &str) -> Result<(Self, Vec<u8>), WalletError> {
+        let format = |message: &str| WalletError::Format(message.to_string());
+        let file: EncryptedFile =
+            serde_json::from_slice(data).map_err(|e| WalletError::Format(e.to_string()))?;
+        if file.version != VERSION {
+            return Err(WalletError::Format(format!(
+                "unsupported version {}",
+                file.version
+            )));
+        }
+        if file.kdf.algorithm != KDF_ALGORITHM || file.cipher != CIPHER {
+            return Err(format("unsupported algorithm"));
+        }
+        if file.kdf.iterations == 0 {
+            return Err(format("invalid kdf iterations"));
+        }
+        let salt = hex::decode(&file.kdf.salt)
+            .ok()
+            .and_then(|salt| salt.try_into().ok())
+            .ok_or_else(|| format("invalid salt"))?;
+        let nonce = hex::decode(&file.nonce)
+            .ok()
+            .and_then(|nonce| Nonce::try_assume_unique_for_key(&nonce).ok())
+            .ok_or_else(|| format("invalid nonce"))?;
+        let mut ciphertext =
+            hex::decode(&file.ciphertext).map_err(|_| format("invalid ciphertext"))?;
+
+        let key = FileKey::derive(password, salt, file.kdf.iterations);
+        // 认证失败无法区分密码错误和文件损坏
+        let plaintext = key
+            .key
+            .open_in_place(nonce, Aad::from(key.aad()), &mut ciphertext)
+            .map_err(|_| WalletError::WrongPassword)?
+            .to_vec();
+        Ok((key, plaintext))
+    }
+}
+
+/// 先写入临时文件再替换，只有所有者可以读写
+///
+/// 临时文件创建时即设置权限，并拒绝打开已存在的文件，上次遗留的临时文件先删除。
+pub fn write(path: &Path, data: &[u8]) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    match fs::remove_file(&tmp) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&tmp)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(tmp, path)
+}
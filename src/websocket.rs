@@ -0,0 +1,245 @@
+use crate::events::{ChainEvent, EventBus};
+use crate::hash_function::hash_block_header;
+use crate::transaction::{decode_address, encode_address, Transaction};
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+use tokio::sync::broadcast::error::RecvError;
+use warp::ws::{Message, WebSocket};
+
+/// 客户端可以订阅的主题
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Topic {
+    NewTip,
+    BlockConnected,
+    BlockDisconnected,
+    MempoolAdd,
+    MempoolRemove,
+    Address(Vec<u8>), // 输入或输出涉及该地址的交易，写作 address:<地址>
+}
+
+impl FromStr for Topic {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "new_tip" => Ok(Topic::NewTip),
+            "block_connected" => Ok(Topic::BlockConnected),
+            "block_disconnected" => Ok(Topic::BlockDisconnected),
+            "mempool_add" => Ok(Topic::MempoolAdd),
+            "mempool_remove" => Ok(Topic::MempoolRemove),
+            _ => match s.strip_prefix("address:") {
+                Some(address) => decode_address(address)
+                    .map(Topic::Address)
+                    .ok_or_else(|| format!("invalid address: {}", address)),
+                None => Err(format!("unknown topic: {}", s)),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Topic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Topic::NewTip => f.write_str("new_tip"),
+            Topic::BlockConnected => f.write_str("block_connected"),
+            Topic::BlockDisconnected => f.write_str("block_disconnected"),
+            Topic::MempoolAdd => f.write_str("mempool_add"),
+            Topic::MempoolRemove => f.write_str("mempool_remove"),
+            Topic::Address(script) => write!(f, "address:{}", encode_address(script)),
+        }
+    }
+}
+
+// 客户端发送的订阅请求，如 {"subscribe": ["new_tip", "address:..."]}
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SubscriptionRequest {
+    #[serde(default)]
+    subscribe: Vec<String>,
+    #[serde(default)]
+    unsubscribe: Vec<String>,
+}
+
+// 交易的输入或输出是否涉及该地址
+fn involves(tx: &Transaction, script: &[u8]) -> bool {
+    tx.outputs
+        .iter()
+        .any(|output| output.script_pubkey == script)
+        || tx
+            .inputs
+            .iter()
+            .any(|input| input.public_key() == Some(script))
+}
+
+// 与已订阅地址相关的交易通知
+fn address_activity(
+    topics: &HashSet<Topic>,
+    transactions: &[Transaction],
+    status: &str,
+    height: Option<u32>,
+) -> Vec<Value> {
+    let mut messages = Vec::new();
+    for topic in topics {
+        let Topic::Address(script) = topic else {
+            continue;
+        };
+        for tx in transactions.iter().filter(|tx| involves(tx, script)) {
+            messages.push(json!({
+                "topic": "address",
+                "address": encode_address(script),
+                "txid": hex::encode(tx.hash()),
+                "status": status,
+                "height": height,
+            }));
+        }
+    }
+    messages
+}
+
+fn block_message(topic: &str, transactions: &[Transaction], hash: [u8; 32], height: u32) -> Value {
+    let txids: Vec<String> = transactions
+        .iter()
+        .map(|tx| hex::encode(tx.hash()))
+        .collect();
+    json!({
+        "topic": topic,
+        "hash": hex::encode(hash),
+        "height": height,
+        "transactions": txids,
+    })
+}
+
+/// 把事件转换为发给订阅者的 JSON 消息，未订阅的主题不产生消息
+pub fn messages(event: &ChainEvent, topics: &HashSet<Topic>) -> Vec<Value> {
+    let mut messages = Vec::new();
+    match event {
+        ChainEvent::NewTip { hash, height } => {
+            if topics.contains(&Topic::NewTip) {
+                messages.push(json!({
+                    "topic": "new_tip",
+                    "hash": hex::encode(hash),
+                    "height": height,
+                }));
+            }
+        }
+        ChainEvent::BlockConnected { block, height } => {
+            let hash = hash_block_header(&block.header);
+            if topics.contains(&Topic::BlockConnected) {
+                messages.push(block_message(
+                    "block_connected",
+                    &block.transactions,
+                    hash,
+                    *height,
+                ));
+            }
+            messages.extend(address_activity(
+                topics,
+                &block.transactions,
+                "confirmed",
+                Some(*height),
+            ));
+        }
+        ChainEvent::BlockDisconnected { block, height } => {
+            let hash = hash_block_header(&block.header);
+            if topics.contains(&Topic::BlockDisconnected) {
+                messages.push(block_message(
+                    "block_disconnected",
+                    &block.transactions,
+                    hash,
+                    *height,
+                ));
+            }
+            messages.extend(address_activity(
+                topics,
+                &block.transactions,
+                "disconnected",
+                Some(*height),
+            ));
+        }
+        ChainEvent::TransactionAdded(tx) => {
+            if topics.contains(&Topic::MempoolAdd) {
+                messages.push(json!({
+                    "topic": "mempool_add",
+                    "txid": hex::encode(tx.hash()),
+                    "transaction": tx.as_ref(),
+                }));
+            }
+            messages.extend(address_activity(
+                topics,
+                std::slice::from_ref(tx.as_ref()),
+                "mempool",
+                None,
+            ));
+        }
+        ChainEvent::TransactionRemoved { txid, reason } => {
+            if topics.contains(&Topic::MempoolRemove) {
+                messages.push(json!({
+                    "topic": "mempool_remove",
+                    "txid": hex::encode(txid),
+                    "reason": reason,
+                }));
+            }
+        }
+    }
+    messages
+}
+
+// 处理订阅请求，返回回复给客户端的消息
+fn update_topics(topics: &mut HashSet<Topic>, text: &str) -> Value {
+    let request: SubscriptionRequest = match serde_json::from_str(text) {
+        Ok(request) => request,
+        Err(e) => return json!({"error": format!("invalid request: {}", e)}),
+    };
+    let parse = |names: &[String]| {
+        names
+            .iter()
+            .map(|name| name.parse::<Topic>())
+            .collect::<Result<Vec<Topic>, String>>()
+    };
+    let (subscribe, unsubscribe) = match (parse(&request.subscribe), parse(&request.unsubscribe)) {
+        (Ok(subscribe), Ok(unsubscribe)) => (subscribe, unsubscribe),
+        (Err(e), _) | (_, Err(e)) => return json!({ "error": e }),
+    };
+    topics.extend(subscribe);
+    for topic in &unsubscribe {
+        topics.remove(topic);
+    }
+    let mut subscribed: Vec<String> = topics.iter().map(Topic::to_string).collect();
+    subscribed.sort();
+    json!({ "subscribed": subscribed })
+}
+
+/// 处理一个 WebSocket 连接：接收订阅请求，推送已订阅主题的事件
+pub async fn serve(socket: WebSocket, events: EventBus) {
+    let mut receiver = events.subscribe();
+    let (mut sink, mut stream) = socket.split();
+    let mut topics = HashSet::new();
+    loop {
+        let replies = tokio::select! {
+            message = stream.next() => match message {
+                Some(Ok(message)) if message.is_text() => {
+                    vec![update_topics(&mut topics, message.to_str().unwrap())]
+                }
+                Some(Ok(message)) if message.is_close() => break,
+                Some(Ok(_)) => continue,
+                Some(Err(_)) | None => break,
+            },
+            event = receiver.recv() => match event {
+                Ok(event) => messages(&event, &topics),
+                // 处理太慢时丢失了部分事件，通知客户端后继续
+                Err(RecvError::Lagged(missed)) => vec![json!({ "error": "lagged", "missed": missed })],
+                Err(RecvError::Closed) => break,
+            },
+        };
+        for reply in replies {
+            if sink.send(Message::text(reply.to_string())).await.is_err() {
+                return;
+            }
+        }
+    }
+    let _ = sink.close().await;
+}
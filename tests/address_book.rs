@@ -0,0 +1,144 @@
+#[cfg(test)]
+mod tests {
+    use block_chain::block_chain::BlockChain;
+    use block_chain::network::addrman::{
+        address_group, AddressBook, BUCKET_SIZE, NEW_BUCKETS_PER_SOURCE_GROUP,
+    };
+    use block_chain::network::manager::PeerManager;
+    use block_chain::network::message::{Message, NetAddress, VersionMessage};
+    use block_chain::network::{read_seeds, Action, Direction, NetworkConfig, PROTOCOL_VERSION};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::collections::HashSet;
+    use std::net::{IpAddr, SocketAddr};
+
+    fn addr(a: u8, b: u8, c: u8) -> SocketAddr {
+        SocketAddr::from(([a, b, c, 1], 3031))
+    }
+
+    #[test]
+    fn test_single_source_cannot_fill_address_book() {
+        let mut book = AddressBook::new([1; 32]);
+        let attacker: IpAddr = "10.0.0.1".parse().unwrap();
+        // 同一来源通告大量不同网段的地址，只能占据少数几个桶
+        for i in 0..4_000u32 {
+            let ip = SocketAddr::from(([(i >> 8) as u8 + 1, i as u8, 0, 1], 3031));
+            book.add(ip, Some(attacker), 0);
+        }
+        assert!(book.len() <= NEW_BUCKETS_PER_SOURCE_GROUP as usize * BUCKET_SIZE);
+
+        // 其他来源的地址仍然可以加入
+        assert!(book.add(addr(8, 8, 8), Some("172.16.0.1".parse().unwrap()), 0));
+    }
+
+    #[test]
+    fn test_select_and_persist() {
+        let mut book = AddressBook::new([2; 32]);
+        book.add(addr(1, 2, 3), None, 10);
+        book.add(addr(1, 2, 4), None, 10);
+        book.add(addr(5, 6, 7), None, 10);
+        book.mark_good(&addr(5, 6, 7), 20);
+
+        // 已连接的网段被排除
+        let mut rng = StdRng::seed_from_u64(1);
+        let groups = HashSet::from([address_group(&addr(1, 2, 0).ip())]);
+        for _ in 0..20 {
+            assert_eq!(
+                book.select(&mut rng, &HashSet::new(), &groups, 100),
+                Some(addr(5, 6, 7))
+            );
+        }
+        // 刚尝试过的地址暂时不再选择
+        book.mark_attempt(&addr(5, 6, 7), 100);
+        assert_eq!(book.select(&mut rng, &HashSet::new(), &groups, 200), None);
+
+        let path = std::env::temp_dir().join(format!("addrman-{}.json", std::process::id()));
+        book.save(&path).unwrap();
+        let loaded = AddressBook::load(&path, [0; 32]).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.len(), 3);
+        let entry = loaded.get(&addr(5, 6, 7)).unwrap();
+        assert!(entry.tried);
+        assert_eq!(entry.last_success, Some(20));
+        assert_eq!(entry.attempts, 1);
+        assert!(!loaded.get(&addr(1, 2, 3)).unwrap().tried);
+    }
+
+    #[test]
+    fn test_getaddr_and_outbound_connections() {
+        let config = NetworkConfig {
+            max_outbound: 2,
+            ..NetworkConfig::default()
+        };
+        let mut chain = BlockChain::new(0);
+        let mut manager = PeerManager::new(config, 1);
+
+        // 出站连接握手成功后请求对方的地址
+        manager.on_connected(1, addr(9, 9, 9).to_string(), Direction::Outbound, &chain, 0);
+        let version = VersionMessage {
+            version: PROTOCOL_VERSION,
+            services: 0,
+            timestamp: 0,
+            nonce: 7,
+            user_agent: "test".into(),
+            genesis_hash: chain.genesis_hash(),
+            best_height: 0,
+            listen_port: 3031,
+        };
+        manager.on_message(1, Message::Version(version), &mut chain, 0);
+        manager.on_message(1, Message::Verack, &mut chain, 0);
+        assert!(manager
+            .take_actions()
+            .iter()
+            .any(|action| matches!(action, Action::Send(1, Message::GetAddr))));
+        assert!(manager.addresses().get(&addr(9, 9, 9)).unwrap().tried);
+
+        let addresses = [addr(1, 1, 1), addr(1, 1, 2), addr(2, 2, 2), addr(9, 9, 1)]
+            .iter()
+            .map(|a| NetAddress {
+                addr: *a,
+                last_seen: 0,
+            })
+            .collect();
+        manager.on_message(1, Message::Addr(addresses), &mut chain, 0);
+        assert_eq!(manager.addresses().len(), 5);
+
+        // 还差一个出站连接：不会选择已连接节点所在的网段
+        manager.take_actions();
+        manager.on_tick(&chain, 1);
+        let connects: Vec<SocketAddr> = manager
+            .take_actions()
+            .into_iter()
+            .filter_map(|action| match action {
+                Action::Connect(addr) => Some(addr),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(connects.len(), 1);
+        assert_ne!(
+            address_group(&connects[0].ip()),
+            address_group(&addr(9, 9, 9).ip())
+        );
+
+        // 连接失败后不会立即重试同一地址
+        manager.on_connect_failed(connects[0]);
+        manager.on_tick(&chain, 2);
+        assert!(!manager
+            .take_actions()
+            .iter()
+            .any(|action| matches!(action, Action::Connect(a) if *a == connects[0])));
+    }
+
+    #[test]
+    fn test_read_seeds() {
+        let path = std::env::temp_dir().join(format!("seeds-{}.txt", std::process::id()));
+        std::fs::write(
+            &path,
+            "# seeds\nseed.example.org:3031\n\n 10.0.0.1:3031 # lan\n",
+        )
+        .unwrap();
+        let seeds = read_seeds(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(seeds, vec!["seed.example.org:3031", "10.0.0.1:3031"]);
+    }
+}
@@ -0,0 +1,315 @@
+#[cfg(test)]
+mod tests {
+    use block_chain::auth::{
+        hash_password, write_cookie, Access, COOKIE_USER, MAX_TRACKED_CLIENTS,
+    };
+    use block_chain::block_chain::BlockChain;
+    use block_chain::config::{Command, ConfigError, NetworkPreset, NodeConfig, RpcConfig};
+    use block_chain::logging::LogLevel;
+    use block_chain::transaction::Transaction;
+    use std::net::SocketAddr;
+    use std::path::{Path, PathBuf};
+    use std::time::Instant;
+
+    fn parse(args: &[&str]) -> Result<Command, ConfigError> {
+        NodeConfig::from_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn run_config(args: &[&str]) -> NodeConfig {
+        match parse(args).unwrap() {
+            Command::Run(config) => config,
+            other => panic!("unexpected command: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_defaults_follow_network_preset() {
+        let config = run_config(&[]);
+        assert_eq!(config.network, NetworkPreset::Main);
+        assert_eq!(config.rpc_addr(), "127.0.0.1:3030".parse().unwrap());
+        let network = config.network_config();
+        assert_eq!(network.listen_addr, Some("127.0.0.1:3031".parse().unwrap()));
+        assert_eq!(
+            network.address_book_path,
+            Some(PathBuf::from("data/peers.json"))
+        );
+        assert!(network.encryption);
+
+        let config = run_config(&["--network", "regtest", "--no-listen"]);
+        assert_eq!(config.network.difficulty(), 0);
+        assert_eq!(config.rpc_addr().port(), 23030);
+        let network = config.network_config();
+        assert_eq!(network.magic, NetworkPreset::Regtest.magic());
+        assert_eq!(network.listen_addr, None);
+    }
+
+    #[test]
+    fn test_file_with_command_line_overrides() {
+        let path = std::env::temp_dir().join(format!("node-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"
+network = "test"
+data_dir = "/var/lib/node"
+log_level = "warn"
+
+[p2p]
+peers = ["10.0.0.1:13031"]
+encryption = false
+
+[mining]
+enabled = true
+threads = 2
+
+[mempool]
+max_transactions = 100
+"#,
+        )
+        .unwrap();
+
+        let config = run_config(&[
+            "--config",
+            path.to_str().unwrap(),
+            "--rpc-listen",
+            "0.0.0.0:8080",
+            "--rpc-cookie",
+            "--peer",
+            "10.0.0.2:13031",
+            "10.0.0.3:13031",
+            "--log-level",
+            "debug",
+        ]);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.network, NetworkPreset::Test);
+        assert_eq!(config.data_dir, PathBuf::from("/var/lib/node"));
+        assert_eq!(config.log_level, LogLevel::Debug);
+        assert_eq!(config.rpc_addr(), "0.0.0.0:8080".parse().unwrap());
+        assert_eq!(config.p2p.listen, Some("127.0.0.1:13031".parse().unwrap()));
+        let peers: Vec<SocketAddr> = ["10.0.0.1:13031", "10.0.0.2:13031", "10.0.0.3:13031"]
+            .iter()
+            .map(|addr| addr.parse().unwrap())
+            .collect();
+        assert_eq!(config.p2p.peers, peers);
+        assert!(!config.p2p.encryption);
+        assert!(config.mining.enabled);
+        assert_eq!(config.mining.threads, Some(2));
+        assert_eq!(config.mempool.max_transactions, 100);
+
+        // --print-config 输出的 TOML 能还原出相同的配置
+        let printed = match parse(&["--network", "regtest", "--print-config"]).unwrap() {
+            Command::PrintConfig(config) => config,
+            other => panic!("unexpected command: {:?}", other),
+        };
+        assert_eq!(NodeConfig::from_toml(&printed.to_toml()).unwrap(), printed);
+    }
+
+    #[test]
+    fn test_rpc_listeners_and_tls() {
+        let config = NodeConfig::from_toml(
+            "[rpc]\nlisten = [\"0.0.0.0:3030\", \"[::]:3030\"]\ncookie = true\n\n[rpc.tls]\ncert = \"node.crt\"\nkey = \"node.key\"",
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+        let listeners: Vec<SocketAddr> = vec![
+            "0.0.0.0:3030".parse().unwrap(),
+            "[::]:3030".parse().unwrap(),
+        ];
+        assert_eq!(config.rpc_addrs(), listeners);
+        let tls = config.rpc.tls.unwrap();
+        assert_eq!(tls.cert, PathBuf::from("node.crt"));
+        assert_eq!(tls.key, PathBuf::from("node.key"));
+
+        // 单个地址也可以直接写成字符串
+        let config = NodeConfig::from_toml("[rpc]\nlisten = \"[::1]:3030\"").unwrap();
+        assert_eq!(config.rpc_addrs(), vec!["[::1]:3030".parse().unwrap()]);
+
+        // 命令行中的多个 --rpc-listen 替换配置文件中的地址
+        let config = run_config(&[
+            "--rpc-listen",
+            "127.0.0.1:8080",
+            "--rpc-listen",
+            "[::1]:8080",
+            "--rpc-tls-cert",
+            "cert.pem",
+            "--rpc-tls-key",
+            "key.pem",
+        ]);
+        let listeners: Vec<SocketAddr> = vec![
+            "127.0.0.1:8080".parse().unwrap(),
+            "[::1]:8080".parse().unwrap(),
+        ];
+        assert_eq!(config.rpc.listen, listeners);
+        assert_eq!(config.rpc_addr(), listeners[0]);
+        assert_eq!(config.rpc.tls.unwrap().key, PathBuf::from("key.pem"));
+    }
+
+    #[test]
+    fn test_invalid_configuration() {
+        assert!(matches!(parse(&["--help"]), Ok(Command::Help)));
+        assert!(matches!(
+            parse(&["--verbose"]),
+            Err(ConfigError::UnknownFlag(_))
+        ));
+        assert!(matches!(
+            parse(&["--rpc-listen"]),
+            Err(ConfigError::MissingValue(_))
+        ));
+        assert!(matches!(
+            parse(&["--network", "moon"]),
+            Err(ConfigError::InvalidValue(_, _))
+        ));
+        assert!(matches!(
+            parse(&["not-an-address"]),
+            Err(ConfigError::InvalidValue(_, _))
+        ));
+        assert!(matches!(
+            parse(&["--mining-threads", "0"]),
+            Err(ConfigError::Invalid("mining.threads", _))
+        ));
+        assert!(matches!(
+            parse(&["--mining-address", "abcd"]),
+            Err(ConfigError::Invalid("mining.address", _))
+        ));
+        assert!(matches!(
+            parse(&["--max-mempool", "0"]),
+            Err(ConfigError::Invalid("mempool.max_transactions", _))
+        ));
+        assert!(matches!(
+            parse(&["--rpc-listen", "127.0.0.1:3031"]),
+            Err(ConfigError::Invalid("p2p.listen", _))
+        ));
+        // 不接受入站连接时 P2P 地址不会冲突
+        assert!(parse(&["--rpc-listen", "127.0.0.1:3031", "--no-listen"]).is_ok());
+        assert!(matches!(
+            parse(&[
+                "--rpc-listen",
+                "127.0.0.1:8080",
+                "--rpc-listen",
+                "127.0.0.1:8080"
+            ]),
+            Err(ConfigError::Invalid("rpc.listen", _))
+        ));
+        // 端口 0 由系统分配，可以重复
+        assert!(parse(&["--rpc-listen", "127.0.0.1:0", "--rpc-listen", "127.0.0.1:0"]).is_ok());
+        // 未配置凭据时只能监听本机地址
+        assert!(matches!(
+            parse(&["--rpc-listen", "0.0.0.0:8080"]),
+            Err(ConfigError::Invalid("rpc.listen", _))
+        ));
+        assert!(matches!(
+            parse(&[
+                "--rpc-listen",
+                "127.0.0.1:8080",
+                "--rpc-listen",
+                "[::]:8080"
+            ]),
+            Err(ConfigError::Invalid("rpc.listen", _))
+        ));
+        assert!(parse(&["--rpc-listen", "0.0.0.0:8080", "--rpc-cookie"]).is_ok());
+        assert!(matches!(
+            parse(&["--rpc-tls-cert", "cert.pem"]),
+            Err(ConfigError::Invalid("rpc.tls", _))
+        ));
+
+        assert!(matches!(
+            parse(&["--rpc-rate-limit", "0"]),
+            Err(ConfigError::Invalid("rpc.rate_limit", _))
+        ));
+        assert!(matches!(
+            parse(&["--wallet"]),
+            Err(ConfigError::Invalid("wallet.password_file", _))
+        ));
+        assert!(matches!(
+            parse(&["--create-wallet"]),
+            Err(ConfigError::Invalid("wallet.password_file", _))
+        ));
+        let wallet = run_config(&["--wallet", "--wallet-password-file", "pw.txt"]);
+        assert_eq!(wallet.wallet_path(), PathBuf::from("data/wallet.json"));
+        assert!(matches!(
+            parse(&["--wallet-password-file", "pw.txt", "--wallet-file", "w.json", "--create-wallet"]),
+            Ok(Command::CreateWallet(config)) if config.wallet_path() == Path::new("w.json")
+        ));
+        assert!(matches!(
+            parse(&["--gen-rpcauth", "alice"]),
+            Ok(Command::GenerateAuth(name)) if name == "alice"
+        ));
+        let credentials = |extra: &str| {
+            NodeConfig::from_toml(&format!(
+                "[[rpc.users]]\nname = \"alice\"\nhmac = \"{}\"\ngroup = \"wallet\"\n{}",
+                hash_password("salt", "secret"),
+                extra
+            ))
+            .unwrap()
+            .validate()
+        };
+        assert!(credentials("").is_ok());
+        assert!(matches!(
+            credentials("[[rpc.tokens]]\nname = \"alice\"\ntoken = \"t\"\ngroup = \"read\""),
+            Err(ConfigError::Invalid("rpc.users", _))
+        ));
+        assert!(matches!(
+            NodeConfig::from_toml(
+                "[[rpc.users]]\nname = \"bob\"\nhmac = \"nosalt\"\ngroup = \"admin\""
+            )
+            .unwrap()
+            .validate(),
+            Err(ConfigError::Invalid("rpc.users", _))
+        ));
+        assert!(NodeConfig::from_toml("[rpc]\nanonymous = \"root\"").is_err());
+
+        // 配置文件中的未知字段和错误类型
+        assert!(NodeConfig::from_toml("prot = 1").is_err());
+        assert!(NodeConfig::from_toml("[mining]\nthreads = \"four\"").is_err());
+        assert!(matches!(
+            parse(&["--config", "/nonexistent/node.toml"]),
+            Err(ConfigError::Io(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_mempool_limit_drops_oldest() {
+        let mut chain = BlockChain::new(0);
+        chain.set_pool_limit(Some(2));
+        let txs: Vec<Transaction> = (1..=3).map(|value| Transaction::new(value, 0)).collect();
+        for tx in &txs {
+            chain.add_transaction(tx.clone());
+        }
+        let pool = chain.transaction_pool.lock().unwrap();
+        let hashes: Vec<[u8; 32]> = pool.iter().map(|tx| tx.hash()).collect();
+        assert_eq!(hashes, vec![txs[1].hash(), txs[2].hash()]);
+    }
+
+    #[test]
+    fn test_rate_limit_table_is_bounded() {
+        let config = RpcConfig {
+            rate_limit: Some(1),
+            ..RpcConfig::default()
+        };
+        let access = Access::new(&config, None);
+        let now = Instant::now();
+        for client in 0..=MAX_TRACKED_CLIENTS {
+            assert!(access.allow(&client.to_string(), now));
+        }
+        // 没有空闲的客户端时丢弃最久未请求的一个
+        assert_eq!(access.tracked_clients(), MAX_TRACKED_CLIENTS);
+        assert!(!access.allow(&MAX_TRACKED_CLIENTS.to_string(), now));
+    }
+
+    #[test]
+    fn test_cookie_file_is_replaced() {
+        let path = std::env::temp_dir().join(format!(".cookie-{}", std::process::id()));
+        let first = write_cookie(&path).unwrap();
+        let second = write_cookie(&path).unwrap();
+        assert_ne!(first, second);
+        let cookie = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(cookie, format!("{}:{}", COOKIE_USER, second));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,89 @@
+#[cfg(test)]
+mod tests {
+    use block_chain::block_chain::{BlockChain, BlockError};
+    use block_chain::export::{encode_block, ExportFormat, ImportError, Importer};
+
+    fn source_chain(blocks: usize) -> BlockChain {
+        let mut chain = BlockChain::new(0);
+        for _ in 0..blocks {
+            let block = chain.block_template(Some(&[2u8; 32]));
+            chain.process_block(block).unwrap();
+        }
+        chain
+    }
+
+    #[test]
+    fn test_import_in_small_chunks() {
+        let source = source_chain(3);
+        for format in [ExportFormat::Ndjson, ExportFormat::Binary] {
+            let data: Vec<u8> = source
+                .blocks
+                .iter()
+                .flat_map(|block| encode_block(format, block))
+                .collect();
+            // 每次只追加 7 个字节，记录会跨越多次追加
+            let mut chain = BlockChain::new(0);
+            let mut importer = Importer::new(format);
+            for chunk in data.chunks(7) {
+                for block in importer.push(chunk).unwrap() {
+                    importer.apply(&mut chain, block).unwrap();
+                }
+            }
+            assert!(importer.finish().unwrap().is_none());
+            assert_eq!(chain.tip_hash(), source.tip_hash());
+            assert_eq!(importer.summary().connected, 3);
+            assert_eq!(importer.summary().already_known, 1);
+        }
+
+        // NDJSON 的最后一行可以没有换行
+        let mut importer = Importer::new(ExportFormat::Ndjson);
+        let mut line = encode_block(ExportFormat::Ndjson, &source.blocks[1]);
+        line.pop();
+        assert!(importer.push(&line).unwrap().is_empty());
+        assert!(importer.finish().unwrap().is_some());
+
+        // 大量空行不会耗尽栈
+        let mut importer = Importer::new(ExportFormat::Ndjson);
+        let mut data = vec![b'\n'; 1_000_000];
+        data.extend(encode_block(ExportFormat::Ndjson, &source.blocks[1]));
+        assert_eq!(importer.push(&data).unwrap().len(), 1);
+        assert!(importer.finish().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_import_rejects_bad_data() {
+        let source = source_chain(2);
+
+        // 缺少父区块的区块无法连接
+        let mut chain = BlockChain::new(0);
+        let mut importer = Importer::new(ExportFormat::Binary);
+        let blocks = importer
+            .push(&encode_block(ExportFormat::Binary, &source.blocks[2]))
+            .unwrap();
+        assert_eq!(
+            importer.apply(&mut chain, blocks[0].clone()),
+            Err(ImportError::Rejected(0, BlockError::UnknownParent))
+        );
+
+        // 数据在记录中间结束
+        let mut importer = Importer::new(ExportFormat::Binary);
+        let record = encode_block(ExportFormat::Binary, &source.blocks[1]);
+        assert!(importer
+            .push(&record[..record.len() - 1])
+            .unwrap()
+            .is_empty());
+        assert_eq!(importer.finish().unwrap_err(), ImportError::Truncated);
+
+        // 超长的记录在读完之前就被拒绝
+        let mut importer = Importer::new(ExportFormat::Binary);
+        assert_eq!(
+            importer.push(&u32::MAX.to_le_bytes()).unwrap_err(),
+            ImportError::RecordTooLarge(0)
+        );
+        let mut importer = Importer::new(ExportFormat::Ndjson);
+        assert!(matches!(
+            importer.push(b"not json\n"),
+            Err(ImportError::Malformed(0, _))
+        ));
+    }
+}
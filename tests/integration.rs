@@ -1,11 +1,15 @@
 #[cfg(test)]
 mod tests {
-    use block_chain::block_chain::{Block, BlockChain};
-    use block_chain::hash_function::sha256_hash;
+    use block_chain::block_chain::{
+        Block, BlockChain, TransactionError, TransactionPool, BLOCK_REWARD,
+    };
+    use block_chain::events::{ChainEvent, RemovalReason};
+    use block_chain::hash_function::{hash_block_header, sha256_hash};
     use block_chain::serialization::{deserialize_bc, serialize_bc};
-    use block_chain::transaction::Transaction;
+    use block_chain::transaction::{Transaction, TxIn, TxOut};
     use ring::rand::SystemRandom;
-    use ring::signature::Ed25519KeyPair;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+    use std::sync::{Arc, Mutex};
 
     #[test]
     fn test_block_chain() {
@@ -78,22 +82,275 @@ mod tests {
     }
     #[test]
     fn test_lock_time() {
-        let mut blockchain = BlockChain::new(0);
-        assert_eq!(blockchain.blocks.len(), 1); // 创世区块
-        let tx = Transaction::new(100, 0);
-        let mut tx_1000 = tx.clone();
+        let alice = key_pair();
+        let script = alice.public_key().as_ref().to_vec();
+        let (mut blockchain, coinbase) = funded_chain(&alice);
+        assert_eq!(blockchain.blocks.len(), 2); // 创世区块和 coinbase 所在的区块
+        let tx = spend(&alice, coinbase, BLOCK_REWARD, &script);
+        let mut tx_1000 = spend(&alice, tx.hash(), BLOCK_REWARD, &script);
         tx_1000.lock_time = 1000;
-        let mut tx_1 = tx.clone();
+        tx_1000.sign(&alice, 0);
+        let mut tx_1 = tx_1000.clone();
         tx_1.lock_time = 1;
-        blockchain.add_transaction(tx);
+        tx_1.sign(&alice, 0);
+        blockchain.add_transaction(tx.clone());
         blockchain.add_transaction(tx_1000);
         // mine工作
         blockchain.mine_block();
-        assert_eq!(blockchain.blocks.len(), 2); // 解释:只有创世区块和lock_time为0的
+        assert_eq!(blockchain.blocks.len(), 3);
+        // 解释:只打包了lock_time为0的tx
+        let hashes: Vec<[u8; 32]> = blockchain.blocks[2]
+            .transactions
+            .iter()
+            .map(|tx| tx.hash())
+            .collect();
+        assert_eq!(hashes, vec![tx.hash()]);
         assert_eq!(blockchain.transaction_pool.lock().unwrap().len(), 0); // 交易池应该为空
-        blockchain.add_transaction(tx_1);
+        blockchain.add_transaction(tx_1.clone());
         // mine工作
         blockchain.mine_block();
-        assert_eq!(blockchain.blocks.len(), 3); // 解释:有创世区块、lock_time为0的tx和lock_time为1的tx_1
+        assert_eq!(blockchain.blocks.len(), 4); // 解释:lock_time为1的tx_1已到期
+        assert_eq!(blockchain.blocks[3].transactions[0].hash(), tx_1.hash());
+    }
+
+    fn key_pair() -> Ed25519KeyPair {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap()
+    }
+
+    // 高度 1 的 coinbase 支付给 key_pair 的链，返回链和 coinbase 的哈希
+    fn funded_chain(key_pair: &Ed25519KeyPair) -> (BlockChain, [u8; 32]) {
+        let mut chain = BlockChain::new(0);
+        let block = chain.block_template(Some(key_pair.public_key().as_ref()));
+        chain.process_block(block).unwrap();
+        let coinbase = chain.blocks[1].transactions[0].hash();
+        (chain, coinbase)
+    }
+
+    #[test]
+    fn test_block_and_transaction_index() {
+        let (alice, bob) = (key_pair(), key_pair());
+        let (mut chain_a, coinbase) = funded_chain(&alice);
+        let tx = spend(&alice, coinbase, 10, &[1u8; 32]);
+        chain_a.add_transaction(tx.clone());
+        chain_a.mine_block();
+        let block_a = hash_block_header(&chain_a.blocks[2].header);
+        assert_eq!(chain_a.find_transaction(&tx.hash()).unwrap().1, 2);
+        assert!(chain_a.is_main_chain(&block_a));
+        assert_eq!(
+            chain_a.block_at(2).unwrap().transactions[0].hash(),
+            tx.hash()
+        );
+
+        let mut chain_b = BlockChain::new(0);
+        chain_b.process_block(chain_a.blocks[1].clone()).unwrap();
+        let tx_b1 = spend(&alice, coinbase, 20, bob.public_key().as_ref());
+        chain_b.add_transaction(tx_b1.clone());
+        chain_b.mine_block();
+        let tx_b2 = spend(&bob, tx_b1.hash(), 20, &[2u8; 32]);
+        chain_b.add_transaction(tx_b2.clone());
+        chain_b.mine_block();
+
+        // 重组后索引指向新的主链，断开的区块仍可按哈希查到
+        chain_a.process_block(chain_b.blocks[2].clone()).unwrap();
+        chain_a.process_block(chain_b.blocks[3].clone()).unwrap();
+        assert!(chain_a.find_transaction(&tx.hash()).is_none());
+        assert_eq!(chain_a.find_transaction(&tx_b2.hash()).unwrap().1, 3);
+        assert!(!chain_a.is_main_chain(&block_a));
+        assert_eq!(chain_a.block_height(&block_a), Some(2));
+        assert!(chain_a.get_block(&block_a).is_some());
+
+        // 直接修改区块列表后重建索引
+        chain_a.blocks.truncate(3);
+        chain_a.reindex();
+        assert!(chain_a.find_transaction(&tx_b2.hash()).is_none());
+        assert_eq!(chain_a.find_transaction(&tx_b1.hash()).unwrap().1, 2);
+    }
+
+    // 花费父交易中锁定给 key_pair 的输出，支付给 to
+    fn spend(key_pair: &Ed25519KeyPair, parent: [u8; 32], value: u64, to: &[u8]) -> Transaction {
+        let mut tx = Transaction::new(value, 0);
+        tx.inputs = vec![TxIn {
+            previous_output: parent,
+            ..TxIn::new()
+        }];
+        tx.outputs = vec![TxOut {
+            value,
+            script_pubkey: to.to_vec(),
+        }];
+        tx.sign(key_pair, 0);
+        tx
+    }
+
+    #[test]
+    fn test_mempool_validation() {
+        let rng = SystemRandom::new();
+        let keys: Vec<Ed25519KeyPair> = (0..2)
+            .map(|_| {
+                let pkcs8 = Ed25519KeyPair::generate_pkcs8(&rng).unwrap();
+                Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap()
+            })
+            .collect();
+        let alice = keys[0].public_key().as_ref().to_vec();
+        let bob = keys[1].public_key().as_ref().to_vec();
+        let mut chain = BlockChain::new(0);
+        let block = chain.block_template(Some(&alice));
+        chain.process_block(block).unwrap();
+        let coinbase = chain.blocks[1].transactions[0].hash();
+
+        // 金额、签名和父交易错误
+        let too_much = spend(&keys[0], coinbase, BLOCK_REWARD + 1, &bob);
+        assert_eq!(
+            chain.check_transaction(&too_much),
+            Err(TransactionError::InsufficientFunds)
+        );
+        let mut tampered = spend(&keys[0], coinbase, BLOCK_REWARD, &bob);
+        tampered.outputs[0].script_pubkey = alice.clone();
+        assert_eq!(
+            chain.check_transaction(&tampered),
+            Err(TransactionError::InvalidSignature)
+        );
+        assert_eq!(
+            chain.check_transaction(&spend(&keys[1], coinbase, 1, &bob)),
+            Err(TransactionError::NoSpendableOutput)
+        );
+        assert_eq!(
+            chain.check_transaction(&spend(&keys[0], [7; 32], 1, &bob)),
+            Err(TransactionError::MissingParent)
+        );
+        assert_eq!(
+            chain.check_transaction(&Transaction::new(1, 0)),
+            Err(TransactionError::MissingSignature)
+        );
+        let mut future = spend(&keys[0], coinbase, 1, &bob);
+        future.lock_time = 100;
+        future.sign(&keys[0], 0);
+        assert_eq!(
+            chain.check_transaction(&future),
+            Err(TransactionError::NonFinal)
+        );
+        assert_eq!(
+            chain.check_transaction(&chain.blocks[1].transactions[0]),
+            Err(TransactionError::Coinbase)
+        );
+
+        // 不含之后交易的分叉链，交易池独立
+        let mut fork = chain.clone();
+        fork.transaction_pool = Arc::new(Mutex::new(TransactionPool::new()));
+
+        // 接受后不能重复提交，也不能再次花费同一输出；交易池中的交易可以作为父交易
+        let payment = spend(&keys[0], coinbase, BLOCK_REWARD, &bob);
+        let txid = chain.accept_transaction(payment.clone()).unwrap();
+        assert_eq!(
+            chain.accept_transaction(payment),
+            Err(TransactionError::AlreadyKnown)
+        );
+        assert_eq!(
+            chain.check_transaction(&spend(&keys[0], coinbase, 10, &alice)),
+            Err(TransactionError::DoubleSpend)
+        );
+        let child = spend(&keys[1], txid, BLOCK_REWARD, &alice);
+        assert!(chain.accept_transaction(child).is_ok());
+
+        // 打包后主链上的花费同样算作冲突
+        chain.mine_block();
+        assert_eq!(chain.balance(&alice), BLOCK_REWARD);
+        assert_eq!(
+            chain.check_transaction(&spend(&keys[0], coinbase, 10, &alice)),
+            Err(TransactionError::DoubleSpend)
+        );
+
+        // 重组断开该区块后花费回到交易池；移出交易池后输出可以再次花费
+        fork.mine_block();
+        fork.mine_block();
+        chain.process_block(fork.blocks[2].clone()).unwrap();
+        chain.process_block(fork.blocks[3].clone()).unwrap();
+        assert!(!chain.contains_transaction(&txid));
+        assert!(chain.transaction_pool.lock().unwrap().contains(&txid));
+        assert_eq!(
+            chain
+                .transaction_pool
+                .lock()
+                .unwrap()
+                .get_by_hash(&txid)
+                .map(Transaction::hash),
+            Some(txid)
+        );
+        assert_eq!(
+            chain.check_transaction(&spend(&keys[0], coinbase, 10, &alice)),
+            Err(TransactionError::DoubleSpend)
+        );
+        let removed = chain.transaction_pool.lock().unwrap().remove_if(|_| true);
+        assert_eq!(removed.len(), 2);
+        assert!(!chain.transaction_pool.lock().unwrap().contains(&txid));
+        assert!(chain
+            .transaction_pool
+            .lock()
+            .unwrap()
+            .get_by_hash(&txid)
+            .is_none());
+        assert!(chain
+            .check_transaction(&spend(&keys[0], coinbase, 10, &alice))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_chain_events() {
+        let alice = key_pair();
+        let (mut chain_a, coinbase) = funded_chain(&alice);
+        let mut events = chain_a.events().subscribe();
+        let tx = spend(&alice, coinbase, 10, &[1u8; 32]);
+        chain_a.add_transaction(tx.clone());
+        chain_a.mine_block();
+        let block_a = hash_block_header(&chain_a.blocks[2].header);
+
+        let mut received = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            received.push(event);
+        }
+        assert!(
+            matches!(&received[0], ChainEvent::TransactionAdded(added) if added.hash() == tx.hash())
+        );
+        assert!(matches!(
+            received[1],
+            ChainEvent::TransactionRemoved {
+                reason: RemovalReason::Included,
+                ..
+            }
+        ));
+        assert!(matches!(
+            &received[2],
+            ChainEvent::BlockConnected { height: 2, .. }
+        ));
+        assert!(matches!(received[3], ChainEvent::NewTip { hash, height: 2 } if hash == block_a));
+        assert_eq!(received.len(), 4);
+
+        // 重组：先断开原链的区块，交易放回交易池，再连接新链的区块
+        let mut chain_b = BlockChain::new(0);
+        chain_b.process_block(chain_a.blocks[1].clone()).unwrap();
+        chain_b.mine_block();
+        chain_b.mine_block();
+        chain_a.process_block(chain_b.blocks[2].clone()).unwrap();
+        chain_a.process_block(chain_b.blocks[3].clone()).unwrap();
+        let mut received = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            received.push(event);
+        }
+        assert!(
+            matches!(&received[0], ChainEvent::BlockDisconnected { block, height: 2 } if hash_block_header(&block.header) == block_a)
+        );
+        assert!(
+            matches!(&received[1], ChainEvent::TransactionAdded(added) if added.hash() == tx.hash())
+        );
+        assert!(matches!(
+            &received[2],
+            ChainEvent::BlockConnected { height: 2, .. }
+        ));
+        assert!(matches!(
+            &received[3],
+            ChainEvent::BlockConnected { height: 3, .. }
+        ));
+        assert!(matches!(received[4], ChainEvent::NewTip { height: 3, .. }));
+        assert_eq!(received.len(), 5);
     }
 }
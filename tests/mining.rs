@@ -0,0 +1,289 @@
+#[cfg(test)]
+mod tests {
+    use block_chain::block_chain::{
+        Block, BlockChain, BlockError, BlockHeader, TransactionError, BLOCK_REWARD,
+    };
+    use block_chain::hash_function::{calculate_merkle_root, hash_block_header};
+    use block_chain::miner::{meets_difficulty, Miner};
+    use block_chain::mining::{BlockTemplate, MiningService};
+    use block_chain::serialization::{deserialize_bc, serialize_bc};
+    use block_chain::transaction::{encode_address, Transaction, TxIn, TxOut};
+    use ring::rand::SystemRandom;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::sync::Mutex as AsyncMutex;
+
+    fn key_pair() -> Ed25519KeyPair {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap()
+    }
+
+    // 挖出一个 coinbase 支付给 key_pair 的区块，返回 coinbase 的哈希
+    fn fund(blockchain: &mut BlockChain, key_pair: &Ed25519KeyPair) -> [u8; 32] {
+        let mut block = blockchain.block_template(Some(key_pair.public_key().as_ref()));
+        let cancel = AtomicBool::new(false);
+        block.header = Miner::new(1)
+            .mine(&block.header, blockchain.difficulty(), &cancel)
+            .unwrap();
+        let coinbase = block.transactions[0].hash();
+        blockchain.process_block(block).unwrap();
+        coinbase
+    }
+
+    // 花费父交易中锁定给 key_pair 的输出，支付给 to
+    fn spend(key_pair: &Ed25519KeyPair, parent: [u8; 32], value: u64, to: &[u8]) -> Transaction {
+        let mut tx = Transaction::new(value, 0);
+        tx.inputs = vec![TxIn {
+            previous_output: parent,
+            ..TxIn::new()
+        }];
+        tx.outputs = vec![TxOut {
+            value,
+            script_pubkey: to.to_vec(),
+        }];
+        tx.sign(key_pair, 0);
+        tx
+    }
+
+    #[test]
+    fn test_multi_threaded_miner() {
+        let mut blockchain = BlockChain::new(1);
+        let template = blockchain.take_block_template();
+        let cancel = AtomicBool::new(false);
+        let header = Miner::new(4)
+            .mine(&template.header, 1, &cancel)
+            .expect("miner should find a nonce");
+        assert!(meets_difficulty(&hash_block_header(&header), 1));
+        assert_eq!(header.prev_block_hash, blockchain.tip_hash());
+    }
+
+    #[test]
+    fn test_nonce_overflow_rolls_timestamp() {
+        let mut blockchain = BlockChain::new(1);
+        let mut header = blockchain.take_block_template().header;
+        // 从接近 u32::MAX 的位置开始搜索，溢出时不应 panic
+        header.nonce = u32::MAX - 3;
+        let cancel = AtomicBool::new(false);
+        let mined = Miner::new(2).mine(&header, 1, &cancel).unwrap();
+        assert!(meets_difficulty(&hash_block_header(&mined), 1));
+        assert!(mined.timestamp >= header.timestamp);
+    }
+
+    #[test]
+    fn test_cancel_mining() {
+        let mut blockchain = BlockChain::new(1);
+        let template = blockchain.take_block_template();
+        let cancel = AtomicBool::new(true);
+        // 难度 32 几乎不可能满足，只能通过取消返回
+        assert!(Miner::new(2).mine(&template.header, 32, &cancel).is_none());
+    }
+
+    #[test]
+    fn test_submit_stale_block() {
+        let mut blockchain = BlockChain::new(0);
+        let alice = key_pair();
+        let coinbase = fund(&mut blockchain, &alice);
+        blockchain.add_transaction(spend(&alice, coinbase, 10, &[1u8; 32]));
+        let template = blockchain.take_block_template();
+        assert_eq!(template.transactions.len(), 1);
+        // 模板生成后链顶发生变化
+        blockchain.mine_block_with(&Miner::new(1));
+        assert!(!blockchain.submit_mined_block(template));
+        assert_eq!(blockchain.blocks.len(), 3);
+        assert_eq!(blockchain.transaction_pool.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_background_mining_service() {
+        let blockchain = Arc::new(AsyncMutex::new(BlockChain::new(1)));
+        let payout = encode_address(&[7u8; 32]);
+        let service = MiningService::new(blockchain.clone(), Miner::new(2), Some(payout));
+        assert!(service.start());
+        assert!(!service.start()); // 重复启动无效
+
+        let mut found = 0;
+        for _ in 0..100 {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            found = service.status().await.blocks_found;
+            if found > 0 {
+                break;
+            }
+        }
+        assert!(found > 0, "background miner should find a block");
+        let status = service.status().await;
+        assert!(status.running);
+        assert_eq!(status.difficulty, 1);
+        assert!(service.stop());
+        assert!(!service.status().await.running);
+
+        // 挖出的区块第一笔交易是支付到收款地址的 coinbase
+        let blockchain = blockchain.lock().await;
+        let block = &blockchain.blocks[1];
+        assert!(block.transactions[0].is_coinbase());
+        assert_eq!(
+            block.transactions[0].outputs[0].script_pubkey,
+            vec![7u8; 32]
+        );
+    }
+
+    // 按模板构造一个区块：coinbase 加上模板中的候选交易
+    fn block_from_template(template: &BlockTemplate, coinbase_value: u64) -> Block {
+        let mut transactions = vec![Transaction::coinbase(
+            template.height,
+            0,
+            coinbase_value,
+            vec![1u8; 32],
+        )];
+        for tx in &template.transactions {
+            transactions.push(deserialize_bc(&hex::decode(&tx.data).unwrap()).unwrap());
+        }
+        Block {
+            header: BlockHeader {
+                version: template.version,
+                prev_block_hash: hex::decode(&template.prev_hash)
+                    .unwrap()
+                    .try_into()
+                    .unwrap(),
+                merkle_root: calculate_merkle_root(&transactions),
+                timestamp: template.curtime,
+                bits: template.bits,
+                nonce: 0,
+            },
+            transactions,
+        }
+    }
+
+    #[test]
+    fn test_template_and_submit_block() {
+        let mut blockchain = BlockChain::new(1);
+        let alice = key_pair();
+        let coinbase = fund(&mut blockchain, &alice);
+        blockchain.add_transaction(spend(&alice, coinbase, 10, &[1u8; 32]));
+        let template = BlockTemplate::new(&blockchain);
+        assert_eq!(template.height, 2);
+        assert_eq!(template.transactions.len(), 1);
+
+        let mut block = block_from_template(&template, template.coinbase_value);
+        let cancel = AtomicBool::new(false);
+        block.header = Miner::new(2).mine(&block.header, 1, &cancel).unwrap();
+
+        // 经过十六进制序列化往返后提交
+        let data = hex::encode(serialize_bc(&block).unwrap());
+        let decoded: Block = deserialize_bc(&hex::decode(data).unwrap()).unwrap();
+        assert_eq!(blockchain.submit_block(decoded.clone()), Ok(()));
+        assert_eq!(blockchain.blocks.len(), 3);
+        assert_eq!(blockchain.transaction_pool.lock().unwrap().len(), 0);
+        // 重复提交的区块不再指向链顶
+        assert_eq!(
+            blockchain.submit_block(decoded),
+            Err(BlockError::PrevHashMismatch)
+        );
+    }
+
+    #[test]
+    fn test_submit_invalid_block() {
+        let mut blockchain = BlockChain::new(0);
+        let template = BlockTemplate::new(&blockchain);
+
+        let mut bad_merkle = block_from_template(&template, template.coinbase_value);
+        bad_merkle.header.merkle_root = [1; 32];
+        assert_eq!(
+            blockchain.submit_block(bad_merkle),
+            Err(BlockError::MerkleRootMismatch)
+        );
+
+        let greedy = block_from_template(&template, template.coinbase_value + 1);
+        assert_eq!(
+            blockchain.submit_block(greedy),
+            Err(BlockError::BadCoinbase)
+        );
+
+        // 金额之和溢出的 coinbase 不能绕过奖励上限
+        let mut overflow = block_from_template(&template, u64::MAX);
+        overflow.transactions[0].outputs.push(TxOut {
+            value: 51,
+            script_pubkey: vec![1u8; 32],
+        });
+        overflow.header.merkle_root = calculate_merkle_root(&overflow.transactions);
+        assert_eq!(
+            blockchain.submit_block(overflow),
+            Err(BlockError::BadCoinbase)
+        );
+
+        let mut bad_bits = block_from_template(&template, template.coinbase_value);
+        bad_bits.header.bits = 5;
+        assert_eq!(blockchain.submit_block(bad_bits), Err(BlockError::BadBits));
+
+        // 不可反序列化的数据返回错误而不是 panic
+        assert!(deserialize_bc::<Block>(&[1, 2, 3]).is_err());
+        assert_eq!(blockchain.blocks.len(), 1);
+    }
+
+    #[test]
+    fn test_submit_block_checks_inputs() {
+        let mut blockchain = BlockChain::new(0);
+        let (alice, bob) = (key_pair(), key_pair());
+        let coinbase = fund(&mut blockchain, &alice);
+        let bob_script = bob.public_key().as_ref().to_vec();
+
+        // 交易池中无效的交易不会进入模板
+        blockchain.add_transaction(Transaction::new(1, 0));
+        let template = BlockTemplate::new(&blockchain);
+        assert!(template.transactions.is_empty());
+
+        let block_with = |transactions: Vec<Transaction>| {
+            let mut block = block_from_template(&template, template.coinbase_value);
+            block.transactions.extend(transactions);
+            block.header.merkle_root = calculate_merkle_root(&block.transactions);
+            block
+        };
+        let cases = [
+            (vec![Transaction::new(1, 0)], BlockError::InvalidSignature),
+            (
+                vec![spend(&alice, [7; 32], 1, &bob_script)],
+                BlockError::InvalidTransaction(TransactionError::MissingParent),
+            ),
+            (
+                vec![spend(&alice, coinbase, BLOCK_REWARD + 1, &bob_script)],
+                BlockError::InvalidTransaction(TransactionError::InsufficientFunds),
+            ),
+            (
+                vec![
+                    spend(&alice, coinbase, 1, &bob_script),
+                    spend(&alice, coinbase, 2, &bob_script),
+                ],
+                BlockError::InvalidTransaction(TransactionError::DoubleSpend),
+            ),
+        ];
+        for (transactions, error) in cases {
+            assert_eq!(
+                blockchain.submit_block(block_with(transactions)),
+                Err(error)
+            );
+        }
+
+        // 同一区块中可以花费之前交易的输出
+        let payment = spend(&alice, coinbase, BLOCK_REWARD, &bob_script);
+        let child = spend(&bob, payment.hash(), BLOCK_REWARD, &[1u8; 32]);
+        assert_eq!(
+            blockchain.submit_block(block_with(vec![payment, child])),
+            Ok(())
+        );
+
+        // 主链上已花费的输出不能再次花费
+        let template = BlockTemplate::new(&blockchain);
+        let mut block = block_from_template(&template, template.coinbase_value);
+        block
+            .transactions
+            .push(spend(&alice, coinbase, 1, &bob_script));
+        block.header.merkle_root = calculate_merkle_root(&block.transactions);
+        assert_eq!(
+            blockchain.submit_block(block),
+            Err(BlockError::InvalidTransaction(
+                TransactionError::DoubleSpend
+            ))
+        );
+    }
+}
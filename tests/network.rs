@@ -0,0 +1,1050 @@
+#[cfg(test)]
+mod tests {
+    use block_chain::block_chain::BlockHeader;
+    use block_chain::block_chain::{
+        Block, BlockChain, BlockError, BlockStatus, MAX_REORG_DEPTH, MAX_TRANSACTION_SIZE,
+    };
+    use block_chain::hash_function::{calculate_merkle_root, hash_block_header};
+    use block_chain::miner::Miner;
+    use block_chain::network::banlist::BanList;
+    use block_chain::network::compact::siphash24;
+    use block_chain::network::manager::PeerManager;
+    use block_chain::network::message::MAX_HEADERS;
+    use block_chain::network::message::{
+        decode_frame, encode_frame, FrameError, Inventory, Message, VersionMessage,
+    };
+    use block_chain::network::orphan::{
+        OrphanBlocks, OrphanPool, MAX_ORPHAN_BLOCKS, MAX_ORPHAN_BLOCK_BYTES,
+        MAX_ORPHAN_BLOCK_BYTES_PER_PEER, MAX_ORPHAN_TRANSACTIONS, ORPHAN_TX_EXPIRY_MS,
+    };
+    use block_chain::network::peer::PeerState;
+    use block_chain::network::sync::{
+        BlockSync, HeadersOutcome, MAX_BLOCKS_IN_FLIGHT_PER_PEER, MAX_PENDING_HEADERS,
+    };
+    use block_chain::network::transport::{Identity, TransportError};
+    use block_chain::network::{
+        tcp, transport, Action, Direction, NetworkConfig, PeerId, DEFAULT_MAGIC, PROTOCOL_VERSION,
+    };
+    use block_chain::transaction::{Transaction, TxIn, TxOut};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use ring::rand::SystemRandom;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::sync::Mutex as AsyncMutex;
+
+    // 在两个状态机之间转发消息，直到没有新的动作
+    fn pump(
+        a: &mut PeerManager,
+        chain_a: &mut BlockChain,
+        b: &mut PeerManager,
+        chain_b: &mut BlockChain,
+        now: u64,
+    ) -> Vec<String> {
+        let mut disconnects = Vec::new();
+        loop {
+            let from_a = a.take_actions();
+            let from_b = b.take_actions();
+            if from_a.is_empty() && from_b.is_empty() {
+                return disconnects;
+            }
+            for action in from_a {
+                match action {
+                    Action::Send(_, message) => b.on_message(1, message, chain_b, now),
+                    Action::Disconnect(_, reason) => disconnects.push(reason),
+                    Action::Connect(_) => {}
+                }
+            }
+            for action in from_b {
+                match action {
+                    Action::Send(_, message) => a.on_message(1, message, chain_a, now),
+                    Action::Disconnect(_, reason) => disconnects.push(reason),
+                    Action::Connect(_) => {}
+                }
+            }
+        }
+    }
+
+    // 挖出指定数量区块的链
+    fn mined_chain(blocks: usize) -> BlockChain {
+        let mut chain = BlockChain::new(0);
+        for _ in 0..blocks {
+            chain.mine_block_with(&Miner::new(1));
+        }
+        chain
+    }
+
+    fn key_pair() -> Ed25519KeyPair {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap()
+    }
+
+    // 高度 1 的区块，coinbase 支付给 key_pair
+    fn funding_block(key_pair: &Ed25519KeyPair) -> Block {
+        BlockChain::new(0).block_template(Some(key_pair.public_key().as_ref()))
+    }
+
+    // 花费父交易中锁定给 key_pair 的输出，支付给 to
+    fn spend(key_pair: &Ed25519KeyPair, parent: [u8; 32], value: u64, to: &[u8]) -> Transaction {
+        let mut tx = Transaction::new(value, 0);
+        tx.inputs = vec![TxIn {
+            previous_output: parent,
+            ..TxIn::new()
+        }];
+        tx.outputs = vec![TxOut {
+            value,
+            script_pubkey: to.to_vec(),
+        }];
+        tx.sign(key_pair, 0);
+        tx
+    }
+
+    // 在多个节点之间转发消息：links 把 (节点, 连接) 映射到对端的 (节点, 连接)，
+    // 返回所有发送过的 (发送方, 接收方, 消息) 记录
+    fn pump_all(
+        nodes: &mut [(PeerManager, BlockChain)],
+        links: &HashMap<(usize, PeerId), (usize, PeerId)>,
+        now: u64,
+    ) -> Vec<(usize, usize, Message)> {
+        let mut log = Vec::new();
+        loop {
+            let mut delivered = false;
+            for from in 0..nodes.len() {
+                for action in nodes[from].0.take_actions() {
+                    if let Action::Send(peer, message) = action {
+                        let (to, id) = links[&(from, peer)];
+                        log.push((from, to, message.clone()));
+                        let (manager, chain) = &mut nodes[to];
+                        manager.on_message(id, message, chain, now);
+                        delivered = true;
+                    }
+                }
+            }
+            if !delivered {
+                return log;
+            }
+        }
+    }
+
+    // 模拟对方完成握手，对方声明的链顶高度为 best_height
+    fn handshake(manager: &mut PeerManager, chain: &mut BlockChain, best_height: u32) {
+        manager.on_connected(1, "peer".into(), Direction::Outbound, chain, 0);
+        let version = version_message(chain, best_height);
+        manager.on_message(1, Message::Version(version), chain, 0);
+        manager.on_message(1, Message::Verack, chain, 0);
+    }
+
+    fn version_message(chain: &BlockChain, best_height: u32) -> VersionMessage {
+        VersionMessage {
+            version: PROTOCOL_VERSION,
+            services: 0,
+            timestamp: 0,
+            nonce: 7,
+            user_agent: "test".into(),
+            genesis_hash: chain.genesis_hash(),
+            best_height,
+            listen_port: 0,
+        }
+    }
+
+    #[test]
+    fn test_block_locator() {
+        let chain = mined_chain(20);
+        let locator = chain.block_locator();
+        assert_eq!(locator[0], chain.tip_hash());
+        assert_eq!(*locator.last().unwrap(), chain.genesis_hash());
+        // 最近 10 个逐个列出，之后间隔加倍
+        assert_eq!(locator.len(), 13);
+
+        // 对方只有前 5 个区块时，从第 6 个区块开始返回
+        let mut short = BlockChain::new(0);
+        short.blocks = chain.blocks[..6].to_vec();
+        let headers = chain.locate_headers(&short.block_locator(), [0; 32], 2_000);
+        assert_eq!(headers.len(), 15);
+        assert_eq!(headers[0], chain.blocks[6].header);
+        let stop = hash_block_header(&chain.blocks[8].header);
+        assert_eq!(
+            chain
+                .locate_headers(&short.block_locator(), stop, 2_000)
+                .len(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_headers_first_sync_from_multiple_peers() {
+        let chain_a = mined_chain(40);
+        let chain_c = chain_a.clone();
+        let config = NetworkConfig::default();
+        let mut nodes = vec![
+            (PeerManager::new(config.clone(), 1), chain_a),
+            (PeerManager::new(config.clone(), 2), BlockChain::new(0)),
+            (PeerManager::new(config, 3), chain_c),
+        ];
+        // 新节点 1 同时连接节点 0 和节点 2
+        let mut links = HashMap::new();
+        links.insert((1, 1), (0, 1));
+        links.insert((0, 1), (1, 1));
+        links.insert((1, 2), (2, 1));
+        links.insert((2, 1), (1, 2));
+        for (node, peer, direction) in [
+            (1, 1, Direction::Outbound),
+            (0, 1, Direction::Inbound),
+            (1, 2, Direction::Outbound),
+            (2, 1, Direction::Inbound),
+        ] {
+            let (manager, chain) = &mut nodes[node];
+            manager.on_connected(peer, "peer".into(), direction, chain, 0);
+        }
+
+        let log = pump_all(&mut nodes, &links, 0);
+        assert_eq!(nodes[1].1.height(), 40);
+        assert_eq!(nodes[1].1.tip_hash(), nodes[0].1.tip_hash());
+
+        // 只向一个节点请求区块头，区块体从两个节点并行下载
+        let header_requests = log
+            .iter()
+            .filter(|(from, _, m)| *from == 1 && matches!(m, Message::GetHeaders { .. }))
+            .count();
+        assert_eq!(header_requests, 1);
+        for peer in [0, 2] {
+            let requested: usize = log
+                .iter()
+                .filter_map(|(from, to, m)| match m {
+                    Message::GetData(items) if *from == 1 && *to == peer => Some(items.len()),
+                    _ => None,
+                })
+                .sum();
+            assert!(requested > 0 && requested <= 40);
+        }
+    }
+
+    #[test]
+    fn test_sync_stall_and_invalid_headers() {
+        let chain_a = mined_chain(20);
+        let mut chain_b = BlockChain::new(0);
+        let mut b = PeerManager::new(NetworkConfig::default(), 2);
+        handshake(&mut b, &mut chain_b, 20);
+        let Some(Action::Send(1, Message::GetHeaders { locator, stop })) = b.take_actions().pop()
+        else {
+            panic!("expected getheaders");
+        };
+        let headers = chain_a.locate_headers(&locator, stop, 2_000);
+        b.on_message(1, Message::Headers(headers), &mut chain_b, 0);
+        let Some(Action::Send(1, Message::GetData(items))) = b.take_actions().pop() else {
+            panic!("expected getdata");
+        };
+        assert_eq!(items.len(), MAX_BLOCKS_IN_FLIGHT_PER_PEER);
+
+        // 除第一个区块外都已送达，区块无法连接，超时后判定对方停滞
+        for block in &chain_a.blocks[2..=items.len()] {
+            b.on_message(1, Message::Block(block.clone()), &mut chain_b, 0);
+        }
+        assert_eq!(chain_b.height(), 0);
+        b.take_actions();
+        b.on_tick(&chain_b, 5_001);
+        assert!(matches!(
+            &b.take_actions()[..],
+            [Action::Disconnect(1, reason)] if reason == "block download stalled"
+        ));
+
+        // 接不上已知链的区块头只增加分数
+        let mut c = PeerManager::new(NetworkConfig::default(), 3);
+        handshake(&mut c, &mut chain_b, 20);
+        c.take_actions();
+        let headers: Vec<_> = chain_a.blocks[1..8]
+            .iter()
+            .map(|block| block.header.clone())
+            .collect();
+        c.on_message(1, Message::Headers(headers[4..].to_vec()), &mut chain_b, 0);
+        assert_eq!(c.peers()[0].ban_score, 20);
+
+        // 时间戳超前的区块头不计分，也不再向对方请求区块头
+        c.take_actions();
+        let mut future = headers[..1].to_vec();
+        future[0].timestamp = u32::MAX;
+        c.on_message(1, Message::Headers(future), &mut chain_b, 0);
+        assert_eq!(c.peers()[0].ban_score, 20);
+        assert!(c.take_actions().is_empty());
+
+        // 难度不符的区块头被拒绝
+        let mut invalid = headers;
+        invalid[3].bits = 1;
+        c.on_message(1, Message::Headers(invalid), &mut chain_b, 0);
+        assert!(matches!(
+            c.take_actions().last(),
+            Some(Action::Disconnect(1, reason)) if reason == "invalid headers"
+        ));
+    }
+
+    #[test]
+    fn test_pending_headers_are_capped() {
+        let chain = BlockChain::new(0);
+        let mut sync = BlockSync::new();
+        let mut prev = chain.tip_hash();
+        let mut headers = Vec::new();
+        for nonce in 0..(MAX_PENDING_HEADERS + MAX_HEADERS) as u32 {
+            let header = BlockHeader {
+                version: 1,
+                prev_block_hash: prev,
+                merkle_root: [0; 32],
+                timestamp: 0,
+                bits: 0,
+                nonce,
+            };
+            prev = hash_block_header(&header);
+            headers.push(header);
+        }
+
+        let mut outcome = HeadersOutcome::More;
+        for batch in headers.chunks(MAX_HEADERS) {
+            assert_eq!(outcome, HeadersOutcome::More);
+            outcome = sync.on_headers(1, batch.to_vec(), &chain).unwrap();
+        }
+        // 达到上限后不再接收，也不再请求更多区块头
+        assert_eq!(outcome, HeadersOutcome::Complete);
+        assert_eq!(sync.header_height(), MAX_PENDING_HEADERS as u32);
+        assert!(!sync.wants_headers());
+        let last = hash_block_header(&headers[MAX_PENDING_HEADERS - 1]);
+        assert_eq!(sync.height_of(&last), Some(MAX_PENDING_HEADERS as u32));
+        assert_eq!(sync.height_of(&prev), None);
+    }
+
+    #[test]
+    fn test_misbehaviour_bans_peer() {
+        let config = NetworkConfig::default();
+        let ban_duration = config.ban_duration_ms;
+        let mut chain = BlockChain::new(0);
+        let mut manager = PeerManager::new(config, 1);
+        let peer = "10.0.0.5:3031".to_string();
+        manager.on_connected(1, peer.clone(), Direction::Inbound, &chain, 0);
+        manager.on_message(
+            1,
+            Message::Version(version_message(&chain, 0)),
+            &mut chain,
+            0,
+        );
+        manager.on_message(1, Message::Verack, &mut chain, 0);
+        manager.take_actions();
+
+        // 超大的消息每次加 20 分，达到 100 分时断开并封禁
+        let oversized = vec![Inventory::Tx([0; 32]); 50_001];
+        for _ in 0..4 {
+            manager.on_message(1, Message::Inv(oversized.clone()), &mut chain, 0);
+        }
+        manager.on_malformed(1, &FrameError::BadChecksum, 0);
+        assert_eq!(manager.peers()[0].ban_score, 90);
+        assert!(manager.take_actions().is_empty());
+        manager.on_malformed(1, &FrameError::Malformed("eof".into()), 0);
+        assert!(matches!(
+            &manager.take_actions()[..],
+            [Action::Disconnect(1, _)]
+        ));
+        let ip = "10.0.0.5".parse().unwrap();
+        assert!(manager.bans().is_banned(&ip, 0));
+
+        // 被封禁的地址重新连接时立即断开
+        manager.on_connected(2, "10.0.0.5:4000".into(), Direction::Inbound, &chain, 10);
+        assert!(matches!(
+            &manager.take_actions()[..],
+            [Action::Disconnect(2, reason)] if reason == "banned"
+        ));
+
+        // 无效区块直接封禁
+        manager.on_connected(3, "10.0.0.6:3031".into(), Direction::Inbound, &chain, 0);
+        manager.on_message(
+            3,
+            Message::Version(version_message(&chain, 0)),
+            &mut chain,
+            0,
+        );
+        manager.on_message(3, Message::Verack, &mut chain, 0);
+        let mut block = chain.block_template(None);
+        block.header.merkle_root = [1; 32];
+        manager.on_message(3, Message::Block(block), &mut chain, 0);
+        assert!(manager.bans().is_banned(&"10.0.0.6".parse().unwrap(), 0));
+
+        // 到期后自动解封，也可以手动解封
+        manager.on_tick(&chain, ban_duration + 1);
+        assert!(!manager.bans().is_banned(&ip, ban_duration + 1));
+        assert!(manager.bans().entries().is_empty());
+        manager.ban(ip, Some(1_000), "manual", 0);
+        assert!(manager.unban(&ip));
+        assert!(!manager.unban(&ip));
+    }
+
+    #[test]
+    fn test_ban_list_persistence() {
+        let mut bans = BanList::new();
+        let ip = "10.0.0.7".parse().unwrap();
+        bans.ban(ip, 0, 1_000, "invalid block");
+        bans.ban(ip, 500, 100, "shorter ban does not shorten");
+        assert!(bans.take_changed());
+        assert!(!bans.take_changed());
+
+        let path = std::env::temp_dir().join(format!("bans-{}.json", std::process::id()));
+        bans.save(&path).unwrap();
+        let loaded = BanList::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.entries(), bans.entries());
+        assert_eq!(loaded.entries()[0].until, 1_000);
+        assert!(loaded.is_banned(&ip, 999));
+        assert!(!loaded.is_banned(&ip, 1_000));
+    }
+
+    #[test]
+    fn test_frame_round_trip() {
+        let frame = encode_frame(DEFAULT_MAGIC, &Message::Ping(42));
+        // 数据不完整时等待更多数据
+        assert!(decode_frame(DEFAULT_MAGIC, &frame[..10]).unwrap().is_none());
+        assert!(decode_frame(DEFAULT_MAGIC, &frame[..frame.len() - 1])
+            .unwrap()
+            .is_none());
+        let (message, used) = decode_frame(DEFAULT_MAGIC, &frame).unwrap().unwrap();
+        assert!(matches!(message, Message::Ping(42)));
+        assert_eq!(used, frame.len());
+
+        assert_eq!(
+            decode_frame([0; 4], &frame).unwrap_err(),
+            FrameError::BadMagic
+        );
+        let mut corrupted = frame.clone();
+        *corrupted.last_mut().unwrap() ^= 1;
+        assert_eq!(
+            decode_frame(DEFAULT_MAGIC, &corrupted).unwrap_err(),
+            FrameError::BadChecksum
+        );
+        let mut oversized = frame.clone();
+        oversized[16..20].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            decode_frame(DEFAULT_MAGIC, &oversized),
+            Err(FrameError::PayloadTooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn test_handshake_and_ping_timeout() {
+        let config = NetworkConfig::default();
+        let (mut a, mut chain_a) = (PeerManager::new(config.clone(), 1), BlockChain::new(0));
+        let (mut b, mut chain_b) = (PeerManager::new(config.clone(), 2), BlockChain::new(0));
+        a.on_connected(1, "b".into(), Direction::Outbound, &chain_a, 0);
+        b.on_connected(1, "a".into(), Direction::Inbound, &chain_b, 0);
+        assert!(pump(&mut a, &mut chain_a, &mut b, &mut chain_b, 0).is_empty());
+        assert_eq!(a.peers()[0].state, PeerState::Ready);
+        assert_eq!(b.peers()[0].state, PeerState::Ready);
+        assert_eq!(b.peers()[0].best_height, Some(0));
+
+        // ping 得到回复后记录延迟
+        a.on_tick(&chain_a, config.ping_interval_ms);
+        pump(
+            &mut a,
+            &mut chain_a,
+            &mut b,
+            &mut chain_b,
+            config.ping_interval_ms + 5,
+        );
+        assert_eq!(a.peers()[0].latency_ms, Some(5));
+
+        // 对方不回复 pong 时超时断开
+        let now = config.ping_interval_ms * 2;
+        a.on_tick(&chain_a, now);
+        assert!(matches!(
+            a.take_actions()[..],
+            [Action::Send(1, Message::Ping(_))]
+        ));
+        a.on_tick(&chain_a, now + config.ping_timeout_ms + 1);
+        assert!(matches!(
+            &a.take_actions()[..],
+            [Action::Disconnect(1, reason)] if reason == "ping timeout"
+        ));
+        assert!(a.peers().is_empty());
+    }
+
+    #[test]
+    fn test_handshake_rejects_other_chain() {
+        let config = NetworkConfig::default();
+        let (mut a, mut chain_a) = (PeerManager::new(config.clone(), 1), BlockChain::new(0));
+        let (mut b, mut chain_b) = (PeerManager::new(config, 2), BlockChain::new(0));
+        chain_b.blocks[0].header.nonce = 1; // 不同的创世区块
+        a.on_connected(1, "b".into(), Direction::Outbound, &chain_a, 0);
+        b.on_connected(1, "a".into(), Direction::Inbound, &chain_b, 0);
+        let disconnects = pump(&mut a, &mut chain_a, &mut b, &mut chain_b, 0);
+        assert_eq!(disconnects, vec!["genesis hash mismatch".to_string()]);
+
+        // 连接到自己时断开
+        let mut c = PeerManager::new(NetworkConfig::default(), 3);
+        c.on_connected(1, "self".into(), Direction::Outbound, &chain_a, 0);
+        c.on_connected(2, "self".into(), Direction::Inbound, &chain_a, 0);
+        let Action::Send(_, version) = c.take_actions().remove(0) else {
+            panic!("expected version");
+        };
+        c.on_message(2, version, &mut chain_a, 0);
+        assert!(matches!(
+            &c.take_actions()[..],
+            [Action::Disconnect(2, reason)] if reason == "connected to self"
+        ));
+    }
+
+    #[test]
+    fn test_block_relay_with_orphans() {
+        let config = NetworkConfig::default();
+        let (mut a, mut chain_a) = (PeerManager::new(config.clone(), 1), BlockChain::new(0));
+        let (mut b, mut chain_b) = (PeerManager::new(config, 2), BlockChain::new(0));
+        a.on_connected(1, "b".into(), Direction::Outbound, &chain_a, 0);
+        b.on_connected(1, "a".into(), Direction::Inbound, &chain_b, 0);
+        pump(&mut a, &mut chain_a, &mut b, &mut chain_b, 0);
+
+        // 对方握手时要求紧凑区块，新链顶直接以紧凑区块通告
+        chain_a.mine_block_with(&Miner::new(1));
+        a.on_tick(&chain_a, 1);
+        assert!(matches!(
+            &a.take_actions()[..],
+            [Action::Send(1, Message::CmpctBlock(compact))] if compact.block_hash() == chain_a.tip_hash()
+        ));
+        a.announce_tip(&chain_a);
+        assert!(a.take_actions().is_empty());
+
+        // 只通告最新的链顶时，对方先把它作为孤块，再请求缺失的父区块
+        chain_a.mine_block_with(&Miner::new(1));
+        chain_a.mine_block_with(&Miner::new(1));
+        a.on_tick(&chain_a, 2);
+        pump(&mut a, &mut chain_a, &mut b, &mut chain_b, 2);
+        assert_eq!(chain_b.height(), 3);
+        assert_eq!(chain_b.tip_hash(), chain_a.tip_hash());
+    }
+
+    #[test]
+    fn test_siphash_reference_vector() {
+        let key: Vec<u8> = (0..16).collect();
+        let k0 = u64::from_le_bytes(key[..8].try_into().unwrap());
+        let k1 = u64::from_le_bytes(key[8..].try_into().unwrap());
+        let data: Vec<u8> = (0..15).collect();
+        assert_eq!(siphash24(k0, k1, &data), 0xa129ca6149be45e5);
+    }
+
+    #[test]
+    fn test_compact_block_reconstruction() {
+        let config = NetworkConfig::default();
+        let (mut a, mut chain_a) = (PeerManager::new(config.clone(), 1), BlockChain::new(0));
+        let (mut b, mut chain_b) = (PeerManager::new(config, 2), BlockChain::new(0));
+        let (alice, bob) = (key_pair(), key_pair());
+        let funding = funding_block(&alice);
+        let coinbase = funding.transactions[0].hash();
+        chain_a.process_block(funding.clone()).unwrap();
+        chain_b.process_block(funding.clone()).unwrap();
+        a.on_connected(1, "b".into(), Direction::Outbound, &chain_a, 0);
+        b.on_connected(1, "a".into(), Direction::Inbound, &chain_b, 0);
+        pump(&mut a, &mut chain_a, &mut b, &mut chain_b, 0);
+
+        // b 的交易池中只有区块中的一部分交易
+        let shared = spend(&alice, coinbase, 10, bob.public_key().as_ref());
+        let missing = spend(&bob, shared.hash(), 10, &[1u8; 32]);
+        chain_a.add_transaction(shared.clone());
+        chain_a.add_transaction(missing.clone());
+        chain_b.add_transaction(shared.clone());
+        chain_a.mine_block_with(&Miner::new(1));
+        let block = chain_a.blocks.last().unwrap().clone();
+        let missing_index = block
+            .transactions
+            .iter()
+            .position(|tx| tx.hash() == missing.hash())
+            .unwrap() as u32;
+
+        a.on_tick(&chain_a, 1);
+        let Some(Action::Send(1, Message::CmpctBlock(compact))) = a.take_actions().pop() else {
+            panic!("expected cmpctblock");
+        };
+        assert_eq!(compact.short_ids.len(), block.transactions.len());
+        b.on_message(1, Message::CmpctBlock(compact.clone()), &mut chain_b, 1);
+
+        // 只请求缺失的交易，补齐后区块连接到链上
+        let Some(Action::Send(1, request)) = b.take_actions().pop() else {
+            panic!("expected getblocktxn");
+        };
+        assert!(matches!(
+            &request,
+            Message::GetBlockTxn { indexes, .. } if indexes[..] == [missing_index]
+        ));
+        a.on_message(1, request, &mut chain_a, 1);
+        pump(&mut a, &mut chain_a, &mut b, &mut chain_b, 1);
+        assert_eq!(chain_b.tip_hash(), chain_a.tip_hash());
+        assert!(chain_b.transaction_pool.lock().unwrap().is_empty());
+
+        // 交易池已有全部交易时无需额外往返
+        let (mut c, mut chain_c) = (
+            PeerManager::new(NetworkConfig::default(), 3),
+            BlockChain::new(0),
+        );
+        chain_c.process_block(funding).unwrap();
+        handshake(&mut c, &mut chain_c, 0);
+        chain_c.add_transaction(shared);
+        chain_c.add_transaction(missing);
+        c.take_actions();
+        c.on_message(1, Message::CmpctBlock(compact), &mut chain_c, 1);
+        assert_eq!(chain_c.tip_hash(), chain_a.tip_hash());
+        assert!(!c
+            .take_actions()
+            .iter()
+            .any(|action| matches!(action, Action::Send(_, Message::GetBlockTxn { .. }))));
+    }
+
+    #[test]
+    fn test_orphan_transactions_and_trickle() {
+        let mut chain = BlockChain::new(0);
+        let (alice, bob, carol) = (key_pair(), key_pair(), key_pair());
+        let (dave, erin) = (key_pair(), key_pair());
+        let funding = funding_block(&alice);
+        chain.process_block(funding.clone()).unwrap();
+        let late_funding = chain.block_template(Some(dave.public_key().as_ref()));
+        chain.process_block(late_funding.clone()).unwrap();
+        let mut manager = PeerManager::new(NetworkConfig::default(), 1);
+        handshake(&mut manager, &mut chain, 0);
+        manager.on_connected(2, "other".into(), Direction::Inbound, &chain, 0);
+        let version = VersionMessage {
+            nonce: 8,
+            ..version_message(&chain, 0)
+        };
+        manager.on_message(2, Message::Version(version), &mut chain, 0);
+        manager.on_message(2, Message::Verack, &mut chain, 0);
+        manager.take_actions();
+
+        // 父交易未知时暂存为孤立交易，并向对方请求父交易
+        let parent = spend(
+            &alice,
+            funding.transactions[0].hash(),
+            10,
+            bob.public_key().as_ref(),
+        );
+        let child = spend(&bob, parent.hash(), 10, carol.public_key().as_ref());
+        let grandchild = spend(&carol, child.hash(), 10, &[1u8; 32]);
+        manager.on_message(1, Message::Tx(grandchild.clone()), &mut chain, 0);
+        manager.on_message(1, Message::Tx(child.clone()), &mut chain, 0);
+        assert!(chain.transaction_pool.lock().unwrap().is_empty());
+        let requested: Vec<Inventory> = manager
+            .take_actions()
+            .into_iter()
+            .filter_map(|action| match action {
+                Action::Send(1, Message::GetData(items)) => Some(items),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+        assert_eq!(
+            requested,
+            vec![Inventory::Tx(child.hash()), Inventory::Tx(parent.hash())]
+        );
+
+        // 父交易到达后孤立交易依次进入交易池
+        manager.on_message(1, Message::Tx(parent.clone()), &mut chain, 0);
+        assert_eq!(chain.transaction_pool.lock().unwrap().len(), 3);
+
+        // 交易不会立即通告，而是在定时器中批量通告给尚不知道它们的节点
+        assert!(manager.take_actions().is_empty());
+        manager.on_tick(&chain, 1);
+        let announced: Vec<(u64, Vec<Inventory>)> = manager
+            .take_actions()
+            .into_iter()
+            .filter_map(|action| match action {
+                Action::Send(id, Message::Inv(items)) if matches!(items[0], Inventory::Tx(_)) => {
+                    Some((id, items))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(announced.len(), 1);
+        assert_eq!(announced[0].0, 2);
+        let mut items = announced[0].1.clone();
+        items.sort_by_key(|item| format!("{:?}", item));
+        let mut expected: Vec<Inventory> = [&parent, &child, &grandchild]
+            .iter()
+            .map(|tx| Inventory::Tx(tx.hash()))
+            .collect();
+        expected.sort_by_key(|item| format!("{:?}", item));
+        assert_eq!(items, expected);
+
+        // 超时的孤立交易被丢弃，父交易到达后也不会进入交易池
+        let late_parent = spend(
+            &dave,
+            late_funding.transactions[0].hash(),
+            10,
+            erin.public_key().as_ref(),
+        );
+        let late_child = spend(&erin, late_parent.hash(), 10, &[1u8; 32]);
+        manager.on_message(1, Message::Tx(late_child), &mut chain, 10);
+        manager.on_tick(&chain, 10 + ORPHAN_TX_EXPIRY_MS);
+        manager.on_message(
+            1,
+            Message::Tx(late_parent),
+            &mut chain,
+            10 + ORPHAN_TX_EXPIRY_MS,
+        );
+        assert_eq!(chain.transaction_pool.lock().unwrap().len(), 4);
+
+        // 重复收到已知的交易不计分，无效的交易按不当行为计分
+        manager.take_actions();
+        manager.on_message(1, Message::Tx(parent), &mut chain, 20 + ORPHAN_TX_EXPIRY_MS);
+        assert!(manager.take_actions().is_empty());
+        manager.on_message(
+            2,
+            Message::Tx(Transaction::new(1, 0)),
+            &mut chain,
+            20 + ORPHAN_TX_EXPIRY_MS,
+        );
+        assert!(matches!(
+            &manager.take_actions()[..],
+            [Action::Disconnect(2, reason)] if reason == "invalid transaction"
+        ));
+    }
+
+    #[test]
+    fn test_invalid_orphan_scores_sender() {
+        let mut chain = BlockChain::new(0);
+        let (alice, bob) = (key_pair(), key_pair());
+        let funding = funding_block(&alice);
+        chain.process_block(funding.clone()).unwrap();
+        let mut manager = PeerManager::new(NetworkConfig::default(), 1);
+        handshake(&mut manager, &mut chain, 0);
+        manager.take_actions();
+
+        // 签名正确但超额花费的孤立交易在父交易到达后被拒绝，发来它的节点被断开
+        let parent = spend(
+            &alice,
+            funding.transactions[0].hash(),
+            10,
+            bob.public_key().as_ref(),
+        );
+        let overspend = spend(&bob, parent.hash(), 11, &[1u8; 32]);
+        manager.on_message(1, Message::Tx(overspend), &mut chain, 0);
+        manager.take_actions();
+        manager.on_message(1, Message::Tx(parent), &mut chain, 0);
+        assert_eq!(chain.transaction_pool.lock().unwrap().len(), 1);
+        assert!(matches!(
+            &manager.take_actions()[..],
+            [Action::Disconnect(1, reason)] if reason == "invalid transaction"
+        ));
+    }
+
+    #[test]
+    fn test_orphan_pool_is_bounded() {
+        let mut pool = OrphanPool::new();
+        let mut rng = StdRng::seed_from_u64(1);
+        let key = key_pair();
+        let parent = [5; 32];
+        for value in 0..MAX_ORPHAN_TRANSACTIONS as u64 * 2 {
+            let tx = spend(&key, parent, value, &[1u8; 32]);
+            assert!(pool.add(&mut rng, tx, vec![parent], value % 3, 0));
+        }
+        assert_eq!(pool.len(), MAX_ORPHAN_TRANSACTIONS);
+        assert_eq!(pool.children(&parent).len(), MAX_ORPHAN_TRANSACTIONS);
+
+        pool.remove_peer(0);
+        assert!(pool.len() < MAX_ORPHAN_TRANSACTIONS);
+        assert!(pool.children(&parent).len() == pool.len());
+        let remaining = pool.len();
+        assert_eq!(pool.expire(ORPHAN_TX_EXPIRY_MS - 1), 0);
+        assert_eq!(pool.expire(ORPHAN_TX_EXPIRY_MS), remaining);
+        assert!(pool.is_empty());
+
+        // 未签名或超过长度上限的交易不会进入孤立交易池
+        let mut unsigned = Transaction::new(1, 0);
+        unsigned.inputs[0].previous_output = parent;
+        assert!(!pool.add(&mut rng, unsigned, vec![parent], 1, 0));
+        let oversized = spend(&key, parent, 1, &vec![0; MAX_TRANSACTION_SIZE]);
+        assert!(!pool.add(&mut rng, oversized, vec![parent], 1, 0));
+        assert!(pool.is_empty());
+    }
+
+    // 父区块未知、带有一笔 size 字节锁定脚本交易的区块
+    fn orphan_block(nonce: u32, size: usize) -> Block {
+        let mut block = Block::genesis();
+        block.header.prev_block_hash = [9; 32];
+        block.header.nonce = nonce;
+        let mut tx = Transaction::new(1, 0);
+        tx.outputs[0].script_pubkey = vec![0; size];
+        block.transactions.push(tx);
+        block
+    }
+
+    #[test]
+    fn test_orphan_blocks_are_bounded() {
+        let mut orphans = OrphanBlocks::new();
+        let mut rng = StdRng::seed_from_u64(1);
+        const MIB: usize = 1024 * 1024;
+
+        // 单个节点超过字节上限时淘汰它最早发来的孤块
+        let per_peer = MAX_ORPHAN_BLOCK_BYTES_PER_PEER / MIB;
+        let blocks: Vec<Block> = (0..per_peer as u32 + 1)
+            .map(|nonce| orphan_block(nonce, MIB))
+            .collect();
+        for (now, block) in blocks.iter().enumerate() {
+            assert!(orphans.add(&mut rng, block.clone(), 1, now as u64));
+        }
+        assert!(orphans.peer_bytes(1) <= MAX_ORPHAN_BLOCK_BYTES_PER_PEER);
+        assert!(!orphans.contains(&hash_block_header(&blocks[0].header)));
+        assert!(orphans.contains(&hash_block_header(&blocks[per_peer].header)));
+        // 超过单节点上限的区块直接拒绝
+        let huge = orphan_block(u32::MAX, MAX_ORPHAN_BLOCK_BYTES_PER_PEER);
+        assert!(!orphans.add(&mut rng, huge, 2, 0));
+
+        // 多个节点合计超过总字节上限时随机淘汰
+        for peer in 2..2 + (MAX_ORPHAN_BLOCK_BYTES / MAX_ORPHAN_BLOCK_BYTES_PER_PEER) as u64 {
+            for nonce in 0..per_peer as u32 {
+                orphans.add(&mut rng, orphan_block(nonce, MIB + peer as usize), peer, 0);
+            }
+        }
+        assert!(orphans.bytes() <= MAX_ORPHAN_BLOCK_BYTES);
+
+        // 区块数不超过上限，等待同一父区块的孤块都能找到
+        let mut orphans = OrphanBlocks::new();
+        for nonce in 0..MAX_ORPHAN_BLOCKS as u32 * 2 {
+            assert!(orphans.add(&mut rng, orphan_block(nonce, 1), nonce as u64 % 3, 0));
+        }
+        assert_eq!(orphans.len(), MAX_ORPHAN_BLOCKS);
+        assert_eq!(orphans.children(&[9; 32]).len(), MAX_ORPHAN_BLOCKS);
+        for child in orphans.children(&[9; 32]) {
+            assert!(orphans.remove(&child).is_some());
+        }
+        assert!(orphans.is_empty());
+        assert_eq!(orphans.bytes(), 0);
+    }
+
+    #[test]
+    fn test_orphan_block_without_work_is_rejected() {
+        let mut chain = BlockChain::new(1);
+        let mut manager = PeerManager::new(NetworkConfig::default(), 1);
+        handshake(&mut manager, &mut chain, 10);
+        manager.take_actions();
+
+        // 父区块未知且不满足难度的区块不会被缓存，发送方被断开
+        let mut block = orphan_block(0, 1);
+        block.header.bits = 1;
+        block.header.merkle_root = calculate_merkle_root(&block.transactions);
+        while hash_block_header(&block.header)[0] == 0 {
+            block.header.nonce += 1;
+        }
+        manager.on_message(1, Message::Block(block), &mut chain, 0);
+        assert!(manager.take_actions().iter().any(
+            |action| matches!(action, Action::Disconnect(1, reason) if reason == "invalid block")
+        ));
+    }
+
+    #[test]
+    fn test_orphans_connect_through_side_chain() {
+        let fork = mined_chain(3);
+        let mut chain = mined_chain(1);
+        let mut manager = PeerManager::new(NetworkConfig::default(), 1);
+        handshake(&mut manager, &mut chain, 3);
+
+        // 子区块先到达，父区块最后到达时只能作为分叉，缓存的子区块仍要继续连接
+        for block in fork.blocks[1..].iter().rev() {
+            manager.on_message(1, Message::Block(block.clone()), &mut chain, 0);
+        }
+        assert_eq!(chain.tip_hash(), fork.tip_hash());
+    }
+
+    #[test]
+    fn test_reorganize_to_longer_chain() {
+        let mut chain_a = BlockChain::new(0);
+        let mut chain_b = BlockChain::new(0);
+        let alice = key_pair();
+        let funding = funding_block(&alice);
+        let coinbase = funding.transactions[0].hash();
+        chain_a.process_block(funding.clone()).unwrap();
+        chain_b.process_block(funding).unwrap();
+        chain_a.add_transaction(spend(&alice, coinbase, 10, &[1u8; 32]));
+        chain_a.mine_block_with(&Miner::new(1));
+        assert_eq!(chain_a.blocks[2].transactions.len(), 1);
+        chain_b.mine_block_with(&Miner::new(1));
+        chain_b.mine_block_with(&Miner::new(1));
+
+        // 等长的分叉只保存为侧链
+        assert_eq!(
+            chain_a.process_block(chain_b.blocks[2].clone()).unwrap(),
+            BlockStatus::SideChain
+        );
+        assert_eq!(
+            chain_a.process_block(chain_b.blocks[3].clone()).unwrap(),
+            BlockStatus::Reorganized {
+                disconnected: 1,
+                connected: 2
+            }
+        );
+        assert_eq!(chain_a.tip_hash(), chain_b.tip_hash());
+        // 被断开区块中的交易回到交易池
+        assert_eq!(chain_a.transaction_pool.lock().unwrap().len(), 1);
+        assert_eq!(
+            chain_a.process_block(chain_b.blocks[3].clone()).unwrap(),
+            BlockStatus::AlreadyKnown
+        );
+    }
+
+    #[test]
+    fn test_deep_side_blocks_are_pruned() {
+        let mut chain_a = BlockChain::new(0);
+        let mut chain_b = BlockChain::new(0);
+        chain_b.mine_block_with(&Miner::new(1));
+        chain_b.mine_block_with(&Miner::new(1));
+        for _ in 0..MAX_REORG_DEPTH {
+            chain_a.mine_block();
+        }
+
+        // 仍在回退深度内的分叉区块保存为侧链
+        let side = chain_b.blocks[1].clone();
+        assert_eq!(
+            chain_a.process_block(side.clone()).unwrap(),
+            BlockStatus::SideChain
+        );
+        assert!(chain_a.contains_block(&hash_block_header(&side.header)));
+        // 主链延长后分叉点过深，侧链区块被丢弃，之后的分叉区块找不到父区块
+        chain_a.mine_block();
+        assert!(!chain_a.contains_block(&hash_block_header(&side.header)));
+        assert_eq!(
+            chain_a.process_block(chain_b.blocks[2].clone()),
+            Err(BlockError::UnknownParent)
+        );
+    }
+
+    #[test]
+    fn test_identity_file_is_private() {
+        let path = std::env::temp_dir().join(format!("identity-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let identity = Identity::load_or_generate(&path).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        // 再次启动时读取同一个身份
+        let loaded = Identity::load_or_generate(&path).unwrap();
+        assert_eq!(loaded.public_key(), identity.public_key());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_handshake() {
+        let (alice, bob) = (Identity::generate(), Identity::generate());
+        let trusted = [bob.public_key(), alice.public_key()];
+        let (mut a, mut b) = tokio::io::duplex(4096);
+        let (channel_a, channel_b) = tokio::join!(
+            transport::handshake(&mut a, &alice, true, DEFAULT_MAGIC, &[]),
+            transport::handshake(&mut b, &bob, false, DEFAULT_MAGIC, &trusted),
+        );
+        let (mut channel_a, mut channel_b) = (channel_a.unwrap(), channel_b.unwrap());
+        assert_eq!(channel_a.remote_identity, bob.public_key());
+        assert_eq!(channel_b.remote_identity, alice.public_key());
+
+        // 双向加密，密文中不出现明文
+        let mut record = channel_a.sealer.seal(b"hello bob");
+        assert!(!record.windows(9).any(|w| w == b"hello bob"));
+        let mut tampered = channel_b.sealer.seal(b"hello alice");
+        assert_eq!(
+            channel_b.opener.open(&mut record).unwrap().unwrap(),
+            b"hello bob"
+        );
+        assert!(record.is_empty());
+        // 被篡改的记录无法通过认证
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        assert!(matches!(
+            channel_a.opener.open(&mut tampered),
+            Err(TransportError::Decrypt)
+        ));
+
+        // 不在信任列表中的身份被拒绝
+        let trusted = [bob.public_key()];
+        let (mut a, b) = tokio::io::duplex(4096);
+        let (accepted, rejected) = tokio::join!(
+            transport::handshake(&mut a, &alice, true, DEFAULT_MAGIC, &[]),
+            async {
+                // 拒绝后关闭连接
+                let mut b = b;
+                transport::handshake(&mut b, &bob, false, DEFAULT_MAGIC, &trusted).await
+            },
+        );
+        assert!(accepted.is_err());
+        assert!(
+            matches!(rejected, Err(TransportError::UntrustedIdentity(key)) if key == alice.public_key())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tcp_transaction_broadcast() {
+        transaction_broadcast(false).await;
+    }
+
+    #[tokio::test]
+    async fn test_tcp_encrypted_transaction_broadcast() {
+        transaction_broadcast(true).await;
+    }
+
+    // 两个 TCP 节点建立连接后，一方通告的交易到达另一方的交易池
+    async fn transaction_broadcast(encryption: bool) {
+        let listen = NetworkConfig {
+            listen_addr: Some("127.0.0.1:0".parse().unwrap()),
+            tx_relay_interval_ms: 0,
+            encryption,
+            ..NetworkConfig::default()
+        };
+        let alice = key_pair();
+        let funding = funding_block(&alice);
+        let coinbase = funding.transactions[0].hash();
+        let (mut chain_a, mut chain_b) = (BlockChain::new(0), BlockChain::new(0));
+        chain_a.process_block(funding.clone()).unwrap();
+        chain_b.process_block(funding).unwrap();
+        let chain_a = Arc::new(AsyncMutex::new(chain_a));
+        let chain_b = Arc::new(AsyncMutex::new(chain_b));
+        let node_a = tcp::start(listen.clone(), chain_a.clone()).await.unwrap();
+        let node_b = tcp::start(
+            NetworkConfig {
+                peers: vec![node_a.local_addr().unwrap()],
+                ..listen
+            },
+            chain_b.clone(),
+        )
+        .await
+        .unwrap();
+
+        for _ in 0..100 {
+            let ready = node_a
+                .peers()
+                .await
+                .iter()
+                .any(|p| p.state == PeerState::Ready)
+                && node_b
+                    .peers()
+                    .await
+                    .iter()
+                    .any(|p| p.state == PeerState::Ready);
+            if ready {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(node_b.peers().await[0].direction, Direction::Outbound);
+        // 加密连接中双方都知道对方的身份
+        assert_eq!(
+            node_b.peers().await[0].identity,
+            node_a.identity().map(hex::encode)
+        );
+        assert_eq!(
+            node_a.peers().await[0].identity,
+            node_b.identity().map(hex::encode)
+        );
+        assert_eq!(node_a.identity().is_some(), encryption);
+
+        let tx = spend(&alice, coinbase, 5, &[1u8; 32]);
+        chain_b.lock().await.add_transaction(tx.clone());
+        node_b.broadcast_transaction(tx);
+        // 交易在下一次定时器触发时批量通告
+        for _ in 0..200 {
+            if chain_a.lock().await.transaction_pool.lock().unwrap().len() == 1 {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("transaction was not received by peer");
+    }
+}
@@ -0,0 +1,770 @@
+#[cfg(test)]
+mod tests {
+    use block_chain::auth::{hash_password, Group};
+    use block_chain::block_chain::{Block, BLOCK_REWARD};
+    use block_chain::config::{
+        NetworkPreset, NodeConfig, TlsConfig, TokenCredential, UserCredential,
+    };
+    use block_chain::hash_function::hash_block_header;
+    use block_chain::rpc::ROUTES;
+    use block_chain::serialization::{deserialize_bc, serialize_bc};
+    use block_chain::service::{Node, NodeError, NodeHandle};
+    use block_chain::transaction::{encode_address, Transaction, TxIn};
+    use block_chain::wallet::mnemonic::Mnemonic;
+    use block_chain::wallet::Wallet;
+    use futures_util::{SinkExt, StreamExt};
+    use reqwest::{Method, RequestBuilder};
+    use ring::rand::SystemRandom;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+    use serde_json::{json, Value};
+    use std::ops::Deref;
+    use std::path::PathBuf;
+    use std::time::Duration;
+    use tokio_tungstenite::tungstenite::Message;
+
+    // 回环地址上的 regtest 节点，端口由系统分配
+    fn regtest_config(name: &str) -> NodeConfig {
+        let mut config = NodeConfig {
+            network: NetworkPreset::Regtest,
+            data_dir: std::env::temp_dir().join(format!("node-{}-{}", name, std::process::id())),
+            ..NodeConfig::default()
+        };
+        config.rpc.listen = vec!["127.0.0.1:0".parse().unwrap()];
+        config.p2p.listen = Some("127.0.0.1:0".parse().unwrap());
+        config.p2p.seeds_file = None;
+        config.p2p.encryption = false;
+        config
+    }
+
+    fn key_pair() -> Ed25519KeyPair {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap()
+    }
+
+    // 测试用节点：Drop 时关闭节点并删除数据目录，断言失败时也会清理
+    struct TestNode {
+        handle: Option<NodeHandle>,
+        data_dir: PathBuf,
+        client: reqwest::Client,
+    }
+
+    impl TestNode {
+        async fn start(config: NodeConfig) -> TestNode {
+            let data_dir = config.data_dir.clone();
+            let handle = Node::start(config).await.unwrap();
+            TestNode {
+                handle: Some(handle),
+                data_dir,
+                client: reqwest::Client::new(),
+            }
+        }
+
+        // 关闭节点，保留数据目录直到 Drop
+        async fn stop(&mut self) {
+            if let Some(handle) = self.handle.take() {
+                handle.shutdown().await;
+            }
+        }
+
+        fn url(&self, path: &str) -> String {
+            format!("http://{}{}", self.rpc_addr(), path)
+        }
+
+        fn request(&self, method: Method, path: &str) -> RequestBuilder {
+            self.client.request(method, self.url(path))
+        }
+
+        async fn get(&self, path: &str) -> Value {
+            let response = self.request(Method::GET, path).send().await.unwrap();
+            response.json().await.unwrap()
+        }
+
+        async fn status(&self, path: &str) -> u16 {
+            let response = self.request(Method::GET, path).send().await.unwrap();
+            response.status().as_u16()
+        }
+
+        // 发送请求，返回状态码和 JSON 响应体（不是 JSON 时为 Null）
+        async fn send(request: RequestBuilder) -> (u16, Value) {
+            let response = request.send().await.unwrap();
+            let status = response.status().as_u16();
+            let text = response.text().await.unwrap();
+            (status, serde_json::from_str(&text).unwrap_or(Value::Null))
+        }
+
+        async fn post(&self, path: &str, body: &Value) -> (u16, Value) {
+            TestNode::send(self.request(Method::POST, path).json(body)).await
+        }
+
+        async fn post_raw(&self, path: &str, body: impl Into<reqwest::Body>) -> (u16, Value) {
+            TestNode::send(self.request(Method::POST, path).body(body)).await
+        }
+
+        // JSON-RPC 调用，返回完整的响应对象
+        async fn call(&self, method: &str, params: Value) -> Value {
+            let body = json!({"jsonrpc": "2.0", "method": method, "params": params, "id": 1});
+            self.post("/rpc", &body).await.1
+        }
+    }
+
+    impl Deref for TestNode {
+        type Target = NodeHandle;
+
+        fn deref(&self) -> &NodeHandle {
+            self.handle.as_ref().expect("node is stopped")
+        }
+    }
+
+    impl Drop for TestNode {
+        fn drop(&mut self) {
+            if let Some(handle) = self.handle.take() {
+                tokio::task::block_in_place(|| {
+                    tokio::runtime::Handle::current().block_on(handle.shutdown())
+                });
+            }
+            let _ = std::fs::remove_dir_all(&self.data_dir);
+        }
+    }
+
+    type Socket = tokio_tungstenite::WebSocketStream<
+        tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+    >;
+
+    // 读取下一条 WebSocket 文本消息
+    async fn read(socket: &mut Socket) -> Value {
+        loop {
+            let message = tokio::time::timeout(Duration::from_secs(5), socket.next())
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            if let Message::Text(text) = message {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_start_serve_and_shutdown() {
+        let mut node = TestNode::start(regtest_config("serve")).await;
+        assert_ne!(node.rpc_addr().port(), 0);
+        assert!(node.p2p_addr().is_some());
+
+        let (code, _) = node
+            .post("/transaction", &json!({"value": 100, "lock_time": 0}))
+            .await;
+        assert_eq!(code, 200);
+        assert_eq!(node.get("/pool").await.as_array().unwrap().len(), 1);
+        assert_eq!(
+            node.get("/blocks").await["blocks"]
+                .as_array()
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // 关闭后不再接受请求，地址簿写入数据目录
+        let url = node.url("/pool");
+        node.stop().await;
+        assert!(reqwest::get(url).await.is_err());
+        assert!(node.data_dir.join("peers.json").exists());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_nodes_sync_over_p2p() {
+        let a = TestNode::start(regtest_config("sync-a")).await;
+        let mut config = regtest_config("sync-b");
+        config.p2p.peers = vec![a.p2p_addr().unwrap()];
+        let b = TestNode::start(config).await;
+
+        // A 上出块后在下一次定时器通告给 B
+        {
+            let mut chain = a.node().blockchain.lock().await;
+            let block = chain.block_template(Some(&[1u8; 32]));
+            chain.process_block(block).unwrap();
+        }
+        let mut synced = false;
+        for _ in 0..50 {
+            if b.node().blockchain.lock().await.height() == 1 {
+                synced = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert!(synced);
+        assert_eq!(
+            b.get("/blocks").await["blocks"].as_array().unwrap().len(),
+            2
+        );
+
+        // 通过挖矿接口提交的区块同样会通告给 B
+        let block = a
+            .node()
+            .blockchain
+            .lock()
+            .await
+            .block_template(Some(&[2u8; 32]));
+        let raw = hex::encode(serialize_bc(&block).unwrap());
+        let (status, _) = a.post("/mining/submit", &json!({"block": raw})).await;
+        assert_eq!(status, 200);
+        let mut synced = false;
+        for _ in 0..50 {
+            if b.node().blockchain.lock().await.height() == 2 {
+                synced = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert!(synced);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_lookup_endpoints() {
+        let node = TestNode::start(regtest_config("lookup")).await;
+        let key_pair = key_pair();
+        let coinbase = {
+            let mut chain = node.node().blockchain.lock().await;
+            let block = chain.block_template(Some(key_pair.public_key().as_ref()));
+            let coinbase = block.transactions[0].hash();
+            chain.process_block(block).unwrap();
+            coinbase
+        };
+        let mut tx = Transaction::new(7, 0);
+        tx.inputs = vec![TxIn {
+            previous_output: coinbase,
+            ..TxIn::new()
+        }];
+        tx.sign(&key_pair, 0);
+        let txid = hex::encode(tx.hash());
+        node.node().blockchain.lock().await.add_transaction(tx);
+
+        let info = node.get(&format!("/tx/{}", txid)).await;
+        assert_eq!(info["status"], "mempool");
+        assert_eq!(info["confirmations"], 0);
+
+        // 打包进区块后可以按哈希和高度查到
+        let block_hash = {
+            let mut chain = node.node().blockchain.lock().await;
+            let block = chain.block_template(Some(&[1u8; 32]));
+            let hash = hash_block_header(&block.header);
+            chain.process_block(block).unwrap();
+            hex::encode(hash)
+        };
+        let tip = node.get("/tip").await;
+        assert_eq!(tip["height"], 2);
+        assert_eq!(tip["hash"], block_hash);
+
+        let block = node.get(&format!("/block/{}", block_hash)).await;
+        assert_eq!(block["height"], 2);
+        assert_eq!(block["confirmations"], 1);
+        assert_eq!(block["transactions"].as_array().unwrap().len(), 2);
+        assert_eq!(node.get("/block/height/2").await["hash"], block_hash);
+
+        let info = node.get(&format!("/tx/{}", txid)).await;
+        assert_eq!(info["status"], "confirmed");
+        assert_eq!(info["block_hash"], block_hash);
+        assert_eq!(info["confirmations"], 1);
+
+        let headers = node.get("/headers?from=2&count=5").await;
+        assert_eq!(headers.as_array().unwrap().len(), 1);
+        assert_eq!(headers[0]["hash"], block_hash);
+        assert_eq!(node.get("/headers").await.as_array().unwrap().len(), 3);
+
+        assert_eq!(node.status("/block/height/3").await, 404);
+        assert_eq!(node.status(&format!("/tx/{}", "00".repeat(32))).await, 404);
+        assert_eq!(node.status("/block/xyz").await, 400);
+
+        // 封禁时长换算为毫秒时溢出的请求被拒绝
+        let ban = |duration: u64| json!({"ip": "10.0.0.9", "duration": duration});
+        assert_eq!(node.post("/bans", &ban(u64::MAX)).await.0, 400);
+        assert!(node.get("/bans").await.as_array().unwrap().is_empty());
+        assert_eq!(node.post("/bans", &ban(60)).await.0, 200);
+        assert_eq!(node.get("/bans").await[0]["ip"], "10.0.0.9");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_paginate_export_and_import() {
+        let a = TestNode::start(regtest_config("export-a")).await;
+        let mut config = regtest_config("export-b");
+        config.p2p.inbound = false;
+        let b = TestNode::start(config).await;
+        {
+            let mut chain = a.node().blockchain.lock().await;
+            for _ in 0..4 {
+                let block = chain.block_template(Some(&[1u8; 32]));
+                chain.process_block(block).unwrap();
+            }
+        }
+
+        // 按游标分页，最后一页没有 next_cursor
+        let page = a.get("/blocks?limit=2").await;
+        assert_eq!(page["blocks"].as_array().unwrap().len(), 2);
+        let cursor = page["next_cursor"].as_str().unwrap().to_string();
+        let page = a.get(&format!("/blocks?limit=2&cursor={}", cursor)).await;
+        assert_eq!(page["blocks"].as_array().unwrap().len(), 2);
+        let cursor = page["next_cursor"].as_str().unwrap().to_string();
+        let page = a.get(&format!("/blocks?limit=2&cursor={}", cursor)).await;
+        assert_eq!(page["blocks"].as_array().unwrap().len(), 1);
+        assert!(page["next_cursor"].is_null());
+        assert_eq!(
+            a.status(&format!("/blocks?cursor={}", "00".repeat(32)))
+                .await,
+            409
+        );
+
+        // 导出的区块在另一个节点上逐块校验导入
+        let export = |format: &str| {
+            let request = a.request(Method::GET, &format!("/export?format={}", format));
+            async move { request.send().await.unwrap().bytes().await.unwrap() }
+        };
+        let ndjson = export("ndjson").await;
+        assert_eq!(ndjson.iter().filter(|b| **b == b'\n').count(), 5);
+        let binary = export("binary").await;
+        let (code, result) = b.post_raw("/import?format=binary", binary).await;
+        assert_eq!(code, 200);
+        assert_eq!(result["imported"]["connected"], 4);
+        assert_eq!(result["imported"]["already_known"], 1);
+        assert_eq!(
+            b.node().blockchain.lock().await.tip_hash(),
+            a.node().blockchain.lock().await.tip_hash()
+        );
+
+        let (code, result) = b.post_raw("/import?format=ndjson", ndjson).await;
+        assert_eq!(code, 200);
+        assert_eq!(result["imported"]["already_known"], 5);
+        let (code, result) = b
+            .post_raw("/import?format=ndjson", "{\"header\": 1}\n")
+            .await;
+        assert_eq!(code, 400);
+        assert!(result["error"].as_str().unwrap().contains("#0"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_json_rpc() {
+        let node = TestNode::start(regtest_config("jsonrpc")).await;
+        let key_pair = key_pair();
+        let address = encode_address(key_pair.public_key().as_ref());
+        let hashes = node.call("generate", json!([2, address])).await["result"].clone();
+        assert_eq!(hashes.as_array().unwrap().len(), 2);
+        assert_eq!(node.call("getblockcount", json!([])).await["result"], 2);
+        assert_eq!(
+            node.call("getblockhash", json!([2])).await["result"],
+            hashes[1]
+        );
+        assert_eq!(
+            node.call("getbalance", json!({"address": address})).await["result"],
+            2 * BLOCK_REWARD
+        );
+
+        // 按名称传参，verbosity 为 0 时返回序列化区块
+        let raw = node
+            .call("getblock", json!({"blockhash": hashes[0], "verbosity": 0}))
+            .await;
+        let block: Block =
+            deserialize_bc(&hex::decode(raw["result"].as_str().unwrap()).unwrap()).unwrap();
+        assert_eq!(hex::encode(hash_block_header(&block.header)), hashes[0]);
+        let info = node.call("getblock", json!([hashes[0]])).await;
+        assert_eq!(info["result"]["height"], 1);
+
+        // 花费第一个区块的 coinbase 输出
+        let mut tx = Transaction::new(BLOCK_REWARD, 0);
+        tx.inputs = vec![TxIn {
+            previous_output: block.transactions[0].hash(),
+            ..TxIn::new()
+        }];
+        tx.sign(&key_pair, 0);
+        let raw_tx = hex::encode(serialize_bc(&tx).unwrap());
+        let txid = node.call("sendrawtransaction", json!([raw_tx])).await["result"].clone();
+        assert_eq!(txid, hex::encode(tx.hash()));
+        let rejected = node.call("sendrawtransaction", json!([raw_tx])).await;
+        assert_eq!(rejected["error"]["code"], -32002);
+        assert_eq!(
+            node.call("getmempoolinfo", json!([])).await["result"]["size"],
+            1
+        );
+        let info = node.call("getrawtransaction", json!([txid, true])).await;
+        assert_eq!(info["result"]["status"], "mempool");
+        assert_eq!(
+            node.call("getrawtransaction", json!([txid])).await["result"],
+            raw_tx
+        );
+
+        // 批量请求：通知没有响应，错误按请求分别返回
+        let batch = json!([
+            {"jsonrpc": "2.0", "method": "getblockcount", "id": "a"},
+            {"jsonrpc": "2.0", "method": "getblockcount"},
+            {"jsonrpc": "2.0", "method": "nosuchmethod", "id": "b"},
+            {"jsonrpc": "2.0", "method": "getblockhash", "params": ["x"], "id": "c"},
+            {"jsonrpc": "2.0", "method": "getblock", "params": ["00"], "id": "d"},
+            {"method": "getblockcount", "id": "e"},
+            1
+        ]);
+        let (code, responses) = node.post("/rpc", &batch).await;
+        assert_eq!(code, 200);
+        let responses = responses.as_array().unwrap();
+        assert_eq!(responses.len(), 6);
+        assert_eq!(responses[0]["id"], "a");
+        assert_eq!(responses[0]["result"], 2);
+        assert_eq!(responses[1]["error"]["code"], -32601);
+        assert_eq!(responses[2]["error"]["code"], -32602);
+        assert_eq!(responses[3]["error"]["code"], -32602);
+        assert_eq!(responses[4]["error"]["code"], -32600);
+        assert_eq!(responses[5]["error"]["code"], -32600);
+        assert!(responses[5]["id"].is_null());
+
+        let too_many = node.call("generate", json!([u64::MAX])).await;
+        assert_eq!(too_many["error"]["code"], -32602);
+        let missing = node.call("getblock", json!([hex::encode([0u8; 32])])).await;
+        assert_eq!(missing["error"]["code"], -32001);
+        // 未启用钱包
+        let no_wallet = node.call("getnewaddress", json!([])).await;
+        assert_eq!(no_wallet["error"]["code"], -32004);
+        assert_eq!(node.post_raw("/rpc", "{").await.1["error"]["code"], -32700);
+        assert_eq!(node.post_raw("/rpc", "[]").await.1["error"]["code"], -32600);
+        let notification = json!({"jsonrpc": "2.0", "method": "getblockcount"});
+        assert_eq!(node.post("/rpc", &notification).await.0, 204);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_submit_raw_transaction() {
+        let node = TestNode::start(regtest_config("rawtx")).await;
+        let key_pair = key_pair();
+        let coinbase = {
+            let mut chain = node.node().blockchain.lock().await;
+            let block = chain.block_template(Some(key_pair.public_key().as_ref()));
+            let coinbase = block.transactions[0].hash();
+            chain.process_block(block).unwrap();
+            coinbase
+        };
+
+        let node = &node;
+        let submit = |tx: &Transaction| {
+            let body = json!({ "tx": hex::encode(serialize_bc(tx).unwrap()) });
+            async move { node.post("/tx/raw", &body).await }
+        };
+        let signed = |value: u64| {
+            let mut tx = Transaction::new(value, 0);
+            tx.inputs = vec![TxIn {
+                previous_output: coinbase,
+                ..TxIn::new()
+            }];
+            tx.sign(&key_pair, 0);
+            tx
+        };
+
+        let tx = signed(BLOCK_REWARD);
+        let (code, result) = submit(&tx).await;
+        assert_eq!(code, 200);
+        assert_eq!(result["txid"], hex::encode(tx.hash()));
+        assert_eq!(node.get("/pool").await.as_array().unwrap().len(), 1);
+
+        let (code, result) = submit(&tx).await;
+        assert_eq!(code, 409);
+        assert_eq!(result["error"], "already_known");
+        let (code, result) = submit(&signed(1)).await;
+        assert_eq!(code, 409);
+        assert_eq!(result["error"], "double_spend");
+        let (code, result) = submit(&Transaction::new(1, 0)).await;
+        assert_eq!(code, 422);
+        assert_eq!(result["error"], "missing_signature");
+        let (code, result) = node.post("/tx/raw", &json!({"tx": "zz"})).await;
+        assert_eq!(code, 400);
+        assert_eq!(result["error"], "malformed");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_wallet_rpc() {
+        let mut config = regtest_config("wallet");
+        let data_dir = config.data_dir.clone();
+        std::fs::create_dir_all(&data_dir).unwrap();
+        let password_file = data_dir.join("wallet.password");
+        std::fs::write(&password_file, "secret\n").unwrap();
+        let mnemonic = Mnemonic::generate(12).unwrap();
+        Wallet::create(&config.wallet_path(), "secret", &mnemonic, "").unwrap();
+        config.wallet.enabled = true;
+        config.wallet.password_file = Some(password_file);
+        let node = TestNode::start(config).await;
+
+        let address = node.call("getnewaddress", json!([])).await["result"].clone();
+        assert_ne!(
+            node.call("getnewaddress", json!([])).await["result"],
+            address
+        );
+        node.call("generate", json!([2, address])).await;
+        let unspent = node.call("listunspent", json!([])).await["result"].clone();
+        assert_eq!(unspent.as_array().unwrap().len(), 2);
+        assert_eq!(unspent[0]["address"], address);
+        assert_eq!(unspent[0]["amount"], BLOCK_REWARD);
+        assert_eq!(unspent[0]["confirmations"], 2);
+        assert_eq!(
+            node.call("listunspent", json!({"minconf": 2})).await["result"]
+                .as_array()
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // 两个币都被花掉，找零在打包前不可用
+        let bob = encode_address(&[9; 32]);
+        let amount = BLOCK_REWARD + 10;
+        let txid = node.call("sendtoaddress", json!([bob, amount])).await["result"].clone();
+        let info = node.call("getrawtransaction", json!([txid, true])).await;
+        assert_eq!(info["result"]["status"], "mempool");
+        assert_eq!(
+            node.call("listunspent", json!([])).await["result"],
+            json!([])
+        );
+        let rejected = node
+            .call("sendtoaddress", json!({"address": bob, "amount": 1}))
+            .await;
+        assert_eq!(rejected["error"]["code"], -32004);
+
+        node.call("generate", json!([1, bob])).await;
+        assert_eq!(
+            node.call("getbalance", json!([bob])).await["result"],
+            BLOCK_REWARD + amount
+        );
+        let unspent = node.call("listunspent", json!([])).await["result"].clone();
+        assert_eq!(unspent.as_array().unwrap().len(), 1);
+        let change = unspent[0]["amount"].as_u64().unwrap();
+        assert!(change < BLOCK_REWARD - 10 && change > BLOCK_REWARD - 20);
+        for params in [
+            json!([bob, 0]),
+            json!(["abcd", 1]),
+            json!([bob, 1, u64::MAX]),
+        ] {
+            assert_eq!(
+                node.call("sendtoaddress", params).await["error"]["code"],
+                -32602
+            );
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_websocket_subscriptions() {
+        let node = TestNode::start(regtest_config("websocket")).await;
+        let url = format!("ws://{}/ws", node.rpc_addr());
+        let (mut socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+
+        let address = encode_address(&[3u8; 32]);
+        let request =
+            json!({"subscribe": ["new_tip", "mempool_add", format!("address:{}", address)]});
+        socket
+            .send(Message::Text(request.to_string()))
+            .await
+            .unwrap();
+        let reply = read(&mut socket).await;
+        assert_eq!(reply["subscribed"].as_array().unwrap().len(), 3);
+        socket
+            .send(Message::Text(json!({"subscribe": ["moon"]}).to_string()))
+            .await
+            .unwrap();
+        assert!(read(&mut socket).await["error"]
+            .as_str()
+            .unwrap()
+            .contains("moon"));
+
+        let tx = Transaction::new(5, 0);
+        let (block_hash, coinbase) = {
+            let mut chain = node.node().blockchain.lock().await;
+            chain.add_transaction(tx.clone());
+            let block = chain.block_template(Some(&[3u8; 32]));
+            let coinbase = block.transactions[0].hash();
+            let hash = hash_block_header(&block.header);
+            chain.process_block(block).unwrap();
+            (hash, coinbase)
+        };
+
+        // 未订阅的 mempool_remove 和 block_connected 不会推送
+        let added = read(&mut socket).await;
+        assert_eq!(added["topic"], "mempool_add");
+        assert_eq!(added["txid"], hex::encode(tx.hash()));
+        let activity = read(&mut socket).await;
+        assert_eq!(activity["topic"], "address");
+        assert_eq!(activity["address"], address);
+        assert_eq!(activity["txid"], hex::encode(coinbase));
+        assert_eq!(activity["status"], "confirmed");
+        assert_eq!(activity["height"], 1);
+        let tip = read(&mut socket).await;
+        assert_eq!(tip["topic"], "new_tip");
+        assert_eq!(tip["hash"], hex::encode(block_hash));
+        assert_eq!(tip["height"], 1);
+
+        socket.close(None).await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_rpc_authentication() {
+        let mut config = regtest_config("auth");
+        config.rpc.cookie = true;
+        config.rpc.rate_limit = Some(20);
+        config.rpc.tokens.push(TokenCredential {
+            name: "dashboard".to_string(),
+            token: "read-token".to_string(),
+            group: Group::Read,
+        });
+        config.rpc.users.push(UserCredential {
+            name: "alice".to_string(),
+            hmac: hash_password("salt", "secret"),
+            group: Group::Wallet,
+        });
+        let cookie_path = config.cookie_path();
+        let mut node = TestNode::start(config).await;
+        let cookie = std::fs::read_to_string(&cookie_path).unwrap();
+        let (user, password) = cookie.split_once(':').unwrap();
+
+        let rpc =
+            |method: &str| json!({"jsonrpc": "2.0", "method": method, "params": [1], "id": 1});
+        let tip = || node.request(Method::GET, "/tip");
+        let read = |request: RequestBuilder| request.bearer_auth("read-token");
+        let admin = |request: RequestBuilder| request.basic_auth(user, Some(password));
+
+        // 未认证或凭据错误
+        assert_eq!(TestNode::send(tip()).await.0, 401);
+        assert_eq!(
+            TestNode::send(tip().basic_auth("alice", Some("wrong")))
+                .await
+                .0,
+            401
+        );
+
+        // 只读令牌可以查询，不能挖矿；JSON-RPC 按方法检查权限
+        assert_eq!(TestNode::send(read(tip())).await.0, 200);
+        let start = read(node.request(Method::POST, "/mining/start")).json(&json!({}));
+        assert_eq!(TestNode::send(start).await.0, 403);
+        let batch = read(node.request(Method::POST, "/rpc"))
+            .json(&json!([rpc("getblockcount"), rpc("generate")]));
+        let (_, response) = TestNode::send(batch).await;
+        assert_eq!(response[0]["result"], 0);
+        assert_eq!(response[1]["error"]["code"], -32003);
+
+        // 钱包用户可以提交交易，cookie 凭据有管理员权限
+        let submit = node
+            .request(Method::POST, "/tx/raw")
+            .basic_auth("alice", Some("secret"))
+            .json(&json!({"tx": "zz"}));
+        assert_eq!(TestNode::send(submit).await.0, 400);
+        let generate = admin(node.request(Method::POST, "/rpc")).json(&rpc("generate"));
+        let (_, response) = TestNode::send(generate).await;
+        assert_eq!(response["result"].as_array().unwrap().len(), 1);
+
+        // 超过每秒请求数后返回 429，其他客户端不受影响
+        let mut limited = false;
+        for _ in 0..40 {
+            if TestNode::send(admin(tip())).await.0 == 429 {
+                limited = true;
+                break;
+            }
+        }
+        assert!(limited);
+        assert_eq!(TestNode::send(read(tip())).await.0, 200);
+
+        node.stop().await;
+        assert!(!cookie_path.exists());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_tls_and_multiple_listeners() {
+        let fixtures = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data");
+        let mut config = regtest_config("tls");
+        config.rpc.listen = vec!["127.0.0.1:0".parse().unwrap(), "[::1]:0".parse().unwrap()];
+        config.rpc.tls = Some(TlsConfig {
+            cert: fixtures.join("cert.pem"),
+            key: fixtures.join("key.pem"),
+        });
+
+        // 证书文件不存在时启动失败
+        let mut missing = config.clone();
+        missing.rpc.tls = Some(TlsConfig {
+            cert: fixtures.join("missing.pem"),
+            key: fixtures.join("key.pem"),
+        });
+        assert!(matches!(
+            Node::start(missing).await,
+            Err(NodeError::Rpc(_, _))
+        ));
+
+        let node = TestNode::start(config).await;
+        assert_eq!(node.rpc_addrs().len(), 2);
+        assert!(node.rpc_addrs()[1].is_ipv6());
+
+        // 两个监听器都使用 HTTPS，证书由测试用的自签名证书校验
+        let cert = std::fs::read(fixtures.join("cert.pem")).unwrap();
+        let client = reqwest::Client::builder()
+            .add_root_certificate(reqwest::Certificate::from_pem(&cert).unwrap())
+            .build()
+            .unwrap();
+        for addr in node.rpc_addrs() {
+            let url = format!("https://{}/tip", addr);
+            let response = client.get(url).send().await.unwrap();
+            assert_eq!(response.status().as_u16(), 200);
+        }
+        // 不接受明文 HTTP
+        assert!(client.get(node.url("/tip")).send().await.is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_openapi_document_covers_routes() {
+        let node = TestNode::start(regtest_config("openapi")).await;
+        let document = node.get("/openapi.json").await;
+        assert!(document["openapi"].as_str().unwrap().starts_with("3.0"));
+
+        // 文档中的接口和权限组与 ROUTES 一致
+        let mut documented = Vec::new();
+        for (path, operations) in document["paths"].as_object().unwrap() {
+            for (method, operation) in operations.as_object().unwrap() {
+                documented.push((
+                    method.clone(),
+                    path.clone(),
+                    operation["x-permission-group"].clone(),
+                ));
+            }
+        }
+        documented.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+        let mut declared: Vec<(String, String, Value)> = ROUTES
+            .iter()
+            .map(|(method, path, group)| (method.to_string(), path.to_string(), json!(group)))
+            .collect();
+        declared.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+        assert_eq!(documented, declared);
+
+        // 每个接口都由服务器处理：未匹配的路由返回空的 404，方法不符返回 405
+        for (method, path, _) in ROUTES {
+            let path = path
+                .replace("{txid}", &"00".repeat(32))
+                .replace("{hash}", &"00".repeat(32))
+                .replace("{height}", "0")
+                .replace("{ip}", "10.0.0.1");
+            let method = Method::from_bytes(method.to_uppercase().as_bytes()).unwrap();
+            let response = node.request(method.clone(), &path).send().await.unwrap();
+            let status = response.status().as_u16();
+            let body = response.bytes().await.unwrap();
+            assert!(
+                status != 405 && !(status == 404 && body.is_empty()),
+                "{} {} is not served: {}",
+                method,
+                path,
+                status
+            );
+        }
+
+        // 引用的 schema 都在 components 中
+        let schemas = document["components"]["schemas"].as_object().unwrap();
+        for name in ["Block", "Transaction", "BlockInfo", "TransactionError"] {
+            assert!(schemas.contains_key(name), "missing schema {}", name);
+        }
+        let text = document.to_string();
+        for reference in text.split("\"$ref\":\"#/components/schemas/").skip(1) {
+            let name = &reference[..reference.find('"').unwrap()];
+            assert!(schemas.contains_key(name), "dangling reference {}", name);
+        }
+        let tip = &document["paths"]["/tip"]["get"];
+        assert_eq!(tip["x-permission-group"], "read");
+        assert_eq!(
+            tip["responses"]["200"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/TipInfo"
+        );
+        let headers = &document["paths"]["/headers"]["get"]["parameters"];
+        assert_eq!(headers.as_array().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,129 @@
+#[cfg(test)]
+mod tests {
+    use block_chain::block_chain::BlockStatus;
+    use block_chain::network::sim::Simulator;
+    use block_chain::transaction::{Transaction, TxIn, TxOut};
+
+    // 节点连成一条线：0 - 1 - 2 - ... - (n-1)
+    fn line(sim: &mut Simulator, nodes: usize) {
+        for _ in 0..nodes {
+            sim.add_node(0);
+        }
+        for i in 1..nodes {
+            assert!(sim.connect(i - 1, i));
+        }
+        sim.run_for(1_000);
+    }
+
+    #[test]
+    fn test_block_and_transaction_propagation() {
+        let mut sim = Simulator::new(1);
+        sim.set_latency(50, 200);
+        line(&mut sim, 8);
+        for i in 0..8 {
+            assert!(!sim.node(i).manager.ready_peers().is_empty());
+        }
+
+        // 区块逐跳传播到最远的节点
+        assert_eq!(sim.mine(0), Ok(BlockStatus::Connected));
+        assert!(sim.run_until(|sim| sim.converged(), 10_000));
+        assert_eq!(sim.node(7).chain.height(), 1);
+
+        // 交易随机延迟批量通告，最终进入所有节点的交易池
+        let coinbase = sim.node(7).chain.blocks[1].transactions[0].hash();
+        let mut tx = Transaction::new(42, 0);
+        tx.inputs = vec![TxIn {
+            previous_output: coinbase,
+            ..TxIn::new()
+        }];
+        tx.outputs = vec![TxOut {
+            value: 42,
+            script_pubkey: vec![7u8; 32],
+        }];
+        tx.sign(sim.node(0).key_pair(), 0);
+        let hash = tx.hash();
+        sim.submit_transaction(7, tx).unwrap();
+        let all_have_tx = |sim: &Simulator| {
+            (0..sim.len()).all(|i| {
+                let pool = sim.node(i).chain.transaction_pool.lock().unwrap();
+                pool.iter().any(|tx| tx.hash() == hash)
+            })
+        };
+        assert!(sim.run_until(all_have_tx, 120_000));
+
+        // 另一端挖出包含该交易的区块，所有交易池被清空
+        sim.mine(0).unwrap();
+        assert!(sim.run_until(|sim| sim.converged(), 10_000));
+        for i in 0..8 {
+            assert_eq!(sim.node(i).chain.height(), 2);
+            assert!(sim
+                .node(i)
+                .chain
+                .transaction_pool
+                .lock()
+                .unwrap()
+                .is_empty());
+        }
+        assert_eq!(sim.dropped(), 0);
+    }
+
+    #[test]
+    fn test_partition_fork_and_reorg() {
+        let mut sim = Simulator::new(2);
+        line(&mut sim, 6);
+
+        // 分区两侧各自出块，形成分叉
+        sim.partition(&[&[0, 1, 2], &[3, 4, 5]]);
+        sim.mine(0).unwrap();
+        sim.mine(5).unwrap();
+        sim.mine(5).unwrap();
+        sim.run_for(5_000);
+        assert_eq!(sim.node(2).chain.height(), 1);
+        assert_eq!(sim.node(3).chain.height(), 2);
+        assert!(!sim.converged());
+
+        // 分区恢复后，更长链的新区块使较短一侧重组
+        sim.heal();
+        sim.mine(5).unwrap();
+        assert!(sim.run_until(|sim| sim.converged(), 20_000));
+        for i in 0..6 {
+            assert_eq!(sim.node(i).chain.height(), 3);
+        }
+        assert!(sim.dropped() > 0);
+    }
+
+    #[test]
+    fn test_message_drops() {
+        let mut sim = Simulator::new(3);
+        line(&mut sim, 2);
+
+        // 消息全部丢失时区块无法传播
+        sim.set_drop_rate(1.0);
+        sim.mine(0).unwrap();
+        sim.run_for(5_000);
+        assert_eq!(sim.node(1).chain.height(), 0);
+
+        // 网络恢复后，下一个区块带着缺失的父区块一起同步过去
+        sim.set_drop_rate(0.0);
+        sim.mine(0).unwrap();
+        assert!(sim.run_until(|sim| sim.converged(), 10_000));
+        assert_eq!(sim.node(1).chain.height(), 2);
+    }
+
+    #[test]
+    fn test_same_seed_same_schedule() {
+        let run = |seed| {
+            let mut sim = Simulator::new(seed);
+            sim.set_latency(10, 500);
+            line(&mut sim, 5);
+            sim.mine(0).unwrap();
+            sim.mine(4).unwrap();
+            sim.run_until(|sim| sim.converged(), 10_000);
+            let tips: Vec<[u8; 32]> = (0..sim.len())
+                .map(|i| sim.node(i).chain.tip_hash())
+                .collect();
+            (sim.now(), sim.delivered(), tips)
+        };
+        assert_eq!(run(7), run(7));
+    }
+}
@@ -0,0 +1,208 @@
+#[cfg(test)]
+mod tests {
+    use block_chain::block_chain::BlockChain;
+    use block_chain::hash_function::hash_block_header;
+    use block_chain::miner::{leading_zero_bits, meets_difficulty};
+    use block_chain::stratum::{Job, StratumConfig, StratumServer, MAX_JOBS, MAX_LINE_LENGTH};
+    use block_chain::transaction::{encode_address, Transaction};
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+    use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+    use tokio::net::TcpStream;
+    use tokio::sync::Mutex as AsyncMutex;
+
+    // 本地模拟矿工
+    struct MockMiner {
+        lines: Lines<BufReader<OwnedReadHalf>>,
+        writer: OwnedWriteHalf,
+        next_id: u64,
+        job: Option<Job>,
+        share_difficulty: u32,
+    }
+
+    impl MockMiner {
+        async fn connect(addr: std::net::SocketAddr) -> Self {
+            let (reader, writer) = TcpStream::connect(addr).await.unwrap().into_split();
+            MockMiner {
+                lines: BufReader::new(reader).lines(),
+                writer,
+                next_id: 1,
+                job: None,
+                share_difficulty: 0,
+            }
+        }
+
+        // 发送请求并等待对应 id 的响应，期间处理服务器的通知
+        async fn call(&mut self, method: &str, params: Value) -> Value {
+            let id = self.next_id;
+            self.next_id += 1;
+            let request = json!({"id": id, "method": method, "params": params});
+            self.writer
+                .write_all(format!("{}\n", request).as_bytes())
+                .await
+                .unwrap();
+            loop {
+                let message = self.read().await;
+                if message["id"] == json!(id) {
+                    return message;
+                }
+            }
+        }
+
+        async fn read(&mut self) -> Value {
+            let line = self.lines.next_line().await.unwrap().unwrap();
+            let message: Value = serde_json::from_str(&line).unwrap();
+            match message["method"].as_str() {
+                Some("mining.notify") => self.job = Job::from_params(&message["params"]),
+                Some("mining.set_difficulty") => {
+                    self.share_difficulty = message["params"][0].as_u64().unwrap() as u32
+                }
+                _ => {}
+            }
+            message
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stratum_mock_miner() {
+        let blockchain = Arc::new(AsyncMutex::new(BlockChain::new(1)));
+        let config = StratumConfig {
+            payout_address: encode_address(&[9u8; 32]),
+            share_difficulty: 4,
+        };
+        let server = StratumServer::new(blockchain.clone(), config).unwrap();
+        let addr = server.listen("127.0.0.1:0".parse().unwrap()).await.unwrap();
+
+        let mut miner = MockMiner::connect(addr).await;
+        let subscribed = miner.call("mining.subscribe", json!([])).await;
+        let extranonce1 = hex::decode(subscribed["result"][1].as_str().unwrap()).unwrap();
+        assert_eq!(subscribed["result"][2], json!(4));
+        // 未授权的 worker 不能提交
+        let rejected = miner
+            .call("mining.submit", json!(["w", "1", "00000000", "0", "0"]))
+            .await;
+        assert_eq!(rejected["error"][0], json!(24));
+        let authorized = miner.call("mining.authorize", json!(["w", "x"])).await;
+        assert_eq!(authorized["result"], json!(true));
+        while miner.job.is_none() {
+            miner.read().await;
+        }
+        assert_eq!(miner.share_difficulty, 4);
+
+        let job = miner.job.clone().unwrap();
+        let extranonce2 = [1u8, 2, 3, 4];
+        let mut share_submitted = false;
+        let mut low_submitted = false;
+        for nonce in 0u32.. {
+            let header = job.header(&extranonce1, &extranonce2, job.ntime, nonce);
+            let hash = hash_block_header(&header);
+            let params = json!([
+                "w",
+                job.job_id,
+                hex::encode(extranonce2),
+                format!("{:08x}", job.ntime),
+                format!("{:08x}", nonce)
+            ]);
+            if leading_zero_bits(&hash) < 4 {
+                if !low_submitted {
+                    let response = miner.call("mining.submit", params.clone()).await;
+                    assert_eq!(response["error"][0], json!(23));
+                    // 低难度提交不被记录，再次提交仍按难度拒绝
+                    let response = miner.call("mining.submit", params).await;
+                    assert_eq!(response["error"][0], json!(23));
+                    low_submitted = true;
+                }
+                continue;
+            }
+            let response = miner.call("mining.submit", params.clone()).await;
+            assert_eq!(response["result"], json!(true));
+            if meets_difficulty(&hash, 1) {
+                break;
+            }
+            if !share_submitted {
+                // 重复提交同一份额被拒绝
+                let duplicate = miner.call("mining.submit", params).await;
+                assert_eq!(duplicate["error"][0], json!(22));
+                share_submitted = true;
+            }
+        }
+
+        assert!(server.shares_accepted() >= 1);
+        assert_eq!(server.blocks_found(), 1);
+        let blockchain = blockchain.lock().await;
+        assert_eq!(blockchain.blocks.len(), 2);
+        let coinbase = &blockchain.blocks[1].transactions[0];
+        assert!(coinbase.is_coinbase());
+        assert_eq!(coinbase.outputs[0].script_pubkey, vec![9u8; 32]);
+        // 上链后区块哈希仍满足难度
+        assert!(meets_difficulty(&blockchain.tip_hash(), 1));
+    }
+
+    #[tokio::test]
+    async fn test_stratum_rejects_overlong_line() {
+        let blockchain = Arc::new(AsyncMutex::new(BlockChain::new(1)));
+        let config = StratumConfig {
+            payout_address: encode_address(&[9u8; 32]),
+            share_difficulty: 4,
+        };
+        let server = StratumServer::new(blockchain, config).unwrap();
+        let addr = server.listen("127.0.0.1:0".parse().unwrap()).await.unwrap();
+
+        // 长度恰为上限的请求仍可处理
+        let mut miner = MockMiner::connect(addr).await;
+        let request = json!({"id": 1, "method": "mining.subscribe", "params": []}).to_string();
+        let padded = format!("{:<width$}\n", request, width = MAX_LINE_LENGTH);
+        miner.writer.write_all(padded.as_bytes()).await.unwrap();
+        let response = miner.read().await;
+        assert_eq!(response["id"], json!(1));
+
+        // 不带换行的超长输入导致连接被关闭
+        let mut miner = MockMiner::connect(addr).await;
+        let flood = vec![b'x'; MAX_LINE_LENGTH + 1];
+        let _ = miner.writer.write_all(&flood).await;
+        assert!(miner
+            .lines
+            .next_line()
+            .await
+            .map_or(true, |line| line.is_none()));
+    }
+
+    #[tokio::test]
+    async fn test_stratum_evicts_old_jobs() {
+        let blockchain = Arc::new(AsyncMutex::new(BlockChain::new(8)));
+        let config = StratumConfig {
+            payout_address: encode_address(&[9u8; 32]),
+            share_difficulty: 4,
+        };
+        let server = StratumServer::new(blockchain.clone(), config).unwrap();
+        let addr = server.listen("127.0.0.1:0".parse().unwrap()).await.unwrap();
+
+        let mut miner = MockMiner::connect(addr).await;
+        miner.call("mining.subscribe", json!([])).await;
+        miner.call("mining.authorize", json!(["w", "x"])).await;
+        while miner.job.is_none() {
+            miner.read().await;
+        }
+        let first = miner.job.clone().unwrap().job_id;
+
+        // 交易池每次变化都生成新任务，超过上限后最早的任务被淘汰
+        for extra_nonce in 0..MAX_JOBS as u64 {
+            let tx = Transaction::coinbase(1, extra_nonce, 1, vec![1u8; 32]);
+            let job_id = miner.job.clone().unwrap().job_id;
+            blockchain
+                .lock()
+                .await
+                .transaction_pool
+                .lock()
+                .unwrap()
+                .push_back(tx);
+            while miner.job.as_ref().unwrap().job_id == job_id {
+                miner.read().await;
+            }
+        }
+        let params = json!(["w", first, "00000000", "00000000", "00000000"]);
+        let response = miner.call("mining.submit", params).await;
+        assert_eq!(response["error"][0], json!(21));
+    }
+}
@@ -0,0 +1,353 @@
+#[cfg(test)]
+mod tests {
+    use block_chain::block_chain::{BlockChain, BLOCK_REWARD};
+    use block_chain::serialization::serialize_bc;
+    use block_chain::transaction::Transaction;
+    use block_chain::wallet::coin_selection::{
+        fee_for_size, select_coins, DEFAULT_FEE_RATE, INPUT_SIZE, OUTPUT_SIZE, TX_BASE_SIZE,
+    };
+    use block_chain::wallet::hd::{
+        Account, DerivationPath, ExtendedKey, KeyChain, PathError, HARDENED,
+    };
+    use block_chain::wallet::mnemonic::{Mnemonic, MnemonicError};
+    use block_chain::wallet::{Wallet, WalletError};
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("wallet-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // 挖出一个 coinbase 支付给该脚本的区块
+    fn mine_to(chain: &mut BlockChain, script: &[u8]) {
+        let block = chain.block_template(Some(script));
+        chain.process_block(block).unwrap();
+    }
+
+    #[test]
+    fn test_mnemonic_vectors() {
+        // BIP39 官方测试向量，口令为 TREZOR
+        let vectors = [
+            (
+                "00000000000000000000000000000000",
+                "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+                "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04",
+            ),
+            (
+                "7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f",
+                "legal winner thank year wave sausage worth useful legal winner thank yellow",
+                "2e8905819b8723fe2c1d161860e5ee1830318dbf49a83bd451cfb8440c28bd6fa457fe1296106559a3c80937a1c1069be3a3a5bd381ee6260e8d9739fce1f607",
+            ),
+        ];
+        for (entropy, phrase, seed) in vectors {
+            let mnemonic = Mnemonic::from_entropy(&hex::decode(entropy).unwrap()).unwrap();
+            assert_eq!(mnemonic.phrase(), phrase);
+            assert_eq!(hex::encode(mnemonic.to_seed("TREZOR")), seed);
+            assert_eq!(Mnemonic::from_phrase(phrase).unwrap(), mnemonic);
+        }
+
+        // 大小写和空白不影响解析
+        let mnemonic = Mnemonic::generate(24).unwrap();
+        let messy = format!("  {}\n", mnemonic.phrase().to_uppercase());
+        assert_eq!(Mnemonic::from_phrase(&messy).unwrap(), mnemonic);
+        assert_eq!(mnemonic.entropy().len(), 32);
+
+        assert_eq!(
+            Mnemonic::from_phrase(&["abandon"; 12].join(" ")),
+            Err(MnemonicError::Checksum)
+        );
+        assert_eq!(
+            Mnemonic::from_phrase("abandon about"),
+            Err(MnemonicError::WordCount(2))
+        );
+        assert!(matches!(
+            Mnemonic::from_phrase(&["blockchain"; 12].join(" ")),
+            Err(MnemonicError::UnknownWord(_))
+        ));
+        assert!(Mnemonic::generate(13).is_err());
+    }
+
+    #[test]
+    fn test_slip10_vectors() {
+        // SLIP-10 Ed25519 测试向量 1
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        let master = ExtendedKey::master(&seed);
+        assert_eq!(
+            hex::encode(master.secret()),
+            "2b4be7f19ee27bbf30c667b642d5f4aa69fd169872f8fc3059c08ebae2eb19e7"
+        );
+        assert_eq!(
+            hex::encode(master.chain_code()),
+            "90046a93de5380a72b5e45010748567d5ea02bbf6522f979e05c0d8d8ca9fffb"
+        );
+        assert_eq!(
+            hex::encode(master.public_key()),
+            "a4b2856bfec510abab89753fac1ac0e1112364e7d250545963f135f2a33188ed"
+        );
+        let child = master.derive(&"m/0'".parse().unwrap());
+        assert_eq!(
+            hex::encode(child.secret()),
+            "68e0fe46dfb67e368c75379acec591dad19df3cde26e63b93a8e704f1dade7a3"
+        );
+        assert_eq!(
+            hex::encode(child.chain_code()),
+            "8b59aa11380b624e81507a27fedda59fea6d0b779a778918a2fd3590e16e9c69"
+        );
+        assert_eq!(
+            hex::encode(child.public_key()),
+            "8c8a13df77a28f3445213a0f432fde644acaa215fc72dcdf300d5efaa85d350c"
+        );
+
+        let path: DerivationPath = "m/44'/1'/0h/1H/7'".parse().unwrap();
+        assert_eq!(path.to_string(), "m/44'/1'/0'/1'/7'");
+        assert_eq!(path.indexes()[0], 44 | HARDENED);
+        assert_eq!(
+            "m/44'/1".parse::<DerivationPath>(),
+            Err(PathError::NotHardened(1))
+        );
+        assert!("44'/1'".parse::<DerivationPath>().is_err());
+        assert!(DerivationPath::hardened(&[HARDENED]).is_err());
+    }
+
+    #[test]
+    fn test_account_key_chains() {
+        let mnemonic = Mnemonic::generate(12).unwrap();
+        let seed = mnemonic.to_seed("");
+        let account = Account::new(&seed, 0).unwrap();
+        assert_eq!(account.path().to_string(), "m/44'/1'/0'");
+
+        let receive: Vec<_> = account.keys(KeyChain::Receive, 0).take(3).collect();
+        let change: Vec<_> = account.keys(KeyChain::Change, 0).take(3).collect();
+        assert_eq!(receive[2].path.to_string(), "m/44'/1'/0'/0'/2'");
+        assert_eq!(change[0].path.to_string(), "m/44'/1'/0'/1'/0'");
+        let mut addresses: Vec<String> = receive
+            .iter()
+            .chain(&change)
+            .map(|key| key.address())
+            .collect();
+        addresses.sort();
+        addresses.dedup();
+        assert_eq!(addresses.len(), 6);
+
+        // 账户密钥与从主密钥按完整路径派生的结果一致
+        let direct = ExtendedKey::master(&seed).derive(&receive[1].path);
+        assert_eq!(direct.public_key(), receive[1].public_key());
+
+        // 从同一助记词恢复出相同的地址
+        let restored = Mnemonic::from_phrase(&mnemonic.phrase()).unwrap();
+        let restored = Account::new(&restored.to_seed(""), 0).unwrap();
+        assert_eq!(
+            restored.derive(KeyChain::Receive, 2).unwrap().address(),
+            receive[2].address()
+        );
+        // 不同口令得到不同的钱包
+        let other = Account::new(&mnemonic.to_seed("extra"), 0).unwrap();
+        assert_ne!(
+            other.derive(KeyChain::Receive, 0).unwrap().address(),
+            receive[0].address()
+        );
+
+        // 派生的密钥可以签名交易
+        let key = &receive[0];
+        let mut tx = Transaction::new(10, 0);
+        tx.sign(&key.key_pair(), 0);
+        assert!(tx.verify_signature(0));
+        assert_eq!(tx.inputs[0].public_key(), Some(&key.public_key()[..]));
+    }
+
+    #[test]
+    fn test_coin_selection() {
+        let mut rng = rand::rng();
+        // 不计手续费时分支定界找到恰好相等的组合，不需要找零
+        let selection = select_coins(&[10, 20, 30, 45], 50, 0, &mut rng).unwrap();
+        assert_eq!(selection.inputs, vec![1, 2]);
+        assert_eq!((selection.change, selection.fee), (0, 0));
+
+        // 每字节 1 个单位：超出部分不值得找零时并入手续费
+        let values = [1000, 2000, 5000];
+        let selection = select_coins(&values, 1700, 1000, &mut rng).unwrap();
+        assert_eq!(selection.inputs, vec![1]);
+        assert_eq!((selection.change, selection.fee), (0, 300));
+        // 否则找零，手续费恰好等于一个输入、两个输出的交易大小
+        let selection = select_coins(&values, 1500, 1000, &mut rng).unwrap();
+        assert_eq!(selection.inputs, vec![1]);
+        assert_eq!(selection.change, 240);
+        assert_eq!(selection.fee, TX_BASE_SIZE + INPUT_SIZE + 2 * OUTPUT_SIZE);
+
+        // 大量相同的小额币：分支定界找不到结果，由背包算法选出最少的币
+        let selection = select_coins(&[10; 100], 333, 0, &mut rng).unwrap();
+        assert_eq!(selection.inputs.len(), 34);
+        assert_eq!(selection.change, 7);
+
+        assert!(select_coins(&values, 8000, 1000, &mut rng).is_none());
+        // 费率或金额之和溢出时不选币
+        assert_eq!(fee_for_size(u64::MAX, INPUT_SIZE), None);
+        assert!(select_coins(&values, 1500, u64::MAX / 100, &mut rng).is_none());
+        assert!(select_coins(&[u64::MAX, 10], 5, 0, &mut rng).is_none());
+        // 不足以支付自身手续费的币不会被选中
+        assert!(select_coins(&[100, 100], 1, 1000, &mut rng).is_none());
+    }
+
+    #[test]
+    fn test_encrypted_wallet_file() {
+        let dir = temp_dir("file");
+        let path = dir.join("wallet.json");
+        let mnemonic = Mnemonic::generate(12).unwrap();
+        let mut wallet = Wallet::create(&path, "secret", &mnemonic, "extra").unwrap();
+        let first = wallet.new_address().unwrap();
+        assert_ne!(wallet.new_address().unwrap(), first);
+
+        // 文件中没有明文的助记词，只有所有者可以读写
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(!content.contains(&mnemonic.phrase()));
+        let file: serde_json::Value = serde_json::from_str(&content).unwrap();
+        assert_eq!(file["cipher"], "chacha20-poly1305");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        // 上次保存中断遗留的临时文件不影响之后的保存
+        std::fs::write(path.with_extension("tmp"), "stale").unwrap();
+        wallet.new_address().unwrap();
+        assert!(!path.with_extension("tmp").exists());
+
+        assert!(matches!(
+            Wallet::create(&path, "secret", &mnemonic, ""),
+            Err(WalletError::Exists(_))
+        ));
+        assert!(matches!(
+            Wallet::open(&path, "wrong"),
+            Err(WalletError::WrongPassword)
+        ));
+        // 重新打开后恢复助记词、口令和地址索引
+        let mut reopened = Wallet::open(&path, "secret").unwrap();
+        assert_eq!(reopened.mnemonic(), &mnemonic);
+        let account = Account::new(&mnemonic.to_seed("extra"), 0).unwrap();
+        assert_eq!(
+            reopened.new_address().unwrap(),
+            account.derive(KeyChain::Receive, 3).unwrap().address()
+        );
+        assert_eq!(
+            first,
+            account.derive(KeyChain::Receive, 0).unwrap().address()
+        );
+
+        // 篡改密钥派生参数后无法解密
+        let mut file: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        file["kdf"]["iterations"] = serde_json::json!(1);
+        std::fs::write(&path, file.to_string()).unwrap();
+        assert!(matches!(
+            Wallet::open(&path, "secret"),
+            Err(WalletError::WrongPassword)
+        ));
+        std::fs::write(&path, "{}").unwrap();
+        assert!(matches!(
+            Wallet::open(&path, "secret"),
+            Err(WalletError::Format(_))
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_scan_and_send() {
+        let dir = temp_dir("send");
+        let path = dir.join("wallet.json");
+        let mnemonic = Mnemonic::generate(12).unwrap();
+        let account = Account::new(&mnemonic.to_seed(""), 0).unwrap();
+        let mut wallet = Wallet::create(&path, "secret", &mnemonic, "").unwrap();
+        let first = hex::decode(wallet.new_address().unwrap()).unwrap();
+        let second = hex::decode(wallet.new_address().unwrap()).unwrap();
+        // 尚未分配、但在预先派生范围内的地址
+        let ahead = account.derive(KeyChain::Receive, 5).unwrap().public_key();
+        let bob = ExtendedKey::master(&[7; 32]).public_key().to_vec();
+
+        let mut chain = BlockChain::new(0);
+        mine_to(&mut chain, &first);
+        mine_to(&mut chain, &first);
+        mine_to(&mut chain, &second);
+        mine_to(&mut chain, &ahead);
+        mine_to(&mut chain, &bob);
+        wallet.scan(&chain).unwrap();
+        let coins = wallet.list_unspent(1);
+        assert_eq!(coins.len(), 4);
+        assert_eq!(wallet.balance(), 4 * BLOCK_REWARD);
+        assert_eq!(wallet.confirmations(coins[0]), 5);
+        assert_eq!(wallet.list_unspent(3).len(), 3);
+        // 用到的预先派生地址之前的索引都已分配
+        assert_eq!(
+            wallet.new_address().unwrap(),
+            account.derive(KeyChain::Receive, 6).unwrap().address()
+        );
+
+        assert!(matches!(
+            wallet.create_transaction(&bob, 0, DEFAULT_FEE_RATE),
+            Err(WalletError::InvalidAmount)
+        ));
+        assert!(matches!(
+            wallet.create_transaction(&bob, 1, u64::MAX),
+            Err(WalletError::InvalidFeeRate(_))
+        ));
+        assert!(matches!(
+            wallet.create_transaction(&bob, 4 * BLOCK_REWARD, DEFAULT_FEE_RATE),
+            Err(WalletError::InsufficientFunds { available, .. }) if available == 4 * BLOCK_REWARD
+        ));
+
+        // 多个输入分别签名，估算的大小与序列化后的大小一致
+        let amount = 2 * BLOCK_REWARD + 20;
+        let tx = wallet
+            .create_transaction(&bob, amount, DEFAULT_FEE_RATE)
+            .unwrap();
+        assert_eq!(tx.inputs.len(), 3);
+        assert_eq!(tx.outputs.len(), 2);
+        assert!((0..tx.inputs.len()).all(|index| tx.verify_signature(index)));
+        let size = serialize_bc(&tx).unwrap().len() as u64;
+        assert_eq!(size, TX_BASE_SIZE + 3 * INPUT_SIZE + 2 * OUTPUT_SIZE);
+        let change = tx.outputs[1].value;
+        let fee = 3 * BLOCK_REWARD - amount - change;
+        assert!(fee >= fee_for_size(DEFAULT_FEE_RATE, size).unwrap());
+        assert_eq!(
+            tx.outputs[1].script_pubkey,
+            account.derive(KeyChain::Change, 0).unwrap().public_key()
+        );
+        // 选中的币不能再次使用
+        assert_eq!(wallet.balance(), BLOCK_REWARD);
+
+        chain.accept_transaction(tx).unwrap();
+        wallet.scan(&chain).unwrap();
+        assert_eq!(wallet.balance(), BLOCK_REWARD);
+        mine_to(&mut chain, &bob);
+        wallet.scan(&chain).unwrap();
+        assert_eq!(wallet.balance(), BLOCK_REWARD + change);
+        assert_eq!(chain.balance(&bob), 2 * BLOCK_REWARD + amount);
+
+        // 从文件恢复的钱包重新扫描得到相同的币，包括找零
+        let mut restored = Wallet::open(&path, "secret").unwrap();
+        restored.scan(&chain).unwrap();
+        assert_eq!(restored.balance(), BLOCK_REWARD + change);
+        let change_coin = restored
+            .list_unspent(1)
+            .into_iter()
+            .find(|coin| coin.chain == KeyChain::Change)
+            .cloned()
+            .unwrap();
+        assert_eq!(change_coin.value, change);
+
+        // 另一个实例花费的币和找零在原钱包中同样可见
+        let before = restored.balance();
+        let tx = restored
+            .create_transaction(&bob, change + 10, DEFAULT_FEE_RATE)
+            .unwrap();
+        let spent = before - restored.balance();
+        let returned: u64 = tx.outputs[1..].iter().map(|output| output.value).sum();
+        chain.accept_transaction(tx).unwrap();
+        mine_to(&mut chain, &bob);
+        wallet.scan(&chain).unwrap();
+        assert_eq!(wallet.balance(), before - spent + returned);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}